@@ -1,16 +1,15 @@
 use std::collections::HashMap;
-use std::io::BufReader;
+use std::sync::mpsc::{Receiver, TryRecvError, channel};
 use std::time::Instant;
 
 use eframe::emath::Rect;
 use eframe::epaint::Color32;
-use egui::{Frame, Ui, ViewportBuilder};
+use egui::{Align2, Frame, Ui, ViewportBuilder};
 use gerber_viewer::BoundingBox;
 use gerber_viewer::GerberTransform;
-use gerber_viewer::gerber_parser::parse;
 use gerber_viewer::{
-    GerberLayer, GerberRenderer, RenderConfiguration, ToPosition, UiState, ViewState, draw_arrow, draw_crosshair,
-    draw_marker, draw_outline,
+    GerberLayer, GerberLoadError, GerberRenderer, GridSpacing, LayerConfiguration, RenderConfiguration, ToPosition,
+    UiState, ViewState, draw_arrow, draw_circle, draw_crosshair, draw_marker_at, draw_outline, draw_viewport_text,
 };
 use nalgebra::{Point2, Vector2, Vector3};
 
@@ -20,6 +19,8 @@ struct Settings {
     use_vertex_numbering: bool,
     use_shape_numbering: bool,
     use_shape_bboxes: bool,
+    use_spatial_index_cells: bool,
+    grid: Option<GridSpacing>,
     zoom_factor: f32,
     rotation_speed_deg_per_sec: f32,
     initial_rotation: f32,
@@ -35,8 +36,8 @@ struct Settings {
     /// scaling is more important when rendering multiple layers where each layer needs a different scaling.
     default_scale: f64,
 
-    /// radius of the markers, in gerber coordinates
-    marker_radius: f32,
+    /// radius of the markers, in gerber units, see `draw_marker_at`
+    marker_radius: f64,
 }
 
 impl Default for Settings {
@@ -46,6 +47,8 @@ impl Default for Settings {
             use_vertex_numbering: false,
             use_shape_numbering: false,
             use_shape_bboxes: false,
+            use_spatial_index_cells: false,
+            grid: Some(GridSpacing::Auto),
             zoom_factor: 1.0,
             rotation_speed_deg_per_sec: 0.0,
             initial_rotation: 0.0_f32.to_radians(),
@@ -54,7 +57,7 @@ impl Default for Settings {
             design_offset: Vector2::new(0.0, 0.0),
             default_scale: 1.0,
 
-            // radius of the markers, in gerber coordinates (assuming Millimeters here)
+            // the demo files are in millimeters
             marker_radius: 2.5,
         }
     }
@@ -100,6 +103,9 @@ struct GerberViewerInstance {
     ui_state: UiState,
     needs_view_fitting: bool,
     transform: GerberTransform,
+
+    /// the layer being parsed in the background, see `reparse`
+    loading: Option<Receiver<Result<GerberLayer, GerberLoadError>>>,
 }
 
 impl GerberViewerInstance {
@@ -116,7 +122,9 @@ impl GerberViewerInstance {
             use_unique_shape_colors: settings.use_unique_shape_colors,
             use_shape_numbering: settings.use_shape_numbering,
             use_shape_bboxes: settings.use_shape_bboxes,
+            use_spatial_index_cells: settings.use_spatial_index_cells,
             use_vertex_numbering: settings.use_vertex_numbering,
+            grid: settings.grid,
 
             // use the default for any remaining options, doing this makes adding options easier in the future.
             ..RenderConfiguration::default()
@@ -141,27 +149,48 @@ impl GerberViewerInstance {
             ui_state: Default::default(),
             needs_view_fitting: true,
             transform,
+            loading: None,
         }
     }
 
     fn build_layer(source: &str) -> GerberLayer {
         //
-        // parse the gerber file
+        // parse the gerber file and build a layer
         //
-        let reader = BufReader::new(source.as_bytes());
-        let doc = parse(reader).unwrap();
+        GerberLayer::from_bytes(source.as_bytes()).unwrap()
+    }
 
-        //
-        // build a layer
-        //
-        let commands = doc.into_commands();
-        GerberLayer::new(commands)
+    /// parses the source in the background, so the UI stays responsive, the current layer is shown until it's done.
+    fn reparse(&mut self, source: &str, ctx: &egui::Context) {
+        let (sender, receiver) = channel();
+        let ctx = ctx.clone();
+        GerberLayer::load_in_background(
+            source.as_bytes().to_vec(),
+            LayerConfiguration::default(),
+            move |result| {
+                // the instance may have been closed while loading
+                let _ = sender.send(result);
+                ctx.request_repaint();
+            },
+        );
+        self.loading = Some(receiver);
     }
 
-    fn reparse(&mut self, source: &str) {
-        let gerber_layer = Self::build_layer(source);
-        self.gerber_layer = gerber_layer;
-        self.needs_view_fitting = true;
+    fn poll_loading(&mut self) {
+        let Some(receiver) = &self.loading else {
+            return;
+        };
+
+        match receiver.try_recv() {
+            Ok(result) => {
+                self.loading = None;
+                self.gerber_layer = result.unwrap();
+                self.needs_view_fitting = true;
+            }
+            Err(TryRecvError::Empty) => {}
+            // parsing panicked
+            Err(TryRecvError::Disconnected) => self.loading = None,
+        }
     }
 
     fn fit_view(&mut self, viewport: Rect) {
@@ -178,18 +207,40 @@ impl GerberViewerInstance {
         let layer_bbox = layer_bbox.apply_transform_matrix(&matrix);
 
         self.view_state
-            .fit_view(viewport, &layer_bbox, self.settings.zoom_factor);
+            .home(viewport, &layer_bbox, self.settings.zoom_factor);
         self.needs_view_fitting = false;
     }
 
     fn ui(&mut self, ui: &mut egui::Ui, frame_delta: f32) {
+        self.poll_loading();
+
         egui::Panel::bottom(ui.id().with("bottom_panel")).show(ui, |ui| {
             let message = self
                 .ui_state
-                .cursor_gerber_coords
+                .cursor_origin_coords
                 .map(|coords| format!("X:{:.6}, Y:{:.6}", coords.x, coords.y))
                 .unwrap_or("None".to_string());
             ui.label(format!("Coordinates: {}", message));
+            ui.label(format!("Image transform: {}", self.gerber_layer.image_transform()));
+
+            ui.collapsing("Legend", |ui| {
+                let legend = self
+                    .renderer_configuration
+                    .legend(&self.gerber_layer, Color32::WHITE);
+
+                egui::ScrollArea::vertical()
+                    .max_height(100.0)
+                    .show(ui, |ui| {
+                        for (color, description) in legend {
+                            ui.horizontal(|ui| {
+                                let (rect, _) = ui.allocate_exact_size(egui::Vec2::splat(12.0), egui::Sense::hover());
+                                ui.painter()
+                                    .rect_filled(rect, 2.0, color);
+                                ui.label(description);
+                            });
+                        }
+                    });
+            });
         });
 
         egui::CentralPanel::default()
@@ -258,6 +309,18 @@ impl GerberViewerInstance {
                     })
                     .collect::<Vec<_>>();
 
+                // Compute the smallest enclosing circle (CYAN), a point on the circle is transformed to get the
+                // scaled radius
+                let (circle_center, circle_radius) = self.gerber_layer.bounding_circle();
+                let transform_point = |v: Point2<f64>| {
+                    let transformed = matrix * Vector3::new(v.x, v.y, 1.0);
+                    self.view_state
+                        .gerber_to_screen_coords(Point2::new(transformed.x, transformed.y))
+                };
+                let circle_center_screen = transform_point(circle_center);
+                let circle_radius_screen =
+                    circle_center_screen.distance(transform_point(circle_center + Vector2::new(circle_radius, 0.0)));
+
                 let response = ui.allocate_rect(ui.available_rect_before_wrap(), egui::Sense::drag());
                 let viewport = response.rect;
 
@@ -270,6 +333,8 @@ impl GerberViewerInstance {
                 //
                 self.ui_state
                     .update(ui, &viewport, &response, &mut self.view_state);
+                self.ui_state
+                    .update_hovered_primitive(&self.gerber_layer, &self.transform);
 
                 //
                 // Show the gerber layer and other overlays
@@ -280,20 +345,20 @@ impl GerberViewerInstance {
                 draw_crosshair(&painter, self.ui_state.origin_screen_pos, Color32::BLUE);
                 draw_crosshair(&painter, self.ui_state.center_screen_pos, Color32::LIGHT_GRAY);
 
-                GerberRenderer::new(
+                let renderer = GerberRenderer::new(
                     &self.renderer_configuration,
                     self.view_state,
                     &self.transform,
                     &self.gerber_layer,
-                )
-                .paint_layer(&painter, Color32::WHITE);
+                );
+                let render_stats = renderer.paint_layer_with_stats(&painter, Color32::WHITE);
+                renderer.paint_hovered_outline(&painter, &self.ui_state);
 
                 // if you want to display multiple layers, call `paint_layer` for each layer.
 
                 draw_outline(&painter, bbox_vertices_screen, Color32::RED);
                 draw_outline(&painter, outline_vertices_screen, Color32::GREEN);
-
-                let screen_radius = self.settings.marker_radius * self.view_state.scale;
+                draw_circle(&painter, circle_center_screen, circle_radius_screen, Color32::CYAN);
 
                 let design_offset_screen_position = self.view_state.gerber_to_screen_coords(
                     self.settings
@@ -306,23 +371,43 @@ impl GerberViewerInstance {
                     self.ui_state.origin_screen_pos,
                     Color32::ORANGE,
                 );
-                draw_marker(
+                draw_marker_at(
                     &painter,
-                    design_offset_screen_position,
+                    &self.view_state,
+                    self.settings
+                        .design_offset
+                        .to_position(),
                     Color32::ORANGE,
                     Color32::YELLOW,
-                    screen_radius,
+                    self.settings.marker_radius,
                 );
 
-                let design_origin_screen_position = self
-                    .view_state
-                    .gerber_to_screen_coords((self.settings.center_offset - self.settings.design_offset).to_position());
-                draw_marker(
+                draw_marker_at(
                     &painter,
-                    design_origin_screen_position,
+                    &self.view_state,
+                    (self.settings.center_offset - self.settings.design_offset).to_position(),
                     Color32::PURPLE,
                     Color32::MAGENTA,
-                    screen_radius,
+                    self.settings.marker_radius,
+                );
+
+                //
+                // HUD, fixed in the viewport regardless of pan and zoom
+                //
+
+                draw_viewport_text(
+                    &painter,
+                    viewport,
+                    Align2::RIGHT_TOP,
+                    format!("Zoom: {:.2}", self.view_state.scale),
+                    Color32::LIGHT_GRAY,
+                );
+                draw_viewport_text(
+                    &painter,
+                    viewport,
+                    Align2::LEFT_BOTTOM,
+                    format!("Rendered: {}", render_stats),
+                    Color32::LIGHT_GRAY,
                 );
             });
     }
@@ -342,13 +427,18 @@ enum DemoKind {
     Rectangles,
     RegionNonOverlappingContours,
     EasyEdaUnclosedRegionTest1,
+    LegacyDeprecatedCodes,
     Arcs,
+    RegionArcs,
     MacroCenterLine,
     MacroVectorLine,
     MacroRoundedRectangle,
     MacroPolygons,
     MacroPolygonsConcave,
+    MacroArithmetic,
     StepRepeat,
+    Polarity,
+    MacroExposure,
     #[allow(dead_code)]
     LocalFile,
     Playground,
@@ -444,6 +534,12 @@ impl DemoApp {
                 include_str!("../assets/arcs.gbr"),
                 Default::default(),
             ),
+            Demo::new(
+                DemoKind::RegionArcs,
+                "Region - Arcs",
+                include_str!("../assets/region-arcs.gbr"),
+                Default::default(),
+            ),
             Demo::new(
                 DemoKind::MacroCenterLine,
                 "Macro - Center-line",
@@ -474,12 +570,30 @@ impl DemoApp {
                 include_str!("../assets/macro-polygons-concave.gbr"),
                 Default::default(),
             ),
+            Demo::new(
+                DemoKind::MacroArithmetic,
+                "Macro - Arithmetic",
+                include_str!("../assets/macro-arithmetic.gbr"),
+                Default::default(),
+            ),
             Demo::new(
                 DemoKind::StepRepeat,
                 "Step Repeat",
                 include_str!("../assets/step-repeat.gbr"),
                 Default::default(),
             ),
+            Demo::new(
+                DemoKind::Polarity,
+                "Polarity",
+                include_str!("../assets/polarity.gbr"),
+                Default::default(),
+            ),
+            Demo::new(
+                DemoKind::MacroExposure,
+                "Macro exposure",
+                include_str!("../assets/macro-exposure.gbr"),
+                Default::default(),
+            ),
             Demo::new(
                 DemoKind::MirroringRotationScaling,
                 "Mirroring rotation and scaling",
@@ -510,6 +624,12 @@ impl DemoApp {
                 include_str!("../assets/easyeda-unclosed-region-test-1.gbr"),
                 Default::default(),
             ),
+            Demo::new(
+                DemoKind::LegacyDeprecatedCodes,
+                "Legacy - Deprecated Codes",
+                include_str!("../assets/legacy-deprecated-codes.gbr"),
+                Default::default(),
+            ),
             //Demo::new(DemoKind::LocalFile, "LocalFile", include_str!(r#"D:\Users\Hydra\Documents\DipTrace\Projects\SPRacingRXN1\Export\SPRacingRXN1-RevB-20240507-1510_gerberx2\TopSilk.gbr"#), Settings::local_file_settings()),
         ];
 
@@ -691,8 +811,7 @@ impl eframe::App for DemoApp {
             if demo.reparse_requested {
                 demo.reparse_requested = false;
                 if let Some(instance) = self.instances.get_mut(&demo.kind) {
-                    instance.reparse(&demo.source);
-                    ctx.request_repaint();
+                    instance.reparse(&demo.source, ctx);
                 }
             }
         }
@@ -0,0 +1,288 @@
+use std::f64::consts::{PI, TAU};
+
+use nalgebra::{Point2, Vector2};
+
+use crate::geometry;
+use crate::layer::{
+    ArcGerberPrimitive, CircleGerberPrimitive, GerberLayer, GerberPrimitive, LineGerberPrimitive,
+    PolygonGerberPrimitive,
+};
+use crate::types::Exposure;
+
+impl GerberPrimitive {
+    /// The area covered by the primitive, in square gerber units, always positive regardless of the exposure.
+    pub fn area(&self) -> f64 {
+        match self {
+            GerberPrimitive::Circle(circle) => circle.area(),
+            GerberPrimitive::Rectangle(rectangle) => rectangle.width.abs() * rectangle.height.abs(),
+            GerberPrimitive::RoundedRectangle(rectangle) => {
+                let radius = rectangle.corner_radius;
+
+                rectangle.width * rectangle.height - (4.0 - PI) * radius * radius
+            }
+            GerberPrimitive::Line(line) => line.area(),
+            GerberPrimitive::Arc(arc) => arc.area(),
+            GerberPrimitive::Polygon(polygon) => polygon.area_and_centroid().0,
+        }
+    }
+
+    /// The centroid (center of mass) of the area covered by the primitive, in gerber units.
+    pub fn centroid(&self) -> Point2<f64> {
+        match self {
+            GerberPrimitive::Circle(circle) => circle.center,
+            GerberPrimitive::Rectangle(rectangle) => {
+                rectangle.origin + Vector2::new(rectangle.width, rectangle.height) / 2.0
+            }
+            GerberPrimitive::RoundedRectangle(rectangle) => {
+                rectangle.origin + Vector2::new(rectangle.width, rectangle.height) / 2.0
+            }
+            GerberPrimitive::Line(line) => Point2::from((line.start.coords + line.end.coords) / 2.0),
+            GerberPrimitive::Arc(arc) => arc.centroid(),
+            GerberPrimitive::Polygon(polygon) => polygon.area_and_centroid().1,
+        }
+    }
+}
+
+impl CircleGerberPrimitive {
+    fn area(&self) -> f64 {
+        let circle_area = |diameter: f64| PI * diameter * diameter / 4.0;
+
+        circle_area(self.diameter) - circle_area(self.hole_diameter.unwrap_or(0.0))
+    }
+}
+
+impl LineGerberPrimitive {
+    /// Lines have round ends, so the area is a rectangle plus a circle.
+    fn area(&self) -> f64 {
+        let length = (self.end - self.start)
+            .dot(&(self.end - self.start))
+            .sqrt();
+
+        length * self.width + PI * self.width * self.width / 4.0
+    }
+}
+
+impl ArcGerberPrimitive {
+    /// The inner and outer radius of the band covered by the arc.
+    fn band(&self) -> (f64, f64) {
+        (
+            (self.radius - self.width / 2.0).max(0.0),
+            self.radius + self.width / 2.0,
+        )
+    }
+
+    fn sweep(&self) -> f64 {
+        match self.is_full_circle() {
+            true => TAU,
+            false => self.sweep_angle.abs(),
+        }
+    }
+
+    /// Arcs have flat ends, so the area is an annular sector.
+    fn area(&self) -> f64 {
+        let (inner, outer) = self.band();
+
+        self.sweep() / 2.0 * (outer * outer - inner * inner)
+    }
+
+    fn centroid(&self) -> Point2<f64> {
+        let (inner, outer) = self.band();
+        let half_sweep = self.sweep() / 2.0;
+        if self.is_full_circle() || outer * outer - inner * inner <= 0.0 {
+            return self.center;
+        }
+
+        // centroid of an annular sector, on the bisector of the sweep
+        let distance = 2.0 / 3.0 * (outer.powi(3) - inner.powi(3)) / (outer * outer - inner * inner) * half_sweep.sin()
+            / half_sweep;
+        let bisector = self.start_angle + self.sweep_angle / 2.0;
+
+        self.center + Vector2::new(bisector.cos(), bisector.sin()) * distance
+    }
+}
+
+impl PolygonGerberPrimitive {
+    /// Uses the shoelace formula, see [`geometry::signed_area`], the winding of the vertices doesn't matter.
+    fn area_and_centroid(&self) -> (f64, Point2<f64>) {
+        let vertices = &self.geometry.relative_vertices;
+
+        let signed_area = geometry::signed_area(vertices);
+        if signed_area == 0.0 {
+            return (0.0, self.center);
+        }
+
+        let mut moment = Vector2::new(0.0, 0.0);
+        for (index, current) in vertices.iter().enumerate() {
+            let next = vertices[(index + 1) % vertices.len()];
+            let cross = current.x * next.y - next.x * current.y;
+            moment += (current.coords + next.coords) * cross / 6.0;
+        }
+
+        (signed_area.abs(), self.center + moment / signed_area)
+    }
+}
+
+impl GerberLayer {
+    /// Returns the area-weighted centroid of the copper, i.e. the primitives that add material minus the primitives
+    /// that cut out material, e.g. to center the view on the "mass" of the board rather than the bounding box.
+    ///
+    /// This is an approximation, overlapping primitives are counted once for each primitive, and a cut-out subtracts
+    /// its whole area even where it doesn't overlap any copper.  Each repeat of the [`GerberLayer::step_repeats`]
+    /// counts.
+    ///
+    /// Coordinates are in gerber units, before the image transform is applied.  Returns `None` if the layer has no
+    /// copper, i.e. the total area is not positive.
+    pub fn copper_centroid(&self) -> Option<Point2<f64>> {
+        let mut total_area = 0.0;
+        let mut moment = Vector2::new(0.0, 0.0);
+
+        for primitive in self.expanded_primitives().iter() {
+            let area = match primitive.exposure() {
+                Exposure::Add => primitive.area(),
+                Exposure::CutOut => -primitive.area(),
+            };
+
+            total_area += area;
+            moment += primitive.centroid().coords * area;
+        }
+
+        match total_area > 0.0 {
+            true => Some(Point2::from(moment / total_area)),
+            false => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod copper_centroid_tests {
+    use std::sync::Arc;
+
+    use gerber_types::{Aperture, Circle, Command, ExtendedCode, Polarity, Rectangular};
+
+    use super::*;
+    use crate::LayerConfiguration;
+    use crate::layer::{PolygonGeometry, PrimitiveOrigin};
+    use crate::testing::commands::{definition, flash, flashes, header, select, step_repeat};
+
+    fn layer(apertures: Vec<Aperture>, flashes: &[(i32, Polarity, (f64, f64))]) -> GerberLayer {
+        let mut commands: Vec<Command> = header();
+        for (index, aperture) in apertures.into_iter().enumerate() {
+            commands.push(definition(10 + index as i32, aperture));
+        }
+        for &(code, polarity, (x, y)) in flashes {
+            commands.extend([ExtendedCode::LoadPolarity(polarity).into(), select(code), flash(x, y)]);
+        }
+
+        GerberLayer::new(commands)
+    }
+
+    fn assert_near(actual: Point2<f64>, expected: Point2<f64>) {
+        let difference = actual - expected;
+        assert!(difference.dot(&difference).sqrt() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn test_centroid_is_weighted_by_area() {
+        // given
+        // a large pad at the origin and a small feature far away
+        let layer = layer(
+            vec![Aperture::Circle(Circle::new(3.0)), Aperture::Circle(Circle::new(1.0))],
+            &[(10, Polarity::Dark, (0.0, 0.0)), (11, Polarity::Dark, (10.0, 0.0))],
+        );
+
+        // when
+        let centroid = layer.copper_centroid().unwrap();
+
+        // then
+        // the areas are 9:1
+        assert_near(centroid, Point2::new(1.0, 0.0));
+    }
+
+    #[test]
+    fn test_cut_outs_are_subtracted() {
+        // given
+        // the right half of the rectangle is cut out
+        let layer = layer(
+            vec![
+                Aperture::Rectangle(Rectangular::new(4.0, 2.0)),
+                Aperture::Rectangle(Rectangular::new(2.0, 2.0)),
+            ],
+            &[(10, Polarity::Dark, (2.0, 1.0)), (11, Polarity::Clear, (3.0, 1.0))],
+        );
+
+        // expect
+        assert_near(layer.copper_centroid().unwrap(), Point2::new(1.0, 1.0));
+    }
+
+    #[test]
+    fn test_step_repeats_are_counted() {
+        // given
+        // a 2mm pad at the origin, then a 3 x 1 step-and-repeat of a 2mm pad at x = 10, 20 and 30
+        let mut commands = flashes(2.0, &[(0.0, 0.0)]);
+        commands.extend(step_repeat(3, 1, 10.0, vec![flash(10.0, 0.0)]));
+        let layer = GerberLayer::new_with_configuration(commands, &LayerConfiguration {
+            instanced_step_repeat: true,
+            ..LayerConfiguration::default()
+        });
+
+        // expect
+        assert_near(layer.copper_centroid().unwrap(), Point2::new(15.0, 0.0));
+    }
+
+    #[test]
+    fn test_no_copper() {
+        // given
+        let layer = layer(vec![Aperture::Circle(Circle::new(1.0))], &[(
+            10,
+            Polarity::Clear,
+            (0.0, 0.0),
+        )]);
+
+        // expect
+        assert_eq!(layer.copper_centroid(), None);
+    }
+
+    #[test]
+    fn test_primitive_areas_and_centroids() {
+        // given
+        let line = GerberPrimitive::Line(LineGerberPrimitive {
+            start: Point2::new(0.0, 0.0),
+            end: Point2::new(2.0, 0.0),
+            width: 1.0,
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        });
+        // a half ring, radius 1.5 to 2.5, above the x axis
+        let arc = GerberPrimitive::Arc(ArcGerberPrimitive {
+            center: Point2::new(0.0, 0.0),
+            radius: 2.0,
+            width: 1.0,
+            start_angle: 0.0,
+            sweep_angle: PI,
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        });
+        // a triangle, the vertices are relative to the center
+        let triangle = GerberPrimitive::Polygon(PolygonGerberPrimitive {
+            center: Point2::new(1.0, 1.0),
+            exposure: Exposure::Add,
+            geometry: Arc::new(PolygonGeometry {
+                relative_vertices: vec![Point2::new(0.0, 0.0), Point2::new(3.0, 0.0), Point2::new(0.0, 3.0)],
+                tessellation: None,
+                is_convex: true,
+            }),
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        });
+
+        // expect
+        assert!((line.area() - (2.0 + PI / 4.0)).abs() < 1e-9);
+        assert_near(line.centroid(), Point2::new(1.0, 0.0));
+
+        assert!((arc.area() - PI / 2.0 * (2.5 * 2.5 - 1.5 * 1.5)).abs() < 1e-9);
+        let expected_distance = 4.0 * (2.5_f64.powi(3) - 1.5_f64.powi(3)) / (3.0 * PI * (2.5 * 2.5 - 1.5 * 1.5));
+        assert_near(arc.centroid(), Point2::new(0.0, expected_distance));
+
+        assert!((triangle.area() - 4.5).abs() < 1e-9);
+        assert_near(triangle.centroid(), Point2::new(2.0, 2.0));
+    }
+}
@@ -0,0 +1,309 @@
+use log::warn;
+use nalgebra::Point2;
+
+use crate::geometry::{BoundingBox, simplify_polyline};
+use crate::layer::{GerberLayer, GerberPrimitive};
+use crate::types::Exposure;
+
+/// Maximum distance between the end of a draw and the start of the next one for them to be connected, in gerber
+/// units, the end points of arcs are computed so they can be slightly off.
+const CHAIN_TOLERANCE: f64 = 1e-4;
+
+/// The closed contours of a board outline, in gerber coordinates, see [`GerberLayer::as_clip_path`].
+///
+/// The inside is determined with the even-odd rule, so a contour inside another contour, e.g. a cut-out in the board,
+/// is outside.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClipPath {
+    contours: Vec<Vec<Point2<f64>>>,
+}
+
+impl ClipPath {
+    /// Each contour is a closed polygon, the last point is not repeated.
+    pub fn new(contours: Vec<Vec<Point2<f64>>>) -> Self {
+        Self {
+            contours,
+        }
+    }
+
+    pub fn contours(&self) -> &[Vec<Point2<f64>>] {
+        &self.contours
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.contours.is_empty()
+    }
+
+    pub fn bounding_box(&self) -> BoundingBox {
+        let points = self
+            .contours
+            .iter()
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+        BoundingBox::from_points(&points)
+    }
+
+    /// Returns a copy with fewer vertices, e.g. from the flattened arcs of rounded corners, see [`simplify_polyline`].
+    ///
+    /// Vertices within `tolerance` of the simplified contours are removed, `tolerance` is in gerber units.  Contours
+    /// that simplify to fewer than 3 vertices are dropped.
+    pub fn simplified(&self, tolerance: f64) -> Self {
+        let contours = self
+            .contours
+            .iter()
+            .filter_map(|contour| {
+                let mut closed = contour.clone();
+                closed.push(*contour.first()?);
+
+                let mut simplified = simplify_polyline(&closed, tolerance);
+                simplified.pop();
+                (simplified.len() >= 3).then_some(simplified)
+            })
+            .collect();
+
+        Self::new(contours)
+    }
+
+    /// Returns true if the point is inside the clip path, using the even-odd rule.
+    pub fn contains(&self, point: Point2<f64>) -> bool {
+        let mut inside = false;
+        for contour in &self.contours {
+            for (index, a) in contour.iter().enumerate() {
+                let b = contour[(index + 1) % contour.len()];
+                if (a.y > point.y) != (b.y > point.y) {
+                    let x = a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x);
+                    if point.x < x {
+                        inside = !inside;
+                    }
+                }
+            }
+        }
+        inside
+    }
+}
+
+impl GerberLayer {
+    /// Connects the draws (lines and arcs) of a profile layer, i.e. the board outline, into closed contours, e.g. to
+    /// mask the copper layers outside the board with `GerberRenderer::paint_clip_mask`.
+    ///
+    /// The center lines of the draws are used, the width of the draws is ignored.  Draws are connected when the end of
+    /// one is the start (or the end) of another, in any order.  Draws that don't form a closed contour are ignored, a
+    /// warning is logged for each.  Other primitives, e.g. the circles at the ends of arcs, are ignored.
+    pub fn as_clip_path(&self) -> ClipPath {
+        let mut contours = Vec::new();
+        let mut paths: Vec<Vec<Point2<f64>>> = Vec::new();
+
+        for primitive in self.expanded_primitives().iter() {
+            match primitive {
+                GerberPrimitive::Line(line) if line.exposure == Exposure::Add && line.start != line.end => {
+                    paths.push(vec![line.start, line.end]);
+                }
+                GerberPrimitive::Arc(arc) if arc.exposure == Exposure::Add => {
+                    let mut points = arc
+                        .generate_points()
+                        .into_iter()
+                        .map(|point| arc.center + point.coords)
+                        .collect::<Vec<_>>();
+                    match arc.is_full_circle() {
+                        true => {
+                            points.pop();
+                            contours.push(points);
+                        }
+                        false => paths.push(points),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        while let Some(mut contour) = paths.pop() {
+            loop {
+                let end = *contour.last().unwrap();
+                if contour.len() > 2 && is_same_point(end, contour[0]) {
+                    contour.pop();
+                    contours.push(contour);
+                    break;
+                }
+
+                let next = paths
+                    .iter()
+                    .position(|path| is_same_point(path[0], end) || is_same_point(*path.last().unwrap(), end));
+                let Some(next) = next else {
+                    warn!("Ignored open contour in clip path. start: {}, end: {}", contour[0], end);
+                    break;
+                };
+
+                let mut path = paths.swap_remove(next);
+                if !is_same_point(path[0], end) {
+                    path.reverse();
+                }
+                contour.extend(path.into_iter().skip(1));
+            }
+        }
+
+        ClipPath::new(contours)
+    }
+}
+
+fn is_same_point(a: Point2<f64>, b: Point2<f64>) -> bool {
+    (a.x - b.x).abs() <= CHAIN_TOLERANCE && (a.y - b.y).abs() <= CHAIN_TOLERANCE
+}
+
+#[cfg(test)]
+mod clip_path_tests {
+    use std::f64::consts::FRAC_PI_2;
+
+    use rstest::rstest;
+
+    use super::*;
+    use crate::layer::{ArcGerberPrimitive, CircleGerberPrimitive, LineGerberPrimitive, PrimitiveOrigin};
+
+    fn line(start: (f64, f64), end: (f64, f64)) -> GerberPrimitive {
+        GerberPrimitive::Line(LineGerberPrimitive {
+            start: Point2::new(start.0, start.1),
+            end: Point2::new(end.0, end.1),
+            width: 0.1,
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        })
+    }
+
+    fn layer(primitives: Vec<GerberPrimitive>) -> GerberLayer {
+        let mut layer = GerberLayer::new(vec![]);
+        for primitive in primitives {
+            layer.push_primitive(primitive);
+        }
+        layer
+    }
+
+    #[test]
+    fn test_rectangle_in_any_order() {
+        // given
+        // out of order, and the second edge is reversed
+        let layer = layer(vec![
+            line((0.0, 0.0), (10.0, 0.0)),
+            line((10.0, 5.0), (0.0, 5.0)),
+            line((10.0, 5.0), (10.0, 0.0)),
+            line((0.0, 5.0), (0.0, 0.0)),
+        ]);
+
+        // when
+        let clip_path = layer.as_clip_path();
+
+        // then
+        assert_eq!(clip_path.contours().len(), 1);
+        assert_eq!(clip_path.contours()[0].len(), 4);
+        assert_eq!(clip_path.bounding_box().max, Point2::new(10.0, 5.0));
+    }
+
+    #[rstest]
+    #[case::inside(Point2::new(1.0, 1.0), true)]
+    #[case::outside(Point2::new(-1.0, 1.0), false)]
+    // in the hole, a circle of radius 1 around (5, 2.5)
+    #[case::hole(Point2::new(5.0, 2.5), false)]
+    // in the rounded corner, outside the arc
+    #[case::corner(Point2::new(9.9, 4.9), false)]
+    fn test_contains(#[case] point: Point2<f64>, #[case] expected: bool) {
+        // given
+        // a 10x5 rectangle, with a rounded top right corner and a round hole
+        let layer = layer(vec![
+            line((0.0, 0.0), (10.0, 0.0)),
+            line((10.0, 0.0), (10.0, 4.0)),
+            GerberPrimitive::Arc(ArcGerberPrimitive {
+                center: Point2::new(9.0, 4.0),
+                radius: 1.0,
+                width: 0.1,
+                start_angle: 0.0,
+                sweep_angle: FRAC_PI_2,
+                exposure: Exposure::Add,
+                primitive_origin: PrimitiveOrigin::Programmatic,
+            }),
+            line((9.0, 5.0), (0.0, 5.0)),
+            line((0.0, 5.0), (0.0, 0.0)),
+            GerberPrimitive::Arc(ArcGerberPrimitive {
+                center: Point2::new(5.0, 2.5),
+                radius: 1.0,
+                width: 0.1,
+                start_angle: 0.0,
+                sweep_angle: 0.0,
+                exposure: Exposure::Add,
+                primitive_origin: PrimitiveOrigin::Programmatic,
+            }),
+            // ignored, e.g. the end of an arc
+            GerberPrimitive::Circle(CircleGerberPrimitive {
+                center: Point2::new(9.0, 5.0),
+                diameter: 0.1,
+                hole_diameter: None,
+                exposure: Exposure::Add,
+                primitive_origin: PrimitiveOrigin::Programmatic,
+            }),
+        ]);
+
+        // when
+        let clip_path = layer.as_clip_path();
+
+        // then
+        assert_eq!(clip_path.contours().len(), 2);
+        assert_eq!(clip_path.contains(point), expected);
+    }
+
+    #[test]
+    fn test_simplified() {
+        // given
+        // a 10x5 rectangle, with a point in the middle of the bottom edge and a full circle
+        let layer = layer(vec![
+            line((0.0, 0.0), (5.0, 0.0)),
+            line((5.0, 0.0), (10.0, 0.0)),
+            line((10.0, 0.0), (10.0, 5.0)),
+            line((10.0, 5.0), (0.0, 5.0)),
+            line((0.0, 5.0), (0.0, 0.0)),
+            GerberPrimitive::Arc(ArcGerberPrimitive {
+                center: Point2::new(5.0, 2.5),
+                radius: 1.0,
+                width: 0.1,
+                start_angle: 0.0,
+                sweep_angle: 0.0,
+                exposure: Exposure::Add,
+                primitive_origin: PrimitiveOrigin::Programmatic,
+            }),
+        ]);
+        let clip_path = layer.as_clip_path();
+
+        // when
+        let simplified = clip_path.simplified(0.05);
+
+        // then
+        assert_eq!(simplified.contours().len(), 2);
+        let rectangle = simplified
+            .contours()
+            .iter()
+            .find(|contour| contour.len() == 4);
+        assert!(rectangle.is_some(), "contours: {:?}", simplified.contours());
+        let circle = simplified
+            .contours()
+            .iter()
+            .find(|contour| contour.len() != 4)
+            .unwrap();
+        let original_circle = clip_path
+            .contours()
+            .iter()
+            .find(|contour| contour.len() > 5)
+            .unwrap();
+        assert!(circle.len() < original_circle.len());
+        assert!(!simplified.contains(Point2::new(5.0, 2.5)));
+        assert!(simplified.contains(Point2::new(1.0, 1.0)));
+    }
+
+    #[test]
+    fn test_open_contour_is_ignored() {
+        // given
+        let layer = layer(vec![line((0.0, 0.0), (10.0, 0.0)), line((10.0, 0.0), (10.0, 5.0))]);
+
+        // when
+        let clip_path = layer.as_clip_path();
+
+        // then
+        assert!(clip_path.is_empty());
+    }
+}
@@ -50,13 +50,29 @@ pub fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> (u8, u8, u8) {
 }
 
 impl Exposure {
+    /// The fill color used by the built-in renderer for a primitive with this exposure, for use by custom renderers.
+    ///
+    /// Conceptually a cut-out subtracts the primitive from the primitives drawn before it, revealing whatever is below
+    /// the layer.  The renderer approximates that by drawing it over them in black, so a cut-out shows the (black)
+    /// viewer background, not the layers below.  Renderers that can composite layers separately should subtract
+    /// instead, e.g. by drawing cut-outs with a 'clear' blend mode into an offscreen layer.
+    ///
+    /// `Color32` is premultiplied, so a cut-out uses black with the same alpha as `color`, otherwise a semi-transparent
+    /// layer would have opaque black holes, and the anti-aliased edges of the holes would be dark fringes.
     #[cfg(feature = "egui")]
     pub fn to_color(&self, color: &Color32) -> Color32 {
         match self {
-            Exposure::CutOut => Color32::BLACK,
+            Exposure::CutOut => Color32::from_black_alpha(color.a()),
             Exposure::Add => *color,
         }
     }
+
+    /// Same as [`Exposure::to_color`], with the opacity of `color` multiplied by `factor` (0.0 to 1.0) first, e.g. to
+    /// fade a layer.
+    #[cfg(feature = "egui")]
+    pub fn apply_opacity(&self, color: &Color32, factor: f32) -> Color32 {
+        self.to_color(&color.gamma_multiply(factor.clamp(0.0, 1.0)))
+    }
 }
 
 #[cfg(all(test, feature = "egui"))]
@@ -77,4 +93,112 @@ mod exposure_tests {
         let expected = Color32::BLACK;
         assert_eq!(exposure.to_color(&color), expected);
     }
+
+    #[test]
+    fn test_exposure_apply_opacity() {
+        let color = Color32::from_rgb(200, 100, 50);
+        assert_eq!(Exposure::Add.apply_opacity(&color, 0.5), color.gamma_multiply(0.5));
+        assert_eq!(
+            Exposure::CutOut.apply_opacity(&color, 0.5),
+            Color32::from_black_alpha(128)
+        );
+    }
+}
+
+#[cfg(all(test, feature = "egui"))]
+mod premultiplied_alpha_tests {
+    use egui::Context;
+    use egui::epaint::{Mesh, TessellationOptions, Tessellator};
+    use gerber_types::{ExtendedCode, Polarity};
+    use rstest::rstest;
+
+    use super::*;
+    use crate::testing::commands::{circle, draw_to, flash, flashes, move_to, select};
+    use crate::{GerberLayer, GerberRenderer, GerberTransform, RenderConfiguration, ViewState};
+
+    /// Renders `layer` with the built-in renderer and tessellates the shapes, with anti-aliasing, like egui does.
+    fn render_meshes(layer: &GerberLayer, color: Color32) -> Vec<Mesh> {
+        let configuration = RenderConfiguration::default();
+        let view = ViewState {
+            scale: 10.0,
+            ..ViewState::default()
+        };
+        let renderer = GerberRenderer::new(&configuration, view, &GerberTransform::default(), layer);
+        let shapes = renderer.build_shapes(&Context::default(), color);
+
+        let mut tessellator = Tessellator::new(1.0, TessellationOptions::default(), [1, 1], vec![]);
+        shapes
+            .into_iter()
+            .map(|shape| {
+                let mut mesh = Mesh::default();
+                tessellator.tessellate_shape(shape, &mut mesh);
+                mesh
+            })
+            .collect()
+    }
+
+    #[rstest]
+    #[case::opaque(255)]
+    #[case::half(128)]
+    #[case::faint(32)]
+    fn test_edges_have_no_dark_fringe(#[case] alpha: u8) {
+        // given
+        // a dark pad and a track, with a clear pad on top
+        let mut commands = flashes(1.0, &[(0.0, 0.0)]);
+        commands.extend([
+            move_to(2.0, 0.0),
+            draw_to(5.0, 0.0),
+            ExtendedCode::LoadPolarity(Polarity::Clear).into(),
+            circle(11, 0.5),
+            select(11),
+            flash(0.0, 0.0),
+        ]);
+        let layer = GerberLayer::new(commands);
+        let layer_color = Color32::from_rgba_unmultiplied(255, 255, 255, alpha);
+
+        // when
+        let meshes = render_meshes(&layer, layer_color);
+
+        // then
+        // premultiplied white has equal color and alpha channels, a smaller color channel is a darker halo
+        let dark = &meshes[..meshes.len() - 1];
+        let dark_colors: Vec<Color32> = dark
+            .iter()
+            .flat_map(|mesh| {
+                mesh.vertices
+                    .iter()
+                    .map(|vertex| vertex.color)
+            })
+            .collect();
+        assert!(
+            dark_colors
+                .iter()
+                .all(|color| color.r() == color.a() && color.g() == color.a() && color.b() == color.a())
+        );
+
+        // and the anti-aliased edges fade out, not just the layer alpha
+        assert!(dark_colors.contains(&layer_color));
+        assert!(dark_colors.contains(&Color32::TRANSPARENT));
+
+        // and the clear pad is black, with no more alpha than the layer
+        let clear = &meshes[meshes.len() - 1];
+        assert!(!clear.vertices.is_empty());
+        assert!(clear.vertices.iter().all(|vertex| {
+            let color = vertex.color;
+            color.r() == 0 && color.g() == 0 && color.b() == 0 && color.a() <= alpha
+        }));
+    }
+
+    #[test]
+    fn test_cutout_uses_layer_alpha() {
+        // given
+        let layer_color = Color32::from_rgba_unmultiplied(200, 100, 50, 128);
+
+        // when
+        let color = Exposure::CutOut.to_color(&layer_color);
+
+        // then
+        assert_eq!(color, Color32::from_black_alpha(128));
+        assert_eq!(color.a(), layer_color.a());
+    }
 }
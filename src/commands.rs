@@ -0,0 +1,205 @@
+use gerber_parser::GerberDoc;
+use gerber_types::{
+    ApertureAttribute, ApertureDefinition, AttributeDeletionCriterion, Command, ExtendedCode, FileAttribute,
+    ObjectAttribute, Polarity,
+};
+
+use crate::layer::GerberLayer;
+
+/// The parsed command stream of a gerber file, for tooling that inspects the raw commands, e.g. linters or
+/// statistics, without building the primitives of a [`GerberLayer`].
+///
+/// Command indices are the same as the `command_index` used by [`LayerWarning`] and the rest of the crate, so results
+/// can be correlated with a layer built from the same commands, see [`GerberCommands::into_layer`].
+///
+/// Requires the `parser` feature.
+///
+/// [`LayerWarning`]: crate::LayerWarning
+#[derive(Debug, Clone, Default)]
+pub struct GerberCommands {
+    commands: Vec<Command>,
+}
+
+/// An attribute command, see [`GerberCommands::attributes`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AttributeCommand<'a> {
+    /// `TF`
+    File(&'a FileAttribute),
+    /// `TA`
+    Aperture(&'a ApertureAttribute),
+    /// `TO`
+    Object(&'a ObjectAttribute),
+    /// `TD`
+    Delete(&'a AttributeDeletionCriterion),
+}
+
+impl GerberCommands {
+    pub fn new(commands: Vec<Command>) -> Self {
+        Self {
+            commands,
+        }
+    }
+
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    /// Returns the commands with their command index.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Command)> {
+        self.commands.iter().enumerate()
+    }
+
+    /// Returns the aperture definitions (`AD`), in the order they occur.
+    pub fn aperture_definitions(&self) -> impl Iterator<Item = (usize, &ApertureDefinition)> {
+        self.iter()
+            .filter_map(|(index, command)| match command {
+                Command::ExtendedCode(ExtendedCode::ApertureDefinition(definition)) => Some((index, definition)),
+                _ => None,
+            })
+    }
+
+    /// Returns the polarity changes (`LP`), in the order they occur.
+    pub fn polarity_changes(&self) -> impl Iterator<Item = (usize, Polarity)> {
+        self.iter()
+            .filter_map(|(index, command)| match command {
+                Command::ExtendedCode(ExtendedCode::LoadPolarity(polarity)) => Some((index, *polarity)),
+                _ => None,
+            })
+    }
+
+    /// Returns the attribute commands (`TF`, `TA`, `TO` and `TD`), in the order they occur.
+    pub fn attributes(&self) -> impl Iterator<Item = (usize, AttributeCommand<'_>)> {
+        self.iter()
+            .filter_map(|(index, command)| {
+                let attribute = match command {
+                    Command::ExtendedCode(ExtendedCode::FileAttribute(attribute)) => AttributeCommand::File(attribute),
+                    Command::ExtendedCode(ExtendedCode::ApertureAttribute(attribute)) => {
+                        AttributeCommand::Aperture(attribute)
+                    }
+                    Command::ExtendedCode(ExtendedCode::ObjectAttribute(attribute)) => {
+                        AttributeCommand::Object(attribute)
+                    }
+                    Command::ExtendedCode(ExtendedCode::DeleteAttribute(criterion)) => {
+                        AttributeCommand::Delete(criterion)
+                    }
+                    _ => return None,
+                };
+                Some((index, attribute))
+            })
+    }
+
+    pub fn into_commands(self) -> Vec<Command> {
+        self.commands
+    }
+
+    /// Builds the primitives, same as [`GerberLayer::new`].
+    pub fn into_layer(self) -> GerberLayer {
+        GerberLayer::new(self.commands)
+    }
+}
+
+/// Keeps the commands that were parsed successfully, same as [`GerberDoc::into_commands`].
+impl From<GerberDoc> for GerberCommands {
+    fn from(document: GerberDoc) -> Self {
+        Self::new(document.into_commands())
+    }
+}
+
+#[cfg(test)]
+mod gerber_commands_tests {
+    use std::io::BufReader;
+
+    use super::*;
+
+    const SOURCE: &str = r#"
+%TF.FileFunction,Copper,L1,Top*%
+%FSLAX26Y26*%
+%MOMM*%
+%TA.AperFunction,SMDPad,CuDef*%
+%ADD10C,0.5*%
+%TD*%
+%ADD11R,1.0X0.5*%
+D10*
+X0Y0D03*
+%LPC*%
+D11*
+X1000000Y0D03*
+%LPD*%
+M02*
+"#;
+
+    fn commands() -> GerberCommands {
+        GerberCommands::from(gerber_parser::parse(BufReader::new(SOURCE.as_bytes())).unwrap())
+    }
+
+    #[test]
+    fn test_aperture_definitions() {
+        // given
+        let commands = commands();
+
+        // when
+        let codes = commands
+            .aperture_definitions()
+            .map(|(_index, definition)| definition.code)
+            .collect::<Vec<_>>();
+
+        // then
+        assert_eq!(codes, vec![10, 11]);
+    }
+
+    #[test]
+    fn test_polarity_changes() {
+        // given
+        let commands = commands();
+
+        // when
+        let changes = commands
+            .polarity_changes()
+            .map(|(_index, polarity)| polarity)
+            .collect::<Vec<_>>();
+
+        // then
+        assert_eq!(changes, vec![Polarity::Clear, Polarity::Dark]);
+    }
+
+    #[test]
+    fn test_attributes() {
+        // given
+        let commands = commands();
+
+        // when
+        let attributes = commands
+            .attributes()
+            .collect::<Vec<_>>();
+
+        // then
+        assert_eq!(attributes.len(), 3);
+        assert!(matches!(attributes[0].1, AttributeCommand::File(_)));
+        assert!(matches!(attributes[1].1, AttributeCommand::Aperture(_)));
+        assert!(matches!(attributes[2].1, AttributeCommand::Delete(_)));
+
+        // the indices are command indices
+        for (index, _attribute) in attributes {
+            assert!(matches!(
+                commands.commands()[index],
+                Command::ExtendedCode(
+                    ExtendedCode::FileAttribute(_)
+                        | ExtendedCode::ApertureAttribute(_)
+                        | ExtendedCode::DeleteAttribute(_)
+                )
+            ));
+        }
+    }
+
+    #[test]
+    fn test_into_layer() {
+        // given
+        let commands = commands();
+
+        // when
+        let layer = commands.into_layer();
+
+        // then
+        assert_eq!(layer.primitives().len(), 2);
+    }
+}
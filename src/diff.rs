@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::mem::discriminant;
+
+use nalgebra::Point2;
+
+use crate::layer::{GerberLayer, GerberPrimitive};
+
+/// Default tolerance used by [`GerberLayer::diff`], in gerber units.
+///
+/// Small enough to not hide real changes, large enough to absorb rounding from differing coordinate formats.
+pub const DEFAULT_DIFF_TOLERANCE: f64 = 1e-6;
+
+/// The result of comparing two layers, see [`GerberLayer::diff`].
+///
+/// All values are primitive indices, `removed` indexes the primitives of the layer `diff` was called on, `added`
+/// indexes the primitives of the `other` layer.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LayerDiff {
+    /// Primitives that are only in the `other` layer.
+    pub added: Vec<usize>,
+    /// Primitives that are only in this layer.
+    pub removed: Vec<usize>,
+    /// Pairs of `(this, other)` primitives that are at the same location but have different geometry.
+    pub changed: Vec<(usize, usize)>,
+}
+
+impl LayerDiff {
+    /// Returns true if the layers are equal, within the tolerance used to create the diff.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl GerberLayer {
+    /// Compares this layer with `other`, using [`DEFAULT_DIFF_TOLERANCE`].
+    ///
+    /// See [`GerberLayer::diff_with_tolerance`].
+    pub fn diff(&self, other: &GerberLayer) -> LayerDiff {
+        self.diff_with_tolerance(other, DEFAULT_DIFF_TOLERANCE)
+    }
+
+    /// Compares this layer with `other`.
+    ///
+    /// Primitives are matched one-to-one using their geometry, the order of primitives in the layers is not
+    /// significant.
+    ///
+    /// Matching heuristic:
+    /// 1. A primitive is unchanged if the other layer has a primitive of the same kind and exposure with all positions
+    ///    and sizes within `tolerance`.
+    /// 2. Of the remaining primitives, a primitive is changed if the other layer has an unmatched primitive of the same
+    ///    kind whose bounding box center is within `tolerance` of its own, e.g. a pad that changed size or shape.
+    /// 3. Anything else is removed (only in this layer) or added (only in `other`).
+    ///
+    /// `tolerance` is in gerber units (as per the layer's unit) and is applied to each axis separately.  Arc angles are
+    /// compared using the same value, in radians.
+    ///
+    /// Candidates are found by bounding box center, so a primitive that moved by more than `tolerance` is reported as
+    /// removed and added, not as changed.
+    pub fn diff_with_tolerance(&self, other: &GerberLayer, tolerance: f64) -> LayerDiff {
+        let ours = self.primitives();
+        let theirs = other.primitives();
+
+        let index = CenterIndex::new(theirs, tolerance);
+        let mut matched = vec![false; theirs.len()];
+        let mut unmatched: Vec<usize> = Vec::new();
+
+        for (our_index, primitive) in ours.iter().enumerate() {
+            let found = index
+                .candidates(primitive)
+                .find(|&candidate| !matched[candidate] && primitive.approx_eq(&theirs[candidate], tolerance));
+
+            match found {
+                Some(candidate) => matched[candidate] = true,
+                None => unmatched.push(our_index),
+            }
+        }
+
+        let mut diff = LayerDiff::default();
+
+        for our_index in unmatched {
+            let primitive = &ours[our_index];
+            let found = index
+                .candidates(primitive)
+                .find(|&candidate| !matched[candidate] && discriminant(primitive) == discriminant(&theirs[candidate]));
+
+            match found {
+                Some(candidate) => {
+                    matched[candidate] = true;
+                    diff.changed
+                        .push((our_index, candidate));
+                }
+                None => diff.removed.push(our_index),
+            }
+        }
+
+        diff.added = matched
+            .iter()
+            .enumerate()
+            .filter_map(|(index, matched)| (!matched).then_some(index))
+            .collect();
+
+        diff
+    }
+}
+
+/// Buckets primitives by their bounding box center, using cells the size of the tolerance.
+struct CenterIndex<'a> {
+    primitives: &'a [GerberPrimitive],
+    cell_size: f64,
+    tolerance: f64,
+    cells: HashMap<(i64, i64), Vec<usize>>,
+}
+
+impl<'a> CenterIndex<'a> {
+    fn new(primitives: &'a [GerberPrimitive], tolerance: f64) -> Self {
+        // cells larger than the tolerance just yield more candidates, but a tiny (or zero) cell size would overflow the
+        // cell coordinates.
+        let cell_size = tolerance.max(DEFAULT_DIFF_TOLERANCE);
+
+        let mut cells: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+        for (index, primitive) in primitives.iter().enumerate() {
+            let key = Self::key(primitive.bounding_box().center(), cell_size);
+            cells
+                .entry(key)
+                .or_default()
+                .push(index);
+        }
+
+        Self {
+            primitives,
+            cell_size,
+            tolerance,
+            cells,
+        }
+    }
+
+    fn key(point: Point2<f64>, cell_size: f64) -> (i64, i64) {
+        (
+            (point.x / cell_size).floor() as i64,
+            (point.y / cell_size).floor() as i64,
+        )
+    }
+
+    /// Indices of the primitives whose bounding box center is within tolerance of the given primitive's, in layer
+    /// order.
+    fn candidates(&self, primitive: &GerberPrimitive) -> impl Iterator<Item = usize> + '_ {
+        let center = primitive.bounding_box().center();
+        let (x, y) = Self::key(center, self.cell_size);
+
+        let mut candidates: Vec<usize> = (-1..=1)
+            .flat_map(|dx| (-1..=1).map(move |dy| (x + dx, y + dy)))
+            .filter_map(|key| self.cells.get(&key))
+            .flatten()
+            .copied()
+            .filter(|&index| {
+                let other = self.primitives[index]
+                    .bounding_box()
+                    .center();
+                (other.x - center.x).abs() <= self.tolerance && (other.y - center.y).abs() <= self.tolerance
+            })
+            .collect();
+        candidates.sort_unstable();
+
+        candidates.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod diff_tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::testing::commands;
+
+    #[test]
+    fn test_identical_layers() {
+        // given
+        let layer = commands::flashes_layer(1.0, &[(0.0, 0.0), (5.0, 5.0)]);
+
+        // when
+        let diff = layer.diff(&layer.clone());
+
+        // then
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn test_order_is_not_significant() {
+        // given
+        let before = commands::flashes_layer(1.0, &[(0.0, 0.0), (5.0, 5.0)]);
+        let after = commands::flashes_layer(1.0, &[(5.0, 5.0), (0.0, 0.0)]);
+
+        // when
+        let diff = before.diff(&after);
+
+        // then
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn test_added_and_removed() {
+        // given
+        let before = commands::flashes_layer(1.0, &[(0.0, 0.0), (5.0, 5.0)]);
+        let after = commands::flashes_layer(1.0, &[(0.0, 0.0), (10.0, 10.0), (20.0, 20.0)]);
+
+        // when
+        let diff = before.diff(&after);
+
+        // then
+        assert_eq!(diff, LayerDiff {
+            added: vec![1, 2],
+            removed: vec![1],
+            changed: vec![],
+        });
+    }
+
+    #[test]
+    fn test_changed() {
+        // given
+        let before = commands::flashes_layer(1.0, &[(0.0, 0.0)]);
+        let after = commands::flashes_layer(2.0, &[(0.0, 0.0)]);
+
+        // when
+        let diff = before.diff(&after);
+
+        // then
+        assert_eq!(diff, LayerDiff {
+            added: vec![],
+            removed: vec![],
+            changed: vec![(0, 0)],
+        });
+    }
+
+    #[rstest]
+    #[case::within_tolerance(0.01, true)]
+    #[case::outside_tolerance(0.001, false)]
+    fn test_tolerance(#[case] tolerance: f64, #[case] expect_empty: bool) {
+        // given
+        let before = commands::flashes_layer(1.0, &[(0.0, 0.0)]);
+        let after = commands::flashes_layer(1.0, &[(0.005, 0.0)]);
+
+        // when
+        let diff = before.diff_with_tolerance(&after, tolerance);
+
+        // then
+        assert_eq!(diff.is_empty(), expect_empty);
+    }
+}
@@ -1,45 +1,369 @@
-use egui::{Color32, Painter, Pos2, Shape, Stroke};
+use egui::{Align, Align2, Color32, FontId, Painter, Pos2, Rect, Shape, Stroke, StrokeKind, Vec2};
+use nalgebra::Point2;
 
+use crate::{GerberPrimitive, ViewState};
+
+/// Width of the lines used by the drawing helpers, in points.
+///
+/// egui converts points to physical pixels using `pixels_per_point`, so this is the same visual size on HiDPI displays.
+pub const OVERLAY_STROKE_WIDTH: f32 = 1.0;
+
+/// Returns a stroke for the drawing helpers that is never thinner than one physical pixel.
+///
+/// When `pixels_per_point` is less than 1.0 (e.g. the UI is scaled down) a 1 point line would otherwise be less than a
+/// pixel wide and appear faint or disappear.
+fn overlay_stroke(painter: &Painter, color: Color32) -> Stroke {
+    let min_width = 1.0 / painter.pixels_per_point();
+    Stroke::new(OVERLAY_STROKE_WIDTH.max(min_width), color)
+}
+
+/// Draws horizontal and vertical lines across the entire viewport, through `position` (screen coordinates, in points).
+///
+/// The lines are snapped to the center of a physical pixel so that they are crisp on HiDPI displays.
+///
+/// See [`draw_crosshair_styled`] for dashed lines, fixed length arms and a center gap.
 pub fn draw_crosshair(painter: &Painter, position: Pos2, color: Color32) {
-    // Calculate viewport bounds to extend lines across entire view
-    let viewport = painter.clip_rect();
-
-    // Draw a horizontal line (extending across viewport)
-    painter.line_segment(
-        [
-            Pos2::new(viewport.min.x, position.y),
-            Pos2::new(viewport.max.x, position.y),
-        ],
-        Stroke::new(1.0, color),
-    );
+    draw_crosshair_styled(painter, position, color, CrosshairStyle::default());
+}
 
-    // Draw a vertical line (extending across viewport)
-    painter.line_segment(
-        [
-            Pos2::new(position.x, viewport.min.y),
-            Pos2::new(position.x, viewport.max.y),
-        ],
-        Stroke::new(1.0, color),
-    );
+/// How far the arms of a crosshair extend from the center.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CrosshairSpan {
+    /// To the edges of the painter's clip rect.
+    Viewport,
+    /// Each arm is the given length, in points, measured from the center.
+    Fixed(f32),
+}
+
+/// Crosshair style, for [`draw_crosshair_styled`], all lengths are in points.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CrosshairStyle {
+    pub span: CrosshairSpan,
+    /// When set, the arms are drawn as dashes of `dash_length`, separated by `dash_gap`.
+    pub dashed: bool,
+    pub dash_length: f32,
+    pub dash_gap: f32,
+    /// Empty space around the center, so the crosshair doesn't obscure the exact point.
+    pub center_gap: f32,
+}
+
+impl Default for CrosshairStyle {
+    fn default() -> Self {
+        Self {
+            span: CrosshairSpan::Viewport,
+            dashed: false,
+            dash_length: 4.0,
+            dash_gap: 4.0,
+            center_gap: 0.0,
+        }
+    }
+}
+
+/// Draws a crosshair through `position` (screen coordinates, in points), using the given style.
+///
+/// The lines are snapped to the center of a physical pixel so that they are crisp on HiDPI displays.
+pub fn draw_crosshair_styled(painter: &Painter, position: Pos2, color: Color32, style: CrosshairStyle) {
+    let stroke = overlay_stroke(painter, color);
+
+    let x = painter.round_to_pixel_center(position.x);
+    let y = painter.round_to_pixel_center(position.y);
+
+    let (min, max) = match style.span {
+        // Calculate viewport bounds to extend lines across entire view
+        CrosshairSpan::Viewport => {
+            let viewport = painter.clip_rect();
+            (viewport.min, viewport.max)
+        }
+        CrosshairSpan::Fixed(length) => (Pos2::new(x - length, y - length), Pos2::new(x + length, y + length)),
+    };
+
+    let gap = style.center_gap.max(0.0);
+
+    // left, right, up, down; each arm goes from the center gap outwards.
+    let arms = [
+        (Pos2::new(x - gap, y), Pos2::new(min.x, y)),
+        (Pos2::new(x + gap, y), Pos2::new(max.x, y)),
+        (Pos2::new(x, y - gap), Pos2::new(x, min.y)),
+        (Pos2::new(x, y + gap), Pos2::new(x, max.y)),
+    ];
+
+    for (start, end) in arms {
+        // skip arms that are entirely inside the center gap
+        if (end - Pos2::new(x, y)).length() <= gap {
+            continue;
+        }
+
+        if style.dashed {
+            painter.extend(Shape::dashed_line(
+                &[start, end],
+                stroke,
+                style.dash_length,
+                style.dash_gap,
+            ));
+        } else {
+            painter.line_segment([start, end], stroke);
+        }
+    }
 }
 
+/// `start` and `end` are screen coordinates, in points.
 pub fn draw_arrow(painter: &Painter, start: Pos2, end: Pos2, color: Color32) {
-    painter.line_segment([start, end], Stroke::new(1.0, color));
+    painter.line_segment([start, end], overlay_stroke(painter, color));
+}
+
+/// Draws the arc of an angle measurement, from `direction_a` to `direction_b` around `vertex`, with the angle in
+/// degrees as a label just outside the arc, see `measure_angle`.
+///
+/// `vertex` is in screen coordinates, `radius` is in points and the directions are in screen space (Y down), i.e. the
+/// directions of an `AngleMeasurement` with the Y axis flipped.
+pub fn draw_angle(painter: &Painter, vertex: Pos2, direction_a: Vec2, direction_b: Vec2, radius: f32, color: Color32) {
+    const STEPS: usize = 32;
+    const LABEL_GAP: f32 = 4.0;
+
+    let start = direction_a.angle();
+    let sweep =
+        (direction_b.angle() - start + std::f32::consts::PI).rem_euclid(std::f32::consts::TAU) - std::f32::consts::PI;
+
+    let points = (0..=STEPS)
+        .map(|step| vertex + Vec2::angled(start + sweep * step as f32 / STEPS as f32) * radius)
+        .collect::<Vec<_>>();
+    painter.add(Shape::line(points, overlay_stroke(painter, color)));
+
+    let bisector = Vec2::angled(start + sweep / 2.0);
+    painter.text(
+        vertex + bisector * (radius + LABEL_GAP),
+        Align2([away_from(bisector.x), away_from(bisector.y)]),
+        format!("{:.1}°", sweep.abs().to_degrees()),
+        FontId::monospace(12.0),
+        color,
+    );
+}
+
+/// Aligns a label on the side facing away from the arc, for one component of the direction to the label.
+fn away_from(component: f32) -> Align {
+    match component {
+        component if component > 0.3 => Align::Min,
+        component if component < -0.3 => Align::Max,
+        _ => Align::Center,
+    }
+}
+
+/// Distance between the viewport-anchored overlays and the edges of the viewport, in points.
+pub const VIEWPORT_MARGIN: f32 = 8.0;
+
+/// Returns the position of a corner, the middle of an edge, or the center of `viewport`, inset by [`VIEWPORT_MARGIN`],
+/// e.g. `Align2::RIGHT_BOTTOM` is just inside the bottom right corner.
+///
+/// Overlays drawn at this position, with the same `anchor`, stay fixed in the viewport regardless of the pan and zoom
+/// of the view, e.g. for HUD text, a compass or a legend.
+pub fn viewport_anchor_position(viewport: Rect, anchor: Align2) -> Pos2 {
+    let inner = viewport.shrink(VIEWPORT_MARGIN);
+    anchor.pos_in_rect(&inner)
+}
+
+/// Draws text at a fixed position in the viewport, see [`viewport_anchor_position`].
+///
+/// The text is aligned to the anchor, e.g. `Align2::LEFT_TOP` text starts in the top left corner and extends to the
+/// right and down.  Returns the area of the text, in screen coordinates, e.g. to place another overlay next to it.
+pub fn draw_viewport_text(
+    painter: &Painter,
+    viewport: Rect,
+    anchor: Align2,
+    text: impl ToString,
+    color: Color32,
+) -> Rect {
+    painter.text(
+        viewport_anchor_position(viewport, anchor),
+        anchor,
+        text,
+        FontId::monospace(12.0),
+        color,
+    )
 }
 
+/// Draws a legend, a color swatch followed by a description for each entry, at a fixed position in the viewport, see
+/// [`viewport_anchor_position`] and `RenderConfiguration::legend`.
+///
+/// The entries are stacked in the order given, with a translucent background so they are readable on top of the layer.
+/// Returns the area of the legend, in screen coordinates.
+pub fn draw_viewport_legend(
+    painter: &Painter,
+    viewport: Rect,
+    anchor: Align2,
+    entries: &[(Color32, String)],
+    text_color: Color32,
+) -> Rect {
+    const SWATCH_SIZE: f32 = 10.0;
+    const ROW_HEIGHT: f32 = 16.0;
+    const SPACING: f32 = 4.0;
+
+    let font_id = FontId::monospace(12.0);
+    let galleys = entries
+        .iter()
+        .map(|(_color, description)| painter.layout_no_wrap(description.clone(), font_id.clone(), text_color))
+        .collect::<Vec<_>>();
+
+    let text_width = galleys
+        .iter()
+        .map(|galley| galley.size().x)
+        .fold(0.0, f32::max);
+    let size =
+        Vec2::new(SWATCH_SIZE + SPACING + text_width, ROW_HEIGHT * entries.len() as f32) + Vec2::splat(SPACING * 2.0);
+
+    let rect = anchor.anchor_size(viewport_anchor_position(viewport, anchor), size);
+    painter.rect_filled(rect, 2.0, Color32::from_black_alpha(160));
+
+    for (index, ((color, _description), galley)) in entries.iter().zip(galleys).enumerate() {
+        let row_center_y = rect.top() + SPACING + ROW_HEIGHT * (index as f32 + 0.5);
+
+        let swatch = Rect::from_center_size(
+            Pos2::new(rect.left() + SPACING + SWATCH_SIZE / 2.0, row_center_y),
+            Vec2::splat(SWATCH_SIZE),
+        );
+        painter.rect_filled(swatch, 0.0, *color);
+
+        let text_position = Pos2::new(swatch.right() + SPACING, row_center_y - galley.size().y / 2.0);
+        painter.galley(text_position, galley, text_color);
+    }
+
+    rect
+}
+
+/// Draws the outline of a circle, e.g. the one returned by [`GerberLayer::bounding_circle`].
+///
+/// `center` is in screen coordinates and `radius` is in points.
+///
+/// [`GerberLayer::bounding_circle`]: crate::GerberLayer::bounding_circle
+pub fn draw_circle(painter: &Painter, center: Pos2, radius: f32, color: Color32) {
+    let stroke = overlay_stroke(painter, color);
+    painter.circle_stroke(center, radius, stroke);
+}
+
+/// `vertices` are screen coordinates, in points.
+///
+/// See [`draw_outline_styled`] for round or bevelled corners.
 pub fn draw_outline(painter: &Painter, vertices: Vec<Pos2>, color: Color32) {
-    painter.add(Shape::closed_line(vertices, Stroke::new(1.0, color)));
+    draw_outline_styled(painter, vertices, color, JoinStyle::Miter);
+}
+
+/// How the corners of an outline are drawn.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JoinStyle {
+    /// Sharp corners, the edges are extended until they meet.  Long spikes can appear at acute angles.
+    #[default]
+    Miter,
+    /// The corners are rounded, with a radius of half the stroke width.
+    Round,
+    /// The corners are cut off.
+    Bevel,
 }
 
+/// Draws a closed outline through `vertices` (screen coordinates, in points), using the given corner style.
+pub fn draw_outline_styled(painter: &Painter, vertices: Vec<Pos2>, color: Color32, join: JoinStyle) {
+    let stroke = overlay_stroke(painter, color);
+
+    if join == JoinStyle::Miter || vertices.len() < 3 {
+        painter.add(Shape::closed_line(vertices, stroke));
+        return;
+    }
+
+    // egui's `PathStroke` always uses miter joins for closed paths, so the edges are drawn individually (without
+    // joins) and the corners are filled in afterwards.
+    let count = vertices.len();
+    let half_width = stroke.width / 2.0;
+
+    for index in 0..count {
+        let previous = vertices[(index + count - 1) % count];
+        let vertex = vertices[index];
+        let next = vertices[(index + 1) % count];
+
+        painter.line_segment([vertex, next], stroke);
+
+        match join {
+            JoinStyle::Round => {
+                painter.circle_filled(vertex, half_width, color);
+            }
+            JoinStyle::Bevel => {
+                let incoming = (vertex - previous).normalized().rot90() * half_width;
+                let outgoing = (next - vertex).normalized().rot90() * half_width;
+
+                // one of the two triangles is on the outside of the corner, the other one is covered by the edges.
+                for sign in [1.0, -1.0] {
+                    painter.add(Shape::convex_polygon(
+                        vec![vertex, vertex + incoming * sign, vertex + outgoing * sign],
+                        color,
+                        Stroke::NONE,
+                    ));
+                }
+            }
+            // drawn as a closed path, above
+            JoinStyle::Miter => {}
+        }
+    }
+}
+
+/// Size of the handles drawn by [`draw_selection_handles`], in points.
+pub const SELECTION_HANDLE_SIZE: f32 = 6.0;
+
+/// Draws small square handles at the corners and the center of the bounding box of a selected primitive, e.g. for
+/// an editing UI.  This is purely visual, hit-testing the handles is up to the caller.
+///
+/// The handles are a fixed size in screen space, regardless of the zoom level.  The bounding box is converted using
+/// [`ViewState::gerber_to_screen_coords`], so no layer or render transform is applied.
+pub fn draw_selection_handles(painter: &Painter, view: &ViewState, primitive: &GerberPrimitive, color: Color32) {
+    let stroke = overlay_stroke(painter, Color32::BLACK);
+    let bbox = primitive.bounding_box();
+
+    let mut positions = bbox.vertices();
+    positions.push(bbox.center());
+
+    for position in positions {
+        let position = view.gerber_to_screen_coords(position);
+        let center = Pos2::new(
+            painter.round_to_pixel_center(position.x),
+            painter.round_to_pixel_center(position.y),
+        );
+        let handle = Rect::from_center_size(center, Vec2::splat(SELECTION_HANDLE_SIZE));
+
+        // outlined, so the handles are visible on top of primitives of the same color
+        painter.rect(handle, 0.0, color, stroke, StrokeKind::Outside);
+    }
+}
+
+/// `position` is in screen coordinates and `radius` is in points, see [`draw_marker_at`] for gerber units.
 pub fn draw_marker(painter: &Painter, position: Pos2, color1: Color32, color2: Color32, radius: f32) {
+    let stroke1 = overlay_stroke(painter, color1);
+    let stroke2 = overlay_stroke(painter, color2);
+
     let start1 = Pos2::new(position.x - radius, position.y - 0.0);
     let end1 = Pos2::new(position.x + radius, position.y - 0.0);
     let start2 = Pos2::new(position.x + 0.0, position.y - radius);
     let end2 = Pos2::new(position.x + 0.0, position.y + radius);
 
-    painter.line_segment([start1, end1], Stroke::new(1.0, color1));
+    painter.line_segment([start1, end1], stroke1);
 
-    painter.line_segment([start2, end2], Stroke::new(1.0, color1));
+    painter.line_segment([start2, end2], stroke1);
 
-    painter.circle(position, radius * 0.25, Color32::TRANSPARENT, Stroke::new(1.0, color2));
+    painter.circle(position, radius * 0.25, Color32::TRANSPARENT, stroke2);
+}
+
+/// Same as [`draw_marker`], but `position` and `radius` are in gerber units, so the marker scales with the zoom level.
+///
+/// Converted using [`ViewState::gerber_to_screen_coords`] and [`ViewState::gerber_to_screen_length`], so no layer or
+/// render transform is applied.
+pub fn draw_marker_at(
+    painter: &Painter,
+    view: &ViewState,
+    position: Point2<f64>,
+    color1: Color32,
+    color2: Color32,
+    radius: f64,
+) {
+    draw_marker(
+        painter,
+        view.gerber_to_screen_coords(position),
+        color1,
+        color2,
+        view.gerber_to_screen_length(radius),
+    );
 }
@@ -0,0 +1,106 @@
+use nalgebra::Point2;
+
+use crate::layer::GerberLayer;
+use crate::types::Exposure;
+
+/// A drilled hole, e.g. from an Excellon drill file, in gerber units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DrillHole {
+    pub center: Point2<f64>,
+    pub diameter: f64,
+}
+
+impl DrillHole {
+    pub fn new(center: Point2<f64>, diameter: f64) -> Self {
+        Self {
+            center,
+            diameter,
+        }
+    }
+}
+
+impl GerberLayer {
+    /// Returns the indices of the holes whose center is not on copper, e.g. a drill that is misregistered with the pads
+    /// of a copper layer, for a quick registration check, see `GerberRenderer::paint_misregistered_holes`.
+    ///
+    /// A hole is on copper when the top-most primitive at its center adds material, so a hole in a gap cleared in a
+    /// pad is flagged.  Pads, traces and regions all count as copper.  The annular ring is not checked, only the
+    /// center.  The hole centers are in gerber units, before the image transform is applied, same as
+    /// [`GerberLayer::instance_at`].
+    pub fn misregistered_holes(&self, holes: &[DrillHole]) -> Vec<usize> {
+        let primitives = self.primitives();
+
+        holes
+            .iter()
+            .enumerate()
+            .filter(|(_, hole)| {
+                !self
+                    .instance_at(hole.center)
+                    .is_some_and(|instance| primitives[instance.index].exposure() == Exposure::Add)
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod misregistered_holes_tests {
+    use gerber_types::{Command, ExtendedCode, Polarity};
+    use rstest::rstest;
+
+    use super::*;
+    use crate::testing::commands::{circle, draw_to, flash, header, move_to, select};
+
+    /// A 2mm pad at the origin with a 0.5mm gap cleared at (0.5, 0), and a 0.2mm trace from (5, 0) to (10, 0).
+    fn layer() -> GerberLayer {
+        let mut commands: Vec<Command> = header();
+        commands.extend([
+            circle(10, 2.0),
+            circle(11, 0.5),
+            circle(12, 0.2),
+            select(10),
+            flash(0.0, 0.0),
+            select(12),
+            move_to(5.0, 0.0),
+            draw_to(10.0, 0.0),
+            ExtendedCode::LoadPolarity(Polarity::Clear).into(),
+            select(11),
+            flash(0.5, 0.0),
+        ]);
+
+        GerberLayer::new(commands)
+    }
+
+    #[rstest]
+    #[case::centered(Point2::new(0.0, 0.0), false)]
+    #[case::offset_on_pad(Point2::new(-0.8, 0.0), false)]
+    #[case::on_trace(Point2::new(7.5, 0.05), false)]
+    #[case::in_gap(Point2::new(0.5, 0.0), true)]
+    #[case::off_pad(Point2::new(0.0, 1.5), true)]
+    #[case::no_copper(Point2::new(20.0, 20.0), true)]
+    fn test_misregistered_holes(#[case] center: Point2<f64>, #[case] expected: bool) {
+        // given
+        let layer = layer();
+        let holes = [DrillHole::new(center, 0.3)];
+
+        // when
+        let flagged = layer.misregistered_holes(&holes);
+
+        // then
+        assert_eq!(!flagged.is_empty(), expected);
+    }
+
+    #[test]
+    fn test_indices_of_flagged_holes() {
+        // given
+        let layer = layer();
+        let holes = [
+            DrillHole::new(Point2::new(3.0, 0.0), 0.3),
+            DrillHole::new(Point2::new(0.0, 0.0), 0.3),
+            DrillHole::new(Point2::new(0.0, -3.0), 0.3),
+        ];
+
+        // expect
+        assert_eq!(layer.misregistered_holes(&holes), vec![0, 2]);
+    }
+}
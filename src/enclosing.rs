@@ -0,0 +1,260 @@
+use nalgebra::Point2;
+use rand::SeedableRng;
+use rand::prelude::SmallRng;
+use rand::seq::SliceRandom;
+
+use crate::layer::GerberLayer;
+
+/// Points that are this close to the circle, in gerber units, are considered to be inside it, otherwise rounding
+/// errors would make the algorithm rebuild the circle for points on the boundary.
+const EPSILON: f64 = 1e-9;
+
+/// Points are considered collinear when the sine of the angle between the two sides of their triangle is smaller than
+/// this, relative so that it works at any scale.
+const COLLINEAR_EPSILON: f64 = 1e-12;
+
+impl GerberLayer {
+    /// Returns the center and the radius of the smallest circle that encloses the bounding boxes of all the
+    /// primitives, e.g. to check that a layout fits a round board or a rotary fixture.
+    ///
+    /// Uses Welzl's algorithm over the corners of the bounding boxes, so the circle can be slightly larger than one
+    /// that encloses the exact outlines of circles and arcs.  An empty layer returns a circle with a radius of 0 at the
+    /// origin.  The values are in gerber units, before the image transform is applied.  All the repeats of the
+    /// [`GerberLayer::step_repeats`] are enclosed.
+    pub fn bounding_circle(&self) -> (Point2<f64>, f64) {
+        let mut points = self
+            .expanded_primitives()
+            .iter()
+            .flat_map(|primitive| primitive.bounding_box().vertices())
+            .collect::<Vec<_>>();
+
+        // adjacent primitives share corners
+        points.sort_by(|a, b| {
+            a.x.total_cmp(&b.x)
+                .then(a.y.total_cmp(&b.y))
+        });
+        points.dedup();
+
+        // Welzl's algorithm is only linear, on average, for points in a random order, sorted points on a round outline
+        // are its worst case.  A fixed seed keeps the result the same for the same layer.
+        points.shuffle(&mut SmallRng::seed_from_u64(0));
+
+        match minidisk(&points) {
+            Some(circle) => (circle.center, circle.radius),
+            None => (Point2::origin(), 0.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Circle {
+    center: Point2<f64>,
+    radius: f64,
+}
+
+impl Circle {
+    fn from_point(point: Point2<f64>) -> Self {
+        Self {
+            center: point,
+            radius: 0.0,
+        }
+    }
+
+    fn from_diameter(a: Point2<f64>, b: Point2<f64>) -> Self {
+        let center = Point2::new((a.x + b.x) / 2.0, (a.y + b.y) / 2.0);
+        Self {
+            center,
+            radius: distance(center, a),
+        }
+    }
+
+    /// The circumcircle, or `None` if the points are collinear.
+    fn from_triangle(a: Point2<f64>, b: Point2<f64>, c: Point2<f64>) -> Option<Self> {
+        let ab = b - a;
+        let ac = c - a;
+        let ab_squared = ab.dot(&ab);
+        let ac_squared = ac.dot(&ac);
+
+        let d = 2.0 * (ab.x * ac.y - ab.y * ac.x);
+        if d.abs() <= 2.0 * COLLINEAR_EPSILON * (ab_squared * ac_squared).sqrt() {
+            return None;
+        }
+
+        let center = Point2::new(
+            a.x + (ac.y * ab_squared - ab.y * ac_squared) / d,
+            a.y + (ab.x * ac_squared - ac.x * ab_squared) / d,
+        );
+
+        Some(Self {
+            center,
+            radius: distance(center, a),
+        })
+    }
+
+    fn contains(&self, point: Point2<f64>) -> bool {
+        distance(self.center, point) <= self.radius + EPSILON
+    }
+}
+
+fn distance(a: Point2<f64>, b: Point2<f64>) -> f64 {
+    let delta = b - a;
+    delta.dot(&delta).sqrt()
+}
+
+/// Welzl's algorithm, in its iterative form, each loop adds a point that must be on the boundary of the circle.
+fn minidisk(points: &[Point2<f64>]) -> Option<Circle> {
+    let mut circle = Circle::from_point(*points.first()?);
+
+    for (i, &p) in points.iter().enumerate() {
+        if circle.contains(p) {
+            continue;
+        }
+
+        circle = Circle::from_point(p);
+        for (j, &q) in points[..i].iter().enumerate() {
+            if circle.contains(q) {
+                continue;
+            }
+
+            circle = Circle::from_diameter(p, q);
+            for &r in &points[..j] {
+                if circle.contains(r) {
+                    continue;
+                }
+
+                // collinear points are enclosed by the circle through the two outermost ones
+                circle = Circle::from_triangle(p, q, r).unwrap_or_else(|| {
+                    [
+                        Circle::from_diameter(p, q),
+                        Circle::from_diameter(p, r),
+                        Circle::from_diameter(q, r),
+                    ]
+                    .into_iter()
+                    .max_by(|a, b| a.radius.total_cmp(&b.radius))
+                    .unwrap()
+                });
+            }
+        }
+    }
+
+    Some(circle)
+}
+
+#[cfg(test)]
+mod bounding_circle_tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::LayerConfiguration;
+    use crate::testing::commands;
+
+    fn layer(flashes: &[(f64, f64)]) -> GerberLayer {
+        commands::flashes_layer(2.0, flashes)
+    }
+
+    #[rstest]
+    #[case::single(&[(5.0, 5.0)], (5.0, 5.0), 2.0_f64.sqrt())]
+    // the corners of the two bounding boxes that are furthest apart are on the diameter
+    #[case::pair(&[(0.0, 0.0), (10.0, 0.0)], (5.0, 0.0), 37.0_f64.sqrt())]
+    // the centers form a right triangle, the circle goes through the outer corners of the two ends of the hypotenuse
+    #[case::triangle(&[(0.0, 0.0), (10.0, 0.0), (0.0, 10.0), (1.0, 1.0)], (5.0, 5.0), 72.0_f64.sqrt())]
+    #[case::collinear(&[(0.0, 0.0), (5.0, 0.0), (10.0, 0.0)], (5.0, 0.0), 37.0_f64.sqrt())]
+    fn test_bounding_circle(
+        #[case] flashes: &[(f64, f64)],
+        #[case] expected_center: (f64, f64),
+        #[case] expected_radius: f64,
+    ) {
+        // given
+        let layer = layer(flashes);
+
+        // when
+        let (center, radius) = layer.bounding_circle();
+
+        // then
+        assert!((center.x - expected_center.0).abs() < 1e-6, "center: {}", center);
+        assert!((center.y - expected_center.1).abs() < 1e-6, "center: {}", center);
+        assert!((radius - expected_radius).abs() < 1e-6, "radius: {}", radius);
+
+        // and all the corners are enclosed
+        for primitive in layer.primitives() {
+            for vertex in primitive.bounding_box().vertices() {
+                assert!(distance(center, vertex) <= radius + 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn test_many_points_on_a_circle() {
+        // given
+        // a ring of pads, with the corners of their bounding boxes, 20000 points on a round outline
+        let flashes = (0..5000)
+            .map(|index| {
+                let angle = index as f64 * std::f64::consts::TAU / 5000.0;
+                (100.0 * angle.cos(), 100.0 * angle.sin())
+            })
+            .collect::<Vec<_>>();
+        let layer = layer(&flashes);
+
+        // when
+        let (center, radius) = layer.bounding_circle();
+
+        // then
+        // the ring is symmetric, so the circle is centered on it and goes through the outermost corners
+        let expected_radius = layer
+            .primitives()
+            .iter()
+            .flat_map(|primitive| primitive.bounding_box().vertices())
+            .map(|vertex| distance(Point2::origin(), vertex))
+            .fold(0.0, f64::max);
+        assert!(distance(center, Point2::origin()) < 1e-6, "center: {}", center);
+        assert!((radius - expected_radius).abs() < 1e-6, "radius: {}", radius);
+    }
+
+    #[rstest]
+    #[case::small(1e-9)]
+    #[case::large(1e5)]
+    fn test_collinear_points_at_any_scale(#[case] scale: f64) {
+        // given
+        let a = Point2::new(0.0, 0.0);
+        // on the same line, the cross product isn't exactly 0 due to rounding
+        let b = Point2::new(1.1 * scale, 1.3 * scale);
+        let c = Point2::new(3.3 * scale, 3.9 * scale);
+
+        // expect
+        assert!(Circle::from_triangle(a, b, c).is_none());
+
+        // and a right triangle of the same size is not collinear
+        let circle = Circle::from_triangle(a, Point2::new(scale, scale), Point2::new(2.0 * scale, 0.0)).unwrap();
+        assert!((circle.radius - scale).abs() <= scale * 1e-9);
+    }
+
+    #[test]
+    fn test_step_repeats_are_enclosed() {
+        // given
+        // a 2 x 2 step-and-repeat of a 2mm pad, 10mm apart
+        let layer = GerberLayer::new_with_configuration(
+            commands::step_repeat(2, 2, 10.0, commands::flashes(2.0, &[(0.0, 0.0)])),
+            &LayerConfiguration {
+                instanced_step_repeat: true,
+                ..LayerConfiguration::default()
+            },
+        );
+
+        // when
+        let (center, radius) = layer.bounding_circle();
+
+        // then
+        // the circle goes through the outer corners of the repeats
+        assert!(distance(center, Point2::new(5.0, 5.0)) < 1e-6, "center: {}", center);
+        assert!((radius - 72.0_f64.sqrt()).abs() < 1e-6, "radius: {}", radius);
+    }
+
+    #[test]
+    fn test_empty_layer() {
+        // given
+        let layer = GerberLayer::new(vec![]);
+
+        // expect
+        assert_eq!(layer.bounding_circle(), (Point2::origin(), 0.0));
+    }
+}
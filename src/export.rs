@@ -0,0 +1,478 @@
+use std::collections::HashMap;
+use std::f64::consts::{FRAC_PI_2, TAU};
+use std::io::Write;
+
+use gerber_types::{
+    Aperture, ApertureDefinition, Circle, Command, CoordinateFormat, CoordinateMode, CoordinateNumber,
+    CoordinateOffset, Coordinates, DCode, ExtendedCode, GCode, GerberCode, GerberError, ImagePolarity,
+    InterpolationMode, MCode, Operation, Polarity, QuadrantMode, Rectangular, Unit, ZeroOmission,
+};
+use nalgebra::{Point2, Vector2};
+
+use crate::layer::{ArcGerberPrimitive, GerberLayer, GerberPrimitive};
+use crate::types::Exposure;
+
+/// The first aperture code used by [`GerberLayer::to_commands`], codes below 10 are reserved by the gerber spec.
+const FIRST_APERTURE_CODE: i32 = 10;
+
+/// Integer and decimal digits used by [`GerberLayer::to_commands`], 6 decimals is the precision of `gerber_types`.
+const EXPORT_FORMAT_DIGITS: (u8, u8) = (6, 6);
+
+/// Apertures are shared by primitives with the same size, compared in nanometers/nano-inches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ApertureKey {
+    Circle { diameter: i64, hole_diameter: i64 },
+    Rectangle { width: i64, height: i64 },
+}
+
+impl ApertureKey {
+    fn to_aperture(self) -> Aperture {
+        match self {
+            ApertureKey::Circle {
+                diameter,
+                hole_diameter: 0,
+            } => Aperture::Circle(Circle::new(from_nano(diameter))),
+            ApertureKey::Circle {
+                diameter,
+                hole_diameter,
+            } => Aperture::Circle(Circle::with_hole(from_nano(diameter), from_nano(hole_diameter))),
+            ApertureKey::Rectangle {
+                width,
+                height,
+            } => Aperture::Rectangle(Rectangular::new(from_nano(width), from_nano(height))),
+        }
+    }
+}
+
+impl GerberLayer {
+    /// Generates gerber commands that reproduce the primitives of the layer.
+    ///
+    /// Useful for "transform and re-export", e.g. `layer.baked(&transform).to_commands()`.
+    ///
+    /// The output is lossy, it reproduces the geometry, not the original file:
+    /// * Circles and rectangles become flashes of standard apertures, lines and arcs become draws with a circular
+    ///   aperture.  The circles that were added at the ends of arcs are skipped, the arc draw adds them again.
+    /// * Polygons become regions, this includes flashes of polygon, obround and macro apertures, so the aperture
+    ///   definitions, macros, aperture blocks and step-repeats of the original file are not kept.  Rounded rectangles
+    ///   become regions with arcs for the corners.
+    /// * Each repeat of the [`GerberLayer::step_repeats`] is written with its own primitives, at its offset.
+    /// * Attributes, comments and the image transform (`%MI`, `%SF`, etc.) are not written, use
+    ///   [`GerberLayer::baked`] first to apply the image transform to the primitives.
+    /// * Coordinates are written with 6 decimal places, in the units of the layer.
+    /// * A negative image polarity (`%IPNEG*%`) is kept, the exposures are written relative to it.
+    pub fn to_commands(&self) -> Vec<Command> {
+        let format = CoordinateFormat::new(
+            ZeroOmission::Leading,
+            CoordinateMode::Absolute,
+            EXPORT_FORMAT_DIGITS.0,
+            EXPORT_FORMAT_DIGITS.1,
+        );
+        let coordinates = |point: Point2<f64>| Coordinates::new(to_coordinate(point.x), to_coordinate(point.y), format);
+
+        let mut apertures: HashMap<ApertureKey, i32> = HashMap::new();
+        let mut definitions: Vec<Command> = Vec::new();
+        let mut body: Vec<Command> = Vec::new();
+
+        let mut current_aperture = None;
+        let mut current_polarity = Polarity::Dark;
+        let mut current_interpolation_mode = None;
+
+        let mut select_aperture = |body: &mut Vec<Command>, key: ApertureKey| {
+            let code = *apertures.entry(key).or_insert_with(|| {
+                let code = FIRST_APERTURE_CODE + definitions.len() as i32;
+                definitions
+                    .push(ExtendedCode::ApertureDefinition(ApertureDefinition::new(code, key.to_aperture())).into());
+                code
+            });
+
+            if current_aperture != Some(code) {
+                body.push(DCode::SelectAperture(code).into());
+                current_aperture = Some(code);
+            }
+        };
+        let mut set_interpolation_mode = |body: &mut Vec<Command>, mode: InterpolationMode| {
+            if current_interpolation_mode != Some(mode) {
+                body.push(GCode::InterpolationMode(mode).into());
+                current_interpolation_mode = Some(mode);
+            }
+        };
+
+        let primitives = self.expanded_primitives();
+        let arc_caps = arc_caps(&primitives);
+
+        for (index, primitive) in primitives.iter().enumerate() {
+            if arc_caps[index] {
+                // drawing the arc creates them again
+                continue;
+            }
+
+            // inverted again by the `%IP` command
+            let exposure = match self.image_polarity() {
+                ImagePolarity::Positive => primitive.exposure(),
+                ImagePolarity::Negative => primitive.exposure().inverted(),
+            };
+            let polarity = match exposure {
+                Exposure::Add => Polarity::Dark,
+                Exposure::CutOut => Polarity::Clear,
+            };
+            if polarity != current_polarity {
+                body.push(ExtendedCode::LoadPolarity(polarity).into());
+                current_polarity = polarity;
+            }
+
+            match primitive {
+                GerberPrimitive::Circle(circle) => {
+                    select_aperture(&mut body, ApertureKey::Circle {
+                        diameter: to_nano(circle.diameter),
+                        hole_diameter: to_nano(circle.hole_diameter.unwrap_or(0.0)),
+                    });
+                    body.push(DCode::Operation(Operation::Flash(Some(coordinates(circle.center)))).into());
+                }
+                GerberPrimitive::Rectangle(rectangle) => {
+                    select_aperture(&mut body, ApertureKey::Rectangle {
+                        width: to_nano(rectangle.width),
+                        height: to_nano(rectangle.height),
+                    });
+                    let center = rectangle.origin + Vector2::new(rectangle.width, rectangle.height) / 2.0;
+                    body.push(DCode::Operation(Operation::Flash(Some(coordinates(center)))).into());
+                }
+                GerberPrimitive::RoundedRectangle(rectangle) if rectangle.corner_radius <= 0.0 => {
+                    select_aperture(&mut body, ApertureKey::Rectangle {
+                        width: to_nano(rectangle.width),
+                        height: to_nano(rectangle.height),
+                    });
+                    let center = rectangle.origin + Vector2::new(rectangle.width, rectangle.height) / 2.0;
+                    body.push(DCode::Operation(Operation::Flash(Some(coordinates(center)))).into());
+                }
+                GerberPrimitive::RoundedRectangle(rectangle) => {
+                    let radius = rectangle.corner_radius;
+                    let min = rectangle.origin + Vector2::new(radius, radius);
+                    let max = rectangle.origin + Vector2::new(rectangle.width - radius, rectangle.height - radius);
+                    // counter-clockwise, starting with the bottom right corner
+                    let corners = [
+                        Point2::new(max.x, min.y),
+                        Point2::new(max.x, max.y),
+                        Point2::new(min.x, max.y),
+                        Point2::new(min.x, min.y),
+                    ];
+                    let on_corner =
+                        |center: Point2<f64>, angle: f64| center + Vector2::new(angle.cos(), angle.sin()) * radius;
+                    let corner_start = |index: usize| on_corner(corners[index], (index as f64 - 1.0) * FRAC_PI_2);
+
+                    body.push(GCode::RegionMode(true).into());
+                    body.push(DCode::Operation(Operation::Move(Some(coordinates(corner_start(0))))).into());
+                    for (index, center) in corners.iter().enumerate() {
+                        let start = corner_start(index);
+                        let end = on_corner(*center, index as f64 * FRAC_PI_2);
+                        let offset = center - start;
+
+                        set_interpolation_mode(&mut body, InterpolationMode::CounterclockwiseCircular);
+                        body.push(
+                            DCode::Operation(Operation::Interpolate(
+                                Some(coordinates(end)),
+                                Some(CoordinateOffset::new(
+                                    to_coordinate(offset.x),
+                                    to_coordinate(offset.y),
+                                    format,
+                                )),
+                            ))
+                            .into(),
+                        );
+                        set_interpolation_mode(&mut body, InterpolationMode::Linear);
+                        body.push(
+                            DCode::Operation(Operation::Interpolate(
+                                Some(coordinates(corner_start((index + 1) % corners.len()))),
+                                None,
+                            ))
+                            .into(),
+                        );
+                    }
+                    body.push(GCode::RegionMode(false).into());
+                }
+                GerberPrimitive::Line(line) => {
+                    select_aperture(&mut body, ApertureKey::Circle {
+                        diameter: to_nano(line.width),
+                        hole_diameter: 0,
+                    });
+                    set_interpolation_mode(&mut body, InterpolationMode::Linear);
+                    body.push(DCode::Operation(Operation::Move(Some(coordinates(line.start)))).into());
+                    body.push(DCode::Operation(Operation::Interpolate(Some(coordinates(line.end)), None)).into());
+                }
+                GerberPrimitive::Arc(arc) => {
+                    select_aperture(&mut body, ApertureKey::Circle {
+                        diameter: to_nano(arc.width),
+                        hole_diameter: 0,
+                    });
+                    let mode = match arc.sweep_angle >= 0.0 {
+                        true => InterpolationMode::CounterclockwiseCircular,
+                        false => InterpolationMode::ClockwiseCircular,
+                    };
+                    set_interpolation_mode(&mut body, mode);
+
+                    let on_circle = |angle: f64| arc.center + Vector2::new(angle.cos(), angle.sin()) * arc.radius;
+                    let start = on_circle(arc.start_angle);
+                    // with multi-quadrant mode, an arc that ends where it starts is a full circle.
+                    let end = match arc.is_full_circle() || arc.sweep_angle.abs() >= TAU {
+                        true => start,
+                        false => on_circle(arc.start_angle + arc.sweep_angle),
+                    };
+                    let offset = arc.center - start;
+
+                    body.push(DCode::Operation(Operation::Move(Some(coordinates(start)))).into());
+                    body.push(
+                        DCode::Operation(Operation::Interpolate(
+                            Some(coordinates(end)),
+                            Some(CoordinateOffset::new(
+                                to_coordinate(offset.x),
+                                to_coordinate(offset.y),
+                                format,
+                            )),
+                        ))
+                        .into(),
+                    );
+                }
+                GerberPrimitive::Polygon(polygon) => {
+                    let mut vertices = polygon
+                        .geometry
+                        .relative_vertices
+                        .iter()
+                        .map(|vertex| polygon.center + vertex.coords);
+                    let Some(first) = vertices.next() else {
+                        continue;
+                    };
+
+                    set_interpolation_mode(&mut body, InterpolationMode::Linear);
+                    body.push(GCode::RegionMode(true).into());
+                    body.push(DCode::Operation(Operation::Move(Some(coordinates(first)))).into());
+                    for vertex in vertices.chain(std::iter::once(first)) {
+                        body.push(DCode::Operation(Operation::Interpolate(Some(coordinates(vertex)), None)).into());
+                    }
+                    body.push(GCode::RegionMode(false).into());
+                }
+            }
+        }
+
+        let mut commands: Vec<Command> = vec![
+            ExtendedCode::CoordinateFormat(format).into(),
+            ExtendedCode::Unit(
+                self.units()
+                    .unwrap_or(Unit::Millimeters),
+            )
+            .into(),
+            GCode::QuadrantMode(QuadrantMode::Multi).into(),
+        ];
+        if self.image_polarity() == ImagePolarity::Negative {
+            commands.push(ExtendedCode::ImagePolarity(ImagePolarity::Negative).into());
+        }
+        commands.extend(definitions);
+        commands.extend(body);
+        commands.push(MCode::EndOfFile.into());
+
+        commands
+    }
+
+    /// Writes the commands from [`GerberLayer::to_commands`] as gerber text.
+    ///
+    /// Fails if a coordinate doesn't fit in the coordinate format, or on an I/O error.
+    pub fn write_gerber<W: Write>(&self, writer: &mut W) -> Result<(), GerberError> {
+        self.to_commands().serialize(writer)
+    }
+}
+
+/// Flags the circles that were added at the ends of arcs when the layer was built, see `GerberLayer::build_primitives`.
+fn arc_caps(primitives: &[GerberPrimitive]) -> Vec<bool> {
+    const EPSILON: f64 = 1e-9;
+
+    let is_cap = |candidate: Option<&GerberPrimitive>, arc: &ArcGerberPrimitive, angle: f64| {
+        let Some(GerberPrimitive::Circle(circle)) = candidate else {
+            return false;
+        };
+        let point = arc.center + Vector2::new(angle.cos(), angle.sin()) * arc.radius;
+
+        circle.hole_diameter.is_none()
+            && circle.exposure == arc.exposure
+            && (circle.diameter - arc.width).abs() < EPSILON
+            && (circle.center.x - point.x).abs() < EPSILON
+            && (circle.center.y - point.y).abs() < EPSILON
+    };
+
+    let mut caps = vec![false; primitives.len()];
+    for (index, primitive) in primitives.iter().enumerate() {
+        let GerberPrimitive::Arc(arc) = primitive else {
+            continue;
+        };
+        if arc.is_full_circle() || index == 0 {
+            continue;
+        }
+
+        let previous = primitives.get(index - 1);
+        let next = primitives.get(index + 1);
+        if is_cap(previous, arc, arc.start_angle) && is_cap(next, arc, arc.end_angle()) {
+            caps[index - 1] = true;
+            caps[index + 1] = true;
+        }
+    }
+
+    caps
+}
+
+fn to_nano(value: f64) -> i64 {
+    (value * 1_000_000.0).round() as i64
+}
+
+fn from_nano(value: i64) -> f64 {
+    value as f64 / 1_000_000.0
+}
+
+/// Rounds to the nearest representable value, `CoordinateNumber::try_from` truncates, so e.g. 25.4 would become
+/// 25.399999.
+fn to_coordinate(value: f64) -> CoordinateNumber {
+    CoordinateNumber::new(to_nano(value))
+}
+
+#[cfg(test)]
+mod to_commands_tests {
+    use gerber_types::{Aperture, ApertureDefinition, Circle, Command, Rectangular};
+
+    use super::*;
+    use crate::LayerConfiguration;
+    use crate::testing::commands::{at, flash, flashes, format, step_repeat};
+
+    /// One of each kind of primitive, and a clear one.
+    fn layer() -> GerberLayer {
+        GerberLayer::new(vec![
+            ExtendedCode::Unit(Unit::Millimeters).into(),
+            ExtendedCode::CoordinateFormat(format()).into(),
+            GCode::QuadrantMode(QuadrantMode::Multi).into(),
+            ExtendedCode::ApertureDefinition(ApertureDefinition::new(10, Aperture::Circle(Circle::new(1.0)))).into(),
+            ExtendedCode::ApertureDefinition(ApertureDefinition::new(
+                11,
+                Aperture::Rectangle(Rectangular::new(2.0, 1.0)),
+            ))
+            .into(),
+            ExtendedCode::ApertureDefinition(ApertureDefinition::new(
+                12,
+                Aperture::Circle(Circle::with_hole(3.0, 1.0)),
+            ))
+            .into(),
+            DCode::SelectAperture(10).into(),
+            DCode::Operation(Operation::Flash(at(0.0, 0.0))).into(),
+            GCode::InterpolationMode(InterpolationMode::Linear).into(),
+            DCode::Operation(Operation::Move(at(0.0, 5.0))).into(),
+            DCode::Operation(Operation::Interpolate(at(10.0, 5.0), None)).into(),
+            GCode::InterpolationMode(InterpolationMode::ClockwiseCircular).into(),
+            DCode::Operation(Operation::Move(at(20.0, 0.0))).into(),
+            DCode::Operation(Operation::Interpolate(
+                at(25.0, 5.0),
+                Some(CoordinateOffset::new(
+                    CoordinateNumber::try_from(5.0).unwrap(),
+                    CoordinateNumber::try_from(0.0).unwrap(),
+                    format(),
+                )),
+            ))
+            .into(),
+            DCode::SelectAperture(11).into(),
+            DCode::Operation(Operation::Flash(at(30.0, 0.0))).into(),
+            DCode::SelectAperture(12).into(),
+            DCode::Operation(Operation::Flash(at(40.0, 0.0))).into(),
+            ExtendedCode::LoadPolarity(Polarity::Clear).into(),
+            GCode::InterpolationMode(InterpolationMode::Linear).into(),
+            GCode::RegionMode(true).into(),
+            DCode::Operation(Operation::Move(at(0.0, 10.0))).into(),
+            DCode::Operation(Operation::Interpolate(at(5.0, 10.0), None)).into(),
+            DCode::Operation(Operation::Interpolate(at(5.0, 15.0), None)).into(),
+            DCode::Operation(Operation::Interpolate(at(0.0, 10.0), None)).into(),
+            GCode::RegionMode(false).into(),
+        ])
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // given
+        let layer = layer();
+
+        // when
+        let round_tripped = GerberLayer::new(layer.to_commands());
+
+        // then
+        assert_eq!(round_tripped.primitives().len(), layer.primitives().len());
+        assert!(layer.diff(&round_tripped).is_empty());
+    }
+
+    #[test]
+    fn test_step_repeats_are_expanded() {
+        // given
+        let mut commands = flashes(1.0, &[]);
+        commands.extend(step_repeat(3, 2, 10.0, vec![flash(0.0, 0.0), flash(2.0, 0.0)]));
+        let layer = GerberLayer::new_with_configuration(commands.clone(), &LayerConfiguration {
+            instanced_step_repeat: true,
+            ..LayerConfiguration::default()
+        });
+
+        // when
+        let round_tripped = GerberLayer::new(layer.to_commands());
+
+        // then
+        // every repeat is written, same as for a layer that was built without instancing
+        assert_eq!(round_tripped.primitives().len(), 12);
+        assert!(
+            GerberLayer::new(commands)
+                .diff(&round_tripped)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_apertures_are_shared() {
+        // given
+        let layer = layer();
+
+        // when
+        let commands = layer.to_commands();
+
+        // then
+        // the flash, line and arc share the 1mm circle aperture
+        let definitions = commands
+            .iter()
+            .filter(|command| matches!(command, Command::ExtendedCode(ExtendedCode::ApertureDefinition(_))))
+            .count();
+        assert_eq!(definitions, 3);
+    }
+
+    #[cfg(feature = "parser")]
+    #[test]
+    fn test_write_and_parse() {
+        // given
+        let layer = layer();
+        let mut buffer: Vec<u8> = Vec::new();
+
+        // when
+        layer.write_gerber(&mut buffer).unwrap();
+        let document = gerber_parser::parse(std::io::BufReader::new(buffer.as_slice())).unwrap();
+        let parsed = GerberLayer::new(document.into_commands());
+
+        // then
+        assert!(layer.diff(&parsed).is_empty());
+    }
+
+    #[cfg(feature = "parser")]
+    #[test]
+    fn test_rounded_rectangle_region() {
+        // given
+        let layer = GerberLayer::from_bytes(include_bytes!("../demo/assets/macro-rounded-rectangle.gbr")).unwrap();
+        let rectangle = &layer.primitives()[0];
+        assert!(matches!(rectangle, GerberPrimitive::RoundedRectangle(_)));
+
+        // when
+        let round_tripped = GerberLayer::new(layer.to_commands());
+
+        // then
+        // a region, the arcs of the corners are approximated
+        let region = &round_tripped.primitives()[0];
+        assert!(matches!(region, GerberPrimitive::Polygon(_)));
+        let (expected, actual) = (rectangle.bounding_box(), region.bounding_box());
+        assert!((expected.min - actual.min).abs().max() < 1e-6);
+        assert!((expected.max - actual.max).abs().max() < 1e-6);
+        assert!((rectangle.area() - region.area()).abs() / rectangle.area() < 1e-3);
+    }
+}
@@ -89,6 +89,10 @@ pub enum ExpressionEvaluationError {
 }
 
 /// Evaluates a Gerber macro expression using a recursive descent parser.
+///
+/// Gerber spec 2024.05 - 4.5.4.2 - the operators are `+`, `-`, `x` (multiplication) and `/`, with the usual precedence,
+/// parentheses, the unary `+` and `-`, decimals and variables (`$n`).  An uppercase `X` is also accepted for
+/// multiplication, as used by some older files.
 pub fn evaluate_expression(expr: &String, ctx: &MacroContext) -> Result<f64, ExpressionEvaluationError> {
     let mut parser = Parser::new(expr, ctx);
     let result = parser.parse_expression()?;
@@ -168,7 +172,7 @@ impl<'a> Parser<'a> {
                     value /= self.parse_factor()?;
                 }
                 // gerber spec uses 'x' for multiplication (why Camco, why...)
-                Some('x') => {
+                Some('x' | 'X') => {
                     self.bump();
                     value *= self.parse_factor()?;
                 }
@@ -191,7 +195,16 @@ impl<'a> Parser<'a> {
                 Ok(value)
             }
             Some('$') => self.parse_variable(),
-            Some(c) if c.is_ascii_digit() || c == '.' || c == '-' => self.parse_number(),
+            // unary operators, e.g. `-$1` or `-(...)`
+            Some('-') => {
+                self.bump();
+                Ok(-self.parse_factor()?)
+            }
+            Some('+') => {
+                self.bump();
+                self.parse_factor()
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => self.parse_number(),
             Some(c) => Err(ExpressionEvaluationError::UnexpectedChar(c)),
             None => Err(ExpressionEvaluationError::UnexpectedEnd),
         }
@@ -199,11 +212,6 @@ impl<'a> Parser<'a> {
 
     fn parse_number(&mut self) -> Result<f64, ExpressionEvaluationError> {
         let mut s = String::new();
-        if self.peek() == Some('-') {
-            s.push('-');
-            self.bump();
-        }
-
         while let Some(c) = self.peek() {
             if c.is_ascii_digit() || c == '.' {
                 s.push(c);
@@ -334,4 +342,52 @@ mod tests {
         let result = evaluate_expression(&expr, &ctx).unwrap();
         assert_eq!(result, 1.5);
     }
+
+    #[test]
+    fn test_unary_minus_variable_and_parentheses() {
+        let mut ctx = MacroContext::default();
+        ctx.put(1, 5.0).unwrap();
+        ctx.put(2, 2.0).unwrap();
+
+        let expr = "-$1/2+-($1-$2)".to_string(); // -2.5 + -3 = -5.5
+        let result = evaluate_expression(&expr, &ctx).unwrap();
+        assert_eq!(result, -5.5);
+    }
+
+    #[test]
+    fn test_unary_plus_and_double_minus() {
+        let mut ctx = MacroContext::default();
+        ctx.put(1, 5.0).unwrap();
+
+        let expr = "+$1--0.5".to_string(); // 5 - (-0.5) = 5.5
+        let result = evaluate_expression(&expr, &ctx).unwrap();
+        assert_eq!(result, 5.5);
+    }
+
+    #[test]
+    fn test_multiplication_using_uppercase_x() {
+        let mut ctx = MacroContext::default();
+        ctx.put(1, 5.0).unwrap();
+
+        let expr = "$1X2".to_string();
+        let result = evaluate_expression(&expr, &ctx).unwrap();
+        assert_eq!(result, 10.0);
+    }
+
+    #[test]
+    fn test_undefined_variable_is_zero() {
+        let ctx = MacroContext::default();
+
+        let expr = "$3+1".to_string();
+        let result = evaluate_expression(&expr, &ctx).unwrap();
+        assert_eq!(result, 1.0);
+    }
+
+    #[test]
+    fn test_unbalanced_parentheses() {
+        let ctx = MacroContext::default();
+
+        let expr = "(1+2".to_string();
+        assert!(evaluate_expression(&expr, &ctx).is_err());
+    }
 }
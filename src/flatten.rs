@@ -0,0 +1,197 @@
+use i_overlay::core::fill_rule::FillRule;
+use i_overlay::core::overlay_rule::OverlayRule;
+use i_overlay::float::single::SingleFloatOverlay;
+use nalgebra::Point2;
+
+use crate::geometry::{normalize_winding, signed_area};
+use crate::layer::{ArcGerberPrimitive, GerberLayer, PolygonGerberPrimitive};
+use crate::types::{Exposure, Winding};
+
+/// Contours in the format used by the polygon clipping, outer contours counter-clockwise and holes clockwise.
+type Contour = Vec<[f64; 2]>;
+
+impl GerberLayer {
+    /// Merges the primitives into filled regions, dark primitives are unioned and clear primitives are subtracted from
+    /// everything before them, e.g. for an accurate copper area or a clean SVG/DXF export without overlapping shapes.
+    ///
+    /// Each region is an outer contour that adds material, followed by its holes, that cut out material, so rendering
+    /// the polygons in order gives the same image as the layer.  Larger regions come first, so an island inside a hole
+    /// is drawn after the hole.  Arcs and round shapes are approximated with
+    /// [`ArcGerberPrimitive::DEFAULT_STEPS`] points, the image transform is not applied.  The repeats of the
+    /// [`GerberLayer::step_repeats`] are merged like the other primitives.
+    pub fn flatten(&self) -> Vec<PolygonGerberPrimitive> {
+        let mut regions: Vec<Vec<Contour>> = vec![];
+        let mut pending: Vec<Contour> = vec![];
+        let mut pending_exposure = Exposure::Add;
+
+        let apply = |regions: Vec<Vec<Contour>>, contours: &Vec<Contour>, exposure: Exposure| {
+            let rule = match exposure {
+                Exposure::Add => OverlayRule::Union,
+                Exposure::CutOut => OverlayRule::Difference,
+            };
+            regions.overlay(contours, rule, FillRule::NonZero)
+        };
+
+        for primitive in self.expanded_primitives().iter() {
+            let exposure = primitive.exposure();
+            if exposure != pending_exposure && !pending.is_empty() {
+                regions = apply(regions, &pending, pending_exposure);
+                pending.clear();
+            }
+            pending_exposure = exposure;
+
+            let mut outlines = primitive.outlines(ArcGerberPrimitive::DEFAULT_STEPS);
+            let Some((outer, holes)) = outlines.split_first_mut() else {
+                continue;
+            };
+            normalize_winding(outer, holes, Winding::CounterClockwise);
+
+            pending.extend(
+                outlines
+                    .iter()
+                    .filter(|outline| outline.len() >= 3)
+                    .map(|outline| {
+                        outline
+                            .iter()
+                            .map(|point| [point.x, point.y])
+                            .collect()
+                    }),
+            );
+        }
+        if !pending.is_empty() {
+            regions = apply(regions, &pending, pending_exposure);
+        }
+
+        let to_points = |contour: &Contour| -> Vec<Point2<f64>> {
+            contour
+                .iter()
+                .map(|[x, y]| Point2::new(*x, *y))
+                .collect()
+        };
+        let mut regions: Vec<(f64, Vec<Vec<Point2<f64>>>)> = regions
+            .iter()
+            .filter_map(|region| {
+                let contours: Vec<_> = region.iter().map(to_points).collect();
+                let area = signed_area(contours.first()?).abs();
+                Some((area, contours))
+            })
+            .collect();
+        regions.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+        regions
+            .into_iter()
+            .flat_map(|(_, contours)| {
+                contours
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, contour)| {
+                        let exposure = match index {
+                            0 => Exposure::Add,
+                            _ => Exposure::CutOut,
+                        };
+                        PolygonGerberPrimitive::from_vertices(contour, exposure)
+                    })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod flatten_tests {
+    use gerber_types::{ExtendedCode, Polarity};
+
+    use super::*;
+    use crate::layer::WithBoundingBox;
+    use crate::testing::commands::{circle, draw_to, flash, flashes, move_to, select};
+
+    fn area(polygons: &[PolygonGerberPrimitive]) -> f64 {
+        polygons
+            .iter()
+            .map(|polygon| {
+                let area = signed_area(&polygon.contours()[0]).abs();
+                match polygon.exposure {
+                    Exposure::Add => area,
+                    Exposure::CutOut => -area,
+                }
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_overlapping_dark_shapes_are_merged() {
+        // given
+        // two overlapping 2mm pads, and a separate one
+        let layer = GerberLayer::new(flashes(2.0, &[(0.0, 0.0), (1.0, 0.0), (10.0, 0.0)]));
+
+        // when
+        let polygons = layer.flatten();
+
+        // then
+        assert_eq!(polygons.len(), 2);
+        assert!(
+            polygons
+                .iter()
+                .all(|polygon| polygon.exposure == Exposure::Add)
+        );
+
+        // and the larger, merged, region comes first
+        let bbox = polygons[0].bounding_box();
+        assert!((bbox.min.x - -1.0).abs() < 1e-6);
+        assert!((bbox.max.x - 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_clear_shapes_are_subtracted() {
+        // given
+        // a 4mm pad with a 1mm clear pad in the middle, then a 0.5mm dark pad in the hole
+        let mut commands = flashes(4.0, &[(0.0, 0.0)]);
+        commands.extend([
+            ExtendedCode::LoadPolarity(Polarity::Clear).into(),
+            circle(11, 1.0),
+            select(11),
+            flash(0.0, 0.0),
+            ExtendedCode::LoadPolarity(Polarity::Dark).into(),
+            circle(12, 0.5),
+            select(12),
+            flash(0.0, 0.0),
+        ]);
+        let layer = GerberLayer::new(commands);
+
+        // when
+        let polygons = layer.flatten();
+
+        // then
+        // the pad, its hole, and the island in the hole, in that order
+        let exposures: Vec<Exposure> = polygons
+            .iter()
+            .map(|polygon| polygon.exposure)
+            .collect();
+        assert_eq!(exposures, vec![Exposure::Add, Exposure::CutOut, Exposure::Add]);
+
+        // and the area is the ring plus the island, allowing for the approximation of the circles
+        let expected = std::f64::consts::PI * (2.0 * 2.0 - 0.5 * 0.5 + 0.25 * 0.25);
+        assert!((area(&polygons) - expected).abs() / expected < 0.01);
+    }
+
+    #[test]
+    fn test_clear_shapes_only_affect_earlier_shapes() {
+        // given
+        // a clear pad flashed before the dark trace that crosses it
+        let mut commands = vec![ExtendedCode::LoadPolarity(Polarity::Clear).into()];
+        commands.extend(flashes(1.0, &[(0.0, 0.0)]));
+        commands.extend([
+            ExtendedCode::LoadPolarity(Polarity::Dark).into(),
+            move_to(-2.0, 0.0),
+            draw_to(2.0, 0.0),
+        ]);
+        let layer = GerberLayer::new(commands);
+
+        // when
+        let polygons = layer.flatten();
+
+        // then
+        // the trace is not cut
+        assert_eq!(polygons.len(), 1);
+        assert_eq!(polygons[0].exposure, Exposure::Add);
+    }
+}
@@ -1,22 +1,35 @@
 use nalgebra::Point2;
 
+/// A triangle mesh, e.g. the tessellation of a polygon, see
+/// [`PolygonGerberPrimitive::tessellation`](crate::PolygonGerberPrimitive::tessellation).
 #[derive(Debug, Clone)]
 pub struct PolygonMesh {
+    /// In the coordinate space of the tessellated vertices, e.g. gerber units with Y up.
     pub vertices: Vec<[f32; 2]>,
+    /// Three indices into `vertices` for each triangle.
     pub indices: Vec<u32>,
 }
 
 pub fn tessellate_polygon(vertices: &[Point2<f64>]) -> PolygonMesh {
+    tessellate_contours(std::slice::from_ref(&vertices))
+}
+
+/// Tessellates several closed contours as one shape, using the even-odd rule, so a contour inside another one is a
+/// hole.
+pub fn tessellate_contours<V: AsRef<[Point2<f64>]>>(contours: &[V]) -> PolygonMesh {
     use lyon::path::Path;
     use lyon::tessellation::{BuffersBuilder, FillOptions, FillRule, FillTessellator, VertexBuffers};
 
     let mut path_builder = Path::builder();
-    if let Some(first) = vertices.first() {
-        path_builder.begin(lyon::math::Point::new(first.x as f32, first.y as f32));
-        for pos in &vertices[1..] {
-            path_builder.line_to(lyon::math::Point::new(pos.x as f32, pos.y as f32));
+    for vertices in contours {
+        let vertices = vertices.as_ref();
+        if let Some(first) = vertices.first() {
+            path_builder.begin(lyon::math::Point::new(first.x as f32, first.y as f32));
+            for pos in &vertices[1..] {
+                path_builder.line_to(lyon::math::Point::new(pos.x as f32, pos.y as f32));
+            }
+            path_builder.close();
         }
-        path_builder.close();
     }
     let path = path_builder.build();
 
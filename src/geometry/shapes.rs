@@ -1,5 +1,7 @@
 use nalgebra::Point2;
 
+use crate::types::Winding;
+
 pub fn is_convex(vertices: &[Point2<f64>]) -> bool {
     if vertices.len() < 3 {
         return true;
@@ -28,3 +30,451 @@ pub fn is_convex(vertices: &[Point2<f64>]) -> bool {
 
     true
 }
+
+/// Returns the signed area of the closed polygon, using the shoelace formula, positive when the vertices are
+/// counter-clockwise in gerber coordinates (Y up), see [`Winding`].
+pub fn signed_area(vertices: &[Point2<f64>]) -> f64 {
+    let n = vertices.len();
+
+    (0..n)
+        .map(|i| {
+            let (a, b) = (vertices[i], vertices[(i + 1) % n]);
+            a.x * b.y - b.x * a.y
+        })
+        .sum::<f64>()
+        / 2.0
+}
+
+/// Reverses the contours of a polygon with holes, in place, as needed so that the outer contour has the winding
+/// expected by an export format and the holes the opposite winding, so that the holes stay holes with fill rules that
+/// depend on the winding.
+///
+/// E.g. [`Winding::CounterClockwise`] for GeoJSON (RFC 7946) and the non-zero fill rule, [`Winding::Clockwise`] for
+/// ESRI shapefiles.  Contours with fewer than 3 vertices or zero area are left as they are.
+pub fn normalize_winding(outer: &mut [Point2<f64>], holes: &mut [Vec<Point2<f64>>], outer_winding: Winding) {
+    let orient = |contour: &mut [Point2<f64>], winding: Winding| {
+        if signed_area(contour) != 0.0 && Winding::from_vertices(contour) != winding {
+            contour.reverse();
+        }
+    };
+
+    orient(outer, outer_winding);
+    for hole in holes {
+        orient(hole, outer_winding.reversed());
+    }
+}
+
+/// Finds a pair of edges of the closed polygon that cross each other.
+///
+/// Returns the indices of the two edges, where edge `i` is from `vertices[i]` to `vertices[(i + 1) % len]`.
+///
+/// Only proper crossings are reported, edges that touch or overlap (e.g. the coincident edges of a gerber region
+/// 'cut-in') are not considered intersecting.
+pub fn find_self_intersection(vertices: &[Point2<f64>]) -> Option<(usize, usize)> {
+    let n = vertices.len();
+    if n < 4 {
+        return None;
+    }
+
+    let edge = |i: usize| (vertices[i], vertices[(i + 1) % n]);
+
+    // small polygons are faster to check directly
+    const BRUTE_FORCE_LIMIT: usize = 64;
+    if n <= BRUTE_FORCE_LIMIT {
+        for i in 0..n {
+            for j in i + 1..n {
+                if segments_cross(edge(i), edge(j)) {
+                    return Some((i, j));
+                }
+            }
+        }
+        return None;
+    }
+
+    // bucket the edges into a grid, so only edges that are near each other are compared.
+    let (min, max) = vertices.iter().fold(
+        (
+            Point2::new(f64::INFINITY, f64::INFINITY),
+            Point2::new(f64::NEG_INFINITY, f64::NEG_INFINITY),
+        ),
+        |(min, max), v| (min.inf(v), max.sup(v)),
+    );
+    let cells_per_axis = (n as f64).sqrt().ceil() as usize;
+    let cell_size = ((max - min) / cells_per_axis as f64).map(|size| size.max(f64::EPSILON));
+    let cell = |p: Point2<f64>| {
+        (
+            (((p.x - min.x) / cell_size.x) as usize).min(cells_per_axis - 1),
+            (((p.y - min.y) / cell_size.y) as usize).min(cells_per_axis - 1),
+        )
+    };
+
+    let mut cells: Vec<Vec<usize>> = vec![Vec::new(); cells_per_axis * cells_per_axis];
+    for i in 0..n {
+        let (a, b) = edge(i);
+        let (x1, y1) = cell(a.inf(&b));
+        let (x2, y2) = cell(a.sup(&b));
+        for y in y1..=y2 {
+            for x in x1..=x2 {
+                cells[y * cells_per_axis + x].push(i);
+            }
+        }
+    }
+
+    for edges in cells.iter() {
+        for (index, &i) in edges.iter().enumerate() {
+            for &j in &edges[index + 1..] {
+                if segments_cross(edge(i), edge(j)) {
+                    return Some((i.min(j), i.max(j)));
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Splits a closed polygon into the contours joined by 'cut-ins', the pairs of coincident edges a gerber region uses to
+/// connect holes and islands to the outer contour in a single contour.
+///
+/// A contour ends where the polygon returns to a vertex it already visited, the cut-in edges between the contours are
+/// dropped.  The first contour is the one that contains the first vertex, usually the outer contour, the others are in
+/// the order they are closed.  A polygon without cut-ins is returned as a single contour, the last vertex of each
+/// contour is not repeated.
+pub fn split_cut_ins(vertices: &[Point2<f64>]) -> Vec<Vec<Point2<f64>>> {
+    const EPSILON: f64 = 1e-9;
+    let is_same = |a: &Point2<f64>, b: &Point2<f64>| (a.x - b.x).abs() <= EPSILON && (a.y - b.y).abs() <= EPSILON;
+
+    let mut contours = Vec::new();
+    let mut path: Vec<Point2<f64>> = Vec::new();
+    for vertex in vertices {
+        match path
+            .iter()
+            .position(|visited| is_same(visited, vertex))
+        {
+            Some(start) => {
+                let contour = path.split_off(start + 1);
+                // fewer than 2 vertices, plus the revisited one, is a cut-in edge going back, not a contour
+                if contour.len() >= 2 {
+                    let mut closed = vec![path[start]];
+                    closed.extend(contour);
+                    contours.push(closed);
+                }
+            }
+            None => path.push(*vertex),
+        }
+    }
+
+    // the remaining path is closed by the edge from the last vertex to the first
+    if path.len() >= 3 || contours.is_empty() {
+        contours.insert(0, path);
+    }
+
+    contours
+}
+
+/// Simplifies a polyline with the Douglas-Peucker algorithm, e.g. the points of flattened arcs, so outlines and
+/// exports have fewer vertices.
+///
+/// Removes the vertices that are within `tolerance` of the simplified polyline, the first and the last vertex are
+/// always kept.  A tolerance of 0 only removes collinear vertices.  For a closed polygon repeat the first vertex at the
+/// end, so the edge that closes it is simplified too.  `tolerance` is in the units of the points.
+pub fn simplify_polyline(points: &[Point2<f64>], tolerance: f64) -> Vec<Point2<f64>> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+
+    // ranges of points, between two kept vertices, that still need to be simplified
+    let mut ranges = vec![(0, points.len() - 1)];
+    while let Some((first, last)) = ranges.pop() {
+        let farthest = (first + 1..last)
+            .map(|index| (index, distance_to_segment(points[index], points[first], points[last])))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        if let Some((index, distance)) = farthest
+            && distance > tolerance
+        {
+            keep[index] = true;
+            ranges.push((first, index));
+            ranges.push((index, last));
+        }
+    }
+
+    points
+        .iter()
+        .zip(keep)
+        .filter_map(|(point, keep)| keep.then_some(*point))
+        .collect()
+}
+
+/// Distance from `point` to the segment from `a` to `b`, or to `a` if the segment has no length.
+fn distance_to_segment(point: Point2<f64>, a: Point2<f64>, b: Point2<f64>) -> f64 {
+    let ab = b - a;
+    let length_squared = ab.dot(&ab);
+    let t = match length_squared > 0.0 {
+        true => ((point - a).dot(&ab) / length_squared).clamp(0.0, 1.0),
+        false => 0.0,
+    };
+    let delta = point - (a + ab * t);
+
+    delta.dot(&delta).sqrt()
+}
+
+/// True if the segments cross at a single point that is strictly inside both of them.
+fn segments_cross((a1, a2): (Point2<f64>, Point2<f64>), (b1, b2): (Point2<f64>, Point2<f64>)) -> bool {
+    const EPSILON: f64 = 1e-12;
+
+    let orientation = |p: Point2<f64>, q: Point2<f64>, r: Point2<f64>| {
+        let value = (q.x - p.x) * (r.y - p.y) - (q.y - p.y) * (r.x - p.x);
+        if value > EPSILON {
+            1
+        } else if value < -EPSILON {
+            -1
+        } else {
+            0
+        }
+    };
+
+    let d1 = orientation(a1, a2, b1);
+    let d2 = orientation(a1, a2, b2);
+    let d3 = orientation(b1, b2, a1);
+    let d4 = orientation(b1, b2, a2);
+
+    d1 * d2 < 0 && d3 * d4 < 0
+}
+
+#[cfg(test)]
+mod self_intersection_tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case::square(vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)], None)]
+    #[case::bowtie(vec![(0.0, 0.0), (1.0, 1.0), (1.0, 0.0), (0.0, 1.0)], Some((0, 2)))]
+    #[case::cut_in(
+        // a square with a square hole, joined by coincident edges
+        vec![
+            (0.0, 0.0), (4.0, 0.0), (4.0, 4.0), (0.0, 4.0), (0.0, 2.0),
+            (1.0, 2.0), (1.0, 3.0), (3.0, 3.0), (3.0, 1.0), (1.0, 1.0), (1.0, 2.0),
+            (0.0, 2.0),
+        ],
+        None
+    )]
+    fn test_find_self_intersection(#[case] vertices: Vec<(f64, f64)>, #[case] expected: Option<(usize, usize)>) {
+        // given
+        let vertices: Vec<Point2<f64>> = vertices
+            .into_iter()
+            .map(|(x, y)| Point2::new(x, y))
+            .collect();
+
+        // expect
+        assert_eq!(find_self_intersection(&vertices), expected);
+    }
+
+    #[test]
+    fn test_find_self_intersection_large_polygon() {
+        // given
+        // a circle-like polygon with enough vertices to use the grid, with the last two vertices swapped
+        let mut vertices: Vec<Point2<f64>> = (0..200)
+            .map(|i| {
+                let angle = i as f64 / 200.0 * std::f64::consts::TAU;
+                Point2::new(angle.cos() * 10.0, angle.sin() * 10.0)
+            })
+            .collect();
+
+        // expect
+        assert_eq!(find_self_intersection(&vertices), None);
+
+        // when
+        vertices.swap(198, 199);
+
+        // then
+        assert!(find_self_intersection(&vertices).is_some());
+    }
+}
+
+#[cfg(test)]
+mod winding_tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn square(min: f64, max: f64) -> Vec<Point2<f64>> {
+        // counter-clockwise
+        vec![
+            Point2::new(min, min),
+            Point2::new(max, min),
+            Point2::new(max, max),
+            Point2::new(min, max),
+        ]
+    }
+
+    fn reversed(mut vertices: Vec<Point2<f64>>) -> Vec<Point2<f64>> {
+        vertices.reverse();
+        vertices
+    }
+
+    #[test]
+    fn test_signed_area() {
+        assert_eq!(signed_area(&square(0.0, 2.0)), 4.0);
+        assert_eq!(signed_area(&reversed(square(0.0, 2.0))), -4.0);
+        assert_eq!(signed_area(&square(0.0, 2.0)[..2]), 0.0);
+    }
+
+    #[test]
+    fn test_winding_uses_the_sign_of_the_area() {
+        assert_eq!(Winding::from_vertices(&square(0.0, 2.0)), Winding::CounterClockwise);
+        assert_eq!(Winding::from_vertices(&reversed(square(0.0, 2.0))), Winding::Clockwise);
+        assert_eq!(Winding::from_vertices(&square(0.0, 2.0)[..2]), Winding::Clockwise);
+    }
+
+    #[rstest]
+    #[case::already_normalized(square(0.0, 4.0), reversed(square(1.0, 3.0)))]
+    #[case::both_reversed(reversed(square(0.0, 4.0)), square(1.0, 3.0))]
+    #[case::same_direction(square(0.0, 4.0), square(1.0, 3.0))]
+    fn test_ring(#[case] mut outer: Vec<Point2<f64>>, #[case] hole: Vec<Point2<f64>>) {
+        // given
+        let mut holes = vec![hole];
+
+        // when
+        normalize_winding(&mut outer, &mut holes, Winding::CounterClockwise);
+
+        // then
+        assert!(signed_area(&outer) > 0.0);
+        assert!(signed_area(&holes[0]) < 0.0);
+
+        // when
+        normalize_winding(&mut outer, &mut holes, Winding::Clockwise);
+
+        // then
+        assert!(signed_area(&outer) < 0.0);
+        assert!(signed_area(&holes[0]) > 0.0);
+        assert_eq!(signed_area(&outer), -16.0);
+        assert_eq!(signed_area(&holes[0]), 4.0);
+    }
+}
+
+#[cfg(test)]
+mod split_cut_ins_tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn points(vertices: &[(f64, f64)]) -> Vec<Point2<f64>> {
+        vertices
+            .iter()
+            .map(|&(x, y)| Point2::new(x, y))
+            .collect()
+    }
+
+    #[rstest]
+    #[case::square(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)], vec![
+        vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+    ])]
+    #[case::hole(
+        // a square with a square hole, joined by coincident edges
+        &[
+            (0.0, 0.0), (4.0, 0.0), (4.0, 4.0), (0.0, 4.0), (0.0, 2.0),
+            (1.0, 2.0), (1.0, 3.0), (3.0, 3.0), (3.0, 1.0), (1.0, 1.0), (1.0, 2.0),
+            (0.0, 2.0),
+        ],
+        vec![
+            vec![(0.0, 0.0), (4.0, 0.0), (4.0, 4.0), (0.0, 4.0), (0.0, 2.0)],
+            vec![(1.0, 2.0), (1.0, 3.0), (3.0, 3.0), (3.0, 1.0), (1.0, 1.0)],
+        ]
+    )]
+    #[case::two_holes(
+        // the cut-in of the second hole starts at the first hole
+        &[
+            (0.0, 0.0), (9.0, 0.0), (9.0, 4.0), (0.0, 4.0), (0.0, 2.0),
+            (1.0, 2.0), (1.0, 3.0), (3.0, 3.0), (3.0, 2.0),
+            (5.0, 2.0), (5.0, 3.0), (7.0, 3.0), (7.0, 1.0), (5.0, 1.0), (5.0, 2.0),
+            (3.0, 2.0), (3.0, 1.0), (1.0, 1.0), (1.0, 2.0),
+            (0.0, 2.0),
+        ],
+        vec![
+            vec![(0.0, 0.0), (9.0, 0.0), (9.0, 4.0), (0.0, 4.0), (0.0, 2.0)],
+            vec![(5.0, 2.0), (5.0, 3.0), (7.0, 3.0), (7.0, 1.0), (5.0, 1.0)],
+            vec![(1.0, 2.0), (1.0, 3.0), (3.0, 3.0), (3.0, 2.0), (3.0, 1.0), (1.0, 1.0)],
+        ]
+    )]
+    fn test_split_cut_ins(#[case] vertices: &[(f64, f64)], #[case] expected: Vec<Vec<(f64, f64)>>) {
+        // given
+        let vertices = points(vertices);
+        let expected = expected
+            .iter()
+            .map(|contour| points(contour))
+            .collect::<Vec<_>>();
+
+        // expect
+        assert_eq!(split_cut_ins(&vertices), expected);
+    }
+}
+
+#[cfg(test)]
+mod simplify_polyline_tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn points(vertices: &[(f64, f64)]) -> Vec<Point2<f64>> {
+        vertices
+            .iter()
+            .map(|&(x, y)| Point2::new(x, y))
+            .collect()
+    }
+
+    #[rstest]
+    #[case::empty(&[], 1.0, &[])]
+    #[case::segment(&[(0.0, 0.0), (1.0, 1.0)], 1.0, &[(0.0, 0.0), (1.0, 1.0)])]
+    #[case::collinear(&[(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (3.0, 0.0)], 0.0, &[(0.0, 0.0), (3.0, 0.0)])]
+    #[case::within_tolerance(&[(0.0, 0.0), (1.0, 0.1), (2.0, -0.1), (3.0, 0.0)], 0.2, &[(0.0, 0.0), (3.0, 0.0)])]
+    #[case::corner_kept(&[(0.0, 0.0), (1.0, 0.05), (2.0, 0.0), (2.0, 2.0)], 0.1, &[(0.0, 0.0), (2.0, 0.0), (2.0, 2.0)])]
+    #[case::zigzag_above_tolerance(
+        &[(0.0, 0.0), (1.0, 1.0), (2.0, 0.0), (3.0, 1.0), (4.0, 0.0)],
+        0.5,
+        &[(0.0, 0.0), (1.0, 1.0), (2.0, 0.0), (3.0, 1.0), (4.0, 0.0)]
+    )]
+    // closed, the first vertex is repeated at the end
+    #[case::closed_square(
+        &[(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0), (0.0, 1.0), (0.0, 0.0)],
+        0.01,
+        &[(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0), (0.0, 0.0)]
+    )]
+    fn test_simplify_polyline(#[case] input: &[(f64, f64)], #[case] tolerance: f64, #[case] expected: &[(f64, f64)]) {
+        // expect
+        assert_eq!(simplify_polyline(&points(input), tolerance), points(expected));
+    }
+
+    #[test]
+    fn test_flattened_arc() {
+        // given
+        // a quarter circle of radius 10, flattened to 91 points
+        let arc = (0..=90)
+            .map(|degrees| {
+                let angle = (degrees as f64).to_radians();
+                Point2::new(10.0 * angle.cos(), 10.0 * angle.sin())
+            })
+            .collect::<Vec<_>>();
+
+        // when
+        let simplified = simplify_polyline(&arc, 0.01);
+
+        // then
+        assert!(simplified.len() < 30, "len: {}", simplified.len());
+        assert_eq!(simplified.first(), arc.first());
+        assert_eq!(simplified.last(), arc.last());
+        // and every removed point is within the tolerance of the simplified polyline
+        for point in &arc {
+            let distance = simplified
+                .windows(2)
+                .map(|segment| distance_to_segment(*point, segment[0], segment[1]))
+                .fold(f64::INFINITY, f64::min);
+            assert!(distance <= 0.01, "point: {}, distance: {}", point, distance);
+        }
+    }
+}
@@ -2,10 +2,12 @@ use std::f64::consts::PI;
 
 #[cfg(feature = "egui")]
 use egui::{Pos2, Vec2};
-use gerber_types::{AxisSelect, ImageMirroring};
+use gerber_types::{AxisSelect, ImageMirroring, Unit};
 use nalgebra::{Matrix3, Point2, Vector2, Vector3};
 
+use crate::geometry::BoundingBox;
 use crate::geometry::mirroring::Mirroring;
+use crate::types::MM_PER_INCH;
 
 /// Gerber-specific transform.
 /// Transform order: -Origin, Mirroring, Rotation, *Scale, +Origin, +Offset
@@ -16,6 +18,10 @@ use crate::geometry::mirroring::Mirroring;
 #[derive(Debug, Copy, Clone)]
 pub struct GerberTransform {
     /// rotation in radians, positive = counter-clockwise
+    ///
+    /// Counter-clockwise in gerber coordinates (positive Y up), e.g. +90° moves +X to +Y.  The renderer flips the Y
+    /// axis, so it's counter-clockwise on screen too.  Mirroring is applied before the rotation, so a mirrored layer
+    /// also rotates counter-clockwise.  See [`GerberTransform::with_rotation_degrees`] for clockwise-positive angles.
     pub rotation: f32,
     pub mirroring: Mirroring,
     // origin for rotation and mirroring, in gerber coordinates
@@ -38,7 +44,36 @@ impl Default for GerberTransform {
     }
 }
 
+/// The direction that a positive angle rotates, as seen on screen and in gerber coordinates, see
+/// [`GerberTransform::rotation`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RotationDirection {
+    /// The convention used by `GerberTransform::rotation` and the gerber spec.
+    #[default]
+    CounterClockwise,
+    /// E.g. pick-and-place machines and some CAD tools.
+    Clockwise,
+}
+
 impl GerberTransform {
+    /// Sets the rotation from an angle in degrees, where positive angles rotate in `direction`, e.g. for a UI where
+    /// positive angles are clockwise.
+    pub fn with_rotation_degrees(mut self, degrees: f32, direction: RotationDirection) -> Self {
+        self.rotation = match direction {
+            RotationDirection::CounterClockwise => degrees.to_radians(),
+            RotationDirection::Clockwise => -degrees.to_radians(),
+        };
+        self
+    }
+
+    /// Returns the rotation in degrees, where positive angles rotate in `direction`.
+    pub fn rotation_degrees(&self, direction: RotationDirection) -> f32 {
+        match direction {
+            RotationDirection::CounterClockwise => self.rotation.to_degrees(),
+            RotationDirection::Clockwise => -self.rotation.to_degrees(),
+        }
+    }
+
     /// Apply the transform to a logical `Point2` (Gerber-space)
     #[inline]
     pub fn apply_to_position(&self, pos: Point2<f64>) -> Point2<f64> {
@@ -92,6 +127,53 @@ impl GerberTransform {
 
         self
     }
+
+    /// Computes the transform that maps the `from` bounding box onto the `to` bounding box.
+    ///
+    /// Only scale and offset are used, rotation and mirroring are not considered.  Since the scale is uniform the
+    /// aspect ratio is always preserved; `from` is scaled to fit inside `to` and centered on it.
+    ///
+    /// Returns the default (identity) transform if either bounding box is empty, or `from` has no size.
+    pub fn align(from: &BoundingBox, to: &BoundingBox) -> GerberTransform {
+        if from.is_empty() || to.is_empty() || (from.width() == 0.0 && from.height() == 0.0) {
+            return GerberTransform::default();
+        }
+
+        let ratio = |to: f64, from: f64| match from > 0.0 {
+            true => to / from,
+            false => f64::INFINITY,
+        };
+        let scale = f64::min(ratio(to.width(), from.width()), ratio(to.height(), from.height()));
+
+        let from_center = from.center();
+        let to_center = to.center();
+
+        Self {
+            origin: from_center.coords,
+            offset: to_center - from_center,
+            scale,
+            ..GerberTransform::default()
+        }
+    }
+}
+
+impl GerberTransform {
+    /// Creates a transform that scales coordinates in `from` units to `to` units, e.g. to render an inch layer with
+    /// millimeter layers.
+    ///
+    /// Use with [`GerberLayer::units`](crate::GerberLayer::units) to normalize a stack of layers with mixed units.
+    pub fn with_unit_scale(from: Unit, to: Unit) -> GerberTransform {
+        let scale = match (from, to) {
+            (Unit::Inches, Unit::Millimeters) => MM_PER_INCH,
+            (Unit::Millimeters, Unit::Inches) => 1.0 / MM_PER_INCH,
+            (Unit::Inches, Unit::Inches) | (Unit::Millimeters, Unit::Millimeters) => 1.0,
+        };
+
+        Self {
+            scale,
+            ..GerberTransform::default()
+        }
+    }
 }
 
 impl GerberTransform {
@@ -283,6 +365,38 @@ impl Matrix3ScalingExt for Matrix3<f64> {
     }
 }
 
+/// Extension trait that adds an inverse for 2D affine transformation matrices
+pub trait Matrix3AffineInverseExt {
+    /// Returns the inverse of a 2D affine transform matrix, or `None` if it is not invertible (e.g. zero scale)
+    fn affine_inverse(&self) -> Option<Matrix3<f64>>;
+}
+
+impl Matrix3AffineInverseExt for Matrix3<f64> {
+    fn affine_inverse(&self) -> Option<Matrix3<f64>> {
+        let (a, b, c, d) = (self[(0, 0)], self[(0, 1)], self[(1, 0)], self[(1, 1)]);
+        let (tx, ty) = (self[(0, 2)], self[(1, 2)]);
+
+        let determinant = a * d - b * c;
+        if determinant == 0.0 || !determinant.is_finite() {
+            return None;
+        }
+
+        let (ia, ib, ic, id) = (d / determinant, -b / determinant, -c / determinant, a / determinant);
+
+        Some(Matrix3::new(
+            ia,
+            ib,
+            -(ia * tx + ib * ty),
+            ic,
+            id,
+            -(ic * tx + id * ty),
+            0.0,
+            0.0,
+            1.0,
+        ))
+    }
+}
+
 #[cfg(test)]
 mod transform_tests {
     // All tests AI generated by Clause 3.7 Sonnet
@@ -1166,6 +1280,51 @@ impl GerberImageTransform {
 
         mirroring_matrix * scaling_matrix * translate_offset * rotation_matrix * axis_assignment_matrix
     }
+
+    /// Returns true if the transform doesn't change the image, e.g. for a file without image transform commands.
+    pub fn is_identity(&self) -> bool {
+        matches!(self.mirroring, ImageMirroring::None)
+            && self.scale == Vector2::new(1.0, 1.0)
+            && self.offset == Vector2::new(0.0, 0.0)
+            && self.rotation == 0.0
+            && matches!(self.axis_select, AxisSelect::AXBY)
+    }
+}
+
+/// A short summary of the transform, in the order it is applied, e.g. `mirror X, rotate 90°`, or `none`, for
+/// diagnostics, e.g. to explain why a layer appears transformed before the render transform is applied.
+impl std::fmt::Display for GerberImageTransform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // rounded, so that e.g. a rotation of 90 degrees isn't shown as 90.00000000000001
+        let number = |value: f64| (value * 1e6).round() / 1e6;
+
+        let mut parts = Vec::new();
+        match self.mirroring {
+            ImageMirroring::None => {}
+            ImageMirroring::A => parts.push("mirror X".to_string()),
+            ImageMirroring::B => parts.push("mirror Y".to_string()),
+            ImageMirroring::AB => parts.push("mirror X and Y".to_string()),
+        }
+        if self.scale.x != self.scale.y {
+            parts.push(format!("scale {} x {}", number(self.scale.x), number(self.scale.y)));
+        } else if self.scale.x != 1.0 {
+            parts.push(format!("scale {}", number(self.scale.x)));
+        }
+        if self.offset != Vector2::new(0.0, 0.0) {
+            parts.push(format!("offset {}, {}", number(self.offset.x), number(self.offset.y)));
+        }
+        if self.rotation != 0.0 {
+            parts.push(format!("rotate {}°", number(self.rotation.to_degrees())));
+        }
+        if matches!(self.axis_select, AxisSelect::AYBX) {
+            parts.push("swap axes".to_string());
+        }
+
+        match parts.is_empty() {
+            true => write!(f, "none"),
+            false => write!(f, "{}", parts.join(", ")),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Copy)]
@@ -1179,3 +1338,212 @@ impl Default for AxisAssignment {
         AxisAssignment::AXBY
     }
 }
+
+#[cfg(test)]
+mod rotation_direction_tests {
+    use nalgebra::{Point2, Vector3};
+    use rstest::rstest;
+
+    use super::*;
+
+    fn assert_close(actual: Point2<f64>, expected: Point2<f64>) {
+        assert!(
+            (actual.x - expected.x).abs() < 1e-6 && (actual.y - expected.y).abs() < 1e-6,
+            "actual: {}, expected: {}",
+            actual,
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case::counter_clockwise(RotationDirection::CounterClockwise, 90.0, Point2::new(0.0, 1.0))]
+    #[case::clockwise(RotationDirection::Clockwise, 90.0, Point2::new(0.0, -1.0))]
+    #[case::counter_clockwise_negative(RotationDirection::CounterClockwise, -90.0, Point2::new(0.0, -1.0))]
+    fn test_rotation_direction(
+        #[case] direction: RotationDirection,
+        #[case] degrees: f32,
+        #[case] expected: Point2<f64>,
+    ) {
+        // given
+        let transform = GerberTransform::default().with_rotation_degrees(degrees, direction);
+
+        // when
+        let position = transform.apply_to_position(Point2::new(1.0, 0.0));
+        let transformed = transform.to_matrix() * Vector3::new(1.0, 0.0, 1.0);
+
+        // then
+        assert_close(position, expected);
+        assert_close(Point2::new(transformed.x, transformed.y), expected);
+        assert!((transform.rotation_degrees(direction) - degrees).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_rotation_after_mirroring() {
+        // given
+        let transform = GerberTransform {
+            mirroring: Mirroring {
+                x: true,
+                y: false,
+            },
+            ..GerberTransform::default()
+        }
+        .with_rotation_degrees(90.0, RotationDirection::CounterClockwise);
+
+        // when
+        let position = transform.apply_to_position(Point2::new(1.0, 0.0));
+        let transformed = transform.to_matrix() * Vector3::new(1.0, 0.0, 1.0);
+
+        // then +X is mirrored to -X, then rotated counter-clockwise to -Y
+        assert_close(position, Point2::new(0.0, -1.0));
+        assert_close(Point2::new(transformed.x, transformed.y), Point2::new(0.0, -1.0));
+    }
+}
+
+#[cfg(test)]
+mod image_transform_display_tests {
+    use std::f64::consts::FRAC_PI_2;
+
+    use super::*;
+
+    #[test]
+    fn test_identity() {
+        // given
+        let transform = GerberImageTransform::default();
+
+        // expect
+        assert!(transform.is_identity());
+        assert_eq!(transform.to_string(), "none");
+    }
+
+    #[test]
+    fn test_display() {
+        // given
+        let transform = GerberImageTransform {
+            mirroring: ImageMirroring::A,
+            scale: Vector2::new(2.0, 2.0),
+            offset: Vector2::new(1.5, 0.0),
+            rotation: FRAC_PI_2,
+            axis_select: AxisSelect::AXBY,
+        };
+
+        // expect
+        assert!(!transform.is_identity());
+        assert_eq!(transform.to_string(), "mirror X, scale 2, offset 1.5, 0, rotate 90°");
+    }
+}
+
+#[cfg(test)]
+mod align_tests {
+    use nalgebra::Point2;
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case::same(((0.0, 0.0), (10.0, 10.0)), ((0.0, 0.0), (10.0, 10.0)), 1.0)]
+    #[case::offset(((0.0, 0.0), (10.0, 10.0)), ((20.0, 30.0), (30.0, 40.0)), 1.0)]
+    #[case::inches_to_mm(((0.0, 0.0), (1.0, 2.0)), ((0.0, 0.0), (25.4, 50.8)), 25.4)]
+    #[case::fit_width(((0.0, 0.0), (20.0, 10.0)), ((-5.0, -5.0), (5.0, 5.0)), 0.5)]
+    #[case::fit_height(((0.0, 0.0), (10.0, 20.0)), ((-5.0, -5.0), (5.0, 5.0)), 0.5)]
+    fn test_align(
+        #[case] from: ((f64, f64), (f64, f64)),
+        #[case] to: ((f64, f64), (f64, f64)),
+        #[case] expected_scale: f64,
+    ) {
+        // given
+        let bbox = |((min_x, min_y), (max_x, max_y)): ((f64, f64), (f64, f64))| BoundingBox {
+            min: Point2::new(min_x, min_y),
+            max: Point2::new(max_x, max_y),
+        };
+        let (from, to) = (bbox(from), bbox(to));
+
+        // when
+        let transform = GerberTransform::align(&from, &to);
+
+        // then
+        assert!((transform.scale - expected_scale).abs() < 1e-9);
+
+        let aligned = from.apply_transform(&transform);
+        let (center, expected_center) = (aligned.center(), to.center());
+        assert!((center.x - expected_center.x).abs() < 1e-9);
+        assert!((center.y - expected_center.y).abs() < 1e-9);
+        assert!(aligned.width() <= to.width() + 1e-9);
+        assert!(aligned.height() <= to.height() + 1e-9);
+    }
+
+    #[test]
+    fn test_align_empty() {
+        // expect
+        let transform = GerberTransform::align(&BoundingBox::default(), &BoundingBox::default());
+        assert_eq!(transform.scale, 1.0);
+        assert_eq!(transform.offset, Vector2::new(0.0, 0.0));
+    }
+}
+
+#[cfg(test)]
+mod unit_scale_tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case::inches_to_mm(Unit::Inches, Unit::Millimeters, 25.4)]
+    #[case::mm_to_inches(Unit::Millimeters, Unit::Inches, 1.0 / 25.4)]
+    #[case::same_unit(Unit::Inches, Unit::Inches, 1.0)]
+    fn test_scale(#[case] from: Unit, #[case] to: Unit, #[case] expected: f64) {
+        // when
+        let transform = GerberTransform::with_unit_scale(from, to);
+
+        // then
+        assert_eq!(transform.scale, expected);
+        assert_eq!(transform.offset, Vector2::new(0.0, 0.0));
+    }
+}
+
+#[cfg(test)]
+mod affine_inverse_tests {
+    use nalgebra::{Point2, Vector2};
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case::identity(GerberTransform::default())]
+    #[case::rotated_mirrored_scaled(GerberTransform {
+        rotation: 0.5,
+        mirroring: Mirroring { x: true, y: false },
+        origin: Vector2::new(3.0, -2.0),
+        offset: Vector2::new(10.0, 20.0),
+        scale: 2.5,
+    })]
+    fn test_round_trip(#[case] transform: GerberTransform) {
+        // given
+        let matrix = transform.to_matrix();
+        let point = Point2::new(7.0, -4.0);
+
+        // when
+        let inverse = matrix.affine_inverse().unwrap();
+        let result = inverse.transform_point2(matrix.transform_point2(point));
+
+        // then
+        assert!((result.x - point.x).abs() < 1e-9);
+        assert!((result.y - point.y).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_zero_scale_is_not_invertible() {
+        // given
+        let transform = GerberTransform {
+            scale: 0.0,
+            ..GerberTransform::default()
+        };
+
+        // expect
+        assert!(
+            transform
+                .to_matrix()
+                .affine_inverse()
+                .is_none()
+        );
+    }
+}
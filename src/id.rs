@@ -0,0 +1,225 @@
+use std::hash::Hasher;
+
+use nalgebra::Point2;
+
+use crate::layer::{GerberLayer, GerberPrimitive};
+use crate::types::Exposure;
+
+/// Coordinates and sizes are rounded to this precision, in gerber units, before hashing so that the rounding errors
+/// from parsing and building primitives don't change the id.
+const ID_PRECISION: f64 = 1e-6;
+
+/// An identifier for a primitive that is derived from its content (kind, exposure, geometry and aperture), not its
+/// index.
+///
+/// Ids are the same after re-parsing a file, as long as the primitive itself is unchanged, so they can be used to keep
+/// a selection across edits.  The hash function is fixed, so ids can also be stored, e.g. in a project file.
+///
+/// Identical primitives, e.g. the same pad flashed twice at the same position, have the same id; see
+/// [`GerberLayer::index_of_near`] to pick one of them.  The same pad flashed with two apertures, e.g. a via and a
+/// test point with the same size, has two different ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PrimitiveId(pub u64);
+
+impl GerberPrimitive {
+    /// The id of the primitive, `aperture` is the code of the aperture it was created with, see
+    /// [`GerberLayer::primitive_apertures`], or use [`GerberLayer::id_of`].
+    pub fn id(&self, aperture: Option<i32>) -> PrimitiveId {
+        let mut hasher = Fnv1aHasher::default();
+
+        // little-endian, so the id doesn't depend on the platform
+        let write_value = |hasher: &mut Fnv1aHasher, value: f64| {
+            hasher.write(&((value / ID_PRECISION).round() as i64).to_le_bytes());
+        };
+        let write_point = |hasher: &mut Fnv1aHasher, point: &Point2<f64>| {
+            write_value(hasher, point.x);
+            write_value(hasher, point.y);
+        };
+
+        let exposure = match self.exposure() {
+            Exposure::CutOut => 0,
+            Exposure::Add => 1,
+        };
+        hasher.write_u8(exposure);
+
+        match aperture {
+            Some(code) => {
+                hasher.write_u8(1);
+                hasher.write(&code.to_le_bytes());
+            }
+            None => hasher.write_u8(0),
+        }
+
+        match self {
+            GerberPrimitive::Circle(circle) => {
+                hasher.write_u8(0);
+                write_point(&mut hasher, &circle.center);
+                write_value(&mut hasher, circle.diameter);
+                write_value(&mut hasher, circle.hole_diameter.unwrap_or(0.0));
+            }
+            GerberPrimitive::Rectangle(rectangle) => {
+                hasher.write_u8(1);
+                write_point(&mut hasher, &rectangle.origin);
+                write_value(&mut hasher, rectangle.width);
+                write_value(&mut hasher, rectangle.height);
+            }
+            GerberPrimitive::Line(line) => {
+                hasher.write_u8(2);
+                write_point(&mut hasher, &line.start);
+                write_point(&mut hasher, &line.end);
+                write_value(&mut hasher, line.width);
+            }
+            GerberPrimitive::Arc(arc) => {
+                hasher.write_u8(3);
+                write_point(&mut hasher, &arc.center);
+                write_value(&mut hasher, arc.radius);
+                write_value(&mut hasher, arc.width);
+                write_value(&mut hasher, arc.start_angle);
+                write_value(&mut hasher, arc.sweep_angle);
+            }
+            GerberPrimitive::Polygon(polygon) => {
+                hasher.write_u8(4);
+                write_point(&mut hasher, &polygon.center);
+                for vertex in &polygon.geometry.relative_vertices {
+                    write_point(&mut hasher, vertex);
+                }
+            }
+            GerberPrimitive::RoundedRectangle(rectangle) => {
+                hasher.write_u8(5);
+                write_point(&mut hasher, &rectangle.origin);
+                write_value(&mut hasher, rectangle.width);
+                write_value(&mut hasher, rectangle.height);
+                write_value(&mut hasher, rectangle.corner_radius);
+            }
+        }
+
+        PrimitiveId(hasher.finish())
+    }
+}
+
+impl GerberLayer {
+    /// Returns the id of the primitive at `index`, including the aperture it was created with.
+    pub fn id_of(&self, index: usize) -> Option<PrimitiveId> {
+        let primitive = self.primitives().get(index)?;
+
+        Some(primitive.id(self.primitive_apertures()[index]))
+    }
+
+    fn ids(&self) -> impl Iterator<Item = PrimitiveId> + '_ {
+        self.primitives()
+            .iter()
+            .zip(self.primitive_apertures())
+            .map(|(primitive, aperture)| primitive.id(*aperture))
+    }
+
+    /// Returns the index of the first primitive with the given id.
+    pub fn index_of(&self, id: PrimitiveId) -> Option<usize> {
+        self.ids()
+            .position(|candidate| candidate == id)
+    }
+
+    /// Returns the index of the primitive with the given id that is closest to `hint`, usually the index the primitive
+    /// had before a re-parse.
+    ///
+    /// When several primitives have the same id (identical primitives) this keeps the selection on the one that is
+    /// nearest in drawing order, which is the same one unless primitives were added or removed before it.
+    pub fn index_of_near(&self, id: PrimitiveId, hint: usize) -> Option<usize> {
+        self.ids()
+            .enumerate()
+            .filter(|(_, candidate)| *candidate == id)
+            .map(|(index, _)| index)
+            .min_by_key(|index| index.abs_diff(hint))
+    }
+}
+
+/// 64-bit FNV-1a, unlike `std`'s default hasher the output is specified, so ids are the same across builds.
+struct Fnv1aHasher(u64);
+
+impl Default for Fnv1aHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1aHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self
+                .0
+                .wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod primitive_id_tests {
+
+    use super::*;
+    use crate::testing::commands;
+
+    #[test]
+    fn test_id_survives_reparse() {
+        // given
+        let before = commands::flashes_layer(1.0, &[(0.0, 0.0), (5.0, 5.0)]);
+        let id = before.id_of(1).unwrap();
+
+        // when
+        // a primitive is inserted before the selected one
+        let after = commands::flashes_layer(1.0, &[(0.0, 0.0), (2.0, 2.0), (5.0, 5.0)]);
+
+        // then
+        assert_eq!(after.index_of(id), Some(2));
+    }
+
+    #[test]
+    fn test_changed_primitive_has_a_different_id() {
+        // given
+        let before = commands::flashes_layer(1.0, &[(0.0, 0.0)]);
+        let after = commands::flashes_layer(1.5, &[(0.0, 0.0)]);
+
+        // expect
+        assert_ne!(before.id_of(0).unwrap(), after.id_of(0).unwrap());
+        assert_eq!(after.index_of(before.id_of(0).unwrap()), None);
+    }
+
+    #[test]
+    fn test_identical_primitives_use_the_nearest_index() {
+        // given
+        let layer = commands::flashes_layer(1.0, &[(0.0, 0.0), (5.0, 5.0), (0.0, 0.0), (5.0, 5.0), (0.0, 0.0)]);
+        let id = layer.id_of(0).unwrap();
+
+        // expect
+        assert_eq!(layer.index_of(id), Some(0));
+        assert_eq!(layer.index_of_near(id, 3), Some(2));
+        assert_eq!(layer.index_of_near(id, 10), Some(4));
+    }
+
+    #[test]
+    fn test_identical_pads_with_different_apertures_have_different_ids() {
+        // given
+        // the same 1mm pad at the same position, flashed with D10 and with D11
+        let mut commands = commands::flashes(1.0, &[(0.0, 0.0)]);
+        commands.extend([
+            commands::circle(11, 1.0),
+            commands::select(11),
+            commands::flash(0.0, 0.0),
+        ]);
+        let layer = GerberLayer::new(commands);
+
+        // when
+        let d10 = layer.id_of(0).unwrap();
+        let d11 = layer.id_of(1).unwrap();
+
+        // then
+        assert_ne!(d10, d11);
+        assert_eq!(layer.index_of(d11), Some(1));
+
+        // and the geometry alone is the same
+        assert_eq!(layer.primitives()[0].id(None), layer.primitives()[1].id(None));
+    }
+}
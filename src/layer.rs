@@ -1,24 +1,28 @@
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
 use std::ops::{Add, Range};
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
 use gerber_types::{
-    Aperture, ApertureDefinition, ApertureMacro, Command, Coordinates, DCode, ExtendedCode, FunctionCode, GCode,
-    ImageRotation, MacroContent, MacroDecimal, Operation, VariableDefinition,
+    Aperture, ApertureDefinition, ApertureMacro, Command, CommentContent, CoordinateFormat, Coordinates, DCode,
+    ExtendedCode, FunctionCode, GCode, ImagePolarity, ImageRotation, MacroContent, MacroDecimal, Operation,
+    VariableDefinition,
 };
-use gerber_types::{ApertureBlock, Circle, InterpolationMode, QuadrantMode, StepAndRepeat};
+use gerber_types::{ApertureBlock, Circle, InterpolationMode, Polarity, QuadrantMode, StepAndRepeat, Unit};
 use log::{debug, error, info, trace, warn};
-use nalgebra::{Point2, Vector2};
+use nalgebra::{Matrix3, Point2, Vector2};
 
 use super::expressions::{
     ExpressionEvaluationError, MacroContext, evaluate_expression, macro_boolean_to_bool, macro_decimal_pair_to_f64,
     macro_decimal_to_f64, macro_integer_to_u32,
 };
 use super::spacial::deduplicate::DedupEpsilon;
-use super::{GerberImageTransform, ToVector, geometry};
+use super::{GerberImageTransform, GerberTransform, Matrix3Point2Ext, ToVector, geometry};
 use crate::geometry::BoundingBox;
 use crate::geometry::PolygonMesh;
-use crate::types::{Exposure, Winding};
+use crate::query::PrimitiveGrid;
+use crate::types::{ArcDirection, Exposure, Winding};
+use crate::warning::{LayerWarning, UnsupportedFeature};
 
 /// FUTURE if the rendering is always real-time, then caching the points at the time the primitives are created would have
 ///        a performance benefit. e.g. `GerberArcPrimitive::generate_points` and similar methods.
@@ -29,9 +33,26 @@ pub struct GerberLayer {
     #[allow(unused)]
     commands: Vec<Command>,
     gerber_primitives: Vec<GerberPrimitive>,
+    /// Same order as `gerber_primitives`.
+    primitive_apertures: Vec<Option<i32>>,
+    /// See [`GerberLayer::step_repeats`].
+    step_repeats: Vec<StepRepeatBlock>,
     bounding_box: BoundingBox,
+    warnings: Vec<LayerWarning>,
+    /// Summary of `warnings`.
+    unsupported_features: Vec<UnsupportedFeature>,
 
     image_transform: GerberImageTransform,
+    /// `%IP`, when negative the exposure of the primitives has already been inverted, see
+    /// [`GerberLayer::image_polarity`].
+    image_polarity: ImagePolarity,
+    /// `G04` comments, see [`GerberLayer::comments`].
+    comments: Vec<String>,
+    /// See [`GerberLayer::removed_duplicate_count`].
+    removed_duplicates: usize,
+
+    /// Built on first use, by queries such as [`GerberLayer::primitive_at`].
+    spatial_index: OnceLock<PrimitiveGrid>,
 }
 
 impl GerberLayer {
@@ -71,20 +92,190 @@ impl GerberLayer {
 
         transform
     }
+
+    fn build_image_polarity(commands: &[Command]) -> ImagePolarity {
+        commands
+            .iter()
+            .find_map(|command| match command {
+                Command::ExtendedCode(ExtendedCode::ImagePolarity(image_polarity)) => Some(*image_polarity),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    fn build_comments(commands: &[Command]) -> Vec<String> {
+        commands
+            .iter()
+            .filter_map(|command| match command {
+                Command::FunctionCode(FunctionCode::GCode(GCode::Comment(CommentContent::String(comment)))) => {
+                    Some(comment.clone())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Options used when building the primitives of a layer, see [`GerberLayer::new_with_configuration`].
+#[derive(Debug, Clone)]
+pub struct LayerConfiguration {
+    /// Treat regions whose last point isn't the same as their first point as closed, instead of ignoring them.
+    ///
+    /// The gerber spec requires region contours to be closed, but some tools (e.g. EasyEDA) don't close them.
+    /// A [`LayerWarning::UnclosedRegion`] is recorded either way.
+    pub auto_close_regions: bool,
+    /// What to do with flashes of apertures that have a zero size, e.g. a zero-diameter circle, which some exporters
+    /// emit.  A [`LayerWarning::DegenerateAperture`] is recorded for each such aperture either way.
+    pub degenerate_apertures: DegenerateApertures,
+    /// The maximum number of primitives to build, a safety valve for untrusted files, e.g. a step-and-repeat with a
+    /// huge number of repeats.  `None` (the default) means no limit.
+    ///
+    /// When the limit is reached building stops, the layer is incomplete and only has the first `max_primitives`
+    /// primitives, a [`LayerWarning::PrimitiveLimitReached`] is recorded, see
+    /// [`GerberLayer::primitive_limit_reached`].  With [`LayerConfiguration::instanced_step_repeat`] each repeat counts
+    /// as the primitives of its block, a step-and-repeat that exceeds the limit keeps only the repeats that fit.
+    pub max_primitives: Option<usize>,
+    /// Removes exact duplicates of primitives, e.g. a pad that an exporter flashed twice, see
+    /// [`GerberLayer::remove_duplicate_primitives`].
+    ///
+    /// Off by default, so the primitives match the drawing commands.
+    pub remove_duplicate_primitives: bool,
+    /// What to do with the deprecated `G55` (prepare for flash) code, which older CAM software emits before flashes.
+    ///
+    /// Only used when parsing, see `GerberLayer::from_bytes_with_configuration`.  The deprecated `G54` prefix of an
+    /// aperture selection has no effect either way, a [`LayerWarning::DeprecatedCode`] is recorded for it.
+    pub deprecated_codes: DeprecatedCodes,
+    /// Keeps the block of a step-and-repeat (`%SR`) once, with the offsets of its repeats, instead of building a copy
+    /// of its primitives for each repeat, see [`GerberLayer::step_repeats`].  Keeps the memory bounded for large
+    /// panels, the renderer draws the block once for each repeat, with an offset transform.
+    ///
+    /// Off by default.  When on, [`GerberLayer::primitives`] and the APIs that use primitive indexes, e.g.
+    /// [`GerberLayer::primitive_at`], measurements and exports, only see the first repeat, use
+    /// [`GerberLayer::instance_at`] to pick a repeat.  The bounding box includes all the repeats.
+    pub instanced_step_repeat: bool,
+}
+
+impl Default for LayerConfiguration {
+    fn default() -> Self {
+        Self {
+            auto_close_regions: true,
+            degenerate_apertures: DegenerateApertures::default(),
+            max_primitives: None,
+            remove_duplicate_primitives: false,
+            deprecated_codes: DeprecatedCodes::default(),
+            instanced_step_repeat: false,
+        }
+    }
+}
+
+/// See [`LayerConfiguration::degenerate_apertures`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DegenerateApertures {
+    /// Each flash becomes a zero-diameter circle, which the renderer draws as a dot, so the flashes don't silently
+    /// disappear.
+    #[default]
+    Dot,
+    /// Flashes create no primitives.
+    Skip,
+}
+
+/// See [`LayerConfiguration::deprecated_codes`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DeprecatedCodes {
+    /// The `G55` code is removed and the command that follows it is used as usual, e.g. `G55X0Y0D03*` is a flash,
+    /// same as older CAM software intended.  A warning is logged for each one.
+    #[default]
+    Interpret,
+    /// Commands with a `G55` code can't be parsed and are skipped, including any flash that follows it on the same
+    /// line, same as `gerber_parser::parse`.
+    Skip,
+}
+
+/// A step-and-repeat block that is built once and drawn at several offsets, see
+/// [`LayerConfiguration::instanced_step_repeat`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepRepeatBlock {
+    /// The indexes of the primitives of the block, i.e. of the first repeat.
+    pub primitives: Range<usize>,
+    /// The offset of each repeat, in gerber units, the index of an offset is the `repeat_index` of the repeat, see
+    /// [`PrimitiveInstance`](crate::PrimitiveInstance).
+    ///
+    /// The first offset is zero, the repeats are in drawing order, i.e. the columns from left to right and, within
+    /// each column, the rows from bottom to top.
+    pub offsets: Vec<Vector2<f64>>,
+}
+
+impl StepRepeatBlock {
+    /// The bounding box of the first repeat, `primitives` are the primitives of the layer.
+    pub(crate) fn block_bounding_box(&self, primitives: &[GerberPrimitive]) -> BoundingBox {
+        let mut bbox = BoundingBox::default();
+        for primitive in &primitives[self.primitives.clone()] {
+            bbox.expand(&primitive.bounding_box());
+        }
+        bbox
+    }
+}
+
+/// True if the aperture has a zero (or negative) size, i.e. a flash wouldn't cover any area.
+///
+/// A zero-diameter circle is still valid for draws, e.g. for outlines.
+fn is_degenerate_aperture(aperture: &Aperture) -> bool {
+    match aperture {
+        Aperture::Circle(circle) => circle.diameter <= 0.0,
+        Aperture::Rectangle(rect) | Aperture::Obround(rect) => rect.x <= 0.0 || rect.y <= 0.0,
+        Aperture::Polygon(polygon) => polygon.diameter <= 0.0,
+        Aperture::Macro(..) => false,
+    }
 }
 
 impl GerberLayer {
     pub fn new(commands: Vec<Command>) -> Self {
-        let gerber_primitives = GerberLayer::build_primitives(&commands);
-        let bounding_box = GerberLayer::calculate_bounding_box(&gerber_primitives);
+        Self::new_with_configuration(commands, &LayerConfiguration::default())
+    }
+
+    pub fn new_with_configuration(commands: Vec<Command>, configuration: &LayerConfiguration) -> Self {
+        let mut warnings = Vec::new();
+        let (mut gerber_primitives, primitive_apertures, step_repeats) =
+            GerberLayer::build_primitives(&commands, configuration, &mut warnings);
+        let bounding_box = GerberLayer::calculate_instanced_bounding_box(&gerber_primitives, &step_repeats);
         let image_transform = GerberLayer::build_image_transform(&commands);
+        let image_polarity = GerberLayer::build_image_polarity(&commands);
+        let comments = GerberLayer::build_comments(&commands);
 
-        Self {
+        let mut unsupported_features: Vec<UnsupportedFeature> = warnings
+            .iter()
+            .filter_map(LayerWarning::unsupported_feature)
+            .collect();
+        unsupported_features.sort();
+        unsupported_features.dedup();
+
+        if image_polarity == ImagePolarity::Negative {
+            // the objects of a negative image clear the image area, and the clear parts of objects expose it.
+            for primitive in &mut gerber_primitives {
+                primitive.set_exposure(primitive.exposure().inverted());
+            }
+        }
+
+        let mut layer = Self {
             commands,
             gerber_primitives,
+            primitive_apertures,
+            step_repeats,
             bounding_box,
+            warnings,
+            unsupported_features,
             image_transform,
+            image_polarity,
+            comments,
+            removed_duplicates: 0,
+            spatial_index: OnceLock::new(),
+        };
+
+        if configuration.remove_duplicate_primitives {
+            layer.remove_duplicate_primitives();
         }
+
+        layer
     }
 
     /// It's possible to have a gerber file with no primitives
@@ -104,14 +295,430 @@ impl GerberLayer {
         }
     }
 
-    #[allow(unused)]
-    pub(crate) fn primitives(&self) -> &[GerberPrimitive] {
+    /// The primitives of the layer, in the order they are rendered.
+    ///
+    /// Coordinates are in gerber units, before the image transform is applied.
+    pub fn primitives(&self) -> &[GerberPrimitive] {
         &self.gerber_primitives
     }
 
+    /// How each primitive was created, e.g. by a flash or a draw, in the same order as [`GerberLayer::primitives`], see
+    /// [`GerberPrimitive::primitive_origin`].
+    pub fn primitive_origins(&self) -> Vec<PrimitiveOrigin> {
+        self.gerber_primitives
+            .iter()
+            .map(GerberPrimitive::primitive_origin)
+            .collect()
+    }
+
+    /// The code of the aperture (the `nn` of `Dnn`) used to create each primitive, in the same order as
+    /// [`GerberLayer::primitives`], e.g. to hide all the flashes of the aperture used for vias.
+    ///
+    /// For the objects of a block aperture it's the code of the block that was flashed, regions have no aperture.
+    pub fn primitive_apertures(&self) -> &[Option<i32>] {
+        &self.primitive_apertures
+    }
+
+    /// The step-and-repeat blocks that are drawn at several offsets, in the order of their primitives, empty unless
+    /// [`LayerConfiguration::instanced_step_repeat`] is used.
+    pub fn step_repeats(&self) -> &[StepRepeatBlock] {
+        &self.step_repeats
+    }
+
+    /// The primitives of the layer with each repeat of the [`GerberLayer::step_repeats`] translated to its offset, in
+    /// drawing order, i.e. the primitives as if the layer was built without instancing.
+    ///
+    /// Borrows the primitives when the layer has no step-and-repeat blocks.
+    pub(crate) fn expanded_primitives(&self) -> Cow<'_, [GerberPrimitive]> {
+        if self.step_repeats.is_empty() {
+            return Cow::Borrowed(&self.gerber_primitives);
+        }
+
+        let mut primitives = Vec::with_capacity(
+            self.gerber_primitives.len()
+                + self
+                    .step_repeats
+                    .iter()
+                    .map(|block| block.primitives.len() * (block.offsets.len() - 1))
+                    .sum::<usize>(),
+        );
+        let mut next = 0;
+        for block in &self.step_repeats {
+            primitives.extend_from_slice(&self.gerber_primitives[next..block.primitives.start]);
+            let block_primitives = &self.gerber_primitives[block.primitives.clone()];
+            for offset in &block.offsets {
+                let translation = Matrix3::new_translation(offset);
+                primitives.extend(
+                    block_primitives
+                        .iter()
+                        .map(|primitive| primitive.transformed(&translation)),
+                );
+            }
+            next = block.primitives.end;
+        }
+        primitives.extend_from_slice(&self.gerber_primitives[next..]);
+
+        Cow::Owned(primitives)
+    }
+
     pub fn image_transform(&self) -> &GerberImageTransform {
         &self.image_transform
     }
+
+    /// The image polarity of the layer, from the first `%IP` command (a deprecated command, still used by older files).
+    ///
+    /// In a negative image the whole image area, i.e. the bounding box, is dark and the objects clear it.  The
+    /// exposure of the primitives is inverted when the layer is built, so objects drawn with dark polarity are
+    /// [`Exposure::CutOut`], the renderer fills the bounding box before drawing them.
+    pub fn image_polarity(&self) -> ImagePolarity {
+        self.image_polarity
+    }
+
+    /// The text of the `G04` comments, in the order they occur, e.g. to show the tool that generated the file or an
+    /// embedded layer name, which many exporters write in comments.
+    ///
+    /// Standard comments (`G04 #@!`), i.e. attributes written as comments, are not included.
+    pub fn comments(&self) -> &[String] {
+        &self.comments
+    }
+
+    /// The units of the layer, from the first `%MO` command, or `None` if the layer doesn't specify them.
+    pub fn units(&self) -> Option<Unit> {
+        self.commands
+            .iter()
+            .find_map(|command| match command {
+                Command::ExtendedCode(ExtendedCode::Unit(unit)) => Some(*unit),
+                _ => None,
+            })
+    }
+
+    /// The coordinate format of the layer, from the first `%FS` command, or `None` if the layer doesn't specify it.
+    pub fn coordinate_format(&self) -> Option<CoordinateFormat> {
+        self.commands
+            .iter()
+            .find_map(|command| match command {
+                Command::ExtendedCode(ExtendedCode::CoordinateFormat(format)) => Some(*format),
+                _ => None,
+            })
+    }
+
+    /// A grid spacing for the layer, in gerber units, e.g. for a background grid or snapping.
+    ///
+    /// 1mm for metric layers (and layers without units) and 0.1in for imperial layers, but never finer than the
+    /// resolution of the coordinate format, so that every grid point can be expressed in the file's coordinates.
+    pub fn default_grid_spacing(&self) -> f64 {
+        let spacing: f64 = match self.units() {
+            Some(Unit::Millimeters) | None => 1.0,
+            Some(Unit::Inches) => 0.1,
+        };
+
+        match self.coordinate_format() {
+            Some(format) => spacing.max(10.0_f64.powi(-(format.decimal as i32))),
+            None => spacing,
+        }
+    }
+
+    /// The size of the layer's bounding box as a human readable string, e.g. `"100.00 × 80.00 mm"`.
+    ///
+    /// Uses the number of decimal places of the coordinate format, or 2 if the layer doesn't specify it.  Layers
+    /// without units are treated as metric, like [`GerberLayer::default_grid_spacing`].  Empty layers are `0 × 0`.
+    pub fn dimensions_string(&self) -> String {
+        let (width, height) = match self.try_bounding_box() {
+            Some(bbox) => (bbox.width(), bbox.height()),
+            None => (0.0, 0.0),
+        };
+        let precision = match self.coordinate_format() {
+            Some(format) => format.decimal as usize,
+            None => 2,
+        };
+        let units = match self.units() {
+            Some(Unit::Millimeters) | None => "mm",
+            Some(Unit::Inches) => "in",
+        };
+
+        format!("{:.*} × {:.*} {}", precision, width, precision, height, units)
+    }
+
+    pub(crate) fn spatial_index(&self) -> &PrimitiveGrid {
+        self.spatial_index
+            .get_or_init(|| PrimitiveGrid::new(&self.gerber_primitives, &self.bounding_box))
+    }
+
+    /// Problems found while building the layer, in the order they were found.
+    pub fn warnings(&self) -> &[LayerWarning] {
+        &self.warnings
+    }
+
+    /// True if building stopped at [`LayerConfiguration::max_primitives`], i.e. the layer is incomplete.
+    pub fn primitive_limit_reached(&self) -> bool {
+        self.warnings
+            .iter()
+            .any(|warning| matches!(warning, LayerWarning::PrimitiveLimitReached { .. }))
+    }
+
+    /// True if the layer uses features that may not render correctly, see [`GerberLayer::unsupported_features`].
+    pub fn has_unsupported_features(&self) -> bool {
+        !self.unsupported_features.is_empty()
+    }
+
+    /// The categories of the warnings about features that may not render correctly, sorted and without duplicates.
+    pub fn unsupported_features(&self) -> &[UnsupportedFeature] {
+        &self.unsupported_features
+    }
+
+    /// Returns a new layer with the image transform and the given transform applied to the primitives.
+    ///
+    /// The image transform of the returned layer is reset to the default (identity), so rendering it with an identity
+    /// `GerberTransform` gives the same result as rendering this layer with `transform`.
+    ///
+    /// * Rectangles that are no-longer axis-aligned, e.g. after a 45 degree rotation, become polygons.
+    /// * Arcs have their start angle recomputed and mirroring reverses their direction.
+    /// * Widths and diameters are scaled by the geometric mean of the x and y scale factors, i.e. the square root of
+    ///   the absolute determinant, a non-uniform scale (e.g. from `%SF`) can't be represented for round shapes.
+    pub fn baked(&self, transform: &GerberTransform) -> GerberLayer {
+        // same order as the renderer, see `GerberRenderer::new`
+        let matrix = self.image_transform.to_matrix() * transform.to_matrix();
+
+        let gerber_primitives: Vec<GerberPrimitive> = self
+            .gerber_primitives
+            .iter()
+            .map(|primitive| primitive.transformed(&matrix))
+            .collect();
+        // the offsets are moved by the linear part of the transform, the translation applies to the block
+        let step_repeats: Vec<StepRepeatBlock> = self
+            .step_repeats
+            .iter()
+            .map(|step_repeat| StepRepeatBlock {
+                primitives: step_repeat.primitives.clone(),
+                offsets: step_repeat
+                    .offsets
+                    .iter()
+                    .map(|offset| {
+                        matrix.transform_point2(Point2::from(*offset)) - matrix.transform_point2(Point2::origin())
+                    })
+                    .collect(),
+            })
+            .collect();
+        let bounding_box = GerberLayer::calculate_instanced_bounding_box(&gerber_primitives, &step_repeats);
+
+        Self {
+            commands: self.commands.clone(),
+            gerber_primitives,
+            primitive_apertures: self.primitive_apertures.clone(),
+            step_repeats,
+            bounding_box,
+            warnings: self.warnings.clone(),
+            unsupported_features: self.unsupported_features.clone(),
+            image_transform: GerberImageTransform::default(),
+            image_polarity: self.image_polarity,
+            comments: self.comments.clone(),
+            removed_duplicates: self.removed_duplicates,
+            spatial_index: OnceLock::new(),
+        }
+    }
+
+    /// Appends a primitive, e.g. a generated fiducial or keep-out, so that it's rendered, picked and exported with the
+    /// rest of the layer.  The bounding box is updated and the spatial index is rebuilt on the next query.
+    ///
+    /// For programmatic use, this bypasses the gerber semantics: there is no aperture or polarity tracking, the
+    /// exposure of the primitive is used as-is (even for a negative image, see [`GerberLayer::image_polarity`]), its
+    /// origin is [`PrimitiveOrigin::Programmatic`] and it has no aperture.  Coordinates are in gerber units, before the
+    /// image transform is applied.
+    pub fn push_primitive(&mut self, mut primitive: GerberPrimitive) {
+        primitive.set_primitive_origin(PrimitiveOrigin::Programmatic);
+        self.bounding_box
+            .expand(&primitive.bounding_box());
+        self.gerber_primitives.push(primitive);
+        self.primitive_apertures.push(None);
+        self.spatial_index = OnceLock::new();
+    }
+
+    /// Merges consecutive line segments that continue in the same direction into a single line, returns the number of
+    /// segments that were removed.
+    ///
+    /// Some tools draw long traces as many tiny draws, merging them reduces the primitive count and makes picking and
+    /// measuring work on the whole trace.  Only lines that are next to each other in drawing order are merged, they
+    /// must have the same width, exposure and origin (see [`GerberLayer::primitive_origins`]) and the end of the first
+    /// must be the start of the second.  Lines that double back on themselves are not merged.
+    ///
+    /// `tolerance` is in gerber units, it's used for comparing the widths and end points and is the maximum distance
+    /// of a removed joint from the merged line.
+    ///
+    /// Opt-in, the layer is not modified when it's built, so the primitive indexes match the drawing commands.
+    pub fn merge_collinear_lines(&mut self, tolerance: f64) -> usize {
+        let original_count = self.gerber_primitives.len();
+
+        let mut primitives: Vec<GerberPrimitive> = Vec::with_capacity(original_count);
+        let mut apertures: Vec<Option<i32>> = Vec::with_capacity(original_count);
+        // the points where the segments of the last merged line met
+        let mut joints: Vec<Point2<f64>> = Vec::new();
+        let boundaries = self.step_repeat_boundaries();
+        let mut new_indexes: Vec<usize> = Vec::with_capacity(original_count + 1);
+
+        for (index, (primitive, aperture)) in self
+            .gerber_primitives
+            .drain(..)
+            .zip(self.primitive_apertures.drain(..))
+            .enumerate()
+        {
+            new_indexes.push(primitives.len());
+            if let (Some(GerberPrimitive::Line(merged)), GerberPrimitive::Line(line)) =
+                (primitives.last_mut(), &primitive)
+                && !boundaries.contains(&index)
+                && merged.primitive_origin == line.primitive_origin
+                && apertures.last() == Some(&aperture)
+                && merged.can_merge(line, &joints, tolerance)
+            {
+                joints.push(merged.end);
+                merged.end = line.end;
+                continue;
+            }
+
+            joints.clear();
+            primitives.push(primitive);
+            apertures.push(aperture);
+        }
+
+        new_indexes.push(primitives.len());
+
+        self.gerber_primitives = primitives;
+        self.primitive_apertures = apertures;
+        self.remap_step_repeats(&new_indexes);
+        // the bounding box is unchanged, the merged lines cover the same area
+        self.spatial_index = OnceLock::new();
+
+        original_count - self.gerber_primitives.len()
+    }
+
+    /// Removes primitives that are exact duplicates of an earlier primitive, returns the number of primitives that
+    /// were removed.
+    ///
+    /// Some exporters emit the same flash twice, which is confusing with unique shape colors or shape numbering, and
+    /// darker where a translucent color overlaps itself.  Duplicates must have the same kind, geometry, exposure,
+    /// origin (see [`GerberLayer::primitive_origins`]) and aperture.  A duplicate is only removed if no primitive with
+    /// a different exposure is drawn between the two, since a cut-out between them would cover the first but not the
+    /// second.
+    ///
+    /// Opt-in, see [`LayerConfiguration::remove_duplicate_primitives`].
+    pub fn remove_duplicate_primitives(&mut self) -> usize {
+        let original_count = self.gerber_primitives.len();
+
+        // primitives are grouped by the run of same-exposure primitives they are in, and their bounding box, which is
+        // the same for duplicates, so only a few primitives have to be compared.
+        let mut seen: HashMap<(usize, [u64; 4]), Vec<usize>> = HashMap::new();
+        let mut run = 0;
+        let mut previous_exposure = None;
+        let boundaries = self.step_repeat_boundaries();
+        let mut new_indexes: Vec<usize> = Vec::with_capacity(original_count + 1);
+
+        let mut primitives: Vec<GerberPrimitive> = Vec::with_capacity(original_count);
+        let mut apertures: Vec<Option<i32>> = Vec::with_capacity(original_count);
+
+        for (index, (primitive, aperture)) in self
+            .gerber_primitives
+            .drain(..)
+            .zip(self.primitive_apertures.drain(..))
+            .enumerate()
+        {
+            new_indexes.push(primitives.len());
+            let exposure = primitive.exposure();
+            // a step-and-repeat block is drawn several times, so it's a separate run
+            if previous_exposure.is_some_and(|previous| previous != exposure) || boundaries.contains(&index) {
+                run += 1;
+            }
+            previous_exposure = Some(exposure);
+
+            let bbox = primitive.bounding_box();
+            let key = (run, [
+                bbox.min.x.to_bits(),
+                bbox.min.y.to_bits(),
+                bbox.max.x.to_bits(),
+                bbox.max.y.to_bits(),
+            ]);
+
+            let candidates = seen.entry(key).or_default();
+            if candidates.iter().any(|&index| {
+                primitives[index].primitive_origin() == primitive.primitive_origin()
+                    && apertures[index] == aperture
+                    && primitives[index].approx_eq(&primitive, 0.0)
+            }) {
+                continue;
+            }
+
+            candidates.push(primitives.len());
+            primitives.push(primitive);
+            apertures.push(aperture);
+        }
+
+        new_indexes.push(primitives.len());
+
+        self.gerber_primitives = primitives;
+        self.primitive_apertures = apertures;
+        self.remap_step_repeats(&new_indexes);
+        // the bounding box is unchanged, the removed primitives cover the same area
+        self.spatial_index = OnceLock::new();
+
+        let removed = original_count - self.gerber_primitives.len();
+        self.removed_duplicates += removed;
+
+        removed
+    }
+
+    /// The indexes where a step-and-repeat block starts or ends, primitives are not merged or removed across them.
+    fn step_repeat_boundaries(&self) -> HashSet<usize> {
+        self.step_repeats
+            .iter()
+            .flat_map(|step_repeat| [step_repeat.primitives.start, step_repeat.primitives.end])
+            .collect()
+    }
+
+    /// Updates the primitive ranges of the step-and-repeat blocks after primitives were removed, `new_indexes` has the
+    /// new index of each old index, plus the new length.
+    fn remap_step_repeats(&mut self, new_indexes: &[usize]) {
+        for step_repeat in &mut self.step_repeats {
+            step_repeat.primitives = new_indexes[step_repeat.primitives.start]..new_indexes[step_repeat.primitives.end];
+        }
+    }
+
+    /// The number of primitives removed by [`GerberLayer::remove_duplicate_primitives`], including when the layer
+    /// was built, see [`LayerConfiguration::remove_duplicate_primitives`].
+    pub fn removed_duplicate_count(&self) -> usize {
+        self.removed_duplicates
+    }
+}
+
+impl LineGerberPrimitive {
+    /// See [`GerberLayer::merge_collinear_lines`], `joints` are the joints already removed from this line.
+    fn can_merge(&self, next: &LineGerberPrimitive, joints: &[Point2<f64>], tolerance: f64) -> bool {
+        if self.exposure != next.exposure
+            || (self.width - next.width).abs() > tolerance
+            || (self.end - next.start).dot(&(self.end - next.start)) > tolerance * tolerance
+        {
+            return false;
+        }
+
+        let merged = next.end - self.start;
+        let length = merged.dot(&merged).sqrt();
+        if length <= tolerance {
+            // both segments are (nearly) points at the same position
+            return true;
+        }
+
+        // doubling back would shorten the line
+        if (next.end - next.start).dot(&(self.end - self.start)) < 0.0 {
+            return false;
+        }
+
+        let distance_from_merged = |point: &Point2<f64>| {
+            let offset = point - self.start;
+            (merged.x * offset.y - merged.y * offset.x).abs() / length
+        };
+
+        joints
+            .iter()
+            .chain([&self.end])
+            .all(|joint| distance_from_merged(joint) <= tolerance)
+    }
 }
 
 pub trait WithBoundingBox {
@@ -182,6 +789,21 @@ impl WithBoundingBox for RectangleGerberPrimitive {
     }
 }
 
+impl WithBoundingBox for RoundedRectangleGerberPrimitive {
+    fn bounding_box(&self) -> BoundingBox {
+        let Self {
+            origin,
+            width,
+            height,
+            ..
+        } = self;
+        BoundingBox {
+            min: Point2::new(origin.x, origin.y),
+            max: Point2::new(origin.x + width, origin.y + height),
+        }
+    }
+}
+
 impl WithBoundingBox for LineGerberPrimitive {
     fn bounding_box(&self) -> BoundingBox {
         let Self {
@@ -250,14 +872,7 @@ impl GerberLayer {
         let mut bbox = BoundingBox::default();
 
         for primitive in primitives {
-            let primitive_bbox = match primitive {
-                GerberPrimitive::Circle(primitive) => primitive.bounding_box(),
-                GerberPrimitive::Arc(primitive) => primitive.bounding_box(),
-                GerberPrimitive::Rectangle(primitive) => primitive.bounding_box(),
-                GerberPrimitive::Line(primitive) => primitive.bounding_box(),
-                GerberPrimitive::Polygon(primitive) => primitive.bounding_box(),
-            };
-            bbox.expand(&primitive_bbox);
+            bbox.expand(&primitive.bounding_box());
         }
 
         trace!("layer bbox: {:?}", bbox);
@@ -265,11 +880,223 @@ impl GerberLayer {
         bbox
     }
 
-    fn build_primitives(commands: &[Command]) -> Vec<GerberPrimitive> {
+    /// Same as [`GerberLayer::calculate_bounding_box`], including the repeats of the step-and-repeat blocks.
+    fn calculate_instanced_bounding_box(
+        primitives: &Vec<GerberPrimitive>,
+        step_repeats: &[StepRepeatBlock],
+    ) -> BoundingBox {
+        let mut bbox = GerberLayer::calculate_bounding_box(primitives);
+
+        for step_repeat in step_repeats {
+            let block = step_repeat.block_bounding_box(primitives);
+            if block.is_empty() {
+                continue;
+            }
+            for offset in &step_repeat.offsets {
+                bbox.expand(&BoundingBox {
+                    min: block.min + offset,
+                    max: block.max + offset,
+                });
+            }
+        }
+
+        bbox
+    }
+
+    /// Returns a single primitive for the primitives of a macro that draw an axis-aligned rectangle with rounded
+    /// corners, or `None` if they draw anything else.
+    ///
+    /// The corners must be 4 circles of the same diameter, the rest of the shape must be axis-aligned rectangles
+    /// (e.g. outlines, vector lines or center lines) that fill either the rectangle between the centers of the circles
+    /// and the 4 bars along its edges, like KiCad's `RoundRect` macro, or a horizontal and a vertical bar between the
+    /// circles.
+    fn build_rounded_rectangle(primitives: &[GerberPrimitive]) -> Option<GerberPrimitive> {
+        const EPSILON: f64 = 1e-6;
+        let near = |a: Point2<f64>, b: Point2<f64>| (a.x - b.x).abs() <= EPSILON && (a.y - b.y).abs() <= EPSILON;
+        let contains = |outer: &BoundingBox, inner: &BoundingBox| {
+            outer.min.x - EPSILON <= inner.min.x
+                && outer.min.y - EPSILON <= inner.min.y
+                && inner.max.x <= outer.max.x + EPSILON
+                && inner.max.y <= outer.max.y + EPSILON
+        };
+
+        if primitives
+            .iter()
+            .any(|primitive| primitive.exposure() != Exposure::Add)
+        {
+            return None;
+        }
+
+        let (circles, others): (Vec<_>, Vec<_>) = primitives
+            .iter()
+            .partition(|primitive| matches!(primitive, GerberPrimitive::Circle(_)));
+        let circles = circles
+            .into_iter()
+            .filter_map(|primitive| match primitive {
+                GerberPrimitive::Circle(circle) if circle.hole_diameter.is_none() => Some(circle),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let [first, ..] = circles.as_slice() else {
+            return None;
+        };
+        let radius = first.diameter / 2.0;
+        if circles.len() != 4
+            || radius <= 0.0
+            || circles
+                .iter()
+                .any(|circle| (circle.diameter - first.diameter).abs() > EPSILON)
+        {
+            return None;
+        }
+
+        // the circles are at the corners of the body
+        let body = BoundingBox::from_points(
+            &circles
+                .iter()
+                .map(|circle| circle.center)
+                .collect::<Vec<_>>(),
+        );
+        if body.width() <= EPSILON || body.height() <= EPSILON {
+            return None;
+        }
+        let corners = body.vertices();
+        if !corners.iter().all(|corner| {
+            circles
+                .iter()
+                .any(|circle| near(circle.center, *corner))
+        }) {
+            return None;
+        }
+
+        // the other primitives must be axis-aligned rectangles
+        let mut rectangles = Vec::with_capacity(others.len());
+        for primitive in others {
+            let bbox = match primitive {
+                GerberPrimitive::Rectangle(rectangle) => rectangle.bounding_box(),
+                GerberPrimitive::Polygon(polygon) => {
+                    let bbox = polygon.bounding_box();
+                    let vertices = polygon
+                        .geometry
+                        .relative_vertices
+                        .iter()
+                        .map(|vertex| polygon.center + vertex.coords)
+                        .collect::<Vec<_>>();
+                    let is_rectangle = bbox.vertices().iter().all(|corner| {
+                        vertices
+                            .iter()
+                            .any(|vertex| near(*vertex, *corner))
+                    }) && vertices
+                        .iter()
+                        .enumerate()
+                        .all(|(index, vertex)| {
+                            let next = vertices[(index + 1) % vertices.len()];
+                            (vertex.x - next.x).abs() <= EPSILON || (vertex.y - next.y).abs() <= EPSILON
+                        });
+                    if !is_rectangle {
+                        return None;
+                    }
+                    bbox
+                }
+                _ => return None,
+            };
+            rectangles.push(bbox);
+        }
+
+        let horizontal = BoundingBox {
+            min: Point2::new(body.min.x - radius, body.min.y),
+            max: Point2::new(body.max.x + radius, body.max.y),
+        };
+        let vertical = BoundingBox {
+            min: Point2::new(body.min.x, body.min.y - radius),
+            max: Point2::new(body.max.x, body.max.y + radius),
+        };
+
+        // nothing outside the shape
+        if !rectangles
+            .iter()
+            .all(|rectangle| contains(&horizontal, rectangle) || contains(&vertical, rectangle))
+        {
+            return None;
+        }
+
+        // and no gaps inside it
+        let edge = |min: (f64, f64), max: (f64, f64)| BoundingBox {
+            min: Point2::new(min.0, min.1),
+            max: Point2::new(max.0, max.1),
+        };
+        let body_and_bars = [
+            edge((body.min.x, body.min.y), (body.max.x, body.max.y)),
+            edge((body.min.x, body.min.y - radius), (body.max.x, body.min.y + radius)),
+            edge((body.max.x - radius, body.min.y), (body.max.x + radius, body.max.y)),
+            edge((body.min.x, body.max.y - radius), (body.max.x, body.max.y + radius)),
+            edge((body.min.x - radius, body.min.y), (body.min.x + radius, body.max.y)),
+        ];
+        let is_covered = |required: &[BoundingBox]| {
+            required.iter().all(|part| {
+                rectangles
+                    .iter()
+                    .any(|rectangle| contains(rectangle, part))
+            })
+        };
+        if !is_covered(&body_and_bars) && !is_covered(&[horizontal.clone(), vertical.clone()]) {
+            return None;
+        }
+
+        Some(GerberPrimitive::RoundedRectangle(RoundedRectangleGerberPrimitive {
+            origin: Point2::new(horizontal.min.x, vertical.min.y),
+            width: horizontal.width(),
+            height: vertical.height(),
+            corner_radius: radius,
+            exposure: Exposure::Add,
+            primitive_origin: PENDING_ORIGIN,
+        }))
+    }
+
+    /// Builds the polygon for a standard polygon aperture, centered at the origin.
+    fn build_polygon_aperture(polygon: &gerber_types::Polygon) -> GerberPrimitive {
+        let radius = polygon.diameter / 2.0;
+        let vertices_count = polygon.vertices as usize;
+        let mut vertices = Vec::with_capacity(vertices_count);
+
+        // For standard aperture polygon, we need to generate vertices
+        // starting at angle 0 and moving counterclockwise
+        for i in 0..vertices_count {
+            let angle = (2.0 * std::f64::consts::PI * i as f64) / vertices_count as f64;
+            let x = radius * angle.cos();
+            let y = radius * angle.sin();
+
+            // Apply rotation if specified
+            let final_position = if let Some(rotation) = polygon.rotation {
+                let rot_rad = rotation * std::f64::consts::PI / 180.0;
+                let (sin_rot, cos_rot) = rot_rad.sin_cos();
+                Point2::new(x * cos_rot - y * sin_rot, x * sin_rot + y * cos_rot)
+            } else {
+                Point2::new(x, y)
+            };
+
+            vertices.push(final_position);
+        }
+
+        GerberPrimitive::new_polygon(GerberPolygon {
+            center: Point2::origin(),
+            vertices,
+            exposure: Exposure::Add,
+            primitive_origin: PENDING_ORIGIN,
+        })
+    }
+
+    fn build_primitives(
+        commands: &[Command],
+        configuration: &LayerConfiguration,
+        warnings: &mut Vec<LayerWarning>,
+    ) -> (Vec<GerberPrimitive>, Vec<Option<i32>>, Vec<StepRepeatBlock>) {
         #[derive(Debug)]
         struct StepRepeatState {
             initial_position: Point2<f64>,
             start_index: usize,
+            /// The index of the first primitive of the block.
+            start_primitive: usize,
 
             repeat_x: u32,
             repeat_y: u32,
@@ -304,6 +1131,9 @@ impl GerberLayer {
         }
 
         let mut apertures: HashMap<i32, LocalApertureKind> = HashMap::default();
+        // primitives of macro and polygon apertures, keyed by the aperture definition (excluding the code), so that
+        // apertures with the same definition share the polygon geometry, which includes the tessellation.
+        let mut templates: HashMap<String, Vec<GerberPrimitive>> = HashMap::default();
 
         // entries are pushed onto the stack as AB 'open' commands are found
         // popped off the stack and stored in the aperture definitions when a corresponding AB 'close' command is encountered.
@@ -346,6 +1176,32 @@ impl GerberLayer {
                         error!("Aperture block close without matching open");
                     }
                 }
+                Command::ExtendedCode(ExtendedCode::ApertureDefinition(ApertureDefinition {
+                    code,
+                    aperture,
+                })) if is_degenerate_aperture(aperture) => {
+                    warn!(
+                        "Degenerate (zero-size) aperture. code: {}, aperture: {:?}",
+                        code, aperture
+                    );
+                    warnings.push(LayerWarning::DegenerateAperture {
+                        command_index: index,
+                        code: *code,
+                    });
+
+                    let kind = match aperture {
+                        // still valid for draws, flashes are handled when flashed
+                        Aperture::Circle(_) => ApertureKind::Standard(aperture.clone()),
+                        _ => {
+                            let primitives = match configuration.degenerate_apertures {
+                                DegenerateApertures::Dot => vec![GerberPrimitive::dot(Point2::new(0.0, 0.0))],
+                                DegenerateApertures::Skip => vec![],
+                            };
+                            ApertureKind::Template(primitives, PrimitiveOrigin::Flash)
+                        }
+                    };
+                    apertures.insert(*code, LocalApertureKind::Standard(kind));
+                }
                 Command::ExtendedCode(ExtendedCode::ApertureDefinition(ApertureDefinition {
                     code,
                     aperture,
@@ -379,7 +1235,32 @@ impl GerberLayer {
                             };
                             debug!("macro_name_and_args: {}", macro_name_and_args);
 
-                            let mut macro_context = MacroContext::default();
+                            if let Some(primitives) = templates.get(macro_name_and_args) {
+                                trace!("re-using primitives of an identical macro aperture");
+                                apertures.insert(
+                                    *code,
+                                    LocalApertureKind::Standard(ApertureKind::Template(
+                                        primitives.clone(),
+                                        PrimitiveOrigin::Macro,
+                                    )),
+                                );
+                                continue;
+                            }
+
+                            for content in &macro_def.content {
+                                let primitive = match content {
+                                    MacroContent::Moire(_) => "moire",
+                                    MacroContent::Thermal(_) => "thermal",
+                                    _ => continue,
+                                };
+                                warnings.push(LayerWarning::UnsupportedMacroPrimitive {
+                                    command_index: index,
+                                    macro_name: macro_name.clone(),
+                                    primitive,
+                                });
+                            }
+
+                            let mut macro_context = MacroContext::default();
 
                             //
                             // populate the macro_context from the args.
@@ -458,8 +1339,10 @@ impl GerberLayer {
                                             Ok(Some(GerberPrimitive::Circle(CircleGerberPrimitive {
                                                 center: Point2::new(rotated_x, rotated_y),
                                                 diameter,
+                                                hole_diameter: None,
                                                 exposure: macro_boolean_to_bool(&circle.exposure, macro_context)?
                                                     .into(),
+                                                primitive_origin: PENDING_ORIGIN,
                                             })))
                                         }
                                         MacroContent::VectorLine(vector_line) => {
@@ -523,6 +1406,7 @@ impl GerberLayer {
                                                 vertices,
                                                 exposure: macro_boolean_to_bool(&vector_line.exposure, macro_context)?
                                                     .into(),
+                                                primitive_origin: PENDING_ORIGIN,
                                             })))
                                         }
                                         MacroContent::CenterLine(center_line) => {
@@ -563,6 +1447,7 @@ impl GerberLayer {
                                                 vertices,
                                                 exposure: macro_boolean_to_bool(&center_line.exposure, macro_context)?
                                                     .into(),
+                                                primitive_origin: PENDING_ORIGIN,
                                             })))
                                         }
                                         MacroContent::Outline(outline) => {
@@ -608,6 +1493,7 @@ impl GerberLayer {
                                                 vertices,
                                                 exposure: macro_boolean_to_bool(&outline.exposure, macro_context)?
                                                     .into(),
+                                                primitive_origin: PENDING_ORIGIN,
                                             })))
                                         }
                                         MacroContent::Polygon(polygon) => {
@@ -646,6 +1532,7 @@ impl GerberLayer {
                                                 vertices,
                                                 exposure: macro_boolean_to_bool(&polygon.exposure, macro_context)?
                                                     .into(),
+                                                primitive_origin: PENDING_ORIGIN,
                                             })))
                                         }
                                         MacroContent::Moire(_) => {
@@ -653,7 +1540,7 @@ impl GerberLayer {
                                             Ok(None)
                                         }
                                         MacroContent::Thermal(_) => {
-                                            error!("Moire not supported");
+                                            error!("Thermal not supported");
                                             Ok(None)
                                         }
                                         MacroContent::VariableDefinition(VariableDefinition {
@@ -696,14 +1583,48 @@ impl GerberLayer {
 
                             trace!("primitive_defs: {:?}", primitive_defs);
 
-                            apertures.insert(*code, LocalApertureKind::Standard(ApertureKind::Macro(primitive_defs)));
+                            let primitive_defs = match GerberLayer::build_rounded_rectangle(&primitive_defs) {
+                                Some(rounded_rectangle) => vec![rounded_rectangle],
+                                None => primitive_defs,
+                            };
+
+                            templates.insert(macro_name_and_args.to_string(), primitive_defs.clone());
+                            apertures.insert(
+                                *code,
+                                LocalApertureKind::Standard(ApertureKind::Template(
+                                    primitive_defs,
+                                    PrimitiveOrigin::Macro,
+                                )),
+                            );
                         } else {
                             error!(
                                 "Aperture definition references unknown macro. macro_name: {}",
                                 macro_name
                             );
+                            warnings.push(LayerWarning::UndefinedMacro {
+                                command_index: index,
+                                macro_name: macro_name.clone(),
+                            });
                         }
                     }
+                    Aperture::Polygon(polygon) => {
+                        let key = format!(
+                            "P_{}X{}X{}",
+                            polygon.diameter,
+                            polygon.vertices,
+                            polygon.rotation.unwrap_or(0.0)
+                        );
+                        let primitives = templates
+                            .entry(key)
+                            .or_insert_with(|| vec![GerberLayer::build_polygon_aperture(polygon)]);
+                        apertures.insert(
+                            *code,
+                            LocalApertureKind::Standard(ApertureKind::Template(
+                                primitives.clone(),
+                                PrimitiveOrigin::Flash,
+                            )),
+                        );
+                    }
                     _ => {
                         apertures.insert(
                             *code,
@@ -733,6 +1654,18 @@ impl GerberLayer {
 
         // regions are a special case - they are defined by aperture codes
         let mut current_region = None;
+        let mut region_contour_count = 0;
+
+        // same order as `layer_primitives`
+        let mut primitive_apertures: Vec<Option<i32>> = Vec::new();
+        // the code of `current_aperture`
+        let mut current_aperture_code: Option<i32> = None;
+
+        // %LP, the polarity of the objects created by the following commands
+        let mut polarity = Polarity::Dark;
+        // set when replaying a block that was flashed with clear polarity, which toggles the polarity of the objects in
+        // the block.
+        let mut polarity_inverted = false;
 
         let mut index = 0;
 
@@ -740,6 +1673,10 @@ impl GerberLayer {
         let mut step_repeat_state: Option<StepRepeatState> = None;
         // not using an option here to keep the math simple
         let mut step_repeat_offset: Vector2<f64> = Vector2::new(0.0, 0.0);
+        // see `LayerConfiguration::instanced_step_repeat`
+        let mut step_repeats: Vec<StepRepeatBlock> = Vec::new();
+        // the number of primitives drawn by the repeats of `step_repeats`, other than the first
+        let mut repeated_primitives: usize = 0;
 
         #[derive(Debug, Clone)]
         struct ApertureBlockReplayState<'a> {
@@ -749,6 +1686,8 @@ impl GerberLayer {
             initial_offset: Vector2<f64>,
             initial_interpolation_mode: InterpolationMode,
             initial_quadrant_mode: QuadrantMode,
+            initial_polarity: Polarity,
+            initial_polarity_inverted: bool,
         }
 
         let mut aperture_block_replay_stack: Vec<ApertureBlockReplayState> = vec![];
@@ -769,6 +1708,8 @@ impl GerberLayer {
                     current_pos = state.initial_position;
                     interpolation_mode = state.initial_interpolation_mode;
                     quadrant_mode = state.initial_quadrant_mode;
+                    polarity = state.initial_polarity;
+                    polarity_inverted = state.initial_polarity_inverted;
 
                     // furthermore, the statement in the spec "Gerber has no stack of graphics states" is misleading,
                     // since we have to reset the current aperture and restore the offset, both of which require
@@ -777,6 +1718,7 @@ impl GerberLayer {
                     // restore the current aperture to this one, since it may be re-used by the next flash command
                     // before another Dxx code is encountered.
                     current_aperture = apertures.get(&state.block.code);
+                    current_aperture_code = Some(state.block.code);
 
                     // skip the same command, otherwise we'd repeat forever
                     index = state.initial_index + 1;
@@ -799,6 +1741,8 @@ impl GerberLayer {
             );
             let Some(cmd) = commands.get(index) else { break };
 
+            let primitive_count = layer_primitives.len();
+
             match cmd {
                 Command::ExtendedCode(ExtendedCode::ApertureBlock(ApertureBlock::Open {
                     code,
@@ -853,6 +1797,7 @@ impl GerberLayer {
                                 distance_x: *distance_x,
                                 distance_y: *distance_y,
                                 start_index: index + 1,
+                                start_primitive: layer_primitives.len(),
                                 x_index: 0,
                                 y_index: 0,
                             };
@@ -865,7 +1810,60 @@ impl GerberLayer {
                     if !aperture_block_replay_stack.is_empty() {
                         trace!("SR (close) during AB replay");
                     } else {
-                        if let Some(state) = &mut step_repeat_state {
+                        if let Some(state) = &mut step_repeat_state
+                            && configuration.instanced_step_repeat
+                        {
+                            // the block is built once, the other repeats are drawn with an offset, in the same order
+                            // as they are replayed below.
+                            let block_len = layer_primitives.len() - state.start_primitive;
+                            let requested_repeats =
+                                (state.repeat_x.max(1) as usize).saturating_mul(state.repeat_y.max(1) as usize);
+
+                            // the repeats count towards the limit, as if they were expanded, the first repeat is
+                            // already built.
+                            let repeats = match configuration.max_primitives {
+                                Some(max_primitives) if block_len > 0 => {
+                                    let available =
+                                        max_primitives.saturating_sub(layer_primitives.len() + repeated_primitives);
+                                    requested_repeats.min(1 + available / block_len)
+                                }
+                                _ => requested_repeats,
+                            };
+
+                            let offsets: Vec<Vector2<f64>> = (0..state.repeat_x.max(1))
+                                .flat_map(|x_index| (0..state.repeat_y.max(1)).map(move |y_index| (x_index, y_index)))
+                                .take(repeats)
+                                .map(|(x_index, y_index)| {
+                                    Vector2::new(state.distance_x * x_index as f64, state.distance_y * y_index as f64)
+                                })
+                                .collect();
+                            trace!("Step-and-repeat close, instanced, repeats: {}", offsets.len());
+
+                            if offsets.len() > 1 && block_len > 0 {
+                                repeated_primitives += block_len * (offsets.len() - 1);
+                                step_repeats.push(StepRepeatBlock {
+                                    primitives: state.start_primitive..layer_primitives.len(),
+                                    offsets,
+                                });
+                            }
+
+                            current_pos = state.initial_position;
+                            step_repeat_state = None;
+
+                            if repeats < requested_repeats
+                                && let Some(max_primitives) = configuration.max_primitives
+                            {
+                                warn!(
+                                    "Primitive limit reached, the layer is incomplete. limit: {}, repeats: {}/{}",
+                                    max_primitives, repeats, requested_repeats
+                                );
+                                warnings.push(LayerWarning::PrimitiveLimitReached {
+                                    command_index: index,
+                                    limit: max_primitives,
+                                });
+                                break;
+                            }
+                        } else if let Some(state) = &mut step_repeat_state {
                             let mut complete = false;
                             state.y_index += 1;
                             if state.y_index >= state.repeat_y {
@@ -907,20 +1905,31 @@ impl GerberLayer {
                         }
                     }
                 }
+                Command::ExtendedCode(ExtendedCode::LoadPolarity(load_polarity)) => {
+                    polarity = *load_polarity;
+                }
                 Command::FunctionCode(FunctionCode::GCode(GCode::InterpolationMode(mode))) => {
                     interpolation_mode = *mode;
                 }
                 Command::FunctionCode(FunctionCode::GCode(GCode::QuadrantMode(mode))) => {
                     quadrant_mode = *mode;
                 }
+                Command::FunctionCode(FunctionCode::GCode(GCode::SelectAperture)) => {
+                    // G54, the aperture selection that follows it is handled as usual
+                    warnings.push(LayerWarning::DeprecatedCode {
+                        command_index: index,
+                        code: "G54",
+                    });
+                }
                 Command::FunctionCode(FunctionCode::GCode(GCode::RegionMode(enabled))) => {
                     if *enabled {
                         // G36 - Begin Region
-                        current_region = Some(Region::new(index));
+                        current_region = Some(Region::new(index, region_contour_count));
+                        region_contour_count += 1;
                     } else {
                         // G37 - End Region
                         if let Some(region) = current_region.take() {
-                            if let Ok(primitive) = region.finalize(index) {
+                            if let Ok(primitive) = region.finalize(index, configuration, warnings) {
                                 layer_primitives.push(primitive);
                             }
                         }
@@ -929,8 +1938,13 @@ impl GerberLayer {
 
                 Command::FunctionCode(FunctionCode::DCode(DCode::SelectAperture(code))) => {
                     current_aperture = apertures.get(&code);
+                    current_aperture_code = Some(*code);
                     if current_aperture.is_none() {
                         aperture_selection_errors.insert(*code);
+                        warnings.push(LayerWarning::UndefinedAperture {
+                            command_index: index,
+                            code: *code,
+                        });
                     }
                 }
                 Command::FunctionCode(FunctionCode::DCode(DCode::Operation(operation))) => {
@@ -944,11 +1958,12 @@ impl GerberLayer {
                                 let mut region = current_region.take().unwrap();
 
                                 if !region.is_empty() {
-                                    if let Ok(primitive) = region.finalize(index) {
+                                    if let Ok(primitive) = region.finalize(index, configuration, warnings) {
                                         layer_primitives.push(primitive);
                                     }
 
-                                    region = Region::new(index);
+                                    region = Region::new(index, region_contour_count);
+                                    region_contour_count += 1;
                                 }
                                 region.push(end);
 
@@ -960,8 +1975,45 @@ impl GerberLayer {
                             let mut end = current_pos;
                             Self::update_position(&mut end, coords, step_repeat_offset + aperture_block_offset);
                             if let Some(region) = &mut current_region {
-                                // Add vertex to the current region
-                                region.push(end);
+                                match (interpolation_mode, offset) {
+                                    (
+                                        InterpolationMode::ClockwiseCircular
+                                        | InterpolationMode::CounterclockwiseCircular,
+                                        Some(offset),
+                                    ) => {
+                                        // the arc bounds the filled region, so follow it instead of adding a chord
+                                        let offset = Vector2::new(
+                                            offset
+                                                .x
+                                                .map(|x| x.into())
+                                                .unwrap_or(0.0),
+                                            offset
+                                                .y
+                                                .map(|y| y.into())
+                                                .unwrap_or(0.0),
+                                        );
+                                        let arc = ArcGerberPrimitive::from_interpolation(
+                                            current_pos,
+                                            end,
+                                            offset,
+                                            0.0,
+                                            interpolation_mode,
+                                            quadrant_mode,
+                                        );
+                                        let points = arc.generate_points();
+
+                                        // the first point is the current position, and the last point is `end`,
+                                        // which is added exactly so that the contour closes.
+                                        for point in &points[1..points.len() - 1] {
+                                            region.push(arc.center + point.coords);
+                                        }
+                                        region.push(end);
+                                    }
+                                    _ => {
+                                        // Add vertex to the current region
+                                        region.push(end);
+                                    }
+                                }
                             } else {
                                 match current_aperture {
                                     // 2024.05 - 2.3 "Graphical objects"
@@ -981,6 +2033,7 @@ impl GerberLayer {
                                                     end,
                                                     width: stroke_width,
                                                     exposure: Exposure::Add,
+                                                    primitive_origin: PENDING_ORIGIN,
                                                 }));
                                             }
                                             InterpolationMode::ClockwiseCircular
@@ -997,60 +2050,15 @@ impl GerberLayer {
                                                         .map(|y| y.into())
                                                         .unwrap_or(0.0);
 
-                                                    // Calculate center of the arc
-                                                    let center_x = current_pos.x + offset_i;
-                                                    let center_y = current_pos.y + offset_j;
-                                                    let center = Point2::new(center_x, center_y);
-
-                                                    // Calculate radius (distance from current position to center)
-                                                    let radius = ((offset_i * offset_i) + (offset_j * offset_j)).sqrt();
-
-                                                    // Calculate start angle (from center to current position)
-                                                    let start_angle =
-                                                        (current_pos.y - center.y).atan2(current_pos.x - center.x);
-
-                                                    // Calculate end angle (from center to target position)
-                                                    let end_angle = (end.y - center.y).atan2(end.x - center.x);
-
-                                                    // Calculate sweep angle based on interpolation mode
-                                                    let mut sweep_angle = match interpolation_mode {
-                                                        InterpolationMode::ClockwiseCircular => {
-                                                            if end_angle > start_angle {
-                                                                end_angle - start_angle - 2.0 * std::f64::consts::PI
-                                                            } else {
-                                                                end_angle - start_angle
-                                                            }
-                                                        }
-                                                        InterpolationMode::CounterclockwiseCircular => {
-                                                            if end_angle < start_angle {
-                                                                end_angle - start_angle + 2.0 * std::f64::consts::PI
-                                                            } else {
-                                                                end_angle - start_angle
-                                                            }
-                                                        }
-                                                        _ => 0.0, // Should never happen
-                                                    };
-
-                                                    // Adjust for single/multi quadrant mode
-                                                    if let QuadrantMode::Single = quadrant_mode {
-                                                        // In single quadrant mode, sweep angle is always <= 90°
-                                                        if sweep_angle.abs() > std::f64::consts::PI / 2.0 {
-                                                            if sweep_angle > 0.0 {
-                                                                sweep_angle = std::f64::consts::PI / 2.0;
-                                                            } else {
-                                                                sweep_angle = -std::f64::consts::PI / 2.0;
-                                                            }
-                                                        }
-                                                    }
-
-                                                    let arc_primitive = ArcGerberPrimitive {
-                                                        center,
-                                                        radius,
-                                                        width: stroke_width,
-                                                        start_angle,
-                                                        sweep_angle,
-                                                        exposure: Exposure::Add,
-                                                    };
+                                                    let arc_primitive = ArcGerberPrimitive::from_interpolation(
+                                                        current_pos,
+                                                        end,
+                                                        Vector2::new(offset_i, offset_j),
+                                                        stroke_width,
+                                                        interpolation_mode,
+                                                        quadrant_mode,
+                                                    );
+                                                    let center = arc_primitive.center;
 
                                                     if arc_primitive.is_full_circle() {
                                                         // add the arc primitive
@@ -1064,7 +2072,9 @@ impl GerberLayer {
                                                             CircleGerberPrimitive {
                                                                 center: start_point + center.to_vector(),
                                                                 diameter: stroke_width,
+                                                                hole_diameter: None,
                                                                 exposure: Exposure::Add,
+                                                                primitive_origin: PENDING_ORIGIN,
                                                             },
                                                         ));
 
@@ -1076,7 +2086,9 @@ impl GerberLayer {
                                                             CircleGerberPrimitive {
                                                                 center: end_point + center.to_vector(),
                                                                 diameter: stroke_width,
+                                                                hole_diameter: None,
                                                                 exposure: Exposure::Add,
+                                                                primitive_origin: PENDING_ORIGIN,
                                                             },
                                                         ));
                                                     }
@@ -1086,6 +2098,9 @@ impl GerberLayer {
                                     }
                                     Some(aperture) => {
                                         warn!("Unsupported aperture for plotting. aperture: {:?}", aperture);
+                                        warnings.push(LayerWarning::UnsupportedDrawAperture {
+                                            command_index: index,
+                                        });
                                     }
                                     None => {
                                         error!("No aperture selected for plotting");
@@ -1097,6 +2112,9 @@ impl GerberLayer {
                         Operation::Flash(coords, ..) => {
                             if current_region.is_some() {
                                 warn!("Flash operation found within region - ignoring");
+                                warnings.push(LayerWarning::FlashInRegion {
+                                    command_index: index,
+                                });
                             } else {
                                 Self::update_position(
                                     &mut current_pos,
@@ -1106,7 +2124,7 @@ impl GerberLayer {
 
                                 if let Some(aperture) = current_aperture {
                                     match aperture {
-                                        LocalApertureKind::Standard(ApertureKind::Macro(macro_primitives)) => {
+                                        LocalApertureKind::Standard(ApertureKind::Template(macro_primitives, _)) => {
                                             for primitive in macro_primitives {
                                                 let mut primitive = primitive.clone();
                                                 // Update the primitive's position based on flash coordinates
@@ -1130,7 +2148,12 @@ impl GerberLayer {
                                                     GerberPrimitive::Rectangle(RectangleGerberPrimitive {
                                                         origin,
                                                         ..
-                                                    }) => {
+                                                    })
+                                                    | GerberPrimitive::RoundedRectangle(
+                                                        RoundedRectangleGerberPrimitive {
+                                                            origin, ..
+                                                        },
+                                                    ) => {
                                                         *origin += Vector2::new(current_pos.x, current_pos.y);
                                                     }
                                                     GerberPrimitive::Line(LineGerberPrimitive {
@@ -1148,37 +2171,26 @@ impl GerberLayer {
                                         }
                                         LocalApertureKind::Standard(ApertureKind::Standard(aperture)) => {
                                             match aperture {
+                                                Aperture::Circle(circle) if is_degenerate_aperture(aperture) => {
+                                                    trace!("flashing degenerate aperture: {:?}", circle);
+                                                    if configuration.degenerate_apertures == DegenerateApertures::Dot {
+                                                        layer_primitives.push(GerberPrimitive::dot(current_pos));
+                                                    }
+                                                }
                                                 Aperture::Circle(Circle {
                                                     diameter,
                                                     hole_diameter,
                                                 }) => {
-                                                    let primitive = if let Some(hole_diameter) = hole_diameter {
-                                                        let outer_radius = diameter / 2.0;
-                                                        let inner_radius = hole_diameter / 2.0;
-
-                                                        // Mid radius should be the center of where we want our stroke
-                                                        let mid_radius = (outer_radius + inner_radius) / 2.0;
-
-                                                        // For StrokeKind::Middle, width should be exactly (outer_radius - inner_radius)
-                                                        let width = outer_radius - inner_radius;
-
-                                                        GerberPrimitive::Arc(ArcGerberPrimitive {
-                                                            center: current_pos,
-                                                            radius: mid_radius,
-                                                            width,
-                                                            start_angle: 0.0,
-                                                            sweep_angle: 2.0 * std::f64::consts::PI, // Full circle, clockwise
-                                                            exposure: Exposure::Add,
-                                                        })
-                                                    } else {
-                                                        GerberPrimitive::Circle(CircleGerberPrimitive {
+                                                    // a circle with a hole is an annulus (ring), e.g. a through-hole pad
+                                                    layer_primitives.push(GerberPrimitive::Circle(
+                                                        CircleGerberPrimitive {
                                                             center: current_pos,
                                                             diameter: *diameter,
+                                                            hole_diameter: *hole_diameter,
                                                             exposure: Exposure::Add,
-                                                        })
-                                                    };
-
-                                                    layer_primitives.push(primitive);
+                                                            primitive_origin: PENDING_ORIGIN,
+                                                        },
+                                                    ));
                                                 }
 
                                                 Aperture::Rectangle(rect) => {
@@ -1191,44 +2203,13 @@ impl GerberLayer {
                                                             width: rect.x,
                                                             height: rect.y,
                                                             exposure: Exposure::Add,
+                                                            primitive_origin: PENDING_ORIGIN,
                                                         },
                                                     ));
                                                 }
-                                                Aperture::Polygon(polygon) => {
-                                                    let radius = polygon.diameter / 2.0;
-                                                    let vertices_count = polygon.vertices as usize;
-                                                    let mut vertices = Vec::with_capacity(vertices_count);
-
-                                                    // For standard aperture polygon, we need to generate vertices
-                                                    // starting at angle 0 and moving counterclockwise
-                                                    for i in 0..vertices_count {
-                                                        let angle = (2.0 * std::f64::consts::PI * i as f64)
-                                                            / vertices_count as f64;
-                                                        let x = radius * angle.cos();
-                                                        let y = radius * angle.sin();
-
-                                                        // Apply rotation if specified
-                                                        let final_position = if let Some(rotation) = polygon.rotation {
-                                                            let rot_rad = rotation * std::f64::consts::PI / 180.0;
-                                                            let (sin_rot, cos_rot) = rot_rad.sin_cos();
-                                                            Point2::new(
-                                                                x * cos_rot - y * sin_rot,
-                                                                x * sin_rot + y * cos_rot,
-                                                            )
-                                                        } else {
-                                                            Point2::new(x, y)
-                                                        };
-
-                                                        vertices.push(final_position);
-                                                    }
-
-                                                    layer_primitives.push(GerberPrimitive::new_polygon(
-                                                        GerberPolygon {
-                                                            center: current_pos,
-                                                            vertices,
-                                                            exposure: Exposure::Add,
-                                                        },
-                                                    ));
+                                                Aperture::Polygon(_) => {
+                                                    // built when the aperture is defined, see `ApertureKind::Template`
+                                                    warn!("Unexpected polygon aperture: {:?}", aperture);
                                                 }
                                                 Aperture::Obround(rect) => {
                                                     // For an obround, we need to:
@@ -1264,6 +2245,7 @@ impl GerberLayer {
                                                             width: rect_width,
                                                             height: rect_height,
                                                             exposure: Exposure::Add,
+                                                            primitive_origin: PENDING_ORIGIN,
                                                         },
                                                     ));
 
@@ -1274,13 +2256,15 @@ impl GerberLayer {
                                                             CircleGerberPrimitive {
                                                                 center: current_pos + Vector2::new(dx, dy),
                                                                 diameter: circle_radius * 2.0,
+                                                                hole_diameter: None,
                                                                 exposure: Exposure::Add,
+                                                                primitive_origin: PENDING_ORIGIN,
                                                             },
                                                         ));
                                                     }
                                                 }
                                                 Aperture::Macro(code, _args) => {
-                                                    // if the aperture referred to a macro, and the macro was supported, it will have been handled by the `ApertureKind::Macro` handling.
+                                                    // if the aperture referred to a macro, and the macro was supported, it will have been handled by the `ApertureKind::Template` handling.
                                                     warn!("Unsupported macro aperture: {:?}, code: {}", aperture, code);
                                                 }
                                             }
@@ -1295,9 +2279,16 @@ impl GerberLayer {
                                                 initial_offset: aperture_block_offset,
                                                 initial_interpolation_mode: interpolation_mode,
                                                 initial_quadrant_mode: quadrant_mode,
+                                                initial_polarity: polarity,
+                                                initial_polarity_inverted: polarity_inverted,
                                             };
                                             aperture_block_replay_stack.push(state);
 
+                                            // the objects in the block have their own polarity, which is toggled when
+                                            // the block is flashed with clear polarity.
+                                            polarity_inverted ^= polarity == Polarity::Clear;
+                                            polarity = Polarity::Dark;
+
                                             aperture_block_offset = current_pos.to_vector();
                                             index = block.range.start;
                                             continue;
@@ -1311,6 +2302,57 @@ impl GerberLayer {
                 _ => {}
             }
 
+            // all the primitives of a clear object cut out material, including the ones from macro primitives with
+            // their exposure on.
+            if (polarity == Polarity::Clear) != polarity_inverted {
+                for primitive in &mut layer_primitives[primitive_count..] {
+                    primitive.set_exposure(Exposure::CutOut);
+                }
+            }
+
+            // the only place the origin of the primitives is decided, they are built with `PENDING_ORIGIN`
+            let origin = match cmd {
+                _ if !aperture_block_replay_stack.is_empty() => PrimitiveOrigin::Block,
+                Command::FunctionCode(FunctionCode::DCode(DCode::Operation(Operation::Interpolate(..)))) => {
+                    PrimitiveOrigin::Draw
+                }
+                Command::FunctionCode(FunctionCode::DCode(DCode::Operation(Operation::Flash(..)))) => {
+                    match current_aperture {
+                        Some(LocalApertureKind::Standard(ApertureKind::Template(_, origin))) => *origin,
+                        _ => PrimitiveOrigin::Flash,
+                    }
+                }
+                // the end of a region (G37), or the start of the next contour (D02)
+                _ => PrimitiveOrigin::Region,
+            };
+            for primitive in &mut layer_primitives[primitive_count..] {
+                primitive.set_primitive_origin(origin);
+            }
+
+            let aperture = match (aperture_block_replay_stack.first(), origin) {
+                (Some(state), _) => Some(state.block.code),
+                (None, PrimitiveOrigin::Region) => None,
+                (None, _) => current_aperture_code,
+            };
+            primitive_apertures.resize(layer_primitives.len(), aperture);
+
+            if let Some(max_primitives) = configuration.max_primitives
+                && layer_primitives.len() + repeated_primitives > max_primitives
+            {
+                warn!(
+                    "Primitive limit reached, the layer is incomplete. limit: {}, index: {}",
+                    max_primitives, index
+                );
+                warnings.push(LayerWarning::PrimitiveLimitReached {
+                    command_index: index,
+                    limit: max_primitives,
+                });
+                // the repeats never exceed the limit, see `StepAndRepeat::Close`
+                layer_primitives.truncate(max_primitives - repeated_primitives);
+                primitive_apertures.truncate(max_primitives - repeated_primitives);
+                break;
+            }
+
             index += 1;
         }
 
@@ -1324,17 +2366,19 @@ impl GerberLayer {
         info!("layer_primitives: {:?}", layer_primitives.len());
         trace!("layer_primitives: {:?}", layer_primitives);
 
-        layer_primitives
+        (layer_primitives, primitive_apertures, step_repeats)
     }
 }
 
 enum RegionError {
     InsufficientVertices,
+    Unclosed,
 }
 
 struct Region {
     vertices: Vec<Point2<f64>>,
     start_index: usize,
+    contour: usize,
 }
 
 impl Region {
@@ -1344,10 +2388,11 @@ impl Region {
 }
 
 impl Region {
-    fn new(start_index: usize) -> Self {
+    fn new(start_index: usize, contour: usize) -> Self {
         Self {
             vertices: Vec::new(),
             start_index,
+            contour,
         }
     }
 
@@ -1355,16 +2400,34 @@ impl Region {
         self.vertices.push(point);
     }
 
-    fn finalize(mut self, end_index: usize) -> Result<GerberPrimitive, RegionError> {
+    fn finalize(
+        mut self,
+        end_index: usize,
+        configuration: &LayerConfiguration,
+        warnings: &mut Vec<LayerWarning>,
+    ) -> Result<GerberPrimitive, RegionError> {
+        const CLOSURE_EPSILON: f64 = 1e-6;
+
         // SPEC-ISSUE: closed-vs-unclosed-regions - EasyEDA v6.5.48 does not close regions properly
+        let mut is_unclosed = false;
         if self.vertices.len() >= 2 {
             let first = self.vertices.first().unwrap();
             let last = self.vertices.last().unwrap();
-            if first != last {
+            if (first.x - last.x).abs() > CLOSURE_EPSILON || (first.y - last.y).abs() > CLOSURE_EPSILON {
                 warn!(
-                    "Unclosed region detected. start_index: {}, end_index: {}, first: {}, last: {}",
-                    self.start_index, end_index, first, last
+                    "Unclosed region detected. start_index: {}, end_index: {}, first: {}, last: {}, auto_close: {}",
+                    self.start_index, end_index, first, last, configuration.auto_close_regions
                 );
+                if !configuration.auto_close_regions {
+                    warnings.push(LayerWarning::UnclosedRegion {
+                        contour: self.contour,
+                        command_index: self.start_index,
+                        auto_closed: false,
+                    });
+                    return Err(RegionError::Unclosed);
+                }
+                // the closing segment, from the last vertex back to the first, is implied by the polygon.
+                is_unclosed = true;
             } else {
                 // `GerberPolygon` expects an un-closed polygon vertices, so REMOVE the last coordinate from the vertices
                 self.vertices.pop();
@@ -1374,9 +2437,37 @@ impl Region {
         trace!("current_region_vertices: {:?}", self.vertices);
 
         if self.vertices.len() < 3 {
+            warn!(
+                "Region with less than 3 vertices. start_index: {}, end_index: {}",
+                self.start_index, end_index
+            );
+            warnings.push(LayerWarning::DegenerateRegion {
+                contour: self.contour,
+                command_index: self.start_index,
+            });
             return Err(RegionError::InsufficientVertices);
         }
 
+        if is_unclosed {
+            warnings.push(LayerWarning::UnclosedRegion {
+                contour: self.contour,
+                command_index: self.start_index,
+                auto_closed: true,
+            });
+        }
+
+        if let Some(edges) = geometry::find_self_intersection(&self.vertices) {
+            warn!(
+                "Self-intersecting region detected. start_index: {}, end_index: {}, edges: {:?}",
+                self.start_index, end_index, edges
+            );
+            warnings.push(LayerWarning::SelfIntersectingRegion {
+                contour: self.contour,
+                command_index: self.start_index,
+                edges,
+            });
+        }
+
         // Find bounding box
         let min_x = self
             .vertices
@@ -1416,6 +2507,7 @@ impl Region {
             center: Point2::new(center_x, center_y),
             vertices: relative_vertices,
             exposure: Exposure::Add,
+            primitive_origin: PENDING_ORIGIN,
         });
 
         Ok(polygon)
@@ -1425,59 +2517,694 @@ impl Region {
 #[derive(Debug)]
 enum ApertureKind {
     Standard(Aperture),
-    Macro(Vec<GerberPrimitive>),
+    /// Primitives relative to the flash position, built once when the aperture is defined and cloned for each flash.
+    ///
+    /// The geometry of polygons is shared by the clones, which saves memory and tessellation time when the same pad
+    /// is flashed many times.  The origin is the origin of the flashed primitives, i.e. whether they are from a macro.
+    Template(Vec<GerberPrimitive>, PrimitiveOrigin),
+}
+
+/// How a primitive was created, see [`GerberPrimitive::primitive_origin`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PrimitiveOrigin {
+    /// Flash (D03) of a standard aperture.
+    Flash,
+    /// Draw (D01), a line or arc, including the circles at the ends of an arc.
+    Draw,
+    /// Region (G36/G37).
+    Region,
+    /// Flash (D03) of a macro aperture.
+    Macro,
+    /// Any object of a block aperture (`%AB`), when the block is flashed.
+    Block,
+    /// Added with [`GerberLayer::push_primitive`], not by a gerber command.
+    Programmatic,
 }
 
+/// The origin of the primitives built from the commands, `GerberLayer::build_primitives` replaces it once it knows the
+/// command that created them, e.g. a flash of a macro aperture inside a block aperture is a `Block`.
+const PENDING_ORIGIN: PrimitiveOrigin = PrimitiveOrigin::Programmatic;
+
 #[derive(Debug, Clone)]
-pub(crate) enum GerberPrimitive {
+pub enum GerberPrimitive {
     Circle(CircleGerberPrimitive),
     Rectangle(RectangleGerberPrimitive),
+    RoundedRectangle(RoundedRectangleGerberPrimitive),
     Line(LineGerberPrimitive),
     Arc(ArcGerberPrimitive),
     Polygon(PolygonGerberPrimitive),
 }
 
+impl GerberPrimitive {
+    /// A zero-diameter circle, for flashes of degenerate apertures, see [`DegenerateApertures::Dot`].
+    fn dot(center: Point2<f64>) -> Self {
+        GerberPrimitive::Circle(CircleGerberPrimitive {
+            center,
+            diameter: 0.0,
+            hole_diameter: None,
+            exposure: Exposure::Add,
+            primitive_origin: PENDING_ORIGIN,
+        })
+    }
+
+    /// Compares the geometry of two primitives, e.g. to check that a round-trip (bake, export and re-import) preserves
+    /// the geometry, this is the comparison used by [`GerberLayer::diff_with_tolerance`].
+    ///
+    /// Each value is compared on its own, the difference must be at most `tolerance`, a `tolerance` of `0.0` is exact:
+    /// - circles: the center (x and y), the diameter and the hole diameter, which must be present in both or neither.
+    /// - rectangles: the origin, the width and the height, and the corner radius of rounded rectangles.
+    /// - lines: the start, the end and the width, a line is not equal to the same line drawn in the other direction.
+    /// - arcs: the center, the radius and the width, and the start and sweep angles, in radians, with the same
+    ///   `tolerance`.  Start angles are compared modulo a full turn, e.g. `PI` and `-PI` are equal, and the angles of
+    ///   full circles are not compared, see [`ArcGerberPrimitive::is_full_circle`].
+    /// - polygons: the center and each vertex, relative to the center, the vertices must be in the same order,
+    ///   starting with the same vertex.
+    ///
+    /// Positions and sizes are in gerber units.  Primitives of different kinds, or with a different exposure, are
+    /// never equal.
+    pub fn approx_eq(&self, other: &GerberPrimitive, tolerance: f64) -> bool {
+        use std::f64::consts::{PI, TAU};
+
+        let eq = |a: f64, b: f64| (a - b).abs() <= tolerance;
+        let point_eq = |a: &Point2<f64>, b: &Point2<f64>| eq(a.x, b.x) && eq(a.y, b.y);
+        // the difference, wrapped to [-PI, PI)
+        let angle_eq = |a: f64, b: f64| eq((a - b + PI).rem_euclid(TAU) - PI, 0.0);
+
+        match (self, other) {
+            (GerberPrimitive::Circle(a), GerberPrimitive::Circle(b)) => {
+                a.exposure == b.exposure
+                    && point_eq(&a.center, &b.center)
+                    && eq(a.diameter, b.diameter)
+                    && match (a.hole_diameter, b.hole_diameter) {
+                        (Some(a), Some(b)) => eq(a, b),
+                        (None, None) => true,
+                        _ => false,
+                    }
+            }
+            (GerberPrimitive::Rectangle(a), GerberPrimitive::Rectangle(b)) => {
+                a.exposure == b.exposure
+                    && point_eq(&a.origin, &b.origin)
+                    && eq(a.width, b.width)
+                    && eq(a.height, b.height)
+            }
+            (GerberPrimitive::RoundedRectangle(a), GerberPrimitive::RoundedRectangle(b)) => {
+                a.exposure == b.exposure
+                    && point_eq(&a.origin, &b.origin)
+                    && eq(a.width, b.width)
+                    && eq(a.height, b.height)
+                    && eq(a.corner_radius, b.corner_radius)
+            }
+            (GerberPrimitive::Line(a), GerberPrimitive::Line(b)) => {
+                a.exposure == b.exposure
+                    && point_eq(&a.start, &b.start)
+                    && point_eq(&a.end, &b.end)
+                    && eq(a.width, b.width)
+            }
+            (GerberPrimitive::Arc(a), GerberPrimitive::Arc(b)) => {
+                a.exposure == b.exposure
+                    && point_eq(&a.center, &b.center)
+                    && eq(a.radius, b.radius)
+                    && eq(a.width, b.width)
+                    && match (a.is_full_circle(), b.is_full_circle()) {
+                        (true, true) => true,
+                        (false, false) => angle_eq(a.start_angle, b.start_angle) && eq(a.sweep_angle, b.sweep_angle),
+                        _ => false,
+                    }
+            }
+            (GerberPrimitive::Polygon(a), GerberPrimitive::Polygon(b)) => {
+                a.exposure == b.exposure
+                    && point_eq(&a.center, &b.center)
+                    && a.geometry.relative_vertices.len() == b.geometry.relative_vertices.len()
+                    && a.geometry
+                        .relative_vertices
+                        .iter()
+                        .zip(b.geometry.relative_vertices.iter())
+                        .all(|(a, b)| point_eq(a, b))
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns a copy of the primitive with the transformation matrix applied to its geometry.
+    ///
+    /// See [`GerberLayer::baked`].
+    pub(crate) fn transformed(&self, matrix: &Matrix3<f64>) -> GerberPrimitive {
+        let linear = |v: Vector2<f64>| {
+            Vector2::new(
+                matrix[(0, 0)] * v.x + matrix[(0, 1)] * v.y,
+                matrix[(1, 0)] * v.x + matrix[(1, 1)] * v.y,
+            )
+        };
+        let determinant = matrix[(0, 0)] * matrix[(1, 1)] - matrix[(0, 1)] * matrix[(1, 0)];
+        let scale = determinant.abs().sqrt();
+
+        // `GerberTransform::rotation` is an f32, so 90 degrees is not exact
+        const AXIS_ALIGNED_EPSILON: f64 = 1e-6;
+
+        let is_axis_aligned = (matrix[(0, 1)].abs() < AXIS_ALIGNED_EPSILON
+            && matrix[(1, 0)].abs() < AXIS_ALIGNED_EPSILON)
+            || (matrix[(0, 0)].abs() < AXIS_ALIGNED_EPSILON && matrix[(1, 1)].abs() < AXIS_ALIGNED_EPSILON);
+
+        match self {
+            GerberPrimitive::Circle(circle) => GerberPrimitive::Circle(CircleGerberPrimitive {
+                center: matrix.transform_point2(circle.center),
+                diameter: circle.diameter * scale,
+                hole_diameter: circle
+                    .hole_diameter
+                    .map(|hole_diameter| hole_diameter * scale),
+                exposure: circle.exposure,
+                primitive_origin: circle.primitive_origin,
+            }),
+            GerberPrimitive::Line(line) => GerberPrimitive::Line(LineGerberPrimitive {
+                start: matrix.transform_point2(line.start),
+                end: matrix.transform_point2(line.end),
+                width: line.width * scale,
+                exposure: line.exposure,
+                primitive_origin: line.primitive_origin,
+            }),
+            GerberPrimitive::Arc(arc) => {
+                let center = matrix.transform_point2(arc.center);
+                let start = arc.center + Vector2::new(arc.start_angle.cos(), arc.start_angle.sin()) * arc.radius;
+                let start = matrix.transform_point2(start) - center;
+
+                GerberPrimitive::Arc(ArcGerberPrimitive {
+                    center,
+                    radius: arc.radius * scale,
+                    width: arc.width * scale,
+                    start_angle: start.y.atan2(start.x),
+                    sweep_angle: arc.sweep_angle * determinant.signum(),
+                    exposure: arc.exposure,
+                    primitive_origin: arc.primitive_origin,
+                })
+            }
+            GerberPrimitive::Rectangle(rect) => {
+                let corners = [
+                    rect.origin,
+                    rect.origin + Vector2::new(rect.width, 0.0),
+                    rect.origin + Vector2::new(rect.width, rect.height),
+                    rect.origin + Vector2::new(0.0, rect.height),
+                ]
+                .map(|corner| matrix.transform_point2(corner));
+
+                if is_axis_aligned {
+                    let bbox = BoundingBox::from_points(&corners);
+                    GerberPrimitive::Rectangle(RectangleGerberPrimitive {
+                        origin: bbox.min,
+                        width: bbox.width(),
+                        height: bbox.height(),
+                        exposure: rect.exposure,
+                        primitive_origin: rect.primitive_origin,
+                    })
+                } else {
+                    let center = matrix.transform_point2(rect.origin + Vector2::new(rect.width, rect.height) / 2.0);
+                    GerberPrimitive::new_polygon(GerberPolygon {
+                        center,
+                        vertices: corners
+                            .iter()
+                            .map(|corner| Point2::from(corner - center))
+                            .collect(),
+                        exposure: rect.exposure,
+                        primitive_origin: rect.primitive_origin,
+                    })
+                }
+            }
+            GerberPrimitive::RoundedRectangle(rect) => {
+                if is_axis_aligned {
+                    let corners = [rect.origin, rect.origin + Vector2::new(rect.width, rect.height)]
+                        .map(|corner| matrix.transform_point2(corner));
+                    let bbox = BoundingBox::from_points(&corners);
+                    GerberPrimitive::RoundedRectangle(RoundedRectangleGerberPrimitive {
+                        origin: bbox.min,
+                        width: bbox.width(),
+                        height: bbox.height(),
+                        corner_radius: rect.corner_radius * scale,
+                        exposure: rect.exposure,
+                        primitive_origin: rect.primitive_origin,
+                    })
+                } else {
+                    let center = matrix.transform_point2(rect.origin + Vector2::new(rect.width, rect.height) / 2.0);
+                    GerberPrimitive::new_polygon(GerberPolygon {
+                        center,
+                        vertices: rect
+                            .outline(RoundedRectangleGerberPrimitive::DEFAULT_CORNER_STEPS)
+                            .into_iter()
+                            .map(|point| Point2::from(matrix.transform_point2(point) - center))
+                            .collect(),
+                        exposure: rect.exposure,
+                        primitive_origin: rect.primitive_origin,
+                    })
+                }
+            }
+            GerberPrimitive::Polygon(polygon) => GerberPrimitive::new_polygon(GerberPolygon {
+                center: matrix.transform_point2(polygon.center),
+                vertices: polygon
+                    .geometry
+                    .relative_vertices
+                    .iter()
+                    .map(|vertex| Point2::from(linear(vertex.coords)))
+                    .collect(),
+                exposure: polygon.exposure,
+                primitive_origin: polygon.primitive_origin,
+            }),
+        }
+    }
+
+    pub fn exposure(&self) -> Exposure {
+        match self {
+            GerberPrimitive::Circle(primitive) => primitive.exposure,
+            GerberPrimitive::Arc(primitive) => primitive.exposure,
+            GerberPrimitive::Rectangle(primitive) => primitive.exposure,
+            GerberPrimitive::RoundedRectangle(primitive) => primitive.exposure,
+            GerberPrimitive::Line(primitive) => primitive.exposure,
+            GerberPrimitive::Polygon(primitive) => primitive.exposure,
+        }
+    }
+
+    pub(crate) fn set_exposure(&mut self, exposure: Exposure) {
+        match self {
+            GerberPrimitive::Circle(primitive) => primitive.exposure = exposure,
+            GerberPrimitive::Arc(primitive) => primitive.exposure = exposure,
+            GerberPrimitive::Rectangle(primitive) => primitive.exposure = exposure,
+            GerberPrimitive::RoundedRectangle(primitive) => primitive.exposure = exposure,
+            GerberPrimitive::Line(primitive) => primitive.exposure = exposure,
+            GerberPrimitive::Polygon(primitive) => primitive.exposure = exposure,
+        }
+    }
+
+    /// How the primitive was created, e.g. a flash (D03) or a draw (D01), for filtering, e.g. to show only the flashes.
+    pub fn primitive_origin(&self) -> PrimitiveOrigin {
+        match self {
+            GerberPrimitive::Circle(primitive) => primitive.primitive_origin,
+            GerberPrimitive::Arc(primitive) => primitive.primitive_origin,
+            GerberPrimitive::Rectangle(primitive) => primitive.primitive_origin,
+            GerberPrimitive::RoundedRectangle(primitive) => primitive.primitive_origin,
+            GerberPrimitive::Line(primitive) => primitive.primitive_origin,
+            GerberPrimitive::Polygon(primitive) => primitive.primitive_origin,
+        }
+    }
+
+    pub(crate) fn set_primitive_origin(&mut self, primitive_origin: PrimitiveOrigin) {
+        match self {
+            GerberPrimitive::Circle(primitive) => primitive.primitive_origin = primitive_origin,
+            GerberPrimitive::Arc(primitive) => primitive.primitive_origin = primitive_origin,
+            GerberPrimitive::Rectangle(primitive) => primitive.primitive_origin = primitive_origin,
+            GerberPrimitive::RoundedRectangle(primitive) => primitive.primitive_origin = primitive_origin,
+            GerberPrimitive::Line(primitive) => primitive.primitive_origin = primitive_origin,
+            GerberPrimitive::Polygon(primitive) => primitive.primitive_origin = primitive_origin,
+        }
+    }
+
+    pub fn bounding_box(&self) -> BoundingBox {
+        match self {
+            GerberPrimitive::Circle(primitive) => primitive.bounding_box(),
+            GerberPrimitive::Arc(primitive) => primitive.bounding_box(),
+            GerberPrimitive::Rectangle(primitive) => primitive.bounding_box(),
+            GerberPrimitive::RoundedRectangle(primitive) => primitive.bounding_box(),
+            GerberPrimitive::Line(primitive) => primitive.bounding_box(),
+            GerberPrimitive::Polygon(primitive) => primitive.bounding_box(),
+        }
+    }
+
+    /// The closed outlines of the area covered by a primitive, in gerber coordinates, e.g. two circles for a circle
+    /// with a hole.  `steps` is the number of points used for each arc.
+    pub(crate) fn outlines(&self, steps: usize) -> Vec<Vec<Point2<f64>>> {
+        use std::f64::consts::{FRAC_PI_2, PI, TAU};
+
+        let steps = steps.max(2);
+        // points on an arc around `center`, including both ends
+        let arc = |center: Point2<f64>, radius: f64, start: f64, sweep: f64| {
+            (0..=steps)
+                .map(|step| {
+                    let angle = start + sweep * step as f64 / steps as f64;
+                    center + Vector2::new(angle.cos(), angle.sin()) * radius
+                })
+                .collect::<Vec<_>>()
+        };
+        let circle = |center: Point2<f64>, radius: f64| {
+            let mut points = arc(center, radius, 0.0, TAU);
+            points.pop();
+            points
+        };
+
+        match self {
+            GerberPrimitive::Circle(circle_primitive) => {
+                let mut outlines = vec![circle(circle_primitive.center, circle_primitive.diameter / 2.0)];
+                if let Some(hole_diameter) = circle_primitive.hole_diameter
+                    && hole_diameter > 0.0
+                {
+                    outlines.push(circle(circle_primitive.center, hole_diameter / 2.0));
+                }
+                outlines
+            }
+            GerberPrimitive::Rectangle(rectangle) => {
+                let origin = rectangle.origin;
+                vec![vec![
+                    origin,
+                    origin + Vector2::new(rectangle.width, 0.0),
+                    origin + Vector2::new(rectangle.width, rectangle.height),
+                    origin + Vector2::new(0.0, rectangle.height),
+                ]]
+            }
+            GerberPrimitive::RoundedRectangle(rectangle) => vec![rectangle.outline(steps)],
+            GerberPrimitive::Line(line) => {
+                let radius = line.width / 2.0;
+                if line.start == line.end {
+                    return vec![circle(line.start, radius)];
+                }
+
+                // a capsule, round ends around the end points
+                let direction = line.end - line.start;
+                let angle = direction.y.atan2(direction.x);
+                let mut points = arc(line.end, radius, angle - FRAC_PI_2, PI);
+                points.extend(arc(line.start, radius, angle + FRAC_PI_2, PI));
+                vec![points]
+            }
+            GerberPrimitive::Arc(arc_primitive) => {
+                let outer = arc_primitive.radius + arc_primitive.width / 2.0;
+                let inner = (arc_primitive.radius - arc_primitive.width / 2.0).max(0.0);
+
+                if arc_primitive.is_full_circle() {
+                    return vec![circle(arc_primitive.center, outer), circle(arc_primitive.center, inner)];
+                }
+
+                // a band, with flat ends
+                let start = arc_primitive.start_angle;
+                let sweep = arc_primitive.sweep_angle;
+                let mut points = arc(arc_primitive.center, outer, start, sweep);
+                points.extend(
+                    arc(arc_primitive.center, inner, start, sweep)
+                        .into_iter()
+                        .rev(),
+                );
+                vec![points]
+            }
+            GerberPrimitive::Polygon(polygon) => vec![
+                polygon
+                    .geometry
+                    .relative_vertices
+                    .iter()
+                    .map(|vertex| polygon.center + vertex.coords)
+                    .collect(),
+            ],
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
-pub(crate) struct CircleGerberPrimitive {
+pub struct CircleGerberPrimitive {
     pub center: Point2<f64>,
     pub diameter: f64,
+    /// When set, the circle is rendered as a filled ring (annulus) with a hole of this diameter.
+    pub hole_diameter: Option<f64>,
+    pub exposure: Exposure,
+    /// How the primitive was created, see [`GerberPrimitive::primitive_origin`].
+    pub primitive_origin: PrimitiveOrigin,
+}
+
+#[derive(Debug, Clone)]
+pub struct RectangleGerberPrimitive {
+    pub origin: Point2<f64>,
+    pub width: f64,
+    pub height: f64,
     pub exposure: Exposure,
+    /// How the primitive was created, see [`GerberPrimitive::primitive_origin`].
+    pub primitive_origin: PrimitiveOrigin,
 }
 
+/// A rectangle with rounded corners, built from macros that draw one, e.g. the `RoundRect` macro used by KiCad for
+/// pads, instead of the polygons, circles and lines of the macro.
 #[derive(Debug, Clone)]
-pub(crate) struct RectangleGerberPrimitive {
+pub struct RoundedRectangleGerberPrimitive {
     pub origin: Point2<f64>,
     pub width: f64,
     pub height: f64,
+    /// At most half of the width and the height.
+    pub corner_radius: f64,
     pub exposure: Exposure,
+    /// How the primitive was created, see [`GerberPrimitive::primitive_origin`].
+    pub primitive_origin: PrimitiveOrigin,
+}
+
+impl RoundedRectangleGerberPrimitive {
+    /// Default number of points used for each corner, see [`RoundedRectangleGerberPrimitive::outline`].
+    pub const DEFAULT_CORNER_STEPS: usize = 8;
+
+    /// The outline, counter-clockwise, in gerber coordinates, with `steps` points for each corner.
+    ///
+    /// `steps` is clamped to a minimum of 2, a rectangle without rounding has just the 4 corners.
+    pub fn outline(&self, steps: usize) -> Vec<Point2<f64>> {
+        let radius = self.corner_radius;
+        let min = self.origin + Vector2::new(radius, radius);
+        let max = self.origin + Vector2::new(self.width - radius, self.height - radius);
+
+        // the centers of the corners, starting at the bottom right
+        let centers = [
+            Point2::new(max.x, min.y),
+            Point2::new(max.x, max.y),
+            Point2::new(min.x, max.y),
+            Point2::new(min.x, min.y),
+        ];
+
+        if radius <= 0.0 {
+            return centers.to_vec();
+        }
+
+        let steps = steps.max(2);
+        let mut points = Vec::with_capacity(steps * 4);
+        for (corner, center) in centers.into_iter().enumerate() {
+            let start_angle = -std::f64::consts::FRAC_PI_2 + corner as f64 * std::f64::consts::FRAC_PI_2;
+            for step in 0..steps {
+                let angle = start_angle + std::f64::consts::FRAC_PI_2 * step as f64 / (steps - 1) as f64;
+                points.push(center + Vector2::new(angle.cos(), angle.sin()) * radius);
+            }
+        }
+
+        points
+    }
 }
 
 #[derive(Debug, Clone)]
-pub(crate) struct LineGerberPrimitive {
+pub struct LineGerberPrimitive {
     pub start: Point2<f64>,
     pub end: Point2<f64>,
     pub width: f64,
     pub exposure: Exposure,
+    /// How the primitive was created, see [`GerberPrimitive::primitive_origin`].
+    pub primitive_origin: PrimitiveOrigin,
 }
 
 #[derive(Debug, Clone)]
-pub(crate) struct PolygonGerberPrimitive {
+pub struct PolygonGerberPrimitive {
     pub center: Point2<f64>,
     pub exposure: Exposure,
+    /// How the primitive was created, see [`GerberPrimitive::primitive_origin`].
+    pub primitive_origin: PrimitiveOrigin,
     pub geometry: Arc<PolygonGeometry>,
 }
 
+impl PolygonGerberPrimitive {
+    /// The vertices in gerber coordinates, i.e. relative to the origin rather than the center, in the winding expected
+    /// by an export format.
+    ///
+    /// Polygons have a single contour, so it is the outer contour, see [`normalize_winding`](crate::normalize_winding).
+    pub fn vertices_with_winding(&self, winding: Winding) -> Vec<Point2<f64>> {
+        let mut vertices = self
+            .geometry
+            .relative_vertices
+            .iter()
+            .map(|vertex| self.center + vertex.coords)
+            .collect::<Vec<_>>();
+        geometry::normalize_winding(&mut vertices, &mut [], winding);
+
+        vertices
+    }
+
+    /// The number of contours of the polygon, i.e. the outer contour plus the holes and islands that a region joins to
+    /// it with cut-ins, see [`split_cut_ins`](crate::split_cut_ins).
+    ///
+    /// For debugging regions, e.g. to check that a region with holes was parsed with the expected number of contours.
+    pub fn contour_count(&self) -> usize {
+        self.contours().len()
+    }
+
+    /// The vertices of each contour, in gerber coordinates, the outer contour first, see
+    /// [`contour_count`](Self::contour_count).
+    ///
+    /// The contours are found each time, from the vertices of the polygon.
+    pub fn contours(&self) -> Vec<Vec<Point2<f64>>> {
+        geometry::split_cut_ins(&self.geometry.relative_vertices)
+            .into_iter()
+            .map(|contour| {
+                contour
+                    .into_iter()
+                    .map(|vertex| self.center + vertex.coords)
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The triangles the renderer uses for a concave polygon, `None` for a convex polygon, which is drawn from its
+    /// vertices, see [`convex_vertices`](Self::convex_vertices).
+    ///
+    /// The vertices are in gerber units relative to the [`center`](Self::center), with Y up, i.e. before the Y flip to
+    /// screen coordinates, the image transform and any render transform.  The tessellation is computed once, when the
+    /// layer is built, and shared by clones of the primitive.
+    pub fn tessellation(&self) -> Option<&PolygonMesh> {
+        self.geometry.tessellation.as_ref()
+    }
+
+    /// The vertices of a convex polygon, clockwise with Y up, `None` for a concave polygon, see
+    /// [`tessellation`](Self::tessellation).
+    ///
+    /// In the same coordinate space as the tessellation, relative to the center, with Y up.
+    pub fn convex_vertices(&self) -> Option<&[Point2<f64>]> {
+        self.geometry.is_convex.then_some(
+            self.geometry
+                .relative_vertices
+                .as_slice(),
+        )
+    }
+
+    /// The triangles of the polygon, convex or not, e.g. to upload to a GPU, in the same coordinate space as the
+    /// [`tessellation`](Self::tessellation).
+    ///
+    /// Concave polygons borrow the precomputed tessellation, convex polygons are split into a fan of triangles from
+    /// their first vertex.
+    pub fn mesh(&self) -> Cow<'_, PolygonMesh> {
+        if let Some(tessellation) = self.tessellation() {
+            return Cow::Borrowed(tessellation);
+        }
+
+        let vertices = &self.geometry.relative_vertices;
+        Cow::Owned(PolygonMesh {
+            vertices: vertices
+                .iter()
+                .map(|vertex| [vertex.x as f32, vertex.y as f32])
+                .collect(),
+            indices: (1..vertices.len().saturating_sub(1) as u32)
+                .flat_map(|index| [0, index, index + 1])
+                .collect(),
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
-pub(crate) struct ArcGerberPrimitive {
+pub struct ArcGerberPrimitive {
     pub center: Point2<f64>,
     pub radius: f64,
     pub width: f64,
     pub start_angle: f64, // in radians
-    pub sweep_angle: f64, // in radians, positive = clockwise
+    pub sweep_angle: f64, // in radians, positive = counter-clockwise, ±2π (or zero) = full circle
     pub exposure: Exposure,
+    /// How the primitive was created, see [`GerberPrimitive::primitive_origin`].
+    pub primitive_origin: PrimitiveOrigin,
 }
 
 impl ArcGerberPrimitive {
+    /// The arc of a circular interpolation (G02/G03) from `start` to `end`, `offset` is the offset of the center from
+    /// `start` (I/J).
+    fn from_interpolation(
+        start: Point2<f64>,
+        end: Point2<f64>,
+        offset: Vector2<f64>,
+        width: f64,
+        interpolation_mode: InterpolationMode,
+        quadrant_mode: QuadrantMode,
+    ) -> Self {
+        // Calculate center of the arc
+        let center = start + offset;
+
+        // Calculate radius (distance from current position to center)
+        let radius = offset.dot(&offset).sqrt();
+
+        // Calculate start angle (from center to current position)
+        let start_angle = (start.y - center.y).atan2(start.x - center.x);
+
+        // Calculate end angle (from center to target position)
+        let end_angle = (end.y - center.y).atan2(end.x - center.x);
+
+        // Calculate sweep angle based on interpolation mode
+        let mut sweep_angle = match interpolation_mode {
+            InterpolationMode::ClockwiseCircular => {
+                if end_angle > start_angle {
+                    end_angle - start_angle - 2.0 * std::f64::consts::PI
+                } else {
+                    end_angle - start_angle
+                }
+            }
+            InterpolationMode::CounterclockwiseCircular => {
+                if end_angle < start_angle {
+                    end_angle - start_angle + 2.0 * std::f64::consts::PI
+                } else {
+                    end_angle - start_angle
+                }
+            }
+            _ => 0.0, // Should never happen
+        };
+
+        // Adjust for single/multi quadrant mode
+        if let QuadrantMode::Single = quadrant_mode {
+            // In single quadrant mode, sweep angle is always <= 90°
+            if sweep_angle.abs() > std::f64::consts::PI / 2.0 {
+                if sweep_angle > 0.0 {
+                    sweep_angle = std::f64::consts::PI / 2.0;
+                } else {
+                    sweep_angle = -std::f64::consts::PI / 2.0;
+                }
+            }
+        }
+
+        // the start and end points coincide, a full circle, keep the direction of the interpolation
+        if sweep_angle.abs() < 1e-10 {
+            sweep_angle = match interpolation_mode {
+                InterpolationMode::ClockwiseCircular => -2.0 * std::f64::consts::PI,
+                _ => 2.0 * std::f64::consts::PI,
+            };
+        }
+
+        Self {
+            center,
+            radius,
+            width,
+            start_angle,
+            sweep_angle,
+            exposure: Exposure::Add,
+            primitive_origin: PENDING_ORIGIN,
+        }
+    }
+
+    /// The direction of the arc, from the start angle to the end angle, in gerber coordinates (Y up).
+    ///
+    /// Full circles from a circular interpolation keep its direction, a sweep of 0.0 is counter-clockwise.
+    pub fn direction(&self) -> ArcDirection {
+        match self.sweep_angle < 0.0 {
+            true => ArcDirection::Clockwise,
+            false => ArcDirection::CounterClockwise,
+        }
+    }
+
+    /// The signed sweep, in radians, positive = counter-clockwise.
+    ///
+    /// Note: a sweep of ±2π, or 0.0, is a full circle, see [`ArcGerberPrimitive::is_full_circle`].
+    pub fn sweep_radians(&self) -> f64 {
+        self.sweep_angle
+    }
+
+    /// The radius of the center of the stroke, in gerber units.
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    /// The angle of the start point, relative to the center, in radians.
+    pub fn start_angle(&self) -> f64 {
+        self.start_angle
+    }
+
+    /// The angle of the end point, relative to the center, in radians. Not normalized, i.e. `start_angle + sweep`.
+    pub fn end_angle(&self) -> f64 {
+        self.start_angle + self.sweep_angle
+    }
+
     /// Spec 4.7.2 "When start point and end point coincide the result is a full 360° arc"
     ///
     /// However, we to avoid being to strict due to rounding errors.
@@ -1502,6 +3229,28 @@ impl ArcGerberPrimitive {
     }
 
     pub fn generate_points(&self) -> Vec<Point2<f64>> {
+        self.generate_points_with_steps(Self::DEFAULT_STEPS)
+    }
+
+    /// Default number of points used to approximate an arc, see [`ArcGerberPrimitive::generate_points_with_steps`].
+    pub const DEFAULT_STEPS: usize = 32;
+
+    /// The number of line segments that [`ArcGerberPrimitive::generate_points_with_steps`] approximates the arc with,
+    /// i.e. one less than the number of points, for diagnosing faceted arcs.
+    ///
+    /// Use the same `steps` as the renderer, e.g. `RenderConfiguration::effective_arc_steps`, or
+    /// [`ArcGerberPrimitive::DEFAULT_STEPS`] for [`ArcGerberPrimitive::generate_points`].
+    pub fn segment_count(&self, steps: usize) -> usize {
+        let steps = steps.max(2);
+        match self.is_full_circle() {
+            true => steps,
+            false => steps - 1,
+        }
+    }
+
+    /// Generates `steps` points along the arc, relative to the center, `steps + 1` for a full circle so that the
+    /// last point is the same as the first. `steps` is clamped to a minimum of 2.
+    pub fn generate_points_with_steps(&self, steps: usize) -> Vec<Point2<f64>> {
         let Self {
             radius,
             start_angle,
@@ -1512,10 +3261,14 @@ impl ArcGerberPrimitive {
         // Check if this is a full circle
         let is_full_circle = self.is_full_circle();
 
-        let steps = if is_full_circle { 33 } else { 32 };
+        let steps = steps.max(2);
+        let steps = if is_full_circle { steps + 1 } else { steps };
 
         let effective_sweep = if is_full_circle {
-            2.0 * std::f64::consts::PI
+            match self.direction() {
+                ArcDirection::Clockwise => -2.0 * std::f64::consts::PI,
+                ArcDirection::CounterClockwise => 2.0 * std::f64::consts::PI,
+            }
         } else {
             *sweep_angle
         };
@@ -1549,10 +3302,13 @@ impl ArcGerberPrimitive {
     }
 }
 
+/// The shape of a [`PolygonGerberPrimitive`], in gerber units relative to its center, with Y up.
 #[derive(Debug, Clone)]
 pub struct PolygonGeometry {
-    pub relative_vertices: Vec<Point2<f64>>, // Relative to center
-    pub tessellation: Option<PolygonMesh>,   // Precomputed tessellation data
+    /// Clockwise with Y up, i.e. counter-clockwise on screen, without repeating the first vertex.
+    pub relative_vertices: Vec<Point2<f64>>,
+    /// Precomputed for concave polygons, `None` for convex ones.
+    pub tessellation: Option<PolygonMesh>,
     pub is_convex: bool,
 }
 
@@ -1562,6 +3318,7 @@ pub struct GerberPolygon {
     /// Relative to center
     vertices: Vec<Point2<f64>>,
     exposure: Exposure,
+    primitive_origin: PrimitiveOrigin,
 }
 
 impl GerberPolygon {
@@ -1572,15 +3329,36 @@ impl GerberPolygon {
     }
 }
 
-impl GerberPrimitive {
-    fn new_polygon(polygon: GerberPolygon) -> Self {
-        trace!("new_polygon: {:?}", polygon);
-        let is_convex = polygon.is_convex();
+impl PolygonGerberPrimitive {
+    /// A polygon from vertices in gerber coordinates, centered on the center of their bounding box.
+    #[cfg(feature = "flatten")]
+    pub(crate) fn from_vertices(vertices: Vec<Point2<f64>>, exposure: Exposure) -> Self {
+        let center = BoundingBox::from_points(&vertices).center();
+        let vertices = vertices
+            .into_iter()
+            .map(|vertex| Point2::from(vertex - center))
+            .collect();
+
+        match GerberPrimitive::new_polygon(GerberPolygon {
+            center,
+            vertices,
+            exposure,
+            primitive_origin: PrimitiveOrigin::Region,
+        }) {
+            GerberPrimitive::Polygon(polygon) => polygon,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl GerberPrimitive {
+    fn new_polygon(polygon: GerberPolygon) -> Self {
+        trace!("new_polygon: {:?}", polygon);
+        let is_convex = polygon.is_convex();
         let mut relative_vertices = polygon.vertices;
 
-        // Calculate and fix winding order
-        let winding = Winding::from_vertices(&relative_vertices);
-        if matches!(winding, Winding::Clockwise) {
+        // Calculate and fix winding order, polygons are stored clockwise, i.e. counter-clockwise on the screen (Y down)
+        if Winding::from_vertices(&relative_vertices) == Winding::CounterClockwise {
             relative_vertices.reverse();
         }
 
@@ -1598,6 +3376,7 @@ impl GerberPrimitive {
         let polygon = GerberPrimitive::Polygon(PolygonGerberPrimitive {
             center: polygon.center,
             exposure: polygon.exposure,
+            primitive_origin: polygon.primitive_origin,
             geometry: Arc::new(PolygonGeometry {
                 relative_vertices,
                 tessellation,
@@ -2006,18 +3785,16 @@ mod circular_plotting_tests {
 
 #[cfg(test)]
 mod circle_aperture_tests {
-    use std::f64::consts::PI;
-
     use gerber_types::{
         Aperture, ApertureDefinition, Circle, Command, CoordinateFormat, CoordinateMode, CoordinateNumber, Coordinates,
         DCode, ExtendedCode, FunctionCode, Operation, Unit, ZeroOmission,
     };
     use nalgebra::Point2;
 
-    use crate::ArcGerberPrimitive;
+    use crate::CircleGerberPrimitive;
     use crate::testing::dump_gerber_source;
     use crate::types::Exposure;
-    use crate::{GerberLayer, GerberPrimitive};
+    use crate::{GerberLayer, GerberPrimitive, PrimitiveOrigin};
 
     #[test]
     fn test_circle_with_hole_rendering() {
@@ -2060,44 +3837,20 @@ mod circle_aperture_tests {
         assert_eq!(primitives.len(), 1);
 
         match &primitives[0] {
-            GerberPrimitive::Arc(ArcGerberPrimitive {
+            GerberPrimitive::Circle(CircleGerberPrimitive {
                 center: c,
-                radius,
-                width,
-                start_angle,
-                sweep_angle,
+                diameter,
+                hole_diameter: actual_hole_diameter,
                 exposure,
+                primitive_origin,
             }) => {
                 assert_eq!(*c, center);
-
-                // For correct rendering with StrokeKind::Middle
-                // The radius should be midway between outer and inner radius
-                let expected_radius = (outer_diameter / 2.0 + hole_diameter / 2.0) / 2.0;
-
-                assert!(
-                    (radius - expected_radius).abs() < f64::EPSILON,
-                    "Radius should be midway between outer and inner radii ({}), got {}",
-                    expected_radius,
-                    radius
-                );
-
-                // Width should be the difference between outer and inner radius
-                let expected_width = outer_diameter / 2.0 - hole_diameter / 2.0;
-                assert!(
-                    (width - expected_width).abs() < f64::EPSILON,
-                    "Width should equal the difference between outer and inner radii ({}), got {}",
-                    expected_width,
-                    width
-                );
-
-                assert_eq!(*start_angle, 0.0);
-                assert!(
-                    (sweep_angle.abs() - 2.0 * PI).abs() < f64::EPSILON,
-                    "Sweep angle should be 2π radians (full circle)"
-                );
+                assert_eq!(*diameter, outer_diameter);
+                assert_eq!(*actual_hole_diameter, Some(hole_diameter));
                 assert_eq!(*exposure, Exposure::Add);
+                assert_eq!(*primitive_origin, PrimitiveOrigin::Flash);
             }
-            _ => panic!("Expected an Arc primitive for circle with hole"),
+            _ => panic!("Expected a Circle primitive for circle with hole"),
         }
     }
 }
@@ -2126,6 +3879,7 @@ mod bounding_box_arc_tests {
             start_angle,
             sweep_angle,
             exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
         })
     }
 
@@ -2140,6 +3894,7 @@ mod bounding_box_arc_tests {
             start_angle: 0.0_f64.to_radians(),
             sweep_angle: 0.0_f64.to_radians(),
             exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
         };
 
         // when
@@ -2221,6 +3976,7 @@ mod bounding_box_arc_tests {
             start_angle: 0.0_f64.to_radians(),
             sweep_angle: 0.0_f64.to_radians(),
             exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
         };
 
         // when
@@ -2371,3 +4127,1919 @@ mod bounding_box_arc_tests {
         assert!(bbox.max.y <= center_y + radius + half_width + 0.1); // max Y should extend upward
     }
 }
+
+#[cfg(test)]
+mod arc_accessor_tests {
+    use std::f64::consts::{FRAC_PI_2, PI};
+
+    use gerber_types::{CoordinateNumber, CoordinateOffset};
+    use rstest::rstest;
+
+    use super::*;
+    use crate::testing::commands::{at, circle, format, header, move_to, select};
+
+    #[rstest]
+    #[case::counter_clockwise(0.0, FRAC_PI_2, ArcDirection::CounterClockwise, FRAC_PI_2)]
+    #[case::clockwise(0.0, -FRAC_PI_2, ArcDirection::Clockwise, -FRAC_PI_2)]
+    #[case::clockwise_crossing_zero(FRAC_PI_2, -PI, ArcDirection::Clockwise, -FRAC_PI_2)]
+    #[case::clockwise_full_circle(FRAC_PI_2, -2.0 * PI, ArcDirection::Clockwise, FRAC_PI_2 - 2.0 * PI)]
+    fn test_direction_and_angles(
+        #[case] start_angle: f64,
+        #[case] sweep_angle: f64,
+        #[case] expected_direction: ArcDirection,
+        #[case] expected_end_angle: f64,
+    ) {
+        // given
+        let arc = ArcGerberPrimitive {
+            center: Point2::new(10.0, 10.0),
+            radius: 5.0,
+            width: 0.1,
+            start_angle,
+            sweep_angle,
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        };
+
+        // expect
+        assert_eq!(arc.direction(), expected_direction);
+        assert_eq!(arc.radius(), 5.0);
+        assert_eq!(arc.start_angle(), start_angle);
+        assert_eq!(arc.sweep_radians(), sweep_angle);
+        assert!((arc.end_angle() - expected_end_angle).abs() < f64::EPSILON);
+    }
+
+    fn arc_with_sweep(sweep_angle: f64) -> ArcGerberPrimitive {
+        ArcGerberPrimitive {
+            center: Point2::new(0.0, 0.0),
+            radius: 5.0,
+            width: 0.1,
+            start_angle: 0.0,
+            sweep_angle,
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        }
+    }
+
+    #[rstest]
+    #[case::partial(FRAC_PI_2, 1, 1)]
+    #[case::partial_default_steps(FRAC_PI_2, ArcGerberPrimitive::DEFAULT_STEPS, 31)]
+    #[case::full_circle(0.0, 1, 2)]
+    #[case::full_circle_default_steps(0.0, ArcGerberPrimitive::DEFAULT_STEPS, 32)]
+    fn test_segment_count(#[case] sweep_angle: f64, #[case] steps: usize, #[case] expected: usize) {
+        // given
+        let arc = arc_with_sweep(sweep_angle);
+
+        // expect
+        assert_eq!(arc.segment_count(steps), expected);
+        assert_eq!(
+            arc.segment_count(steps),
+            arc.generate_points_with_steps(steps)
+                .len()
+                - 1
+        );
+    }
+
+    #[cfg(feature = "egui")]
+    #[rstest]
+    #[case::draft(crate::RenderQuality::Draft, 15, 16)]
+    #[case::balanced(crate::RenderQuality::Balanced, 31, 32)]
+    #[case::high(crate::RenderQuality::High, 63, 64)]
+    fn test_segment_count_of_render_quality(
+        #[case] quality: crate::RenderQuality,
+        #[case] expected_partial: usize,
+        #[case] expected_full_circle: usize,
+    ) {
+        // given
+        let configuration = crate::RenderConfiguration {
+            quality,
+            ..crate::RenderConfiguration::default()
+        };
+        let steps = configuration.effective_arc_steps();
+
+        // expect
+        assert_eq!(arc_with_sweep(FRAC_PI_2).segment_count(steps), expected_partial);
+        assert_eq!(arc_with_sweep(0.0).segment_count(steps), expected_full_circle);
+    }
+
+    #[rstest]
+    #[case::clockwise(InterpolationMode::ClockwiseCircular, ArcDirection::Clockwise)]
+    #[case::counter_clockwise(InterpolationMode::CounterclockwiseCircular, ArcDirection::CounterClockwise)]
+    fn test_full_circle_keeps_interpolation_direction(
+        #[case] interpolation_mode: InterpolationMode,
+        #[case] expected_direction: ArcDirection,
+    ) {
+        // given
+        // a full circle around (5, 0), starting and ending at the origin
+        let mut commands = header();
+        commands.extend([
+            circle(10, 0.1),
+            select(10),
+            GCode::QuadrantMode(QuadrantMode::Multi).into(),
+            GCode::InterpolationMode(interpolation_mode).into(),
+            move_to(0.0, 0.0),
+            DCode::Operation(Operation::Interpolate(
+                at(0.0, 0.0),
+                Some(CoordinateOffset::new(
+                    CoordinateNumber::try_from(5.0).unwrap(),
+                    CoordinateNumber::try_from(0.0).unwrap(),
+                    format(),
+                )),
+            ))
+            .into(),
+        ]);
+
+        // when
+        let layer = GerberLayer::new(commands);
+
+        // then
+        let [GerberPrimitive::Arc(arc)] = layer.primitives() else {
+            panic!("expected a single arc, got: {:?}", layer.primitives());
+        };
+        assert!(arc.is_full_circle());
+        assert_eq!(arc.direction(), expected_direction);
+
+        // and the points follow the direction, from the left-most point a clockwise circle goes up
+        let points = arc.generate_points();
+        let above = points[1].y > points[0].y;
+        assert_eq!(above, expected_direction == ArcDirection::Clockwise);
+    }
+}
+
+#[cfg(test)]
+mod aperture_template_tests {
+    use gerber_types::{CenterLinePrimitive, CirclePrimitive, MacroBoolean, Polygon};
+
+    use super::*;
+    use crate::testing::commands::{definition, flash, header, select};
+
+    #[test]
+    fn test_macro_primitive_exposure_from_variable() {
+        // given
+        // a square with a round hole, $1 is the exposure of the hole
+        let square_with_hole = ApertureMacro::new("SquareWithHole")
+            .add_content(CenterLinePrimitive::new((
+                MacroDecimal::Value(4.0),
+                MacroDecimal::Value(4.0),
+            )))
+            .add_content(CirclePrimitive::new(MacroDecimal::Value(2.0)).with_exposure(MacroBoolean::Variable(1)));
+        let square_with_hole_definition = |code, exposure| {
+            definition(
+                code,
+                Aperture::Macro("SquareWithHole".to_string(), Some(vec![MacroDecimal::Value(exposure)])),
+            )
+        };
+        let mut commands = header();
+        commands.extend([
+            Command::ExtendedCode(ExtendedCode::ApertureMacro(square_with_hole)),
+            square_with_hole_definition(10, 0.0),
+            square_with_hole_definition(11, 1.0),
+            select(10),
+            flash(0.0, 0.0),
+            select(11),
+            flash(10.0, 0.0),
+        ]);
+
+        // when
+        let layer = GerberLayer::new(commands);
+
+        // then
+        let exposures = layer
+            .primitives()
+            .iter()
+            .map(GerberPrimitive::exposure)
+            .collect::<Vec<_>>();
+        assert_eq!(exposures, vec![
+            Exposure::Add,
+            Exposure::CutOut,
+            Exposure::Add,
+            Exposure::Add
+        ]);
+    }
+
+    #[test]
+    fn test_identical_polygon_apertures_share_geometry() {
+        // given
+        let polygon = |code| definition(code, Aperture::Polygon(Polygon::new(1.0, 6)));
+        let mut commands = header();
+        commands.extend([
+            polygon(10),
+            polygon(11),
+            select(10),
+            flash(0.0, 0.0),
+            flash(2.0, 0.0),
+            select(11),
+            flash(4.0, 0.0),
+        ]);
+
+        // when
+        let layer = GerberLayer::new(commands);
+
+        // then
+        let polygons = layer
+            .primitives()
+            .iter()
+            .map(|primitive| match primitive {
+                GerberPrimitive::Polygon(polygon) => polygon,
+                _ => panic!("expected a polygon"),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(polygons.len(), 3);
+        assert!(Arc::ptr_eq(&polygons[0].geometry, &polygons[1].geometry));
+        assert!(Arc::ptr_eq(&polygons[0].geometry, &polygons[2].geometry));
+        assert_eq!(
+            polygons
+                .iter()
+                .map(|polygon| polygon.center)
+                .collect::<Vec<_>>(),
+            vec![Point2::new(0.0, 0.0), Point2::new(2.0, 0.0), Point2::new(4.0, 0.0)]
+        );
+    }
+}
+
+#[cfg(test)]
+mod bake_tests {
+    use std::f64::consts::FRAC_PI_2;
+
+    use gerber_types::{Rectangular, Unit};
+    use rstest::rstest;
+
+    use super::*;
+    use crate::Mirroring;
+    use crate::testing::commands::{definition, flash, format, select};
+
+    fn flashed_rectangle(x: f64, y: f64, width: f64, height: f64) -> GerberLayer {
+        flashed_rectangle_with_unit(Unit::Millimeters, x, y, width, height)
+    }
+
+    fn flashed_rectangle_with_unit(unit: Unit, x: f64, y: f64, width: f64, height: f64) -> GerberLayer {
+        GerberLayer::new(vec![
+            Command::ExtendedCode(ExtendedCode::Unit(unit)),
+            Command::ExtendedCode(ExtendedCode::CoordinateFormat(format())),
+            definition(10, Aperture::Rectangle(Rectangular::new(width, height))),
+            select(10),
+            flash(x, y),
+        ])
+    }
+
+    #[test]
+    fn test_inches_to_millimeters() {
+        // given
+        let layer = flashed_rectangle_with_unit(Unit::Inches, 0.0, 0.0, 1.0, 1.0);
+        let transform = GerberTransform::with_unit_scale(layer.units().unwrap(), Unit::Millimeters);
+
+        // when
+        let baked = layer.baked(&transform);
+
+        // then
+        assert!((baked.bounding_box().width() - 25.4).abs() < 1e-9);
+        assert!((baked.bounding_box().height() - 25.4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_rotate_90_keeps_rectangle() {
+        // given
+        let layer = flashed_rectangle(10.0, 0.0, 2.0, 1.0);
+        let transform = GerberTransform {
+            rotation: std::f32::consts::FRAC_PI_2,
+            ..GerberTransform::default()
+        };
+
+        // when
+        let baked = layer.baked(&transform);
+
+        // then
+        let GerberPrimitive::Rectangle(rect) = &baked.primitives()[0] else {
+            panic!("expected a rectangle, got {:?}", baked.primitives()[0]);
+        };
+        assert!((rect.width - 1.0).abs() < 1e-6);
+        assert!((rect.height - 2.0).abs() < 1e-6);
+        let center = baked.bounding_box().center();
+        assert!(center.x.abs() < 1e-6);
+        assert!((center.y - 10.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_rotate_45_makes_polygon() {
+        // given
+        let layer = flashed_rectangle(0.0, 0.0, 2.0, 2.0);
+        let transform = GerberTransform {
+            rotation: std::f32::consts::FRAC_PI_4,
+            ..GerberTransform::default()
+        };
+
+        // when
+        let baked = layer.baked(&transform);
+
+        // then
+        let GerberPrimitive::Polygon(polygon) = &baked.primitives()[0] else {
+            panic!("expected a polygon, got {:?}", baked.primitives()[0]);
+        };
+        assert_eq!(polygon.geometry.relative_vertices.len(), 4);
+        let expected_half_diagonal = 2.0_f64.sqrt();
+        assert!((baked.bounding_box().width() - expected_half_diagonal * 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_identity_is_unchanged() {
+        // given
+        let layer = flashed_rectangle(5.0, 5.0, 2.0, 1.0);
+
+        // when
+        let baked = layer.baked(&GerberTransform::default());
+
+        // then
+        assert!(layer.diff(&baked).is_empty());
+    }
+
+    #[rstest]
+    #[case::no_mirroring(false, 0.0, FRAC_PI_2)]
+    #[case::mirror_x(true, std::f64::consts::PI, -FRAC_PI_2)]
+    fn test_arc_mirroring(#[case] mirror_x: bool, #[case] expected_start_angle: f64, #[case] expected_sweep: f64) {
+        // given
+        let arc = GerberPrimitive::Arc(ArcGerberPrimitive {
+            center: Point2::new(0.0, 0.0),
+            radius: 5.0,
+            width: 0.5,
+            start_angle: 0.0,
+            sweep_angle: FRAC_PI_2,
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        });
+        let transform = GerberTransform {
+            mirroring: Mirroring {
+                x: mirror_x,
+                y: false,
+            },
+            ..GerberTransform::default()
+        };
+
+        // when
+        let GerberPrimitive::Arc(arc) = arc.transformed(&transform.to_matrix()) else {
+            panic!("expected an arc");
+        };
+
+        // then
+        assert!((arc.start_angle - expected_start_angle).abs() < 1e-9);
+        assert!((arc.sweep_angle - expected_sweep).abs() < 1e-9);
+        assert!((arc.radius - 5.0).abs() < 1e-9);
+        assert!((arc.width - 0.5).abs() < 1e-9);
+    }
+}
+
+#[cfg(test)]
+mod region_warning_tests {
+    use super::*;
+    use crate::testing::commands::{draw_to, header, move_to};
+
+    fn region(contours: &[&[(f64, f64)]]) -> GerberLayer {
+        region_with_configuration(contours, &LayerConfiguration::default())
+    }
+
+    fn region_with_configuration(contours: &[&[(f64, f64)]], configuration: &LayerConfiguration) -> GerberLayer {
+        let mut commands: Vec<Command> = header();
+        commands.extend([
+            GCode::InterpolationMode(InterpolationMode::Linear).into(),
+            GCode::RegionMode(true).into(),
+        ]);
+        for contour in contours {
+            commands.push(move_to(contour[0].0, contour[0].1));
+            for &(x, y) in &contour[1..] {
+                commands.push(draw_to(x, y));
+            }
+        }
+        commands.push(GCode::RegionMode(false).into());
+
+        GerberLayer::new_with_configuration(commands, configuration)
+    }
+
+    #[test]
+    fn test_closed_region_has_no_warnings() {
+        // given
+        let layer = region(&[&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.0, 0.0)]]);
+
+        // expect
+        assert_eq!(layer.primitives().len(), 1);
+        assert!(layer.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_region_with_hole_contours() {
+        // given
+        // a square with a square hole, joined by a cut-in
+        let layer = region(&[&[
+            (0.0, 0.0),
+            (4.0, 0.0),
+            (4.0, 4.0),
+            (0.0, 4.0),
+            (0.0, 2.0),
+            (1.0, 2.0),
+            (1.0, 3.0),
+            (3.0, 3.0),
+            (3.0, 1.0),
+            (1.0, 1.0),
+            (1.0, 2.0),
+            (0.0, 2.0),
+            (0.0, 0.0),
+        ]]);
+
+        // when
+        let GerberPrimitive::Polygon(polygon) = &layer.primitives()[0] else {
+            panic!("expected a polygon");
+        };
+
+        // then
+        assert_eq!(polygon.contour_count(), 2);
+        // the outer contour ends with the start of the cut-in, the vertices can be reordered by the builder
+        let sizes = polygon
+            .contours()
+            .iter()
+            .map(Vec::len)
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, vec![5, 5]);
+    }
+
+    #[test]
+    fn test_unclosed_region() {
+        // given
+        let layer = region(&[&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.0, 0.0)], &[
+            (2.0, 0.0),
+            (3.0, 0.0),
+            (3.0, 1.0),
+            (2.0, 1.0),
+        ]]);
+
+        // expect
+        assert_eq!(layer.primitives().len(), 2);
+        assert_eq!(layer.warnings(), &[LayerWarning::UnclosedRegion {
+            contour: 1,
+            command_index: 9,
+            auto_closed: true,
+        }]);
+    }
+
+    #[test]
+    fn test_unclosed_region_without_auto_close() {
+        // given
+        let configuration = LayerConfiguration {
+            auto_close_regions: false,
+            ..LayerConfiguration::default()
+        };
+
+        // when
+        let layer = region_with_configuration(
+            &[&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.0, 0.0)], &[
+                (2.0, 0.0),
+                (3.0, 0.0),
+                (3.0, 1.0),
+                (2.0, 1.0),
+            ]],
+            &configuration,
+        );
+
+        // then
+        assert_eq!(layer.primitives().len(), 1);
+        assert_eq!(layer.warnings(), &[LayerWarning::UnclosedRegion {
+            contour: 1,
+            command_index: 9,
+            auto_closed: false,
+        }]);
+    }
+
+    #[test]
+    fn test_self_intersecting_region() {
+        // given
+        let layer = region(&[&[(0.0, 0.0), (1.0, 1.0), (1.0, 0.0), (0.0, 1.0), (0.0, 0.0)]]);
+
+        // expect
+        assert_eq!(layer.primitives().len(), 1);
+        assert_eq!(layer.warnings(), &[LayerWarning::SelfIntersectingRegion {
+            contour: 0,
+            command_index: 3,
+            edges: (0, 2),
+        }]);
+    }
+
+    #[test]
+    fn test_degenerate_region() {
+        // given
+        let layer = region(&[&[(0.0, 0.0), (1.0, 1.0)]]);
+
+        // expect
+        assert!(layer.primitives().is_empty());
+        assert_eq!(layer.warnings(), &[LayerWarning::DegenerateRegion {
+            contour: 0,
+            command_index: 3,
+        }]);
+    }
+}
+
+#[cfg(test)]
+mod polarity_tests {
+    use super::*;
+    use crate::testing::commands;
+
+    fn header() -> Vec<Command> {
+        let mut header = commands::header();
+        header.push(commands::circle(10, 1.0));
+        header
+    }
+
+    fn flash(x: f64) -> Command {
+        commands::flash(x, 0.0)
+    }
+
+    fn load_polarity(polarity: Polarity) -> Command {
+        Command::ExtendedCode(ExtendedCode::LoadPolarity(polarity))
+    }
+
+    fn exposures(layer: &GerberLayer) -> Vec<Exposure> {
+        layer
+            .primitives()
+            .iter()
+            .map(GerberPrimitive::exposure)
+            .collect()
+    }
+
+    #[test]
+    fn test_mid_file_polarity_switches() {
+        // given
+        let mut commands = header();
+        commands.extend([
+            DCode::SelectAperture(10).into(),
+            flash(0.0),
+            load_polarity(Polarity::Clear),
+            flash(1.0),
+            flash(2.0),
+            load_polarity(Polarity::Dark),
+            flash(3.0),
+            load_polarity(Polarity::Clear),
+            flash(4.0),
+        ]);
+
+        // when
+        let layer = GerberLayer::new(commands);
+
+        // then
+        assert_eq!(exposures(&layer), vec![
+            Exposure::Add,
+            Exposure::CutOut,
+            Exposure::CutOut,
+            Exposure::Add,
+            Exposure::CutOut,
+        ]);
+    }
+
+    #[test]
+    fn test_negative_image_inverts_exposures() {
+        // given
+        let mut commands = header();
+        commands.extend([
+            Command::ExtendedCode(ExtendedCode::ImagePolarity(ImagePolarity::Negative)),
+            DCode::SelectAperture(10).into(),
+            flash(0.0),
+            load_polarity(Polarity::Clear),
+            flash(1.0),
+        ]);
+
+        // when
+        let layer = GerberLayer::new(commands);
+
+        // then
+        assert_eq!(layer.image_polarity(), ImagePolarity::Negative);
+        assert_eq!(exposures(&layer), vec![Exposure::CutOut, Exposure::Add]);
+    }
+
+    #[test]
+    fn test_block_flashed_with_clear_polarity_toggles_block_objects() {
+        // given
+        let mut commands = header();
+        commands.extend([
+            Command::ExtendedCode(ExtendedCode::ApertureBlock(ApertureBlock::Open {
+                code: 20,
+            })),
+            DCode::SelectAperture(10).into(),
+            flash(0.0),
+            load_polarity(Polarity::Clear),
+            flash(0.5),
+            Command::ExtendedCode(ExtendedCode::ApertureBlock(ApertureBlock::Close)),
+            load_polarity(Polarity::Clear),
+            DCode::SelectAperture(20).into(),
+            flash(10.0),
+            // the polarity is restored after the block
+            DCode::SelectAperture(10).into(),
+            flash(20.0),
+        ]);
+
+        // when
+        let layer = GerberLayer::new(commands);
+
+        // then
+        assert_eq!(exposures(&layer), vec![
+            Exposure::CutOut,
+            Exposure::Add,
+            Exposure::CutOut
+        ]);
+    }
+}
+
+#[cfg(test)]
+mod merge_collinear_lines_tests {
+    use super::*;
+    use crate::testing::commands::{circle, draw_to, header, move_to, select};
+
+    /// A trace through the points, drawn with a 0.5mm (D10) or 1mm (D11) aperture.
+    fn trace(aperture: i32, points: &[(f64, f64)]) -> Vec<Command> {
+        let mut commands: Vec<Command> = vec![select(aperture), move_to(points[0].0, points[0].1)];
+        for (x, y) in &points[1..] {
+            commands.push(draw_to(*x, *y));
+        }
+        commands
+    }
+
+    fn layer(traces: Vec<Vec<Command>>) -> GerberLayer {
+        let mut commands: Vec<Command> = header();
+        commands.extend([
+            circle(10, 0.5),
+            circle(11, 1.0),
+            FunctionCode::GCode(GCode::InterpolationMode(InterpolationMode::Linear)).into(),
+        ]);
+        commands.extend(traces.into_iter().flatten());
+        GerberLayer::new(commands)
+    }
+
+    fn lines(layer: &GerberLayer) -> Vec<((f64, f64), (f64, f64))> {
+        layer
+            .primitives()
+            .iter()
+            .map(|primitive| match primitive {
+                GerberPrimitive::Line(line) => ((line.start.x, line.start.y), (line.end.x, line.end.y)),
+                _ => panic!("expected a line"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_merges_segments_of_a_straight_trace() {
+        // given
+        let mut layer = layer(vec![trace(10, &[
+            (0.0, 0.0),
+            (1.0, 1.0),
+            (2.0, 2.0),
+            (3.0, 3.0),
+            (3.0, 5.0),
+            (3.0, 6.0),
+        ])]);
+        let bounding_box = layer.bounding_box().clone();
+
+        // when
+        let merged = layer.merge_collinear_lines(1e-6);
+
+        // then
+        assert_eq!(merged, 3);
+        assert_eq!(lines(&layer), vec![((0.0, 0.0), (3.0, 3.0)), ((3.0, 3.0), (3.0, 6.0))]);
+        assert_eq!(layer.bounding_box(), &bounding_box);
+    }
+
+    #[test]
+    fn test_keeps_segments_that_double_back() {
+        // given
+        let mut layer = layer(vec![trace(10, &[(0.0, 0.0), (2.0, 0.0), (1.0, 0.0)])]);
+
+        // when
+        let merged = layer.merge_collinear_lines(1e-6);
+
+        // then
+        assert_eq!(merged, 0);
+        assert_eq!(layer.primitives().len(), 2);
+    }
+
+    #[test]
+    fn test_keeps_segments_with_different_widths() {
+        // given
+        let mut layer = layer(vec![
+            trace(10, &[(0.0, 0.0), (1.0, 0.0)]),
+            trace(11, &[(1.0, 0.0), (2.0, 0.0)]),
+        ]);
+
+        // when
+        let merged = layer.merge_collinear_lines(1e-6);
+
+        // then
+        assert_eq!(merged, 0);
+        assert_eq!(layer.primitives().len(), 2);
+    }
+
+    #[test]
+    fn test_gradual_curve_is_not_merged_into_one_line() {
+        // given
+        // each joint is within the tolerance of the previous segment, but the curve drifts away from a straight line
+        let mut layer = layer(vec![trace(10, &[
+            (0.0, 0.0),
+            (1.0, 0.0),
+            (2.0, 0.001),
+            (3.0, 0.003),
+            (4.0, 0.006),
+        ])]);
+
+        // when
+        layer.merge_collinear_lines(0.0015);
+
+        // then
+        assert_eq!(lines(&layer), vec![
+            ((0.0, 0.0), (3.0, 0.003)),
+            ((3.0, 0.003), (4.0, 0.006))
+        ]);
+    }
+}
+
+#[cfg(test)]
+mod primitive_origin_tests {
+    use gerber_types::{CirclePrimitive, Polygon};
+
+    use super::*;
+    use crate::testing::commands::{definition, draw_to, flash, header, move_to};
+
+    #[test]
+    fn test_primitive_origins() {
+        // given
+        let mut commands = header();
+        commands.extend([
+            Command::ExtendedCode(ExtendedCode::ApertureMacro(
+                ApertureMacro::new("Dot").add_content(CirclePrimitive::new(MacroDecimal::Value(1.0))),
+            )),
+            definition(10, Aperture::Circle(Circle::new(1.0))),
+            definition(11, Aperture::Polygon(Polygon::new(1.0, 6))),
+            definition(12, Aperture::Macro("Dot".to_string(), None)),
+            Command::ExtendedCode(ExtendedCode::ApertureBlock(ApertureBlock::Open {
+                code: 20,
+            })),
+            DCode::SelectAperture(10).into(),
+            flash(0.0, 0.0),
+            Command::ExtendedCode(ExtendedCode::ApertureBlock(ApertureBlock::Close)),
+            FunctionCode::GCode(GCode::InterpolationMode(InterpolationMode::Linear)).into(),
+            // flashes
+            DCode::SelectAperture(10).into(),
+            flash(0.0, 0.0),
+            DCode::SelectAperture(11).into(),
+            flash(2.0, 0.0),
+            DCode::SelectAperture(12).into(),
+            flash(4.0, 0.0),
+            // draw
+            DCode::SelectAperture(10).into(),
+            move_to(0.0, 5.0),
+            draw_to(5.0, 5.0),
+            // region
+            FunctionCode::GCode(GCode::RegionMode(true)).into(),
+            move_to(0.0, 10.0),
+            draw_to(5.0, 10.0),
+            draw_to(5.0, 15.0),
+            draw_to(0.0, 10.0),
+            FunctionCode::GCode(GCode::RegionMode(false)).into(),
+            // block
+            DCode::SelectAperture(20).into(),
+            flash(10.0, 0.0),
+        ]);
+
+        // when
+        let layer = GerberLayer::new(commands);
+
+        // then
+        assert_eq!(layer.primitive_origins(), &[
+            PrimitiveOrigin::Flash,
+            PrimitiveOrigin::Flash,
+            PrimitiveOrigin::Macro,
+            PrimitiveOrigin::Draw,
+            PrimitiveOrigin::Region,
+            PrimitiveOrigin::Block,
+        ]);
+        // the block's objects use the block's code, not the code of the aperture used inside the block
+        assert_eq!(layer.primitive_apertures(), &[
+            Some(10),
+            Some(11),
+            Some(12),
+            Some(10),
+            None,
+            Some(20),
+        ]);
+    }
+
+    #[test]
+    fn test_macro_flash_in_a_block_is_a_block() {
+        // given
+        let mut commands = header();
+        commands.extend([
+            Command::ExtendedCode(ExtendedCode::ApertureMacro(
+                ApertureMacro::new("Dot").add_content(CirclePrimitive::new(MacroDecimal::Value(1.0))),
+            )),
+            definition(12, Aperture::Macro("Dot".to_string(), None)),
+            Command::ExtendedCode(ExtendedCode::ApertureBlock(ApertureBlock::Open {
+                code: 20,
+            })),
+            DCode::SelectAperture(12).into(),
+            flash(0.0, 0.0),
+            Command::ExtendedCode(ExtendedCode::ApertureBlock(ApertureBlock::Close)),
+            DCode::SelectAperture(20).into(),
+            flash(10.0, 0.0),
+        ]);
+
+        // when
+        let layer = GerberLayer::new(commands);
+
+        // then
+        assert_eq!(layer.primitive_origins(), &[PrimitiveOrigin::Block]);
+        assert_eq!(layer.primitive_apertures(), &[Some(20)]);
+    }
+
+    #[test]
+    fn test_primitive_origin_is_kept_by_the_primitive() {
+        // given
+        let mut commands = header();
+        commands.extend([
+            definition(10, Aperture::Circle(Circle::new(1.0))),
+            DCode::SelectAperture(10).into(),
+            move_to(0.0, 0.0),
+            draw_to(5.0, 0.0),
+            flash(10.0, 0.0),
+        ]);
+        let mut layer = GerberLayer::new(commands);
+
+        // when
+        let baked = layer.baked(&GerberTransform {
+            rotation: std::f32::consts::FRAC_PI_4,
+            ..GerberTransform::default()
+        });
+        layer.push_primitive(baked.primitives()[1].clone());
+
+        // then
+        let origins: Vec<PrimitiveOrigin> = layer
+            .primitives()
+            .iter()
+            .map(GerberPrimitive::primitive_origin)
+            .collect();
+        assert_eq!(origins, vec![
+            PrimitiveOrigin::Draw,
+            PrimitiveOrigin::Flash,
+            PrimitiveOrigin::Programmatic
+        ]);
+
+        // and transforming a primitive keeps its origin
+        assert_eq!(baked.primitives()[0].primitive_origin(), PrimitiveOrigin::Draw);
+        assert_eq!(baked.primitives()[1].primitive_origin(), PrimitiveOrigin::Flash);
+    }
+}
+
+#[cfg(test)]
+mod unsupported_features_tests {
+    use gerber_types::{CirclePrimitive, ThermalPrimitive};
+
+    use super::*;
+    use crate::testing::commands;
+
+    fn flash(x: f64) -> Command {
+        commands::flash(x, 0.0)
+    }
+
+    fn layer(commands: Vec<Command>) -> GerberLayer {
+        let mut all_commands = commands::header();
+        all_commands.extend(commands);
+
+        GerberLayer::new(all_commands)
+    }
+
+    #[test]
+    fn test_supported_layer() {
+        // given
+        let layer = layer(vec![
+            Command::ExtendedCode(ExtendedCode::ApertureDefinition(ApertureDefinition::new(
+                10,
+                Aperture::Circle(Circle::new(1.0)),
+            ))),
+            DCode::SelectAperture(10).into(),
+            flash(0.0),
+        ]);
+
+        // expect
+        assert!(!layer.has_unsupported_features());
+        assert!(layer.unsupported_features().is_empty());
+    }
+
+    #[test]
+    fn test_unsupported_features() {
+        // given
+        let thermal = ApertureMacro::new("Thermal")
+            .add_content(CirclePrimitive::new(MacroDecimal::Value(0.5)))
+            .add_content(ThermalPrimitive::new(
+                MacroDecimal::Value(1.0),
+                MacroDecimal::Value(2.0),
+                MacroDecimal::Value(0.2),
+            ));
+
+        // when
+        let layer = layer(vec![
+            Command::ExtendedCode(ExtendedCode::ApertureMacro(thermal)),
+            Command::ExtendedCode(ExtendedCode::ApertureDefinition(ApertureDefinition::new(
+                10,
+                Aperture::Macro("Thermal".to_string(), None),
+            ))),
+            Command::ExtendedCode(ExtendedCode::ApertureDefinition(ApertureDefinition::new(
+                11,
+                Aperture::Macro("Missing".to_string(), None),
+            ))),
+            DCode::SelectAperture(10).into(),
+            flash(0.0),
+            flash(2.0),
+            DCode::SelectAperture(11).into(),
+            flash(4.0),
+        ]);
+
+        // then
+        assert_eq!(layer.warnings(), &[
+            LayerWarning::UnsupportedMacroPrimitive {
+                command_index: 3,
+                macro_name: "Thermal".to_string(),
+                primitive: "thermal",
+            },
+            LayerWarning::UndefinedMacro {
+                command_index: 4,
+                macro_name: "Missing".to_string(),
+            },
+            LayerWarning::UndefinedAperture {
+                command_index: 8,
+                code: 11,
+            },
+        ]);
+        assert!(layer.has_unsupported_features());
+        assert_eq!(layer.unsupported_features(), &[
+            UnsupportedFeature::MacroPrimitive,
+            UnsupportedFeature::UndefinedAperture
+        ]);
+    }
+}
+
+#[cfg(test)]
+mod grid_spacing_tests {
+    use gerber_types::{CoordinateMode, ZeroOmission};
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case::millimeters(Some(Unit::Millimeters), Some(5), 1.0)]
+    #[case::inches(Some(Unit::Inches), Some(4), 0.1)]
+    #[case::coarse_format(Some(Unit::Inches), Some(0), 1.0)]
+    #[case::no_units(None, Some(5), 1.0)]
+    #[case::no_format(Some(Unit::Inches), None, 0.1)]
+    fn test_default_grid_spacing(#[case] unit: Option<Unit>, #[case] decimal: Option<u8>, #[case] expected: f64) {
+        // given
+        let mut commands: Vec<Command> = vec![];
+        if let Some(unit) = unit {
+            commands.push(Command::ExtendedCode(ExtendedCode::Unit(unit)));
+        }
+        if let Some(decimal) = decimal {
+            commands.push(Command::ExtendedCode(ExtendedCode::CoordinateFormat(
+                CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 3, decimal),
+            )));
+        }
+
+        // when
+        let layer = GerberLayer::new(commands);
+
+        // then
+        assert_eq!(layer.default_grid_spacing(), expected);
+    }
+}
+
+#[cfg(test)]
+mod dimensions_string_tests {
+    use gerber_types::{CoordinateMode, CoordinateNumber, Rectangular, ZeroOmission};
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case::millimeters(Some(Unit::Millimeters), Some(2), "100.00 × 80.00 mm")]
+    #[case::inches(Some(Unit::Inches), Some(4), "100.0000 × 80.0000 in")]
+    #[case::no_units(None, Some(3), "100.000 × 80.000 mm")]
+    #[case::no_format(Some(Unit::Millimeters), None, "100.00 × 80.00 mm")]
+    fn test_dimensions_string(#[case] unit: Option<Unit>, #[case] decimal: Option<u8>, #[case] expected: &str) {
+        // given
+        let format = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 3, decimal.unwrap_or(5));
+        let mut commands: Vec<Command> = vec![];
+        if let Some(unit) = unit {
+            commands.push(Command::ExtendedCode(ExtendedCode::Unit(unit)));
+        }
+        if decimal.is_some() {
+            commands.push(Command::ExtendedCode(ExtendedCode::CoordinateFormat(format)));
+        }
+        commands.push(Command::ExtendedCode(ExtendedCode::ApertureDefinition(
+            ApertureDefinition::new(10, Aperture::Rectangle(Rectangular::new(100.0, 80.0))),
+        )));
+        commands.push(DCode::SelectAperture(10).into());
+        commands.push(
+            DCode::Operation(Operation::Flash(Some(Coordinates::new(
+                CoordinateNumber::try_from(50.0).unwrap(),
+                CoordinateNumber::try_from(40.0).unwrap(),
+                format,
+            ))))
+            .into(),
+        );
+
+        // when
+        let layer = GerberLayer::new(commands);
+
+        // then
+        assert_eq!(layer.dimensions_string(), expected);
+    }
+
+    #[test]
+    fn test_empty_layer() {
+        // given
+        let layer = GerberLayer::new(vec![Command::ExtendedCode(ExtendedCode::Unit(Unit::Inches))]);
+
+        // expect
+        assert_eq!(layer.dimensions_string(), "0.00 × 0.00 in");
+    }
+}
+
+#[cfg(test)]
+mod comments_tests {
+    use gerber_types::{ExtendedPosition, FileAttribute, FileFunction, StandardComment};
+
+    use super::*;
+
+    #[test]
+    fn test_comments() {
+        // given
+        let comment = |content: CommentContent| Command::FunctionCode(FunctionCode::GCode(GCode::Comment(content)));
+        let commands = vec![
+            comment(CommentContent::String("Generated by ExampleCAD 1.0".to_string())),
+            comment(CommentContent::Standard(StandardComment::FileAttribute(
+                FileAttribute::FileFunction(FileFunction::Copper {
+                    layer: 1,
+                    pos: ExtendedPosition::Top,
+                    copper_type: None,
+                }),
+            ))),
+            Command::ExtendedCode(ExtendedCode::Unit(Unit::Millimeters)),
+            comment(CommentContent::String("Layer: Top".to_string())),
+        ];
+
+        // when
+        let layer = GerberLayer::new(commands);
+
+        // then
+        assert_eq!(layer.comments(), ["Generated by ExampleCAD 1.0", "Layer: Top"]);
+    }
+}
+
+#[cfg(test)]
+mod degenerate_aperture_tests {
+    use gerber_types::{Polygon, Rectangular};
+
+    use super::*;
+    use crate::testing::commands::{circle, definition, draw_to, flash, header, select};
+
+    fn commands() -> Vec<Command> {
+        let mut commands = header();
+        commands.extend([
+            circle(10, 0.0),
+            definition(11, Aperture::Rectangle(Rectangular::new(1.0, 0.0))),
+            definition(12, Aperture::Polygon(Polygon::new(0.0, 6))),
+            GCode::InterpolationMode(InterpolationMode::Linear).into(),
+            select(10),
+            flash(0.0, 0.0),
+            select(11),
+            flash(1.0, 0.0),
+            select(12),
+            flash(2.0, 0.0),
+            // a zero-width draw, e.g. an outline, is not affected
+            select(10),
+            draw_to(5.0, 0.0),
+        ]);
+        commands
+    }
+
+    #[test]
+    fn test_zero_size_flashes_are_dots() {
+        // when
+        let layer = GerberLayer::new(commands());
+
+        // then
+        assert_eq!(layer.warnings(), &[
+            LayerWarning::DegenerateAperture {
+                command_index: 2,
+                code: 10,
+            },
+            LayerWarning::DegenerateAperture {
+                command_index: 3,
+                code: 11,
+            },
+            LayerWarning::DegenerateAperture {
+                command_index: 4,
+                code: 12,
+            },
+        ]);
+        assert!(!layer.has_unsupported_features());
+
+        let dots = layer
+            .primitives()
+            .iter()
+            .filter_map(|primitive| match primitive {
+                GerberPrimitive::Circle(circle) if circle.diameter == 0.0 => Some(circle.center),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(dots, vec![
+            Point2::new(0.0, 0.0),
+            Point2::new(1.0, 0.0),
+            Point2::new(2.0, 0.0)
+        ]);
+        assert_eq!(layer.primitives().len(), 4);
+        assert!(matches!(layer.primitives()[3], GerberPrimitive::Line(_)));
+    }
+
+    #[test]
+    fn test_zero_size_flashes_can_be_skipped() {
+        // given
+        let configuration = LayerConfiguration {
+            degenerate_apertures: DegenerateApertures::Skip,
+            ..LayerConfiguration::default()
+        };
+
+        // when
+        let layer = GerberLayer::new_with_configuration(commands(), &configuration);
+
+        // then
+        assert_eq!(layer.warnings().len(), 3);
+        assert_eq!(layer.primitives().len(), 1);
+        assert!(matches!(layer.primitives()[0], GerberPrimitive::Line(_)));
+    }
+}
+
+#[cfg(test)]
+mod max_primitives_tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::testing::commands::{circle, flash, header, select};
+
+    /// A 10 x 10 step-and-repeat of a single flash.
+    fn commands() -> Vec<Command> {
+        let mut commands = header();
+        commands.extend([
+            circle(10, 0.5),
+            Command::ExtendedCode(ExtendedCode::StepAndRepeat(StepAndRepeat::Open {
+                repeat_x: 10,
+                repeat_y: 10,
+                distance_x: 1.0,
+                distance_y: 1.0,
+            })),
+            select(10),
+            flash(0.0, 0.0),
+            Command::ExtendedCode(ExtendedCode::StepAndRepeat(StepAndRepeat::Close)),
+        ]);
+        commands
+    }
+
+    #[rstest]
+    #[case::no_limit(None, 100, false)]
+    #[case::exact_limit(Some(100), 100, false)]
+    #[case::limited(Some(25), 25, true)]
+    fn test_max_primitives(
+        #[case] max_primitives: Option<usize>,
+        #[case] expected_count: usize,
+        #[case] expected_limit_reached: bool,
+    ) {
+        // given
+        let configuration = LayerConfiguration {
+            max_primitives,
+            ..LayerConfiguration::default()
+        };
+
+        // when
+        let layer = GerberLayer::new_with_configuration(commands(), &configuration);
+
+        // then
+        assert_eq!(layer.primitives().len(), expected_count);
+        assert_eq!(layer.primitive_origins().len(), expected_count);
+        assert_eq!(layer.primitive_limit_reached(), expected_limit_reached);
+        assert_eq!(
+            layer
+                .warnings()
+                .contains(&LayerWarning::PrimitiveLimitReached {
+                    command_index: 5,
+                    limit: 25,
+                }),
+            expected_limit_reached
+        );
+    }
+}
+
+#[cfg(test)]
+mod step_repeat_tests {
+    use super::*;
+    use crate::query::PrimitiveInstance;
+    use crate::testing::commands::{circle, flash, header, select};
+
+    /// A 3 x 2 step-and-repeat of a 1mm pad and a 0.5mm pad, 10mm apart, then a 2mm pad on top of the last repeat.
+    fn commands() -> Vec<Command> {
+        let mut commands = header();
+        commands.extend([
+            circle(10, 1.0),
+            circle(11, 0.5),
+            circle(12, 2.0),
+            Command::ExtendedCode(ExtendedCode::StepAndRepeat(StepAndRepeat::Open {
+                repeat_x: 3,
+                repeat_y: 2,
+                distance_x: 10.0,
+                distance_y: 10.0,
+            })),
+            select(10),
+            flash(0.0, 0.0),
+            select(11),
+            flash(2.0, 0.0),
+            Command::ExtendedCode(ExtendedCode::StepAndRepeat(StepAndRepeat::Close)),
+            select(12),
+            flash(20.0, 10.0),
+        ]);
+        commands
+    }
+
+    fn instanced() -> LayerConfiguration {
+        LayerConfiguration {
+            instanced_step_repeat: true,
+            ..LayerConfiguration::default()
+        }
+    }
+
+    #[test]
+    fn test_block_is_kept_once() {
+        // when
+        let layer = GerberLayer::new_with_configuration(commands(), &instanced());
+
+        // then
+        assert_eq!(layer.primitives().len(), 3);
+        assert_eq!(layer.step_repeats(), &[StepRepeatBlock {
+            primitives: 0..2,
+            offsets: vec![
+                Vector2::new(0.0, 0.0),
+                Vector2::new(0.0, 10.0),
+                Vector2::new(10.0, 0.0),
+                Vector2::new(10.0, 10.0),
+                Vector2::new(20.0, 0.0),
+                Vector2::new(20.0, 10.0),
+            ],
+        }]);
+        assert_eq!(layer.primitive_apertures(), &[Some(10), Some(11), Some(12)]);
+
+        // and the bounding box includes all the repeats, same as when they are expanded
+        let expanded = GerberLayer::new(commands());
+        assert_eq!(expanded.primitives().len(), 13);
+        assert!(expanded.step_repeats().is_empty());
+        assert_eq!(layer.bounding_box(), expanded.bounding_box());
+    }
+
+    #[test]
+    fn test_huge_step_repeat_is_limited() {
+        // given
+        // 10^10 repeats of a block of 2 pads
+        let mut commands = header();
+        commands.extend([
+            circle(10, 1.0),
+            Command::ExtendedCode(ExtendedCode::StepAndRepeat(StepAndRepeat::Open {
+                repeat_x: 100_000,
+                repeat_y: 100_000,
+                distance_x: 10.0,
+                distance_y: 10.0,
+            })),
+            select(10),
+            flash(0.0, 0.0),
+            flash(2.0, 0.0),
+            Command::ExtendedCode(ExtendedCode::StepAndRepeat(StepAndRepeat::Close)),
+            flash(-10.0, 0.0),
+        ]);
+        let configuration = LayerConfiguration {
+            max_primitives: Some(25),
+            ..instanced()
+        };
+
+        let close_index = commands.len() - 2;
+
+        // when
+        let layer = GerberLayer::new_with_configuration(commands, &configuration);
+
+        // then
+        // only the repeats that fit, building stops at the limit, so the pad after the block is missing
+        assert_eq!(layer.primitives().len(), 2);
+        assert_eq!(layer.step_repeats()[0].offsets.len(), 12);
+        assert_eq!(layer.step_repeats()[0].offsets[11], Vector2::new(0.0, 110.0));
+        assert!(layer.primitive_limit_reached());
+        assert!(
+            layer
+                .warnings()
+                .contains(&LayerWarning::PrimitiveLimitReached {
+                    command_index: close_index,
+                    limit: 25,
+                })
+        );
+    }
+
+    #[test]
+    fn test_repeats_count_towards_the_limit() {
+        // given
+        // the 6 repeats of the block are 12 primitives, the pad after it is the 13th
+        let configuration = LayerConfiguration {
+            max_primitives: Some(12),
+            ..instanced()
+        };
+
+        // when
+        let layer = GerberLayer::new_with_configuration(commands(), &configuration);
+
+        // then
+        assert_eq!(layer.primitives().len(), 2);
+        assert_eq!(layer.step_repeats()[0].offsets.len(), 6);
+        assert!(layer.primitive_limit_reached());
+    }
+
+    #[test]
+    fn test_instance_at_identifies_the_repeat() {
+        // given
+        let layer = GerberLayer::new_with_configuration(commands(), &instanced());
+
+        // expect
+        assert_eq!(
+            layer.instance_at(Point2::new(0.0, 0.0)),
+            Some(PrimitiveInstance {
+                index: 0,
+                repeat_index: 0
+            })
+        );
+        assert_eq!(
+            layer.instance_at(Point2::new(12.0, 10.0)),
+            Some(PrimitiveInstance {
+                index: 1,
+                repeat_index: 3
+            })
+        );
+        // the pad after the block is on top of the last repeat
+        assert_eq!(
+            layer.instance_at(Point2::new(20.0, 10.0)),
+            Some(PrimitiveInstance {
+                index: 2,
+                repeat_index: 0
+            })
+        );
+        assert_eq!(layer.instance_at(Point2::new(5.0, 5.0)), None);
+
+        // and only the first repeat has indexes
+        assert_eq!(layer.primitive_at(Point2::new(12.0, 10.0)), None);
+    }
+
+    #[test]
+    fn test_instance_at_matches_the_expanded_layer() {
+        // given
+        let layer = GerberLayer::new_with_configuration(commands(), &instanced());
+        let expanded = GerberLayer::new(commands());
+
+        for x in 0..3 {
+            for y in 0..2 {
+                // when
+                let point = Point2::new(x as f64 * 10.0 + 2.0, y as f64 * 10.0);
+                let instance = layer.instance_at(point).unwrap();
+
+                // then
+                // the expanded layer has the 2 primitives of each repeat, in the same order
+                let expanded_index = expanded.primitive_at(point).unwrap();
+                assert_eq!(instance.index, 1);
+                assert_eq!(instance.repeat_index * 2 + instance.index, expanded_index);
+            }
+        }
+    }
+
+    #[test]
+    fn test_expanded_primitives_match_the_expanded_layer() {
+        // given
+        let layer = GerberLayer::new_with_configuration(commands(), &instanced());
+        let expanded = GerberLayer::new(commands());
+
+        // when
+        let primitives = layer.expanded_primitives();
+
+        // then
+        assert_eq!(primitives.len(), expanded.primitives().len());
+        for (primitive, expected) in primitives
+            .iter()
+            .zip(expanded.primitives())
+        {
+            assert!(primitive.approx_eq(expected, 1e-9), "{:?} != {:?}", primitive, expected);
+        }
+    }
+
+    #[test]
+    fn test_duplicates_are_not_removed_across_the_block() {
+        // given
+        // a 1mm pad at the origin before the block, the same as the first pad of the block
+        let mut commands = commands();
+        let open = commands
+            .iter()
+            .position(|command| matches!(command, Command::ExtendedCode(ExtendedCode::StepAndRepeat(_))))
+            .unwrap();
+        commands.splice(open..open, [select(10), flash(0.0, 0.0)]);
+        let mut layer = GerberLayer::new_with_configuration(commands, &instanced());
+
+        // when
+        let removed = layer.remove_duplicate_primitives();
+
+        // then
+        assert_eq!(removed, 0);
+        assert_eq!(layer.step_repeats()[0].primitives, 1..3);
+    }
+
+    #[test]
+    fn test_baked_offsets_are_transformed() {
+        // given
+        let layer = GerberLayer::new_with_configuration(commands(), &instanced());
+        let transform = GerberTransform {
+            rotation: std::f32::consts::FRAC_PI_2,
+            offset: Vector2::new(5.0, 0.0),
+            ..GerberTransform::default()
+        };
+
+        // when
+        let baked = layer.baked(&transform);
+
+        // then
+        // rotated, not moved
+        let offset = baked.step_repeats()[0].offsets[2];
+        assert!(offset.x.abs() < 1e-6);
+        assert!((offset.y - 10.0).abs() < 1e-6);
+
+        // and the bounding box is the same as the baked, expanded, layer
+        let expanded = GerberLayer::new(commands()).baked(&transform);
+        let (a, b) = (baked.bounding_box(), expanded.bounding_box());
+        assert!((a.min - b.min).abs().max() < 1e-6);
+        assert!((a.max - b.max).abs().max() < 1e-6);
+    }
+}
+
+#[cfg(test)]
+mod region_arc_tests {
+    use std::f64::consts::PI;
+
+    use gerber_types::{CoordinateNumber, CoordinateOffset};
+    use rstest::rstest;
+
+    use super::*;
+    use crate::testing::commands::{at, format, header};
+
+    /// A half disc of radius 1, the straight edge along the x axis from (1, 0) to (-1, 0), then an arc back to (1, 0)
+    /// through (0, -1).
+    fn half_disc(interpolation_mode: InterpolationMode) -> GerberLayer {
+        let offset = CoordinateOffset::new(
+            CoordinateNumber::try_from(1.0).unwrap(),
+            CoordinateNumber::try_from(0.0).unwrap(),
+            format(),
+        );
+
+        let mut commands = header();
+        commands.extend([
+            GCode::QuadrantMode(QuadrantMode::Multi).into(),
+            GCode::RegionMode(true).into(),
+            DCode::Operation(Operation::Move(at(1.0, 0.0))).into(),
+            GCode::InterpolationMode(InterpolationMode::Linear).into(),
+            DCode::Operation(Operation::Interpolate(at(-1.0, 0.0), None)).into(),
+            GCode::InterpolationMode(interpolation_mode).into(),
+            DCode::Operation(Operation::Interpolate(at(1.0, 0.0), Some(offset))).into(),
+            GCode::RegionMode(false).into(),
+        ]);
+        GerberLayer::new(commands)
+    }
+
+    #[rstest]
+    #[case::counter_clockwise(InterpolationMode::CounterclockwiseCircular, -1.0)]
+    #[case::clockwise(InterpolationMode::ClockwiseCircular, 1.0)]
+    fn test_region_follows_arc(#[case] interpolation_mode: InterpolationMode, #[case] expected_side: f64) {
+        // when
+        let layer = half_disc(interpolation_mode);
+
+        // then
+        assert!(layer.warnings().is_empty());
+        let [GerberPrimitive::Polygon(polygon)] = layer.primitives() else {
+            panic!("expected a single polygon");
+        };
+
+        // the arc is not replaced by a chord, which would leave a region without any area
+        let area = GerberPrimitive::Polygon(polygon.clone()).area();
+        assert!((area - PI / 2.0).abs() < 0.01, "area: {}", area);
+
+        // and it bulges to the correct side
+        let bbox = polygon.bounding_box();
+        let far_side = match expected_side > 0.0 {
+            true => bbox.max.y,
+            false => bbox.min.y,
+        };
+        assert!((far_side - expected_side).abs() < 0.01, "bbox: {:?}", bbox);
+    }
+}
+
+#[cfg(test)]
+mod push_primitive_tests {
+    use super::*;
+
+    fn circle(x: f64, y: f64) -> GerberPrimitive {
+        GerberPrimitive::Circle(CircleGerberPrimitive {
+            center: Point2::new(x, y),
+            diameter: 2.0,
+            hole_diameter: None,
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        })
+    }
+
+    #[test]
+    fn test_push_primitive() {
+        // given
+        let mut layer = GerberLayer::new(vec![]);
+        layer.push_primitive(circle(0.0, 0.0));
+        // build the spatial index
+        assert_eq!(layer.primitive_at(Point2::new(0.0, 0.0)), Some(0));
+
+        // when
+        layer.push_primitive(circle(10.0, 0.0));
+
+        // then
+        assert_eq!(layer.primitives().len(), 2);
+        assert_eq!(layer.primitive_origins(), &[
+            PrimitiveOrigin::Programmatic,
+            PrimitiveOrigin::Programmatic
+        ]);
+        assert_eq!(layer.primitive_apertures(), &[None, None]);
+        assert_eq!(layer.bounding_box().min, Point2::new(-1.0, -1.0));
+        assert_eq!(layer.bounding_box().max, Point2::new(11.0, 1.0));
+
+        // and the spatial index includes the new primitive
+        assert_eq!(layer.primitive_at(Point2::new(10.0, 0.0)), Some(1));
+    }
+}
+
+#[cfg(test)]
+mod remove_duplicate_primitives_tests {
+    use gerber_types::Rectangular;
+
+    use super::*;
+    use crate::testing::commands::{circle, definition, flash, header, select};
+
+    /// Flashes of a 1mm circle (D10) or a 1mm square (D11), with the given polarity.
+    fn layer(flashes: &[(i32, Polarity, (f64, f64))], configuration: &LayerConfiguration) -> GerberLayer {
+        let mut commands: Vec<Command> = header();
+        commands.extend([
+            circle(10, 1.0),
+            definition(11, Aperture::Rectangle(Rectangular::new(1.0, 1.0))),
+        ]);
+        for &(code, polarity, (x, y)) in flashes {
+            commands.extend([
+                Command::ExtendedCode(ExtendedCode::LoadPolarity(polarity)),
+                select(code),
+                flash(x, y),
+            ]);
+        }
+
+        GerberLayer::new_with_configuration(commands, configuration)
+    }
+
+    fn remove_duplicates() -> LayerConfiguration {
+        LayerConfiguration {
+            remove_duplicate_primitives: true,
+            ..LayerConfiguration::default()
+        }
+    }
+
+    #[test]
+    fn test_duplicates_are_kept_by_default() {
+        // given
+        let flashes = [(10, Polarity::Dark, (0.0, 0.0)), (10, Polarity::Dark, (0.0, 0.0))];
+
+        // when
+        let layer = layer(&flashes, &LayerConfiguration::default());
+
+        // then
+        assert_eq!(layer.primitives().len(), 2);
+        assert_eq!(layer.removed_duplicate_count(), 0);
+    }
+
+    #[test]
+    fn test_duplicates_are_removed() {
+        // given
+        let flashes = [
+            (10, Polarity::Dark, (0.0, 0.0)),
+            (11, Polarity::Dark, (0.0, 0.0)),
+            (10, Polarity::Dark, (2.0, 0.0)),
+            (10, Polarity::Dark, (0.0, 0.0)),
+            (11, Polarity::Dark, (0.0, 0.0)),
+        ];
+
+        // when
+        let layer = layer(&flashes, &remove_duplicates());
+
+        // then
+        // the circle and the square at the origin have the same bounding box, but are not duplicates
+        assert_eq!(layer.primitives().len(), 3);
+        assert_eq!(layer.primitive_origins().len(), 3);
+        assert_eq!(layer.removed_duplicate_count(), 2);
+    }
+
+    #[test]
+    fn test_duplicates_separated_by_a_cut_out_are_kept() {
+        // given
+        let flashes = [
+            (10, Polarity::Dark, (0.0, 0.0)),
+            (11, Polarity::Clear, (0.5, 0.0)),
+            (10, Polarity::Dark, (0.0, 0.0)),
+        ];
+
+        // when
+        let layer = layer(&flashes, &remove_duplicates());
+
+        // then
+        // the second flash covers the cut-out, removing it would change the image
+        assert_eq!(layer.primitives().len(), 3);
+        assert_eq!(layer.removed_duplicate_count(), 0);
+    }
+}
+
+#[cfg(all(test, feature = "parser"))]
+mod macro_arithmetic_tests {
+    use super::*;
+
+    #[test]
+    fn test_macro_with_arithmetic() {
+        // given
+        let layer = GerberLayer::from_bytes(include_bytes!("../demo/assets/macro-arithmetic.gbr")).unwrap();
+
+        // when
+        let bboxes = layer
+            .primitives()
+            .iter()
+            .map(|primitive| primitive.bounding_box())
+            .collect::<Vec<_>>();
+
+        // then
+        // two rectangles and two circles for each flash
+        assert_eq!(bboxes.len(), 8);
+        let assert_bbox = |bbox: &BoundingBox, min: (f64, f64), max: (f64, f64)| {
+            assert!(
+                (bbox.min.x - min.0).abs() < 1e-6 && (bbox.min.y - min.1).abs() < 1e-6,
+                "{:?}",
+                bbox
+            );
+            assert!(
+                (bbox.max.x - max.0).abs() < 1e-6 && (bbox.max.y - max.1).abs() < 1e-6,
+                "{:?}",
+                bbox
+            );
+        };
+        // 1.0 x 0.5 pads, 0.4 apart
+        assert_bbox(&bboxes[0], (-1.2, -0.25), (-0.2, 0.25));
+        assert_bbox(&bboxes[1], (0.2, -0.25), (1.2, 0.25));
+        // 0.4 - 0.5 x 0.1 = 0.35
+        assert_bbox(&bboxes[2], (-0.175, -0.175), (0.175, 0.175));
+        // 0.5 / 4 = 0.125, at 0.5 / 2 = 0.25
+        assert_bbox(&bboxes[3], (-0.0625, 0.1875), (0.0625, 0.3125));
+        // 2.0 x 1.0 pads, 1.0 apart
+        assert_bbox(&bboxes[4], (-2.5, 4.5), (-0.5, 5.5));
+        assert_bbox(&bboxes[5], (0.5, 4.5), (2.5, 5.5));
+    }
+}
+
+#[cfg(all(test, feature = "parser"))]
+mod rounded_rectangle_tests {
+    use super::*;
+
+    fn layer() -> GerberLayer {
+        GerberLayer::from_bytes(include_bytes!("../demo/assets/macro-rounded-rectangle.gbr")).unwrap()
+    }
+
+    #[test]
+    fn test_round_rect_macro_is_one_primitive() {
+        // given
+        let layer = layer();
+
+        // when
+        let rectangles = layer
+            .primitives()
+            .iter()
+            .filter_map(|primitive| match primitive {
+                GerberPrimitive::RoundedRectangle(rectangle) => Some(rectangle),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        // then
+        // rotated by 0 and 90 degrees, the ones rotated by 45 and 135 degrees are not axis-aligned
+        assert_eq!(rectangles.len(), 2);
+        let rectangle = rectangles[0];
+        assert!((rectangle.origin.x - 2.0).abs() < 1e-6 && (rectangle.origin.y - 2.0).abs() < 1e-6);
+        assert!((rectangle.width - 6.0).abs() < 1e-6);
+        assert!((rectangle.height - 6.0).abs() < 1e-6);
+        assert!((rectangle.corner_radius - 0.5).abs() < 1e-6);
+
+        // the rotated macros are flashed as polygons, circles and lines
+        assert_eq!(layer.primitives().len(), 2 + 2 * 9);
+    }
+
+    #[test]
+    fn test_other_macros_are_kept() {
+        // given
+        // a circle at each corner, but nothing between them
+        let primitives = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]
+            .map(|(x, y)| {
+                GerberPrimitive::Circle(CircleGerberPrimitive {
+                    center: Point2::new(x, y),
+                    diameter: 0.5,
+                    hole_diameter: None,
+                    exposure: Exposure::Add,
+                    primitive_origin: PrimitiveOrigin::Programmatic,
+                })
+            })
+            .to_vec();
+
+        // expect
+        assert!(GerberLayer::build_rounded_rectangle(&primitives).is_none());
+    }
+
+    #[test]
+    fn test_rotated_by_transform_is_a_polygon() {
+        // given
+        let rectangle = GerberPrimitive::RoundedRectangle(RoundedRectangleGerberPrimitive {
+            origin: Point2::new(0.0, 0.0),
+            width: 2.0,
+            height: 1.0,
+            corner_radius: 0.25,
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        });
+        let transform = GerberTransform {
+            rotation: 45.0_f32.to_radians(),
+            ..GerberTransform::default()
+        };
+
+        // when
+        let rotated = rectangle.transformed(&transform.to_matrix());
+
+        // then
+        let GerberPrimitive::Polygon(polygon) = rotated else {
+            panic!("expected a polygon, got {:?}", rotated);
+        };
+        assert_eq!(
+            polygon.geometry.relative_vertices.len(),
+            4 * RoundedRectangleGerberPrimitive::DEFAULT_CORNER_STEPS
+        );
+    }
+}
+
+#[cfg(test)]
+mod approx_eq_tests {
+    use std::f64::consts::{FRAC_PI_2, PI, TAU};
+
+    use rstest::rstest;
+
+    use super::*;
+
+    fn line(start: (f64, f64), end: (f64, f64), width: f64) -> GerberPrimitive {
+        GerberPrimitive::Line(LineGerberPrimitive {
+            start: Point2::new(start.0, start.1),
+            end: Point2::new(end.0, end.1),
+            width,
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        })
+    }
+
+    fn circle(center: (f64, f64), hole_diameter: Option<f64>) -> GerberPrimitive {
+        GerberPrimitive::Circle(CircleGerberPrimitive {
+            center: Point2::new(center.0, center.1),
+            diameter: 1.0,
+            hole_diameter,
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        })
+    }
+
+    fn arc(start_angle: f64, sweep_angle: f64) -> GerberPrimitive {
+        GerberPrimitive::Arc(ArcGerberPrimitive {
+            center: Point2::new(0.0, 0.0),
+            radius: 1.0,
+            width: 0.1,
+            start_angle,
+            sweep_angle,
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        })
+    }
+
+    #[rstest]
+    #[case::same(line((0.0, 0.0), (1.0, 0.0), 0.1), line((0.0, 0.0), (1.0, 0.0), 0.1), 0.0, true)]
+    #[case::within_tolerance(line((0.0, 0.0), (1.0, 0.0), 0.1), line((0.0005, 0.0), (1.0, -0.0005), 0.1), 1e-3, true)]
+    #[case::beyond_tolerance(line((0.0, 0.0), (1.0, 0.0), 0.1), line((0.0, 0.0), (1.0, 0.0), 0.11), 1e-3, false)]
+    #[case::reversed(line((0.0, 0.0), (1.0, 0.0), 0.1), line((1.0, 0.0), (0.0, 0.0), 0.1), 1e-3, false)]
+    #[case::hole(circle((0.0, 0.0), Some(0.5)), circle((0.0, 0.0), Some(0.5005)), 1e-3, true)]
+    #[case::missing_hole(circle((0.0, 0.0), Some(0.5)), circle((0.0, 0.0), None), 1e-3, false)]
+    #[case::different_kinds(circle((0.0, 0.0), None), line((0.0, 0.0), (0.0, 0.0), 1.0), 1e-3, false)]
+    #[case::same_start_angle(arc(PI, FRAC_PI_2), arc(-PI, FRAC_PI_2), 1e-9, true)]
+    #[case::start_angle_across_zero(arc(-0.0005, FRAC_PI_2), arc(TAU + 0.0005, FRAC_PI_2), 1e-3, true)]
+    #[case::different_start_angle(arc(0.0, FRAC_PI_2), arc(PI, FRAC_PI_2), 1e-3, false)]
+    #[case::full_circles(arc(0.0, TAU), arc(FRAC_PI_2, TAU), 1e-9, true)]
+    #[case::full_circle_and_arc(arc(0.0, TAU), arc(0.0, PI), 1e-9, false)]
+    fn test_approx_eq(
+        #[case] a: GerberPrimitive,
+        #[case] b: GerberPrimitive,
+        #[case] tolerance: f64,
+        #[case] expected: bool,
+    ) {
+        // expect
+        assert_eq!(a.approx_eq(&b, tolerance), expected);
+        assert_eq!(b.approx_eq(&a, tolerance), expected);
+    }
+
+    #[test]
+    fn test_different_exposure() {
+        // given
+        let a = line((0.0, 0.0), (1.0, 0.0), 0.1);
+        let GerberPrimitive::Line(mut b) = a.clone() else {
+            unreachable!()
+        };
+        b.exposure = Exposure::CutOut;
+
+        // expect
+        assert!(!a.approx_eq(&GerberPrimitive::Line(b), 1.0));
+    }
+}
+
+#[cfg(test)]
+mod polygon_mesh_tests {
+    use super::*;
+
+    fn polygon(vertices: &[(f64, f64)]) -> PolygonGerberPrimitive {
+        let primitive = GerberPrimitive::new_polygon(GerberPolygon {
+            center: Point2::new(10.0, 10.0),
+            vertices: vertices
+                .iter()
+                .map(|&(x, y)| Point2::new(x, y))
+                .collect(),
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        });
+        let GerberPrimitive::Polygon(polygon) = primitive else {
+            panic!("not a polygon: {:?}", primitive);
+        };
+        polygon
+    }
+
+    /// The sum of the areas of the triangles.
+    fn area(mesh: &PolygonMesh) -> f64 {
+        mesh.indices
+            .chunks(3)
+            .map(|triangle| {
+                let [a, b, c] = [0, 1, 2].map(|corner| mesh.vertices[triangle[corner] as usize].map(f64::from));
+                ((b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1])).abs() / 2.0
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_convex_polygon() {
+        // given
+        let polygon = polygon(&[(0.0, 0.0), (2.0, 0.0), (2.0, 1.0), (0.0, 1.0)]);
+
+        // when
+        let mesh = polygon.mesh();
+
+        // then
+        assert!(polygon.tessellation().is_none());
+        assert_eq!(polygon.convex_vertices().unwrap().len(), 4);
+        assert!(matches!(mesh, Cow::Owned(_)));
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+        // relative to the center
+        assert!(mesh.vertices.contains(&[2.0, 1.0]));
+        assert_eq!(area(&mesh), 2.0);
+    }
+
+    #[test]
+    fn test_concave_polygon() {
+        // given
+        // an L shape
+        let polygon = polygon(&[(0.0, 0.0), (2.0, 0.0), (2.0, 1.0), (1.0, 1.0), (1.0, 2.0), (0.0, 2.0)]);
+
+        // when
+        let mesh = polygon.mesh();
+
+        // then
+        assert!(polygon.convex_vertices().is_none());
+        assert!(polygon.tessellation().is_some());
+        assert!(matches!(mesh, Cow::Borrowed(_)));
+        assert_eq!(mesh.indices.len() % 3, 0);
+        assert_eq!(area(&mesh), 3.0);
+    }
+}
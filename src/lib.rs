@@ -1,9 +1,29 @@
+mod area;
+mod clip;
 mod color;
+#[cfg(feature = "parser")]
+mod commands;
+mod diff;
+mod drill;
+mod enclosing;
+mod export;
 mod expressions;
+#[cfg(feature = "flatten")]
+mod flatten;
 mod geometry;
+mod id;
 mod layer;
+mod measure;
+#[cfg(feature = "parser")]
+mod parse;
+#[cfg(feature = "pdf")]
+mod pdf;
+mod query;
 mod spacial;
+mod style;
 mod types;
+mod warning;
+mod widths;
 
 #[cfg(feature = "egui")]
 mod renderer;
@@ -11,12 +31,20 @@ mod renderer;
 #[cfg(feature = "egui")]
 mod drawing;
 
+#[cfg(feature = "egui")]
+mod texture;
+
 #[cfg(feature = "egui")]
 mod ui;
 
+pub use clip::*;
 pub use color::*;
+#[cfg(feature = "parser")]
+pub use commands::*;
+pub use diff::*;
 #[cfg(feature = "egui")]
 pub use drawing::*;
+pub use drill::*;
 pub use geometry::*;
 /// re-export 'gerber_parser' crate
 #[cfg(feature = "parser")]
@@ -24,12 +52,24 @@ pub use gerber_parser;
 /// re-export 'gerber_types' crate
 #[cfg(feature = "types")]
 pub use gerber_types;
+pub use id::*;
 pub use layer::*;
+pub use measure::*;
+#[cfg(feature = "parser")]
+pub use parse::*;
+#[cfg(feature = "pdf")]
+pub use pdf::*;
+pub use query::*;
 #[cfg(feature = "egui")]
 pub use renderer::*;
 pub use spacial::*;
+pub use style::*;
+#[cfg(feature = "egui")]
+pub use texture::*;
+pub use types::*;
 #[cfg(feature = "egui")]
 pub use ui::*;
+pub use warning::*;
 
 #[cfg(feature = "testing")]
 pub mod testing;
@@ -0,0 +1,398 @@
+use nalgebra::{Point2, Vector2};
+
+use crate::layer::{ArcGerberPrimitive, GerberPrimitive, LineGerberPrimitive};
+
+/// A straight edge for measurements, in gerber units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Edge {
+    pub start: Point2<f64>,
+    pub end: Point2<f64>,
+}
+
+impl Edge {
+    pub fn new(start: Point2<f64>, end: Point2<f64>) -> Self {
+        Self {
+            start,
+            end,
+        }
+    }
+
+    /// The edge of a picked primitive, e.g. a primitive returned by [`GerberLayer::primitive_at`].
+    ///
+    /// For a line this is its center line.  For an arc this is the tangent at the end of the arc that is nearest to
+    /// `near` (usually the pick position), pointing into the arc and one radius long, so the angle is measured where
+    /// the arc meets the other edge.
+    ///
+    /// Returns `None` for other primitives, zero length lines and full circles.
+    ///
+    /// [`GerberLayer::primitive_at`]: crate::GerberLayer::primitive_at
+    pub fn from_primitive(primitive: &GerberPrimitive, near: Point2<f64>) -> Option<Self> {
+        match primitive {
+            GerberPrimitive::Line(line) if line.start != line.end => Some(Self::new(line.start, line.end)),
+            GerberPrimitive::Arc(arc) if !arc.is_full_circle() && arc.radius > 0.0 => {
+                let point_at = |angle: f64| arc.center + Vector2::new(angle.cos(), angle.sin()) * arc.radius;
+                let start = point_at(arc.start_angle());
+                let end = point_at(arc.end_angle());
+
+                // the counter-clockwise tangent, rotated by 90 degrees from the radius
+                let tangent_at = |angle: f64| Vector2::new(-angle.sin(), angle.cos()) * arc.sweep_angle.signum();
+
+                let (point, into_arc) = match distance(start, near) <= distance(end, near) {
+                    true => (start, tangent_at(arc.start_angle())),
+                    false => (end, -tangent_at(arc.end_angle())),
+                };
+
+                Some(Self::new(point, point + into_arc * arc.radius))
+            }
+            _ => None,
+        }
+    }
+
+    fn direction(&self) -> Vector2<f64> {
+        self.end - self.start
+    }
+
+    /// Measures the angle between this edge and `other`, see [`measure_angle`].
+    ///
+    /// Returns `None` if either edge has zero length or the edges are parallel, in which case there is no vertex.
+    pub fn angle_to(&self, other: &Edge) -> Option<AngleMeasurement> {
+        let direction_a = self.direction();
+        let direction_b = other.direction();
+        if direction_a == Vector2::zeros() || direction_b == Vector2::zeros() {
+            return None;
+        }
+
+        let denominator = cross(direction_a, direction_b);
+        if denominator.abs() <= f64::EPSILON * length(direction_a) * length(direction_b) {
+            return None;
+        }
+
+        // intersection of the (infinite) lines through both edges
+        let t = cross(other.start - self.start, direction_b) / denominator;
+        let vertex = self.start + direction_a * t;
+
+        // point each edge away from the vertex
+        let away_from = |edge: &Edge| match distance(edge.start, vertex) <= distance(edge.end, vertex) {
+            true => edge.direction(),
+            false => -edge.direction(),
+        };
+        let direction_a = away_from(self);
+        let direction_b = away_from(other);
+
+        let degrees = cross(direction_a, direction_b)
+            .abs()
+            .atan2(direction_a.dot(&direction_b))
+            .to_degrees();
+
+        Some(AngleMeasurement {
+            vertex,
+            direction_a: direction_a / length(direction_a),
+            direction_b: direction_b / length(direction_b),
+            degrees,
+        })
+    }
+}
+
+/// The result of [`Edge::angle_to`], in gerber units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AngleMeasurement {
+    /// Where the lines through the edges meet, this may be beyond the ends of the edges.
+    pub vertex: Point2<f64>,
+    /// Unit vector from the vertex along the first edge.
+    pub direction_a: Vector2<f64>,
+    /// Unit vector from the vertex along the second edge.
+    pub direction_b: Vector2<f64>,
+    /// The angle between the directions, 0 to 180 degrees.
+    pub degrees: f64,
+}
+
+/// Returns the angle between two edges in degrees, 0 to 180.
+///
+/// The angle is measured at the point where the lines through the edges meet, between the parts of the edges on the
+/// same side of that point as their far ends, e.g. a trace that turns by 45 degrees measures 135, the angle inside the
+/// bend, regardless of the direction the segments were drawn in.
+///
+/// Parallel edges and zero length edges measure 0.
+///
+/// See [`Edge::from_primitive`] to measure picked lines and arcs, and `draw_angle` to show the result.
+pub fn measure_angle(edge_a: &Edge, edge_b: &Edge) -> f64 {
+    edge_a
+        .angle_to(edge_b)
+        .map_or(0.0, |measurement| measurement.degrees)
+}
+
+/// Returns the grid point nearest to `point`, for a square grid with the given spacing that has a grid point at
+/// `origin`, e.g. to place markers or measurement points on a clean grid.
+///
+/// All values are in gerber units.  `point` is returned unchanged if `spacing` is not positive.
+pub fn snap_to_grid(point: Point2<f64>, spacing: f64, origin: Point2<f64>) -> Point2<f64> {
+    if spacing.is_nan() || spacing <= 0.0 {
+        return point;
+    }
+
+    let snap = |value: f64, origin: f64| origin + ((value - origin) / spacing).round() * spacing;
+
+    Point2::new(snap(point.x, origin.x), snap(point.y, origin.y))
+}
+
+impl LineGerberPrimitive {
+    /// Returns the unit vector along the line, from the start to the end, e.g. to place a label parallel to an edge.
+    ///
+    /// The direction is the same everywhere on a line, `point` is only used for symmetry with
+    /// [`ArcGerberPrimitive::tangent_at`].  Returns `None` for a zero length line.
+    pub fn tangent_at(&self, _point: Point2<f64>) -> Option<Vector2<f64>> {
+        unit(self.end - self.start)
+    }
+
+    /// Returns the unit vector perpendicular to the line, to the left of the direction from the start to the end
+    /// (counter-clockwise, in gerber coordinates), e.g. for a dimension leader perpendicular to an edge.
+    ///
+    /// Returns `None` for a zero length line.
+    pub fn normal_at(&self, point: Point2<f64>) -> Option<Vector2<f64>> {
+        self.tangent_at(point)
+            .map(|tangent| Vector2::new(-tangent.y, tangent.x))
+    }
+}
+
+impl ArcGerberPrimitive {
+    /// Returns the unit vector tangent to the arc, in the direction of the sweep, at the point of the arc's circle
+    /// nearest to `point`, e.g. to place a label along a curved edge.
+    ///
+    /// Full circles follow their [`direction`](ArcGerberPrimitive::direction).  `point` does not need to be on the arc,
+    /// the tangent of the circle is used beyond the ends of the arc.  Returns `None` if `point` is the center or the
+    /// radius is zero.
+    pub fn tangent_at(&self, point: Point2<f64>) -> Option<Vector2<f64>> {
+        let normal = self.normal_at(point)?;
+        let tangent = Vector2::new(-normal.y, normal.x);
+
+        match self.sweep_angle < 0.0 {
+            true => Some(-tangent),
+            false => Some(tangent),
+        }
+    }
+
+    /// Returns the unit vector perpendicular to the arc, pointing away from the center, at the point of the arc's
+    /// circle nearest to `point`, e.g. for a radius dimension.
+    ///
+    /// Returns `None` if `point` is the center or the radius is zero.
+    pub fn normal_at(&self, point: Point2<f64>) -> Option<Vector2<f64>> {
+        if self.radius <= 0.0 {
+            return None;
+        }
+
+        unit(point - self.center)
+    }
+}
+
+/// The unit vector in the direction of `vector`, or `None` if it has no length.
+fn unit(vector: Vector2<f64>) -> Option<Vector2<f64>> {
+    match length(vector) {
+        0.0 => None,
+        vector_length => Some(vector / vector_length),
+    }
+}
+
+fn cross(a: Vector2<f64>, b: Vector2<f64>) -> f64 {
+    a.x * b.y - a.y * b.x
+}
+
+fn length(vector: Vector2<f64>) -> f64 {
+    vector.dot(&vector).sqrt()
+}
+
+fn distance(a: Point2<f64>, b: Point2<f64>) -> f64 {
+    length(b - a)
+}
+
+#[cfg(test)]
+mod measure_angle_tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::layer::{ArcGerberPrimitive, LineGerberPrimitive, PrimitiveOrigin};
+    use crate::types::Exposure;
+
+    fn edge(start: (f64, f64), end: (f64, f64)) -> Edge {
+        Edge::new(Point2::new(start.0, start.1), Point2::new(end.0, end.1))
+    }
+
+    #[rstest]
+    #[case::right_angle(edge((0.0, 0.0), (1.0, 0.0)), edge((0.0, 0.0), (0.0, 1.0)), 90.0)]
+    #[case::bend(edge((-1.0, 0.0), (0.0, 0.0)), edge((0.0, 0.0), (1.0, 1.0)), 135.0)]
+    #[case::reversed_bend(edge((0.0, 0.0), (-1.0, 0.0)), edge((1.0, 1.0), (0.0, 0.0)), 135.0)]
+    #[case::apart(edge((1.0, 0.0), (2.0, 0.0)), edge((0.0, 1.0), (0.0, 2.0)), 90.0)]
+    #[case::acute(edge((0.0, 0.0), (2.0, 0.0)), edge((0.0, 0.0), (1.0, 1.0)), 45.0)]
+    #[case::parallel(edge((0.0, 0.0), (1.0, 0.0)), edge((0.0, 1.0), (1.0, 1.0)), 0.0)]
+    #[case::zero_length(edge((0.0, 0.0), (0.0, 0.0)), edge((0.0, 0.0), (1.0, 1.0)), 0.0)]
+    fn test_measure_angle(#[case] edge_a: Edge, #[case] edge_b: Edge, #[case] expected: f64) {
+        // expect
+        assert!((measure_angle(&edge_a, &edge_b) - expected).abs() < 1e-9);
+        assert!((measure_angle(&edge_b, &edge_a) - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_vertex_beyond_edges() {
+        // given
+        let edge_a = edge((1.0, 0.0), (2.0, 0.0));
+        let edge_b = edge((0.0, 1.0), (0.0, 2.0));
+
+        // when
+        let measurement = edge_a.angle_to(&edge_b).unwrap();
+
+        // then
+        assert_eq!(measurement.vertex, Point2::new(0.0, 0.0));
+        assert_eq!(measurement.direction_a, Vector2::new(1.0, 0.0));
+        assert_eq!(measurement.direction_b, Vector2::new(0.0, 1.0));
+    }
+
+    #[test]
+    fn test_line_meeting_arc() {
+        // given
+        // a line along the x axis to (1, 0), then a counter-clockwise quarter arc from (1, 0) to (0, 1)
+        let line = GerberPrimitive::Line(LineGerberPrimitive {
+            start: Point2::new(-1.0, 0.0),
+            end: Point2::new(1.0, 0.0),
+            width: 0.1,
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        });
+        let arc = GerberPrimitive::Arc(ArcGerberPrimitive {
+            center: Point2::new(0.0, 0.0),
+            radius: 1.0,
+            width: 0.1,
+            start_angle: 0.0,
+            sweep_angle: std::f64::consts::FRAC_PI_2,
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        });
+        let pick = Point2::new(1.0, 0.1);
+
+        // when
+        let line_edge = Edge::from_primitive(&line, pick).unwrap();
+        let arc_edge = Edge::from_primitive(&arc, pick).unwrap();
+
+        // then
+        // the arc leaves the end of the line at a right angle, heading up
+        assert!(distance(arc_edge.start, Point2::new(1.0, 0.0)) < 1e-9);
+        assert!(distance(arc_edge.end, Point2::new(1.0, 1.0)) < 1e-9);
+        assert!((measure_angle(&line_edge, &arc_edge) - 90.0).abs() < 1e-9);
+    }
+}
+
+#[cfg(test)]
+mod snap_to_grid_tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case::on_grid((1.0, 2.0), 0.5, (0.0, 0.0), (1.0, 2.0))]
+    #[case::nearest((1.26, -0.74), 0.5, (0.0, 0.0), (1.5, -0.5))]
+    #[case::offset_origin((1.26, 1.0), 0.5, (0.1, 0.2), (1.1, 1.2))]
+    #[case::zero_spacing((1.26, 1.0), 0.0, (0.0, 0.0), (1.26, 1.0))]
+    #[case::negative_spacing((1.26, 1.0), -0.5, (0.0, 0.0), (1.26, 1.0))]
+    fn test_snap_to_grid(
+        #[case] point: (f64, f64),
+        #[case] spacing: f64,
+        #[case] origin: (f64, f64),
+        #[case] expected: (f64, f64),
+    ) {
+        // when
+        let result = snap_to_grid(Point2::new(point.0, point.1), spacing, Point2::new(origin.0, origin.1));
+
+        // then
+        assert!(distance(result, Point2::new(expected.0, expected.1)) < 1e-9);
+    }
+}
+
+#[cfg(test)]
+mod tangent_tests {
+    use std::f64::consts::{FRAC_1_SQRT_2, FRAC_PI_2};
+
+    use rstest::rstest;
+
+    use super::*;
+    use crate::layer::PrimitiveOrigin;
+    use crate::types::Exposure;
+
+    fn line(start: (f64, f64), end: (f64, f64)) -> LineGerberPrimitive {
+        LineGerberPrimitive {
+            start: Point2::new(start.0, start.1),
+            end: Point2::new(end.0, end.1),
+            width: 0.1,
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        }
+    }
+
+    fn arc(sweep_angle: f64) -> ArcGerberPrimitive {
+        ArcGerberPrimitive {
+            center: Point2::new(1.0, 1.0),
+            radius: 2.0,
+            width: 0.1,
+            start_angle: 0.0,
+            sweep_angle,
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        }
+    }
+
+    fn assert_near(actual: Option<Vector2<f64>>, expected: Option<(f64, f64)>) {
+        match (actual, expected) {
+            (Some(actual), Some(expected)) => assert!(
+                (actual.x - expected.0).abs() < 1e-9 && (actual.y - expected.1).abs() < 1e-9,
+                "actual: {}, expected: {:?}",
+                actual,
+                expected
+            ),
+            (actual, expected) => assert_eq!(actual.map(|vector| (vector.x, vector.y)), expected),
+        }
+    }
+
+    #[rstest]
+    #[case::east(line((0.0, 0.0), (2.0, 0.0)), Some((1.0, 0.0)), Some((0.0, 1.0)))]
+    #[case::west(line((2.0, 0.0), (0.0, 0.0)), Some((-1.0, 0.0)), Some((0.0, -1.0)))]
+    #[case::north(line((0.0, 0.0), (0.0, 3.0)), Some((0.0, 1.0)), Some((-1.0, 0.0)))]
+    #[case::diagonal(
+        line((0.0, 0.0), (1.0, 1.0)),
+        Some((FRAC_1_SQRT_2, FRAC_1_SQRT_2)),
+        Some((-FRAC_1_SQRT_2, FRAC_1_SQRT_2))
+    )]
+    #[case::zero_length(line((1.0, 1.0), (1.0, 1.0)), None, None)]
+    fn test_line(
+        #[case] line: LineGerberPrimitive,
+        #[case] expected_tangent: Option<(f64, f64)>,
+        #[case] expected_normal: Option<(f64, f64)>,
+    ) {
+        // given
+        let point = Point2::new(0.5, 0.0);
+
+        // expect
+        assert_near(line.tangent_at(point), expected_tangent);
+        assert_near(line.normal_at(point), expected_normal);
+    }
+
+    #[rstest]
+    // east of the center, the start of the arc
+    #[case::counter_clockwise_start(FRAC_PI_2, (3.0, 1.0), Some((0.0, 1.0)), Some((1.0, 0.0)))]
+    #[case::clockwise_start(-FRAC_PI_2, (3.0, 1.0), Some((0.0, -1.0)), Some((1.0, 0.0)))]
+    // north of the center, off the circle
+    #[case::counter_clockwise_north(FRAC_PI_2, (1.0, 5.0), Some((-1.0, 0.0)), Some((0.0, 1.0)))]
+    #[case::clockwise_north(-FRAC_PI_2, (1.0, 5.0), Some((1.0, 0.0)), Some((0.0, 1.0)))]
+    #[case::full_circle(0.0, (1.0, -1.0), Some((1.0, 0.0)), Some((0.0, -1.0)))]
+    #[case::center(FRAC_PI_2, (1.0, 1.0), None, None)]
+    fn test_arc(
+        #[case] sweep_angle: f64,
+        #[case] point: (f64, f64),
+        #[case] expected_tangent: Option<(f64, f64)>,
+        #[case] expected_normal: Option<(f64, f64)>,
+    ) {
+        // given
+        let arc = arc(sweep_angle);
+        let point = Point2::new(point.0, point.1);
+
+        // expect
+        assert_near(arc.tangent_at(point), expected_tangent);
+        assert_near(arc.normal_at(point), expected_normal);
+    }
+}
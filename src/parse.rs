@@ -0,0 +1,333 @@
+use std::borrow::Cow;
+use std::io::BufReader;
+use std::str::FromStr;
+
+use gerber_parser::ParseError;
+use log::warn;
+use thiserror::Error;
+
+use crate::layer::{DeprecatedCodes, GerberLayer, LayerConfiguration};
+
+#[derive(Error, Debug)]
+pub enum GerberLoadError {
+    /// The source couldn't be read, e.g. it's not valid UTF-8.
+    #[error("Unable to parse gerber source: {0}")]
+    Parse(#[from] ParseError),
+}
+
+impl GerberLayer {
+    /// Parses a gerber file and builds the primitives, this is the recommended way to create a layer from a file.
+    ///
+    /// Commands that can't be parsed are skipped and logged, same as [`GerberDoc::into_commands`], an error is only
+    /// returned if the source can't be read at all.  Use [`gerber_parser::parse`] directly to inspect the parse errors,
+    /// and [`GerberLayer::from_bytes_with_configuration`] to build the layer with a non-default configuration.
+    ///
+    /// Requires the `parser` feature.
+    ///
+    /// [`GerberDoc::into_commands`]: gerber_parser::GerberDoc::into_commands
+    pub fn from_bytes(bytes: &[u8]) -> Result<GerberLayer, GerberLoadError> {
+        Self::from_bytes_with_configuration(bytes, &LayerConfiguration::default())
+    }
+
+    /// Same as [`GerberLayer::from_bytes`], using the given configuration.
+    pub fn from_bytes_with_configuration(
+        bytes: &[u8],
+        configuration: &LayerConfiguration,
+    ) -> Result<GerberLayer, GerberLoadError> {
+        let source = match (configuration.deprecated_codes, std::str::from_utf8(bytes)) {
+            (DeprecatedCodes::Interpret, Ok(source)) => Cow::Owned(remove_prepare_for_flash(source).into_bytes()),
+            // invalid UTF-8 is reported by the parser
+            _ => Cow::Borrowed(bytes),
+        };
+
+        let document = gerber_parser::parse(BufReader::new(source.as_ref())).map_err(|(_document, error)| error)?;
+
+        for error in document.errors() {
+            warn!("Skipped gerber command. error: {}", error);
+        }
+
+        Ok(GerberLayer::new_with_configuration(
+            document.into_commands(),
+            configuration,
+        ))
+    }
+
+    /// Parses a gerber file and builds the layer on a new thread, so that the UI stays responsive while a large file
+    /// is loaded, same as [`GerberLayer::from_bytes_with_configuration`].
+    ///
+    /// `on_loaded` is called on the background thread, with the layer or the error, e.g. send the result over a
+    /// channel that the UI polls each frame and call `egui::Context::request_repaint` so that the next frame runs.  If
+    /// parsing panics, `on_loaded` is not called.
+    ///
+    /// Threads can't be spawned on `wasm32-unknown-unknown`, use [`GerberLayer::load_with_executor`] there, e.g. with a
+    /// web worker pool, or load on the UI thread.
+    pub fn load_in_background(
+        bytes: Vec<u8>,
+        configuration: LayerConfiguration,
+        on_loaded: impl FnOnce(Result<GerberLayer, GerberLoadError>) + Send + 'static,
+    ) {
+        Self::load_with_executor(
+            bytes,
+            configuration,
+            |task| {
+                std::thread::spawn(task);
+            },
+            on_loaded,
+        );
+    }
+
+    /// Same as [`GerberLayer::load_in_background`], using the given executor instead of a new thread, e.g. a thread
+    /// pool, or a web worker on wasm.
+    ///
+    /// `spawn` is called once, with the task that loads the layer and then calls `on_loaded`.
+    pub fn load_with_executor(
+        bytes: Vec<u8>,
+        configuration: LayerConfiguration,
+        spawn: impl FnOnce(Box<dyn FnOnce() + Send>),
+        on_loaded: impl FnOnce(Result<GerberLayer, GerberLoadError>) + Send + 'static,
+    ) {
+        spawn(Box::new(move || {
+            on_loaded(Self::from_bytes_with_configuration(&bytes, &configuration));
+        }));
+    }
+}
+
+/// Removes the deprecated `G55` (prepare for flash) code, which the parser doesn't support, from each command, so that
+/// a flash that follows it isn't skipped, e.g. `G55X0Y0D03*` or the second command of `D10*G55X0Y0D03*`.
+///
+/// The parser only reads one command per line, so the other commands of a line with a `G55` are moved to lines of
+/// their own.  Lines that only have a `G55` are emptied, so the line numbers in the parse errors are unchanged unless a
+/// `G55` shares its line with several other commands.
+fn remove_prepare_for_flash(source: &str) -> String {
+    source
+        .split_inclusive('\n')
+        .map(|line| {
+            let commands = split_commands(line);
+            if !commands
+                .iter()
+                .any(|command| strip_prepare_for_flash(command).is_some())
+            {
+                return Cow::Borrowed(line);
+            }
+
+            warn!("Removed deprecated G55 code. line: {:?}", line.trim());
+
+            let commands = commands
+                .iter()
+                .map(|command| {
+                    strip_prepare_for_flash(command)
+                        .unwrap_or(command)
+                        .trim()
+                })
+                .filter(|command| !command.is_empty())
+                .collect::<Vec<_>>();
+            let line_ending = &line[line.trim_end().len()..];
+
+            Cow::Owned(format!("{}{}", commands.join("\n"), line_ending))
+        })
+        .collect()
+}
+
+/// Splits a line into its commands, each ends with a `*`, or the `%` of an extended command, which has `*`s inside.
+/// Anything after the last command, e.g. the line ending, is the last item.
+fn split_commands(line: &str) -> Vec<&str> {
+    let mut commands = Vec::new();
+    let mut start = 0;
+    let mut extended = false;
+
+    for (index, c) in line.char_indices() {
+        match c {
+            '%' => {
+                if extended {
+                    commands.push(&line[start..=index]);
+                    start = index + 1;
+                }
+                extended = !extended;
+            }
+            '*' if !extended => {
+                commands.push(&line[start..=index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    if start < line.len() {
+        commands.push(&line[start..]);
+    }
+
+    commands
+}
+
+/// The command without its `G55` prefix, an empty string for a `G55` on its own, or `None` if it isn't a `G55`.
+fn strip_prepare_for_flash(command: &str) -> Option<&str> {
+    let rest = command
+        .trim_start()
+        .strip_prefix("G55")?;
+    // e.g. `G550` is not a G55
+    if rest.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let rest = rest.trim_start();
+    Some(rest.strip_prefix('*').unwrap_or(rest))
+}
+
+/// Same as [`GerberLayer::from_bytes`].
+impl FromStr for GerberLayer {
+    type Err = GerberLoadError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        Self::from_bytes(source.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod from_bytes_tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::LayerWarning;
+
+    const SOURCE: &str = r#"
+%FSLAX26Y26*%
+%MOMM*%
+%ADD10C,0.5*%
+D10*
+X0Y0D03*
+X1000000Y0D03*
+M02*
+"#;
+
+    #[test]
+    fn test_from_bytes() {
+        // when
+        let layer = GerberLayer::from_bytes(SOURCE.as_bytes()).unwrap();
+
+        // then
+        assert_eq!(layer.primitives().len(), 2);
+    }
+
+    #[test]
+    fn test_load_in_background() {
+        // given
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        // when
+        GerberLayer::load_in_background(
+            SOURCE.as_bytes().to_vec(),
+            LayerConfiguration::default(),
+            move |result| sender.send(result).unwrap(),
+        );
+
+        // then
+        let layer = receiver.recv().unwrap().unwrap();
+        assert_eq!(layer.primitives().len(), 2);
+    }
+
+    #[test]
+    fn test_load_with_executor() {
+        // given
+        let mut tasks: Vec<Box<dyn FnOnce() + Send>> = vec![];
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        // when
+        GerberLayer::load_with_executor(
+            SOURCE.as_bytes().to_vec(),
+            LayerConfiguration::default(),
+            |task| tasks.push(task),
+            move |result| sender.send(result).unwrap(),
+        );
+
+        // then
+        // nothing is loaded until the executor runs the task
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(tasks.len(), 1);
+
+        // and
+        tasks.pop().unwrap()();
+        let layer = receiver.try_recv().unwrap().unwrap();
+        assert_eq!(layer.primitives().len(), 2);
+    }
+
+    #[test]
+    fn test_from_str() {
+        // when
+        let layer: GerberLayer = SOURCE.parse().unwrap();
+
+        // then
+        assert_eq!(layer.primitives().len(), 2);
+    }
+
+    const LEGACY_SOURCE: &[u8] = include_bytes!("../demo/assets/legacy-deprecated-codes.gbr");
+
+    #[rstest]
+    #[case::interpret(DeprecatedCodes::Interpret, 5)]
+    // the flashes on the same line as a G55 are skipped
+    #[case::skip(DeprecatedCodes::Skip, 3)]
+    fn test_deprecated_codes(#[case] deprecated_codes: DeprecatedCodes, #[case] expected_primitives: usize) {
+        // given
+        let configuration = LayerConfiguration {
+            deprecated_codes,
+            ..Default::default()
+        };
+
+        // when
+        let layer = GerberLayer::from_bytes_with_configuration(LEGACY_SOURCE, &configuration).unwrap();
+
+        // then
+        assert_eq!(layer.primitives().len(), expected_primitives);
+
+        // and each G54 has a warning
+        let deprecated_codes = layer
+            .warnings()
+            .iter()
+            .filter(|warning| {
+                matches!(warning, LayerWarning::DeprecatedCode {
+                    code: "G54",
+                    ..
+                })
+            })
+            .count();
+        assert_eq!(deprecated_codes, 3);
+        assert!(layer.unsupported_features().is_empty());
+    }
+
+    #[rstest]
+    #[case::own_line("G55*\nX0Y0D03*\n", "\nX0Y0D03*\n")]
+    #[case::prefix("G55X1Y1D03*\n", "X1Y1D03*\n")]
+    #[case::comment("G04 G55*\n", "G04 G55*\n")]
+    #[case::other_code("G550*\n", "G550*\n")]
+    #[case::after_another_command("D10*G55X0Y0D03*\n", "D10*\nX0Y0D03*\n")]
+    #[case::between_commands("D10*G55*X0Y0D03*\r\n", "D10*\nX0Y0D03*\r\n")]
+    #[case::after_an_extended_command("%ADD10C,0.5*%G55X0Y0D03*", "%ADD10C,0.5*%\nX0Y0D03*")]
+    #[case::other_lines_unchanged("D10*X0Y0D03*\n", "D10*X0Y0D03*\n")]
+    fn test_remove_prepare_for_flash(#[case] source: &str, #[case] expected: &str) {
+        // expect
+        assert_eq!(remove_prepare_for_flash(source), expected);
+    }
+
+    #[test]
+    fn test_prepare_for_flash_after_another_command() {
+        // given
+        let source = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10C,0.5*%\nD10*G55X0Y0D03*\nG55X1000000Y0D03*\nM02*\n";
+
+        // when
+        let layer = GerberLayer::from_bytes(source.as_bytes()).unwrap();
+
+        // then
+        assert_eq!(layer.primitives().len(), 2);
+        assert_eq!(layer.primitive_apertures(), &[Some(10), Some(10)]);
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        // given
+        let bytes = [b'D', b'1', b'0', 0xff, b'*'];
+
+        // expect
+        assert!(matches!(
+            GerberLayer::from_bytes(&bytes),
+            Err(GerberLoadError::Parse(_))
+        ));
+    }
+}
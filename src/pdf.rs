@@ -0,0 +1,450 @@
+use std::f64::consts::FRAC_PI_2;
+use std::fmt::Write as _;
+use std::io::Write;
+
+use gerber_types::{ImagePolarity, Unit};
+use nalgebra::{Point2, Vector2};
+
+use crate::layer::{GerberLayer, GerberPrimitive, RoundedRectangleGerberPrimitive};
+use crate::types::{Exposure, MM_PER_INCH};
+
+/// PDF units (points) per inch.
+const POINTS_PER_INCH: f64 = 72.0;
+
+/// Margin around the bounding box of the layer, in PDF units (points), 10mm.
+const PAGE_MARGIN: f64 = 10.0 / MM_PER_INCH * POINTS_PER_INCH;
+
+/// Number of points used to draw each arc.
+const ARC_STEPS: usize = 64;
+
+/// Distance of the control points of a cubic bezier quarter circle, relative to the radius.
+const BEZIER_CIRCLE: f64 = 0.552_284_75;
+
+/// The colors of a PDF, as RGB, see [`GerberLayer::write_pdf_with_colors`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PdfColors {
+    /// The color of the page, i.e. the area outside the primitives, including the margin, and of primitives that cut
+    /// out material.
+    pub background: (u8, u8, u8),
+    /// The color of primitives that add material, e.g. copper.
+    pub foreground: (u8, u8, u8),
+}
+
+impl Default for PdfColors {
+    /// Black on white, same as [`FilmPolarity::Negative`].
+    fn default() -> Self {
+        FilmPolarity::Negative.colors()
+    }
+}
+
+/// Presets for [`PdfColors`], for photoplotter-ready output.
+///
+/// | Polarity   | Background | Foreground (copper) |
+/// |------------|------------|---------------------|
+/// | `Positive` | black      | white               |
+/// | `Negative` | white      | black               |
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilmPolarity {
+    Positive,
+    Negative,
+}
+
+impl FilmPolarity {
+    pub fn colors(self) -> PdfColors {
+        const BLACK: (u8, u8, u8) = (0, 0, 0);
+        const WHITE: (u8, u8, u8) = (255, 255, 255);
+
+        match self {
+            FilmPolarity::Positive => PdfColors {
+                background: BLACK,
+                foreground: WHITE,
+            },
+            FilmPolarity::Negative => PdfColors {
+                background: WHITE,
+                foreground: BLACK,
+            },
+        }
+    }
+}
+
+impl GerberLayer {
+    /// Writes a one-page PDF of the layer using vector paths, e.g. for documentation or printing.
+    ///
+    /// The page is the bounding box of the layer with a 10mm margin, at 1:1 scale.  Primitives that add material are
+    /// black and primitives that cut out material are white, on a white page, see
+    /// [`GerberLayer::write_pdf_with_colors`] for other colors.
+    ///
+    /// Like [`GerberLayer::to_commands`], the image transform is not applied, use [`GerberLayer::baked`] first.
+    /// Arcs are written as polylines, circles as bezier curves.  Every repeat of the [`GerberLayer::step_repeats`] is
+    /// drawn.
+    pub fn write_pdf<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.write_pdf_with_colors(writer, PdfColors::default())
+    }
+
+    /// Same as [`GerberLayer::write_pdf`], with the given colors, e.g. `FilmPolarity::Positive.colors()`.
+    ///
+    /// The whole page is filled with the background color, primitives that add material use the foreground color and
+    /// primitives that cut out material use the background color.  For layers with a negative image polarity the
+    /// bounding box is filled with the foreground color first, like the renderer does.
+    pub fn write_pdf_with_colors<W: Write>(&self, writer: &mut W, colors: PdfColors) -> std::io::Result<()> {
+        let points_per_unit = match self.units() {
+            Some(Unit::Inches) => POINTS_PER_INCH,
+            Some(Unit::Millimeters) | None => POINTS_PER_INCH / MM_PER_INCH,
+        };
+
+        let (min, width, height) = match self.try_bounding_box() {
+            Some(bbox) => (bbox.min, bbox.width(), bbox.height()),
+            None => (Point2::new(0.0, 0.0), 0.0, 0.0),
+        };
+        let page = PdfPage {
+            min,
+            points_per_unit,
+            colors,
+        };
+        let media_box = [
+            width * points_per_unit + PAGE_MARGIN * 2.0,
+            height * points_per_unit + PAGE_MARGIN * 2.0,
+        ];
+
+        let mut content = String::new();
+        let _ = writeln!(
+            content,
+            "{} rg 0 0 {} {} re f",
+            color(colors.background),
+            number(media_box[0]),
+            number(media_box[1])
+        );
+        if self.image_polarity() == ImagePolarity::Negative && !self.is_empty() {
+            let _ = writeln!(
+                content,
+                "{} rg {} {} {} {} re f",
+                color(colors.foreground),
+                number(PAGE_MARGIN),
+                number(PAGE_MARGIN),
+                number(width * points_per_unit),
+                number(height * points_per_unit)
+            );
+        }
+        for primitive in self.expanded_primitives().iter() {
+            page.write_primitive(&mut content, primitive);
+        }
+
+        let objects = [
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Contents 4 0 R >>",
+                number(media_box[0]),
+                number(media_box[1])
+            ),
+            format!("<< /Length {} >>\nstream\n{}endstream", content.len(), content),
+        ];
+
+        let mut pdf = String::from("%PDF-1.4\n");
+        let mut offsets = Vec::with_capacity(objects.len());
+        for (index, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            let _ = writeln!(pdf, "{} 0 obj\n{}\nendobj", index + 1, object);
+        }
+
+        let xref_offset = pdf.len();
+        let _ = writeln!(pdf, "xref\n0 {}\n0000000000 65535 f ", objects.len() + 1);
+        for offset in offsets {
+            let _ = writeln!(pdf, "{:010} 00000 n ", offset);
+        }
+        let _ = writeln!(
+            pdf,
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF",
+            objects.len() + 1,
+            xref_offset
+        );
+
+        writer.write_all(pdf.as_bytes())
+    }
+}
+
+struct PdfPage {
+    /// The gerber coordinates of the bottom-left corner of the content, i.e. inside the margin.
+    min: Point2<f64>,
+    points_per_unit: f64,
+    colors: PdfColors,
+}
+
+impl PdfPage {
+    /// Converts gerber coordinates to PDF coordinates, both have the Y axis pointing up.
+    fn point(&self, point: Point2<f64>) -> String {
+        format!(
+            "{} {}",
+            number((point.x - self.min.x) * self.points_per_unit + PAGE_MARGIN),
+            number((point.y - self.min.y) * self.points_per_unit + PAGE_MARGIN)
+        )
+    }
+
+    fn length(&self, length: f64) -> String {
+        number(length * self.points_per_unit)
+    }
+
+    fn write_primitive(&self, content: &mut String, primitive: &GerberPrimitive) {
+        let rgb = match primitive.exposure() {
+            Exposure::Add => color(self.colors.foreground),
+            Exposure::CutOut => color(self.colors.background),
+        };
+        let _ = writeln!(content, "{} rg {} RG", rgb, rgb);
+
+        match primitive {
+            GerberPrimitive::Circle(circle) => {
+                self.write_circle(content, circle.center, circle.diameter / 2.0);
+                match circle.hole_diameter {
+                    Some(hole_diameter) if hole_diameter > 0.0 => {
+                        self.write_circle(content, circle.center, hole_diameter / 2.0);
+                        // even-odd, so the hole is not filled
+                        content.push_str("f*\n");
+                    }
+                    _ => content.push_str("f\n"),
+                }
+            }
+            GerberPrimitive::Rectangle(rectangle) => {
+                let _ = writeln!(
+                    content,
+                    "{} {} {} re f",
+                    self.point(rectangle.origin),
+                    self.length(rectangle.width),
+                    self.length(rectangle.height)
+                );
+            }
+            GerberPrimitive::RoundedRectangle(rectangle) => {
+                self.write_rounded_rectangle(content, rectangle);
+                content.push_str("f\n");
+            }
+            GerberPrimitive::Line(line) => {
+                // lines are drawn with a circular aperture, so they have round ends
+                let _ = writeln!(
+                    content,
+                    "{} w 1 J {} m {} l S",
+                    self.length(line.width),
+                    self.point(line.start),
+                    self.point(line.end)
+                );
+            }
+            GerberPrimitive::Arc(arc) => {
+                // arcs have flat ends
+                let _ = write!(content, "{} w 0 J", self.length(arc.width));
+                for (index, point) in arc
+                    .generate_points_with_steps(ARC_STEPS)
+                    .iter()
+                    .enumerate()
+                {
+                    let operator = match index {
+                        0 => "m",
+                        _ => "l",
+                    };
+                    let _ = write!(content, " {} {}", self.point(arc.center + point.coords), operator);
+                }
+                content.push_str(" S\n");
+            }
+            GerberPrimitive::Polygon(polygon) => {
+                for (index, vertex) in polygon
+                    .geometry
+                    .relative_vertices
+                    .iter()
+                    .enumerate()
+                {
+                    let operator = match index {
+                        0 => "m",
+                        _ => "l",
+                    };
+                    let _ = write!(content, "{} {} ", self.point(polygon.center + vertex.coords), operator);
+                }
+                content.push_str("h f\n");
+            }
+        }
+    }
+
+    /// Adds a closed path, made of four lines and a bezier curve for each corner, without filling it.
+    fn write_rounded_rectangle(&self, content: &mut String, rectangle: &RoundedRectangleGerberPrimitive) {
+        let radius = rectangle.corner_radius;
+        let min = rectangle.origin + Vector2::new(radius, radius);
+        let max = rectangle.origin + Vector2::new(rectangle.width - radius, rectangle.height - radius);
+        let centers = [
+            Point2::new(max.x, min.y),
+            Point2::new(max.x, max.y),
+            Point2::new(min.x, max.y),
+            Point2::new(min.x, min.y),
+        ];
+        let at = |center: Point2<f64>, angle: f64, tangent: f64| {
+            let (sin, cos) = angle.sin_cos();
+            self.point(center + (Vector2::new(cos, sin) + Vector2::new(-sin, cos) * tangent) * radius)
+        };
+
+        for (index, center) in centers.into_iter().enumerate() {
+            // counter-clockwise, starting with the bottom right corner
+            let start_angle = (index as f64 - 1.0) * FRAC_PI_2;
+            let end_angle = start_angle + FRAC_PI_2;
+            let operator = match index {
+                0 => "m",
+                _ => "l",
+            };
+            let _ = writeln!(content, "{} {}", at(center, start_angle, 0.0), operator);
+            let _ = writeln!(
+                content,
+                "{} {} {} c",
+                at(center, start_angle, BEZIER_CIRCLE),
+                at(center, end_angle, -BEZIER_CIRCLE),
+                at(center, end_angle, 0.0)
+            );
+        }
+        content.push_str("h\n");
+    }
+
+    /// Adds a closed circle path, made of four bezier curves, without filling it.
+    fn write_circle(&self, content: &mut String, center: Point2<f64>, radius: f64) {
+        let at = |x: f64, y: f64| self.point(center + Vector2::new(x, y) * radius);
+        let k = BEZIER_CIRCLE;
+
+        let _ = writeln!(content, "{} m", at(1.0, 0.0));
+        for [control_1, control_2, end] in [
+            [(1.0, k), (k, 1.0), (0.0, 1.0)],
+            [(-k, 1.0), (-1.0, k), (-1.0, 0.0)],
+            [(-1.0, -k), (-k, -1.0), (0.0, -1.0)],
+            [(k, -1.0), (1.0, -k), (1.0, 0.0)],
+        ] {
+            let _ = writeln!(
+                content,
+                "{} {} {} c",
+                at(control_1.0, control_1.1),
+                at(control_2.0, control_2.1),
+                at(end.0, end.1)
+            );
+        }
+        content.push_str("h\n");
+    }
+}
+
+/// Formats a color for the `rg` and `RG` operators.
+fn color((red, green, blue): (u8, u8, u8)) -> String {
+    let component = |value: u8| number(value as f64 / 255.0);
+
+    format!("{} {} {}", component(red), component(green), component(blue))
+}
+
+/// Formats a number for a PDF content stream, PDF doesn't allow exponents.
+fn number(value: f64) -> String {
+    let formatted = format!("{:.4}", value);
+    let trimmed = formatted
+        .trim_end_matches('0')
+        .trim_end_matches('.');
+
+    match trimmed {
+        "-0" => "0".to_string(),
+        _ => trimmed.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod write_pdf_tests {
+    use gerber_types::{Aperture, Command, ExtendedCode, Rectangular};
+
+    use super::*;
+    use crate::testing::commands::{circle, definition, flash, format, select};
+
+    fn layer() -> GerberLayer {
+        GerberLayer::new(vec![
+            Command::ExtendedCode(ExtendedCode::Unit(Unit::Inches)),
+            Command::ExtendedCode(ExtendedCode::CoordinateFormat(format())),
+            circle(10, 0.5),
+            definition(11, Aperture::Rectangle(Rectangular::new(1.0, 0.5))),
+            select(10),
+            flash(0.25, 0.25),
+            select(11),
+            flash(1.5, 0.25),
+        ])
+    }
+
+    #[test]
+    fn test_write_pdf() {
+        // given
+        let layer = layer();
+        let mut buffer = Vec::new();
+
+        // when
+        layer.write_pdf(&mut buffer).unwrap();
+
+        // then
+        let pdf = String::from_utf8(buffer).unwrap();
+        assert!(pdf.starts_with("%PDF-1.4\n"));
+        assert!(pdf.ends_with("%%EOF\n"));
+
+        // 2 x 0.5 inches, plus a 10mm margin on each side
+        assert!(pdf.contains(&format!(
+            "/MediaBox [0 0 {} {}]",
+            number(144.0 + PAGE_MARGIN * 2.0),
+            number(36.0 + PAGE_MARGIN * 2.0)
+        )));
+
+        // the circle and the rectangle
+        assert_eq!(pdf.matches(" c\n").count(), 4);
+        let rectangle_origin = format!("{} {}", number(72.0 + PAGE_MARGIN), number(PAGE_MARGIN));
+        assert!(pdf.contains(&format!("{} 72 36 re f", rectangle_origin)));
+    }
+
+    #[test]
+    fn test_xref_offsets() {
+        // given
+        let layer = layer();
+        let mut buffer = Vec::new();
+
+        // when
+        layer.write_pdf(&mut buffer).unwrap();
+
+        // then
+        let pdf = String::from_utf8(buffer).unwrap();
+        let xref = &pdf[pdf.find("xref\n").unwrap()..];
+        let offsets = xref
+            .lines()
+            .skip(3)
+            .take(4)
+            .map(|line| line[..10].parse::<usize>().unwrap())
+            .collect::<Vec<_>>();
+
+        for (index, offset) in offsets.into_iter().enumerate() {
+            assert!(pdf[offset..].starts_with(&format!("{} 0 obj\n", index + 1)));
+        }
+    }
+
+    #[test]
+    fn test_film_polarity() {
+        // given
+        let layer = layer();
+        let mut buffer = Vec::new();
+
+        // when
+        layer
+            .write_pdf_with_colors(&mut buffer, FilmPolarity::Positive.colors())
+            .unwrap();
+
+        // then
+        let pdf = String::from_utf8(buffer).unwrap();
+        // the page is black and the copper is white
+        assert!(pdf.contains("stream\n0 0 0 rg 0 0 "));
+        assert_eq!(
+            pdf.matches("1 1 1 rg 1 1 1 RG\n")
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_default_colors() {
+        assert_eq!(PdfColors::default().background, (255, 255, 255));
+        assert_eq!(PdfColors::default().foreground, (0, 0, 0));
+        assert_eq!(color((255, 0, 51)), "1 0 0.2");
+    }
+
+    #[test]
+    fn test_number() {
+        assert_eq!(number(1.0), "1");
+        assert_eq!(number(1.25), "1.25");
+        assert_eq!(number(-0.00001), "0");
+        assert_eq!(number(1e-12), "0");
+    }
+}
@@ -0,0 +1,1142 @@
+use std::collections::HashSet;
+use std::f64::consts::TAU;
+
+use nalgebra::{Point2, Vector2};
+
+use crate::geometry::BoundingBox;
+use crate::layer::{
+    ArcGerberPrimitive, CircleGerberPrimitive, GerberLayer, GerberPrimitive, LineGerberPrimitive,
+    PolygonGerberPrimitive, RectangleGerberPrimitive, RoundedRectangleGerberPrimitive,
+};
+
+/// A primitive of a layer and the repeat of the step-and-repeat block it's drawn in, see
+/// [`GerberLayer::instance_at`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrimitiveInstance {
+    /// The index of the primitive, see [`GerberLayer::primitives`].
+    pub index: usize,
+    /// The index of the offset in [`StepRepeatBlock::offsets`](crate::StepRepeatBlock::offsets), `0` for primitives
+    /// that are not in a block.
+    pub repeat_index: usize,
+}
+
+impl GerberLayer {
+    /// Returns the index of the top-most primitive that contains the point, i.e. the last one in drawing order.
+    ///
+    /// `point` is in gerber units, before the image transform and any render transform are applied.
+    ///
+    /// Primitives that cut out material are included, hovering a hole in a pad returns the primitive for the hole.
+    pub fn primitive_at(&self, point: Point2<f64>) -> Option<usize> {
+        let grid = self.spatial_index();
+        let (column, row) = grid.cell_of(point);
+        let primitives = self.primitives();
+
+        grid.cell(column, row)
+            .iter()
+            .rev()
+            .copied()
+            .find(|&index| primitives[index].contains(point))
+    }
+
+    /// Returns the top-most primitive that contains the point and the repeat it belongs to, for layers with
+    /// step-and-repeat blocks that are drawn at several offsets, see
+    /// [`LayerConfiguration::instanced_step_repeat`](crate::LayerConfiguration::instanced_step_repeat).
+    ///
+    /// Same as [`GerberLayer::primitive_at`] otherwise, primitives outside the blocks are repeat `0`.  `point` is in
+    /// gerber units, before the image transform and any render transform are applied.
+    pub fn instance_at(&self, point: Point2<f64>) -> Option<PrimitiveInstance> {
+        let primitives = self.primitives();
+        let step_repeats = self.step_repeats();
+        if step_repeats.is_empty() {
+            return self
+                .primitive_at(point)
+                .map(|index| PrimitiveInstance {
+                    index,
+                    repeat_index: 0,
+                });
+        }
+
+        // the drawing order of the instances, (the start of the block, the repeat index, the index)
+        let mut top: Option<((usize, usize, usize), PrimitiveInstance)> = None;
+        let mut consider = |key: (usize, usize, usize), instance: PrimitiveInstance| {
+            if top.is_none_or(|(top_key, _)| key > top_key) {
+                top = Some((key, instance));
+            }
+        };
+
+        for index in self.primitives_at(point) {
+            if !step_repeats
+                .iter()
+                .any(|step_repeat| step_repeat.primitives.contains(&index))
+            {
+                consider((index, 0, index), PrimitiveInstance {
+                    index,
+                    repeat_index: 0,
+                });
+            }
+        }
+
+        for step_repeat in step_repeats {
+            let block = step_repeat.block_bounding_box(primitives);
+            // the later repeats are on top
+            for (repeat_index, offset) in step_repeat
+                .offsets
+                .iter()
+                .enumerate()
+                .rev()
+            {
+                let local = point - offset;
+                if local.x < block.min.x || local.x > block.max.x || local.y < block.min.y || local.y > block.max.y {
+                    continue;
+                }
+                if let Some(index) = self
+                    .primitives_at(local)
+                    .into_iter()
+                    .rfind(|index| step_repeat.primitives.contains(index))
+                {
+                    consider((step_repeat.primitives.start, repeat_index, index), PrimitiveInstance {
+                        index,
+                        repeat_index,
+                    });
+                    break;
+                }
+            }
+        }
+
+        top.map(|(_, instance)| instance)
+    }
+
+    /// Returns the indices of all the primitives that contain the point, back-to-front, i.e. in drawing order, the
+    /// last one is the one returned by [`GerberLayer::primitive_at`].
+    ///
+    /// Useful for cycling the selection through overlapping primitives, e.g. on repeated clicks.  `point` is in gerber
+    /// units, before the image transform and any render transform are applied.  Primitives that cut out material are
+    /// included.
+    pub fn primitives_at(&self, point: Point2<f64>) -> Vec<usize> {
+        let grid = self.spatial_index();
+        let (column, row) = grid.cell_of(point);
+        let primitives = self.primitives();
+
+        grid.cell(column, row)
+            .iter()
+            .copied()
+            .filter(|&index| primitives[index].contains(point))
+            .collect()
+    }
+
+    /// Returns the index of the primitive with the boundary closest to `query`, and the closest point on that boundary.
+    ///
+    /// The boundary is the outline of the area covered by the primitive, e.g. the edges of a line's capsule (including
+    /// the round ends), both edges of an arc's band or the outer and inner circles of a circle with a hole, so the
+    /// distance between two points on different primitives is the clearance between them.
+    ///
+    /// `query` is in gerber units, before the image transform and any render transform are applied.  The query point
+    /// can be inside or outside the primitive.  Primitives that cut out material are included.
+    ///
+    /// Returns `None` if the layer has no primitives.
+    pub fn nearest_boundary_point(&self, query: Point2<f64>) -> Option<(usize, Point2<f64>)> {
+        let grid = self.spatial_index();
+        let primitives = self.primitives();
+
+        let mut best: Option<(usize, Point2<f64>, f64)> = None;
+        let mut seen: HashSet<usize> = HashSet::new();
+
+        for ring in grid.rings(query) {
+            // the cells in a ring are at least `ring - 1` cells away from the query point, and every point of a
+            // primitive is in one of the cells the primitive is indexed in.
+            if let Some((_, _, distance)) = best
+                && (ring.distance as f64 - 1.0) * grid.cell_size > distance
+            {
+                break;
+            }
+
+            for index in ring.primitives(grid) {
+                if !seen.insert(index) {
+                    continue;
+                }
+
+                let point = primitives[index].nearest_boundary_point(query);
+                let distance = length(point - query);
+                if best.is_none_or(|(_, _, best_distance)| distance < best_distance) {
+                    best = Some((index, point, distance));
+                }
+            }
+        }
+
+        best.map(|(index, point, _)| (index, point))
+    }
+
+    /// Returns the indices of the primitives selected by a rectangle, e.g. a rubber-band drawn for box-selection, in
+    /// drawing order.
+    ///
+    /// `rect` is in gerber units, before the image transform and any render transform are applied, i.e. the same
+    /// coordinates as the primitives.  A rubber-band drawn on screen has to be converted first, and if the transforms
+    /// rotate the layer the rubber-band is no longer axis-aligned, the bounding box of its corners can be used instead.
+    ///
+    /// With [`SelectMode::Intersect`] the bounding box of a primitive only has to overlap the rectangle, so a
+    /// primitive can be selected when the rectangle only overlaps the corner of its bounding box, e.g. the empty corner
+    /// of a circle or a diagonal line.  Primitives that cut out material are included.
+    pub fn primitives_in_rect(&self, rect: &BoundingBox, mode: SelectMode) -> Vec<usize> {
+        if rect.is_empty() {
+            return Vec::new();
+        }
+
+        let grid = self.spatial_index();
+        let primitives = self.primitives();
+
+        let mut indices = grid
+            .primitives_overlapping(rect)
+            .filter(|&index| {
+                let bbox = primitives[index].bounding_box();
+                match mode {
+                    SelectMode::Intersect => {
+                        bbox.min.x <= rect.max.x
+                            && bbox.max.x >= rect.min.x
+                            && bbox.min.y <= rect.max.y
+                            && bbox.max.y >= rect.min.y
+                    }
+                    SelectMode::Contained => {
+                        bbox.min.x >= rect.min.x
+                            && bbox.max.x <= rect.max.x
+                            && bbox.min.y >= rect.min.y
+                            && bbox.max.y <= rect.max.y
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // a primitive is in each cell that its bounding box overlaps
+        indices.sort_unstable();
+        indices.dedup();
+        indices
+    }
+
+    /// Returns the cells of the spatial index used by the queries, with the number of primitives in each cell, a
+    /// debugging aid, see `RenderConfiguration::use_spatial_index_cells`.
+    ///
+    /// Empty cells are omitted.  Cells are in gerber units, before the image transform and any render transform are
+    /// applied.  Builds the index if it hasn't been built yet.
+    pub fn spatial_index_cells(&self) -> Vec<(BoundingBox, usize)> {
+        self.spatial_index().non_empty_cells()
+    }
+}
+
+/// Which primitives are selected by [`GerberLayer::primitives_in_rect`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SelectMode {
+    /// Primitives that overlap the rectangle, including ones that are partially outside it.
+    #[default]
+    Intersect,
+    /// Only primitives that are entirely inside the rectangle, edges are inclusive.
+    Contained,
+}
+
+/// A uniform grid of cells, each holding the indices of the primitives whose bounding box overlaps the cell, in
+/// drawing order.
+///
+/// The cell size is chosen so there are roughly as many cells as primitives.
+#[derive(Debug, Clone)]
+pub(crate) struct PrimitiveGrid {
+    origin: Point2<f64>,
+    cell_size: f64,
+    columns: i64,
+    rows: i64,
+    cells: Vec<Vec<usize>>,
+}
+
+impl PrimitiveGrid {
+    pub(crate) fn new(primitives: &[GerberPrimitive], bounding_box: &BoundingBox) -> Self {
+        if primitives.is_empty() || bounding_box.is_empty() {
+            return Self {
+                origin: Point2::new(0.0, 0.0),
+                cell_size: 1.0,
+                columns: 0,
+                rows: 0,
+                cells: Vec::new(),
+            };
+        }
+
+        let divisions = (primitives.len() as f64).sqrt().ceil();
+        let cell_size = match bounding_box
+            .width()
+            .max(bounding_box.height())
+            / divisions
+        {
+            size if size > 0.0 => size,
+            _ => 1.0,
+        };
+
+        let mut grid = Self {
+            origin: bounding_box.min,
+            cell_size,
+            columns: (bounding_box.width() / cell_size).floor() as i64 + 1,
+            rows: (bounding_box.height() / cell_size).floor() as i64 + 1,
+            cells: Vec::new(),
+        };
+        grid.cells = vec![Vec::new(); (grid.columns * grid.rows) as usize];
+
+        for (index, primitive) in primitives.iter().enumerate() {
+            let bbox = primitive.bounding_box();
+            let (min_column, min_row) = grid.clamped_cell_of(bbox.min);
+            let (max_column, max_row) = grid.clamped_cell_of(bbox.max);
+
+            for row in min_row..=max_row {
+                for column in min_column..=max_column {
+                    grid.cells[(row * grid.columns + column) as usize].push(index);
+                }
+            }
+        }
+
+        grid
+    }
+
+    fn non_empty_cells(&self) -> Vec<(BoundingBox, usize)> {
+        (0..self.rows)
+            .flat_map(|row| (0..self.columns).map(move |column| (column, row)))
+            .filter_map(|(column, row)| {
+                let count = self.cell(column, row).len();
+                if count == 0 {
+                    return None;
+                }
+
+                let min = self.origin + Vector2::new(column as f64, row as f64) * self.cell_size;
+                let bbox = BoundingBox {
+                    min,
+                    max: min + Vector2::new(self.cell_size, self.cell_size),
+                };
+                Some((bbox, count))
+            })
+            .collect()
+    }
+
+    /// The primitives in the cells that overlap the bounding box, a primitive in several cells is returned once for
+    /// each cell.
+    fn primitives_overlapping(&self, bbox: &BoundingBox) -> impl Iterator<Item = usize> + '_ {
+        let (min_column, min_row) = self.cell_of(bbox.min);
+        let (max_column, max_row) = self.cell_of(bbox.max);
+        // only the cells inside the grid, the range is empty if the bounding box is outside the grid
+        let columns = min_column.max(0)..=max_column.min(self.columns - 1);
+        let rows = min_row.max(0)..=max_row.min(self.rows - 1);
+
+        rows.flat_map(move |row| {
+            columns
+                .clone()
+                .map(move |column| (column, row))
+        })
+        .flat_map(|(column, row)| self.cell(column, row).iter().copied())
+    }
+
+    /// The cell containing the point, which may be outside the grid.
+    fn cell_of(&self, point: Point2<f64>) -> (i64, i64) {
+        (
+            ((point.x - self.origin.x) / self.cell_size).floor() as i64,
+            ((point.y - self.origin.y) / self.cell_size).floor() as i64,
+        )
+    }
+
+    fn clamped_cell_of(&self, point: Point2<f64>) -> (i64, i64) {
+        let (column, row) = self.cell_of(point);
+
+        (column.clamp(0, self.columns - 1), row.clamp(0, self.rows - 1))
+    }
+
+    /// The primitives in the cell, empty if the cell is outside the grid.
+    fn cell(&self, column: i64, row: i64) -> &[usize] {
+        match (0..self.columns).contains(&column) && (0..self.rows).contains(&row) {
+            true => &self.cells[(row * self.columns + column) as usize],
+            false => &[],
+        }
+    }
+
+    /// Square rings of cells around the point, nearest first, starting with the first ring that overlaps the grid and
+    /// ending with the ring that covers the rest of the grid.
+    fn rings(&self, point: Point2<f64>) -> impl Iterator<Item = Ring> + use<> {
+        let (column, row) = self.cell_of(point);
+
+        let first = 0
+            .max(-column)
+            .max(column - (self.columns - 1))
+            .max(-row)
+            .max(row - (self.rows - 1));
+        let last = column
+            .max(self.columns - 1 - column)
+            .max(row)
+            .max(self.rows - 1 - row);
+
+        // an empty grid has no rings
+        let last = match self.cells.is_empty() {
+            true => first - 1,
+            false => last,
+        };
+
+        (first..=last).map(move |distance| Ring {
+            column,
+            row,
+            distance,
+        })
+    }
+}
+
+/// The cells at a given chebyshev distance from a center cell.
+struct Ring {
+    column: i64,
+    row: i64,
+    distance: i64,
+}
+
+impl Ring {
+    /// The primitives in the cells of the ring, a primitive in several cells is returned once for each cell.
+    fn primitives<'a>(&self, grid: &'a PrimitiveGrid) -> impl Iterator<Item = usize> + 'a {
+        let (column, row, distance) = (self.column, self.row, self.distance);
+
+        (row - distance..=row + distance)
+            .flat_map(move |y| {
+                let edge = y == row - distance || y == row + distance;
+                let columns: Vec<i64> = match edge {
+                    true => (column - distance..=column + distance).collect(),
+                    false => vec![column - distance, column + distance],
+                };
+                columns.into_iter().map(move |x| (x, y))
+            })
+            .flat_map(move |(x, y)| grid.cell(x, y).iter().copied())
+    }
+}
+
+impl GerberPrimitive {
+    /// Returns true if the point is inside the area covered by the primitive, edges are inclusive.
+    ///
+    /// `point` is in gerber units.
+    pub fn contains(&self, point: Point2<f64>) -> bool {
+        let bbox = self.bounding_box();
+        if point.x < bbox.min.x || point.x > bbox.max.x || point.y < bbox.min.y || point.y > bbox.max.y {
+            return false;
+        }
+
+        match self {
+            GerberPrimitive::Circle(circle) => circle.contains(point),
+            GerberPrimitive::Rectangle(rectangle) => rectangle.contains(point),
+            GerberPrimitive::RoundedRectangle(rectangle) => rectangle.contains(point),
+            GerberPrimitive::Line(line) => line.contains(point),
+            GerberPrimitive::Arc(arc) => arc.contains(point),
+            GerberPrimitive::Polygon(polygon) => polygon.contains(point),
+        }
+    }
+
+    /// Returns the point on the boundary of the primitive that is closest to `point`, see
+    /// [`GerberLayer::nearest_boundary_point`].
+    ///
+    /// `point` is in gerber units.
+    pub fn nearest_boundary_point(&self, point: Point2<f64>) -> Point2<f64> {
+        match self {
+            GerberPrimitive::Circle(circle) => circle.nearest_boundary_point(point),
+            GerberPrimitive::Rectangle(rectangle) => rectangle.nearest_boundary_point(point),
+            GerberPrimitive::RoundedRectangle(rectangle) => rectangle.nearest_boundary_point(point),
+            GerberPrimitive::Line(line) => line.nearest_boundary_point(point),
+            GerberPrimitive::Arc(arc) => arc.nearest_boundary_point(point),
+            GerberPrimitive::Polygon(polygon) => polygon.nearest_boundary_point(point),
+        }
+    }
+}
+
+impl CircleGerberPrimitive {
+    fn contains(&self, point: Point2<f64>) -> bool {
+        let distance = length(point - self.center);
+        let hole_radius = self.hole_diameter.unwrap_or(0.0) / 2.0;
+
+        distance <= self.diameter / 2.0 && (hole_radius == 0.0 || distance >= hole_radius)
+    }
+
+    fn nearest_boundary_point(&self, point: Point2<f64>) -> Point2<f64> {
+        let direction = direction_or_x(point - self.center);
+        let outer = self.center + direction * (self.diameter / 2.0);
+
+        match self.hole_diameter {
+            Some(hole_diameter) if hole_diameter > 0.0 => {
+                let inner = self.center + direction * (hole_diameter / 2.0);
+                nearest_of(point, [outer, inner])
+            }
+            _ => outer,
+        }
+    }
+}
+
+impl RectangleGerberPrimitive {
+    fn contains(&self, point: Point2<f64>) -> bool {
+        let relative = point - self.origin;
+
+        (0.0..=self.width).contains(&relative.x) && (0.0..=self.height).contains(&relative.y)
+    }
+
+    fn nearest_boundary_point(&self, point: Point2<f64>) -> Point2<f64> {
+        let max = self.origin + Vector2::new(self.width, self.height);
+
+        if !self.contains(point) {
+            return Point2::new(point.x.clamp(self.origin.x, max.x), point.y.clamp(self.origin.y, max.y));
+        }
+
+        nearest_of(point, [
+            Point2::new(self.origin.x, point.y),
+            Point2::new(max.x, point.y),
+            Point2::new(point.x, self.origin.y),
+            Point2::new(point.x, max.y),
+        ])
+    }
+}
+
+impl RoundedRectangleGerberPrimitive {
+    /// The rectangle between the centers of the corners, the rounded rectangle is this rectangle grown by the corner
+    /// radius.
+    fn inner(&self) -> (Point2<f64>, Point2<f64>) {
+        let radius = self.corner_radius;
+
+        (
+            self.origin + Vector2::new(radius, radius),
+            self.origin + Vector2::new(self.width - radius, self.height - radius),
+        )
+    }
+
+    fn nearest_inner_point(&self, point: Point2<f64>) -> Point2<f64> {
+        let (min, max) = self.inner();
+
+        Point2::new(point.x.clamp(min.x, max.x), point.y.clamp(min.y, max.y))
+    }
+
+    fn contains(&self, point: Point2<f64>) -> bool {
+        length(point - self.nearest_inner_point(point)) <= self.corner_radius
+    }
+
+    fn nearest_boundary_point(&self, point: Point2<f64>) -> Point2<f64> {
+        let on_inner = self.nearest_inner_point(point);
+        if on_inner != point {
+            return on_inner + direction_or_x(point - on_inner) * self.corner_radius;
+        }
+
+        // inside the inner rectangle, the nearest edge is straight
+        let max = self.origin + Vector2::new(self.width, self.height);
+        nearest_of(point, [
+            Point2::new(self.origin.x, point.y),
+            Point2::new(max.x, point.y),
+            Point2::new(point.x, self.origin.y),
+            Point2::new(point.x, max.y),
+        ])
+    }
+}
+
+impl LineGerberPrimitive {
+    /// Lines have round ends, as they are drawn with a circular aperture.
+    fn contains(&self, point: Point2<f64>) -> bool {
+        distance_to_segment(point, self.start, self.end) <= self.width / 2.0
+    }
+
+    fn nearest_boundary_point(&self, point: Point2<f64>) -> Point2<f64> {
+        let on_segment = nearest_point_on_segment(point, self.start, self.end);
+
+        // on the center line, use the side of the line
+        let segment = self.end - self.start;
+        let side = Vector2::new(-segment.y, segment.x);
+
+        on_segment + direction_or(point - on_segment, side) * (self.width / 2.0)
+    }
+}
+
+impl ArcGerberPrimitive {
+    fn contains(&self, point: Point2<f64>) -> bool {
+        let relative = point - self.center;
+        let distance = length(relative);
+        if (distance - self.radius).abs() > self.width / 2.0 {
+            return false;
+        }
+
+        if self.is_full_circle() {
+            return true;
+        }
+
+        let angle = relative.y.atan2(relative.x);
+
+        self.sweep_contains(angle)
+    }
+
+    /// Arcs are drawn with flat ends, so the boundary is two concentric arcs joined by two radial lines, or two
+    /// circles for a full circle.
+    fn nearest_boundary_point(&self, point: Point2<f64>) -> Point2<f64> {
+        let outer_radius = self.radius + self.width / 2.0;
+        let inner_radius = (self.radius - self.width / 2.0).max(0.0);
+
+        let relative = point - self.center;
+        let angle = relative.y.atan2(relative.x);
+        let on_radius = |radius: f64, angle: f64| self.center + Vector2::new(angle.cos(), angle.sin()) * radius;
+
+        if self.is_full_circle() {
+            let direction = direction_or_x(relative);
+            return nearest_of(point, [
+                self.center + direction * outer_radius,
+                self.center + direction * inner_radius,
+            ]);
+        }
+
+        let end_angle = self.start_angle + self.sweep_angle;
+        let mut candidates = vec![
+            nearest_point_on_segment(
+                point,
+                on_radius(inner_radius, self.start_angle),
+                on_radius(outer_radius, self.start_angle),
+            ),
+            nearest_point_on_segment(
+                point,
+                on_radius(inner_radius, end_angle),
+                on_radius(outer_radius, end_angle),
+            ),
+        ];
+        if self.sweep_contains(angle) {
+            candidates.push(on_radius(outer_radius, angle));
+            candidates.push(on_radius(inner_radius, angle));
+        }
+
+        nearest_of(point, candidates)
+    }
+
+    fn sweep_contains(&self, angle: f64) -> bool {
+        let delta = match self.sweep_angle >= 0.0 {
+            true => angle - self.start_angle,
+            false => self.start_angle - angle,
+        };
+
+        delta.rem_euclid(TAU) <= self.sweep_angle.abs()
+    }
+}
+
+impl PolygonGerberPrimitive {
+    /// Even-odd rule, which matches the tessellation for polygons without self-intersections.
+    fn contains(&self, point: Point2<f64>) -> bool {
+        let point = point - self.center;
+        let vertices = &self.geometry.relative_vertices;
+
+        let mut inside = false;
+        let mut j = vertices.len() - 1;
+        for i in 0..vertices.len() {
+            let (a, b) = (vertices[i], vertices[j]);
+            if (a.y > point.y) != (b.y > point.y) && point.x < (b.x - a.x) * (point.y - a.y) / (b.y - a.y) + a.x {
+                inside = !inside;
+            }
+            j = i;
+        }
+
+        inside
+    }
+
+    fn nearest_boundary_point(&self, point: Point2<f64>) -> Point2<f64> {
+        let vertices = &self.geometry.relative_vertices;
+
+        let edges = (0..vertices.len()).map(|i| {
+            let start = self.center + vertices[i].coords;
+            let end = self.center + vertices[(i + 1) % vertices.len()].coords;
+            nearest_point_on_segment(point, start, end)
+        });
+
+        nearest_of(point, edges)
+    }
+}
+
+fn length(vector: Vector2<f64>) -> f64 {
+    vector.x.hypot(vector.y)
+}
+
+/// The unit vector in the direction of `vector`, or of `fallback` if `vector` has no length.
+fn direction_or(vector: Vector2<f64>, fallback: Vector2<f64>) -> Vector2<f64> {
+    match length(vector) {
+        0.0 => match length(fallback) {
+            0.0 => Vector2::new(1.0, 0.0),
+            fallback_length => fallback / fallback_length,
+        },
+        vector_length => vector / vector_length,
+    }
+}
+
+fn direction_or_x(vector: Vector2<f64>) -> Vector2<f64> {
+    direction_or(vector, Vector2::new(1.0, 0.0))
+}
+
+/// The candidate closest to `point`, the first one on a tie.
+fn nearest_of(point: Point2<f64>, candidates: impl IntoIterator<Item = Point2<f64>>) -> Point2<f64> {
+    candidates
+        .into_iter()
+        .map(|candidate| (candidate, length(candidate - point)))
+        .reduce(|best, candidate| match candidate.1 < best.1 {
+            true => candidate,
+            false => best,
+        })
+        .map(|(candidate, _)| candidate)
+        .unwrap_or(point)
+}
+
+fn nearest_point_on_segment(point: Point2<f64>, start: Point2<f64>, end: Point2<f64>) -> Point2<f64> {
+    let segment = end - start;
+    let length_squared = segment.dot(&segment);
+    if length_squared == 0.0 {
+        return start;
+    }
+
+    let t = ((point - start).dot(&segment) / length_squared).clamp(0.0, 1.0);
+
+    start + segment * t
+}
+
+fn distance_to_segment(point: Point2<f64>, start: Point2<f64>, end: Point2<f64>) -> f64 {
+    length(point - nearest_point_on_segment(point, start, end))
+}
+
+#[cfg(test)]
+mod contains_tests {
+    use std::f64::consts::FRAC_PI_2;
+    use std::sync::Arc;
+
+    use rstest::rstest;
+
+    use super::*;
+    use crate::layer::{PolygonGeometry, PrimitiveOrigin};
+    use crate::types::Exposure;
+
+    #[rstest]
+    #[case::center(0.0, 0.0, true)]
+    #[case::edge(1.0, 0.0, true)]
+    #[case::outside(0.8, 0.8, false)]
+    fn test_circle(#[case] x: f64, #[case] y: f64, #[case] expected: bool) {
+        // given
+        let circle = GerberPrimitive::Circle(CircleGerberPrimitive {
+            center: Point2::new(0.0, 0.0),
+            diameter: 2.0,
+            hole_diameter: None,
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        });
+
+        // expect
+        assert_eq!(circle.contains(Point2::new(x, y)), expected);
+    }
+
+    #[rstest]
+    #[case::in_hole(0.0, 0.0, false)]
+    #[case::in_ring(0.75, 0.0, true)]
+    fn test_circle_with_hole(#[case] x: f64, #[case] y: f64, #[case] expected: bool) {
+        // given
+        let circle = GerberPrimitive::Circle(CircleGerberPrimitive {
+            center: Point2::new(0.0, 0.0),
+            diameter: 2.0,
+            hole_diameter: Some(1.0),
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        });
+
+        // expect
+        assert_eq!(circle.contains(Point2::new(x, y)), expected);
+    }
+
+    #[rstest]
+    #[case::inside(1.5, 0.5, true)]
+    #[case::corner(0.0, 0.0, true)]
+    #[case::outside(2.5, 0.5, false)]
+    fn test_rectangle(#[case] x: f64, #[case] y: f64, #[case] expected: bool) {
+        // given
+        let rectangle = GerberPrimitive::Rectangle(RectangleGerberPrimitive {
+            origin: Point2::new(0.0, 0.0),
+            width: 2.0,
+            height: 1.0,
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        });
+
+        // expect
+        assert_eq!(rectangle.contains(Point2::new(x, y)), expected);
+    }
+
+    #[rstest]
+    #[case::inside(1.0, 0.5, true)]
+    #[case::edge(2.0, 0.5, true)]
+    #[case::cut_off_corner(0.05, 0.05, false)]
+    #[case::rounded_corner(0.2, 0.2, true)]
+    fn test_rounded_rectangle(#[case] x: f64, #[case] y: f64, #[case] expected: bool) {
+        // given
+        let rectangle = GerberPrimitive::RoundedRectangle(RoundedRectangleGerberPrimitive {
+            origin: Point2::new(0.0, 0.0),
+            width: 2.0,
+            height: 1.0,
+            corner_radius: 0.4,
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        });
+
+        // expect
+        assert_eq!(rectangle.contains(Point2::new(x, y)), expected);
+    }
+
+    #[rstest]
+    #[case::on_line(5.0, 0.0, true)]
+    #[case::within_width(5.0, 0.4, true)]
+    #[case::round_end(10.3, 0.3, true)]
+    #[case::square_corner(10.45, 0.45, false)]
+    fn test_line(#[case] x: f64, #[case] y: f64, #[case] expected: bool) {
+        // given
+        let line = GerberPrimitive::Line(LineGerberPrimitive {
+            start: Point2::new(0.0, 0.0),
+            end: Point2::new(10.0, 0.0),
+            width: 1.0,
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        });
+
+        // expect
+        assert_eq!(line.contains(Point2::new(x, y)), expected);
+    }
+
+    #[rstest]
+    #[case::ccw_inside(FRAC_PI_2, 0.0, 5.0, true)]
+    #[case::ccw_outside_sweep(FRAC_PI_2, 0.0, -5.0, false)]
+    #[case::cw_inside(-FRAC_PI_2, 0.0, -5.0, true)]
+    #[case::cw_outside_sweep(-FRAC_PI_2, 0.0, 5.0, false)]
+    #[case::inside_radius(FRAC_PI_2, 0.0, 4.0, false)]
+    fn test_arc(#[case] sweep_angle: f64, #[case] x: f64, #[case] y: f64, #[case] expected: bool) {
+        // given
+        let arc = GerberPrimitive::Arc(ArcGerberPrimitive {
+            center: Point2::new(0.0, 0.0),
+            radius: 5.0,
+            width: 0.5,
+            start_angle: 0.0,
+            sweep_angle,
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        });
+
+        // expect
+        assert_eq!(arc.contains(Point2::new(x, y)), expected);
+    }
+
+    #[rstest]
+    #[case::inside_notch(0.5, 1.5, false)]
+    #[case::inside(0.5, 0.5, true)]
+    #[case::outside(3.0, 0.5, false)]
+    fn test_concave_polygon(#[case] x: f64, #[case] y: f64, #[case] expected: bool) {
+        // given
+        // a 'U' shape, open at the top
+        let relative_vertices = vec![
+            Point2::new(0.0, 0.0),
+            Point2::new(2.0, 0.0),
+            Point2::new(2.0, 2.0),
+            Point2::new(1.5, 2.0),
+            Point2::new(1.5, 1.0),
+            Point2::new(0.5, 1.0),
+            Point2::new(0.5, 2.0),
+            Point2::new(0.0, 2.0),
+        ];
+        let polygon = GerberPrimitive::Polygon(PolygonGerberPrimitive {
+            center: Point2::new(0.0, 0.0),
+            exposure: Exposure::Add,
+            geometry: Arc::new(PolygonGeometry {
+                relative_vertices,
+                tessellation: None,
+                is_convex: false,
+            }),
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        });
+
+        // expect
+        assert_eq!(polygon.contains(Point2::new(x, y)), expected);
+    }
+}
+
+#[cfg(test)]
+mod primitive_at_tests {
+
+    use super::*;
+    use crate::testing::commands;
+
+    #[test]
+    fn test_top_most_primitive() {
+        // given
+        let layer = commands::flashes_layer(2.0, &[(0.0, 0.0), (1.0, 0.0), (10.0, 0.0)]);
+
+        // expect
+        assert_eq!(layer.primitive_at(Point2::new(-0.5, 0.0)), Some(0));
+        // overlapping, the last one is on top
+        assert_eq!(layer.primitive_at(Point2::new(0.5, 0.0)), Some(1));
+        assert_eq!(layer.primitive_at(Point2::new(10.0, 0.0)), Some(2));
+        assert_eq!(layer.primitive_at(Point2::new(5.0, 0.0)), None);
+    }
+
+    #[test]
+    fn test_all_primitives_back_to_front() {
+        // given
+        let layer = commands::flashes_layer(2.0, &[(0.0, 0.0), (10.0, 0.0), (1.0, 0.0), (0.5, 0.5)]);
+
+        // expect
+        assert_eq!(layer.primitives_at(Point2::new(0.5, 0.0)), vec![0, 2, 3]);
+        assert_eq!(layer.primitives_at(Point2::new(-0.9, 0.0)), vec![0]);
+        assert_eq!(layer.primitives_at(Point2::new(5.0, 0.0)), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_spatial_index_cells_cover_primitives() {
+        // given
+        // a ring of pads, 4x4 cells
+        let layer = commands::flashes_layer(2.0, &[
+            (0.0, 0.0),
+            (10.0, 0.0),
+            (20.0, 0.0),
+            (0.0, 10.0),
+            (20.0, 10.0),
+            (0.0, 20.0),
+            (10.0, 20.0),
+            (20.0, 20.0),
+        ]);
+
+        // when
+        let cells = layer.spatial_index_cells();
+
+        // then
+        assert!(
+            cells
+                .iter()
+                .all(|(_, count)| *count > 0)
+        );
+        // the cell in the middle of the ring is empty
+        assert!(cells.len() < 16);
+        for primitive in layer.primitives() {
+            let GerberPrimitive::Circle(circle) = primitive else {
+                panic!("expected a circle");
+            };
+            let center = circle.center;
+            assert!(cells.iter().any(|(cell, _)| {
+                (cell.min.x..=cell.max.x).contains(&center.x) && (cell.min.y..=cell.max.y).contains(&center.y)
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod primitives_in_rect_tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::layer::{CircleGerberPrimitive, PrimitiveOrigin};
+    use crate::types::Exposure;
+
+    fn circles(centers: &[(f64, f64)]) -> GerberLayer {
+        let mut layer = GerberLayer::new(vec![]);
+        for &(x, y) in centers {
+            layer.push_primitive(GerberPrimitive::Circle(CircleGerberPrimitive {
+                center: Point2::new(x, y),
+                diameter: 2.0,
+                hole_diameter: None,
+                exposure: Exposure::Add,
+                primitive_origin: PrimitiveOrigin::Programmatic,
+            }));
+        }
+        layer
+    }
+
+    fn rect(min: (f64, f64), max: (f64, f64)) -> BoundingBox {
+        BoundingBox {
+            min: Point2::new(min.0, min.1),
+            max: Point2::new(max.0, max.1),
+        }
+    }
+
+    #[rstest]
+    #[case::intersect(SelectMode::Intersect, vec![0, 1, 3])]
+    #[case::contained(SelectMode::Contained, vec![0, 3])]
+    fn test_primitives_in_rect(#[case] mode: SelectMode, #[case] expected: Vec<usize>) {
+        // given
+        let layer = circles(&[(0.0, 0.0), (5.0, 0.0), (20.0, 20.0), (2.0, 2.0)]);
+
+        // when
+        // the circle at (5, 0) is partially inside
+        let indices = layer.primitives_in_rect(&rect((-1.0, -1.0), (4.5, 3.0)), mode);
+
+        // then
+        assert_eq!(indices, expected);
+    }
+
+    #[rstest]
+    #[case::outside(rect((100.0, 100.0), (110.0, 110.0)))]
+    #[case::empty(BoundingBox::default())]
+    fn test_nothing_selected(#[case] rect: BoundingBox) {
+        // given
+        let layer = circles(&[(0.0, 0.0), (20.0, 20.0)]);
+
+        // expect
+        assert!(
+            layer
+                .primitives_in_rect(&rect, SelectMode::Intersect)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_rect_covering_the_layer() {
+        // given
+        let layer = circles(&[(0.0, 0.0), (10.0, 0.0), (0.0, 10.0), (10.0, 10.0), (5.0, 5.0)]);
+
+        // when
+        let indices = layer.primitives_in_rect(&rect((-100.0, -100.0), (100.0, 100.0)), SelectMode::Contained);
+
+        // then
+        assert_eq!(indices, vec![0, 1, 2, 3, 4]);
+    }
+}
+
+#[cfg(test)]
+mod nearest_boundary_point_tests {
+    use std::f64::consts::{FRAC_1_SQRT_2, FRAC_PI_2};
+
+    use rstest::rstest;
+
+    use super::*;
+    use crate::layer::PrimitiveOrigin;
+    use crate::testing::commands;
+    use crate::types::Exposure;
+
+    fn assert_point_eq(actual: Point2<f64>, expected: (f64, f64)) {
+        assert!(
+            (actual.x - expected.0).abs() < 1e-9 && (actual.y - expected.1).abs() < 1e-9,
+            "expected {:?}, got {:?}",
+            expected,
+            actual
+        );
+    }
+
+    #[rstest]
+    #[case::outside((5.0, 0.0), (1.0, 0.0))]
+    #[case::inside((0.0, 0.9), (0.0, 1.0))]
+    #[case::in_hole((0.0, -0.1), (0.0, -0.5))]
+    fn test_circle_with_hole(#[case] query: (f64, f64), #[case] expected: (f64, f64)) {
+        // given
+        let circle = GerberPrimitive::Circle(CircleGerberPrimitive {
+            center: Point2::new(0.0, 0.0),
+            diameter: 2.0,
+            hole_diameter: Some(1.0),
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        });
+
+        // expect
+        assert_point_eq(circle.nearest_boundary_point(Point2::new(query.0, query.1)), expected);
+    }
+
+    #[rstest]
+    #[case::outside_corner((3.0, 3.0), (2.0, 1.0))]
+    #[case::outside_edge((1.0, -5.0), (1.0, 0.0))]
+    #[case::inside((1.8, 0.5), (2.0, 0.5))]
+    fn test_rectangle(#[case] query: (f64, f64), #[case] expected: (f64, f64)) {
+        // given
+        let rectangle = GerberPrimitive::Rectangle(RectangleGerberPrimitive {
+            origin: Point2::new(0.0, 0.0),
+            width: 2.0,
+            height: 1.0,
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        });
+
+        // expect
+        assert_point_eq(
+            rectangle.nearest_boundary_point(Point2::new(query.0, query.1)),
+            expected,
+        );
+    }
+
+    #[rstest]
+    #[case::outside_corner((4.5, 3.5), (1.5 + 0.5 * FRAC_1_SQRT_2, 0.5 + 0.5 * FRAC_1_SQRT_2))]
+    #[case::outside_edge((1.0, -5.0), (1.0, 0.0))]
+    #[case::inside((1.0, 0.4), (1.0, 0.0))]
+    fn test_rounded_rectangle(#[case] query: (f64, f64), #[case] expected: (f64, f64)) {
+        // given
+        let rectangle = GerberPrimitive::RoundedRectangle(RoundedRectangleGerberPrimitive {
+            origin: Point2::new(0.0, 0.0),
+            width: 2.0,
+            height: 1.0,
+            corner_radius: 0.5,
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        });
+
+        // expect
+        assert_point_eq(
+            rectangle.nearest_boundary_point(Point2::new(query.0, query.1)),
+            expected,
+        );
+    }
+
+    #[rstest]
+    #[case::beside((5.0, 3.0), (5.0, 0.5))]
+    #[case::round_end((13.0, 0.0), (10.5, 0.0))]
+    #[case::on_center_line((5.0, 0.0), (5.0, 0.5))]
+    fn test_line(#[case] query: (f64, f64), #[case] expected: (f64, f64)) {
+        // given
+        let line = GerberPrimitive::Line(LineGerberPrimitive {
+            start: Point2::new(0.0, 0.0),
+            end: Point2::new(10.0, 0.0),
+            width: 1.0,
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        });
+
+        // expect
+        assert_point_eq(line.nearest_boundary_point(Point2::new(query.0, query.1)), expected);
+    }
+
+    #[rstest]
+    #[case::outer_edge((0.0, 10.0), (0.0, 5.5))]
+    #[case::inner_edge((0.0, 1.0), (0.0, 4.5))]
+    #[case::flat_end((-1.0, 5.2), (0.0, 5.2))]
+    #[case::outside_sweep((5.0, -3.0), (5.0, 0.0))]
+    fn test_arc(#[case] query: (f64, f64), #[case] expected: (f64, f64)) {
+        // given
+        // a quarter arc, from the +x axis to the +y axis
+        let arc = GerberPrimitive::Arc(ArcGerberPrimitive {
+            center: Point2::new(0.0, 0.0),
+            radius: 5.0,
+            width: 1.0,
+            start_angle: 0.0,
+            sweep_angle: FRAC_PI_2,
+            exposure: Exposure::Add,
+            primitive_origin: PrimitiveOrigin::Programmatic,
+        });
+
+        // expect
+        assert_point_eq(arc.nearest_boundary_point(Point2::new(query.0, query.1)), expected);
+    }
+
+    #[test]
+    fn test_empty_layer() {
+        // given
+        let layer = commands::flashes_layer(1.0, &[]);
+
+        // expect
+        assert_eq!(layer.nearest_boundary_point(Point2::new(0.0, 0.0)), None);
+    }
+
+    #[rstest]
+    #[case::inside_grid(12.3, 45.6)]
+    #[case::outside_grid(-30.0, 200.0)]
+    #[case::on_a_pad(20.0, 20.0)]
+    fn test_matches_brute_force(#[case] x: f64, #[case] y: f64) {
+        // given
+        // a deterministic scatter of pads
+        let positions: Vec<(f64, f64)> = (0..200)
+            .map(|i| (((i * 37) % 101) as f64, ((i * 61) % 97) as f64))
+            .collect();
+        let layer = commands::flashes_layer(1.0, &positions);
+        let query = Point2::new(x, y);
+
+        // when
+        let (_, point) = layer
+            .nearest_boundary_point(query)
+            .unwrap();
+
+        // then
+        let expected = layer
+            .primitives()
+            .iter()
+            .map(|primitive| length(primitive.nearest_boundary_point(query) - query))
+            .fold(f64::INFINITY, f64::min);
+        assert!((length(point - query) - expected).abs() < 1e-9);
+    }
+}
@@ -1,22 +1,29 @@
+use std::collections::HashSet;
+use std::ops::Range;
 use std::sync::Arc;
 
-use egui::Painter;
+use egui::Context;
 use egui::epaint::emath::Align2;
 use egui::epaint::{
-    Color32, ColorMode, FontId, Mesh, PathShape, PathStroke, Pos2, Rect, Shape, Stroke, StrokeKind, Vec2, Vertex,
+    CircleShape, Color32, ColorMode, FontId, Mesh, PathShape, PathStroke, Pos2, Rect, RectShape, Shape, Stroke,
+    StrokeKind, TessellationOptions, Tessellator, Vec2, Vertex,
 };
+use gerber_types::ImagePolarity;
 use nalgebra::{Matrix3, Point2, Vector2};
 
-use crate::geometry::{GerberTransform, Matrix3Pos2Ext, Matrix3TransformExt};
+use crate::geometry::{
+    BoundingBox, GerberTransform, Matrix3AffineInverseExt, Matrix3Pos2Ext, Matrix3TransformExt, tessellate_contours,
+};
 use crate::layer::GerberPrimitive;
+use crate::types::Exposure;
 use crate::{
     ArcGerberPrimitive, CircleGerberPrimitive, LineGerberPrimitive, Matrix3ScalingExt, PolygonGerberPrimitive,
-    RectangleGerberPrimitive, WithBoundingBox,
+    RectangleGerberPrimitive, RoundedRectangleGerberPrimitive, WithBoundingBox,
 };
-use crate::{GerberLayer, ViewState, color};
+use crate::{ClipPath, DrillHole, GerberLayer, UiState, ViewState, color};
 
 macro_rules! draw_bbox {
-    ($primitive:ident, $configuration:ident, $painter:ident, $color:ident, $view:ident, $transform_matrix:ident) => {
+    ($primitive:ident, $configuration:ident, $shapes:ident, $color:ident, $view:ident, $transform_matrix:ident) => {
         #[cfg(feature = "egui")]
         if $configuration.use_shape_bboxes {
             let bbox_rect: Rect = $primitive.bounding_box().into();
@@ -43,7 +50,7 @@ macro_rules! draw_bbox {
                 })
                 .collect();
 
-            $painter.add(Shape::Path(PathShape {
+            $shapes.add(Shape::Path(PathShape {
                 points,
                 closed: true,
                 fill: Color32::TRANSPARENT,
@@ -57,16 +64,118 @@ macro_rules! draw_bbox {
     };
 }
 
-#[derive(Debug, Clone)]
+/// Options for [`GerberRenderer`], see the presets [`RenderConfiguration::debug`],
+/// [`RenderConfiguration::presentation`] and [`RenderConfiguration::film`].
+///
+/// With the `serde` feature the configuration can be saved and loaded, e.g. to share a preferred visualization,
+/// missing options are set to their default.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
 pub struct RenderConfiguration {
     /// Gives each shape a unique color.
     pub use_unique_shape_colors: bool,
     /// Draws the shape number in the center of the shape.
     pub use_shape_numbering: bool,
+    /// Only numbers the primitives that are on screen and large enough for the number to be readable, so that shape
+    /// numbering stays usable when zoomed into a small area of a dense board.
+    ///
+    /// Uses the same test as [`GerberRenderer::visible_primitive_indices`], with the screen as the viewport, and a
+    /// minimum on-screen size, the largest side of the primitive's bounding box, of about the height of the number.
+    /// The primitives are drawn as usual.  Ignored unless [`RenderConfiguration::use_shape_numbering`] is set.
+    pub number_visible_shapes_only: bool,
     /// Draws the vertex number at the start of each line.
     pub use_vertex_numbering: bool,
     /// Draws a bounding box for each shape,
     pub use_shape_bboxes: bool,
+    /// Draws all the primitives that add material before any of the primitives that cut out material.
+    ///
+    /// Normally primitives are drawn in command order, so a clear primitive only covers the dark primitives before it.
+    /// With grouping enabled a clear primitive covers every dark primitive in the layer, including ones defined after
+    /// it. The relative order of primitives within each group is unchanged.
+    ///
+    /// Shape numbers and unique shape colors are not affected by the grouping.
+    pub polarity_grouping: bool,
+    /// Sets all the quality related options at once, see [`RenderQuality`].
+    pub quality: RenderQuality,
+    /// Overrides the number of points used to draw each arc, see [`RenderQuality::arc_steps`].
+    pub arc_steps: Option<usize>,
+    /// Overrides the minimum width of lines and arcs, see [`RenderQuality::min_stroke_width`].
+    pub min_stroke_width: Option<f32>,
+    /// Draws the layer without the render transform, in a faded color, beneath the transformed layer.
+    ///
+    /// Shows exactly what the rotation, mirroring, scale and offset did.  The image transform from the gerber file is
+    /// still applied, as it is part of the layer.  Debug options (numbering, bboxes, unique colors) only apply to the
+    /// transformed layer.
+    pub draw_untransformed_ghost: bool,
+    /// Draws the outline of each non-empty cell of the spatial index used by queries such as hit-testing, see
+    /// [`GerberLayer::spatial_index_cells`].
+    ///
+    /// For debugging, builds the index if needed and draws every cell, regardless of the rendered range.
+    pub use_spatial_index_cells: bool,
+    /// Level of detail, primitives whose on-screen size is smaller than this many points are drawn as a single dot.
+    ///
+    /// When zoomed out, tiny features such as pads are invisible as shapes but still cost a mesh each; a dot is much
+    /// cheaper and keeps the outline of the board readable.  Primitives that cut out material are skipped instead, a
+    /// dot-sized hole isn't visible.  The size is the largest side of the primitive's bounding box on screen.
+    ///
+    /// `0.0`, the default, disables it.
+    pub min_feature_pixels: f32,
+    /// Draws connected lines, e.g. the segments of a trace, as one continuous path instead of one capsule per line.
+    ///
+    /// Avoids the seams and, for translucent colors, the darker overlaps where the capsules of consecutive lines meet.
+    /// Lines are connected when each starts exactly where the previous one ends, with the same width and exposure.
+    /// The joins are mitered, see [`RenderConfiguration::miter_limit`], and the ends of the path are round.
+    ///
+    /// Ignored when unique shape colors, shape numbering or shape bounding boxes are used, as they show each primitive.
+    pub continuous_traces: bool,
+    /// Joins of continuous traces whose miter would be longer than this many times the width of the trace are beveled
+    /// instead, so that sharp corners don't produce long spikes, the same as the `stroke-miterlimit` of SVG.
+    ///
+    /// The miter length is measured from the outer tip of the miter to the inner corner of the join, a right angle
+    /// has a ratio of about 1.41.  `4.0`, the default, is the same as SVG.  Only used with
+    /// [`RenderConfiguration::continuous_traces`].
+    pub miter_limit: f32,
+    /// Draws a grid behind the layer, in gerber units, so the grid lines follow the render transform.
+    ///
+    /// Grid lines are always at least a few points apart, when zoomed out the spacing is multiplied by 10 as needed.
+    ///
+    /// `None`, the default, disables the grid.
+    pub grid: Option<GridSpacing>,
+    /// Skips the primitives created with these apertures (the `nn` of `Dnn`), e.g. to hide all the vias, see
+    /// [`GerberLayer::primitive_apertures`].
+    ///
+    /// Empty by default.
+    pub hidden_apertures: HashSet<i32>,
+    /// Highlights the primitives created with this aperture (the `nn` of `Dnn`), e.g. to find everywhere a pad type is
+    /// used: they are drawn in an accent color, at full opacity, and every other primitive is dimmed.
+    ///
+    /// Uses the same aperture codes as [`RenderConfiguration::hidden_apertures`].  Overrides the unique shape colors,
+    /// the [`RenderConfiguration::tint`] is still applied.
+    ///
+    /// `None`, the default, disables it.
+    pub solo_aperture: Option<i32>,
+    /// Draws the primitives without anti-aliasing, so the edges are hard, e.g. when rasterizing for film or a mask,
+    /// where the image is thresholded.
+    ///
+    /// egui anti-aliases shapes by feathering their edges, see `egui::epaint::TessellationOptions::feathering`, which
+    /// is an option for the whole context.  With hard edges the renderer tessellates the primitives itself, without
+    /// feathering, and paints the meshes instead.  The grid and text are not affected.
+    pub hard_edges: bool,
+    /// Draws the primitives that cut out material (clear polarity) as dashed outlines, in a distinct color, instead of
+    /// cutting them out of the layer, so that clear features such as the holes of donuts and thermals are easy to
+    /// spot.
+    ///
+    /// For debugging, the primitives that add material are drawn as usual, without the cut-outs.
+    pub debug_clear_outline: bool,
+    /// Multiplies the color of every primitive with this color, e.g. to dim or recolor a whole layer for a theme or a
+    /// colorblind palette.
+    ///
+    /// Applied after the color of each primitive is chosen, so with unique shape colors each shape is tinted, and to
+    /// the dark image area of a negative image.  White leaves the colors as they are, a translucent tint makes the
+    /// layer translucent.  Overlays with their own colors, e.g. the grid and shape numbers, are not tinted.
+    ///
+    /// `None`, the default, leaves the colors as they are.
+    pub tint: Option<Color32>,
 }
 
 impl Default for RenderConfiguration {
@@ -74,12 +183,348 @@ impl Default for RenderConfiguration {
         Self {
             use_unique_shape_colors: false,
             use_shape_numbering: false,
+            number_visible_shapes_only: false,
             use_vertex_numbering: false,
             use_shape_bboxes: false,
+            polarity_grouping: false,
+            quality: RenderQuality::default(),
+            arc_steps: None,
+            min_stroke_width: None,
+            draw_untransformed_ghost: false,
+            use_spatial_index_cells: false,
+            min_feature_pixels: 0.0,
+            continuous_traces: false,
+            miter_limit: 4.0,
+            grid: None,
+            hidden_apertures: HashSet::new(),
+            solo_aperture: None,
+            hard_edges: false,
+            debug_clear_outline: false,
+            tint: None,
+        }
+    }
+}
+
+impl RenderConfiguration {
+    /// Shows how the layer is built: unique shape colors, shape numbers (of the visible shapes) and bounding boxes, the
+    /// spatial index cells, dashed outlines for the clear primitives and a grid.
+    pub fn debug() -> Self {
+        Self {
+            use_unique_shape_colors: true,
+            use_shape_numbering: true,
+            number_visible_shapes_only: true,
+            use_shape_bboxes: true,
+            use_spatial_index_cells: true,
+            debug_clear_outline: true,
+            grid: Some(GridSpacing::Auto),
+            ..Default::default()
+        }
+    }
+
+    /// For screenshots and demos: high quality, and connected traces without seams.
+    pub fn presentation() -> Self {
+        Self {
+            quality: RenderQuality::High,
+            continuous_traces: true,
+            ..Default::default()
+        }
+    }
+
+    /// For rasterizing the layer for film or a mask: high quality without anti-aliasing, every feature is drawn at
+    /// its real size, however small.
+    pub fn film() -> Self {
+        Self {
+            quality: RenderQuality::High,
+            // hairlines are drawn at their real width
+            min_stroke_width: Some(0.0),
+            hard_edges: true,
+            ..Default::default()
+        }
+    }
+
+    /// The number of points used to draw each arc, the override if set, otherwise the quality preset value.
+    pub fn effective_arc_steps(&self) -> usize {
+        self.arc_steps
+            .unwrap_or_else(|| self.quality.arc_steps())
+    }
+
+    /// The minimum width of lines and arcs, the override if set, otherwise the quality preset value.
+    pub fn effective_min_stroke_width(&self) -> f32 {
+        self.min_stroke_width
+            .unwrap_or_else(|| self.quality.min_stroke_width())
+    }
+
+    /// Describes what each color means when the layer is rendered with this configuration, e.g. to show a legend
+    /// next to the view.
+    ///
+    /// With `use_unique_shape_colors` there is one entry per primitive that adds material, in layer order.  Colors
+    /// that are not used by the layer, e.g. cut-outs in a layer without any, are omitted.
+    pub fn legend(&self, layer: &GerberLayer, base_color: Color32) -> Vec<(Color32, String)> {
+        let primitives = layer.primitives();
+        let mut legend = Vec::new();
+
+        if layer.image_polarity() == ImagePolarity::Negative && !layer.is_empty() {
+            legend.push((base_color, "Negative image area".to_string()));
+        }
+
+        if self.use_unique_shape_colors {
+            for (index, primitive) in primitives
+                .iter()
+                .enumerate()
+                .filter(|(_, primitive)| primitive.exposure() == Exposure::Add)
+            {
+                let kind = match primitive {
+                    GerberPrimitive::Circle(_) => "circle",
+                    GerberPrimitive::Rectangle(_) => "rectangle",
+                    GerberPrimitive::RoundedRectangle(_) => "rounded rectangle",
+                    GerberPrimitive::Line(_) => "line",
+                    GerberPrimitive::Arc(_) => "arc",
+                    GerberPrimitive::Polygon(_) => "polygon",
+                };
+                legend.push((
+                    color::generate_pastel_color(index as u64),
+                    format!("Shape {}, {}", index, kind),
+                ));
+            }
+        } else if primitives
+            .iter()
+            .any(|primitive| primitive.exposure() == Exposure::Add)
+        {
+            legend.push((base_color, "Dark polarity (adds material)".to_string()));
+        }
+
+        if primitives
+            .iter()
+            .any(|primitive| primitive.exposure() == Exposure::CutOut)
+        {
+            match self.debug_clear_outline {
+                true => legend.push((CLEAR_OUTLINE_COLOR, "Clear polarity (outline)".to_string())),
+                false => legend.push((
+                    Exposure::CutOut.to_color(&base_color),
+                    "Clear polarity (cuts out material)".to_string(),
+                )),
+            }
+        }
+
+        if let Some(code) = self.solo_aperture {
+            legend.push((SOLO_APERTURE_COLOR, format!("Aperture D{}", code)));
+        }
+
+        if self.draw_untransformed_ghost {
+            legend.push((
+                base_color.gamma_multiply(GHOST_OPACITY),
+                "Untransformed layer".to_string(),
+            ));
+        }
+        if self.use_spatial_index_cells {
+            legend.push((SPATIAL_INDEX_CELL_COLOR, "Spatial index cell".to_string()));
+        }
+        if self.grid.is_some() {
+            legend.push((GRID_COLOR, "Grid".to_string()));
+        }
+
+        legend
+    }
+}
+
+/// Quality presets, for users that don't want to tune each quality option individually.
+///
+/// The individual options in [`RenderConfiguration`] take precedence over the preset.
+///
+/// Note: anti-aliasing (feathering) and the tessellation of circles are controlled by egui, see
+/// `egui::Context::tessellation_options`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RenderQuality {
+    /// Fewer points per arc, for large layers or slow machines.
+    Draft,
+    /// The default, matches the rendering before quality presets were added.
+    #[default]
+    Balanced,
+    /// More points per arc and hairlines are always at least a point wide, for inspection and screenshots.
+    High,
+}
+
+impl RenderQuality {
+    /// Number of points used to draw each arc.
+    pub fn arc_steps(&self) -> usize {
+        match self {
+            RenderQuality::Draft => 16,
+            RenderQuality::Balanced => ArcGerberPrimitive::DEFAULT_STEPS,
+            RenderQuality::High => 64,
+        }
+    }
+
+    /// Minimum width of lines and arcs, in points, so that thin traces don't disappear when zoomed out.
+    pub fn min_stroke_width(&self) -> f32 {
+        match self {
+            RenderQuality::Draft | RenderQuality::Balanced => 0.0,
+            RenderQuality::High => 1.0,
+        }
+    }
+}
+
+/// The spacing of the grid, see [`RenderConfiguration::grid`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GridSpacing {
+    /// Derived from the units and coordinate format of the layer, see [`GerberLayer::default_grid_spacing`].
+    #[default]
+    Auto,
+    /// In gerber units.
+    Manual(f64),
+}
+
+impl GridSpacing {
+    /// The spacing in gerber units.
+    pub fn spacing(&self, layer: &GerberLayer) -> f64 {
+        match self {
+            GridSpacing::Auto => layer.default_grid_spacing(),
+            GridSpacing::Manual(spacing) => *spacing,
+        }
+    }
+}
+
+/// See [`RenderConfiguration::grid`].
+const GRID_COLOR: Color32 = Color32::from_gray(48);
+
+/// Minimum distance between grid lines, in points, see [`RenderConfiguration::grid`].
+const MIN_GRID_SPACING: f64 = 8.0;
+
+/// Tessellates the shapes without feathering, see [`RenderConfiguration::hard_edges`].
+///
+/// Consecutive shapes are combined into a single mesh, text and meshes are kept as they are, so the order of the shapes
+/// is unchanged.
+fn tessellate_without_feathering(ctx: &Context, shapes: Vec<Shape>) -> Vec<Shape> {
+    let options = TessellationOptions {
+        feathering: false,
+        ..ctx.tessellation_options(|options| *options)
+    };
+    // the font texture is only used for text, which is not tessellated here
+    let mut tessellator = Tessellator::new(ctx.pixels_per_point(), options, [1, 1], vec![]);
+
+    let mut tessellated = Vec::new();
+    let mut mesh = Mesh::default();
+    for shape in shapes {
+        match shape {
+            Shape::Text(_) | Shape::Mesh(_) => {
+                if !mesh.is_empty() {
+                    tessellated.push(Shape::mesh(std::mem::take(&mut mesh)));
+                }
+                tessellated.push(shape);
+            }
+            shape => tessellator.tessellate_shape(shape, &mut mesh),
         }
     }
+    if !mesh.is_empty() {
+        tessellated.push(Shape::mesh(mesh));
+    }
+
+    tessellated
+}
+
+/// What was submitted to egui when painting a layer, see [`GerberRenderer::paint_layer_with_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    /// The number of egui shapes, e.g. one per primitive, or a few meshes with
+    /// [`RenderConfiguration::hard_edges`].  Nested `Shape::Vec` are counted as their contents.
+    pub shapes: usize,
+    /// The number of shapes that are already meshes, i.e. that egui doesn't have to tessellate.
+    pub meshes: usize,
+    /// The number of vertices after tessellation, with the tessellation options of the context, including the
+    /// vertices used for anti-aliasing.
+    pub vertices: usize,
+}
+
+impl RenderStats {
+    fn from_shapes(ctx: &Context, shapes: &[Shape]) -> Self {
+        let options = ctx.tessellation_options(|options| *options);
+        // the font texture is only used for the texture coordinates of text, which don't affect the counts
+        let mut tessellator = Tessellator::new(ctx.pixels_per_point(), options, [1, 1], vec![]);
+
+        let mut stats = RenderStats::default();
+        stats.add_shapes(&mut tessellator, shapes);
+        stats
+    }
+
+    fn add_shapes(&mut self, tessellator: &mut Tessellator, shapes: &[Shape]) {
+        for shape in shapes {
+            match shape {
+                Shape::Vec(shapes) => self.add_shapes(tessellator, shapes),
+                Shape::Noop => {}
+                shape => {
+                    self.shapes += 1;
+                    if let Shape::Mesh(mesh) = shape {
+                        self.meshes += 1;
+                        self.vertices += mesh.vertices.len();
+                        continue;
+                    }
+
+                    let mut mesh = Mesh::default();
+                    tessellator.tessellate_shape(shape.clone(), &mut mesh);
+                    self.vertices += mesh.vertices.len();
+                }
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} shapes ({} meshes), {} vertices",
+            self.shapes, self.meshes, self.vertices
+        )
+    }
 }
 
+/// Draws a 1 point dot, for primitives that are too small to draw, `center` is in screen coordinates.
+fn render_dot(shapes: &mut ShapeList<'_>, center: Pos2, color: Color32) {
+    shapes.rect(
+        Rect::from_center_size(center, Vec2::splat(1.0)),
+        0.0,
+        color,
+        Stroke::NONE,
+        StrokeKind::Inside,
+    );
+}
+
+/// See [`RenderConfiguration::use_spatial_index_cells`].
+const SPATIAL_INDEX_CELL_COLOR: Color32 = Color32::from_rgb(0, 160, 255);
+
+/// Opacity of the untransformed layer, see [`RenderConfiguration::draw_untransformed_ghost`].
+const GHOST_OPACITY: f32 = 0.25;
+
+/// See [`RenderConfiguration::debug_clear_outline`].
+const CLEAR_OUTLINE_COLOR: Color32 = Color32::from_rgb(255, 64, 255);
+
+/// Length of the dashes and the gaps between them, in points, see [`RenderConfiguration::debug_clear_outline`].
+const CLEAR_OUTLINE_DASH: (f32, f32) = (6.0, 4.0);
+
+/// Primitives smaller than this, in points, are not numbered, see [`RenderConfiguration::number_visible_shapes_only`].
+const SHAPE_NUMBER_MIN_PIXELS: f32 = 16.0;
+
+/// See [`RenderConfiguration::solo_aperture`].
+const SOLO_APERTURE_COLOR: Color32 = Color32::from_rgb(255, 140, 0);
+
+/// Opacity of the primitives of the other apertures, see [`RenderConfiguration::solo_aperture`].
+const SOLO_DIM_OPACITY: f32 = 0.2;
+
+/// See [`GerberRenderer::paint_hovered_outline`].
+const HOVER_OUTLINE_COLOR: Color32 = Color32::from_rgb(255, 255, 0);
+
+/// Width of the outlines painted by [`GerberRenderer::paint_outline`], in physical pixels.
+const OUTLINE_PIXELS: f32 = 2.0;
+
+/// Color of the holes painted by [`GerberRenderer::paint_misregistered_holes`].
+const MISREGISTERED_HOLE_COLOR: Color32 = Color32::from_rgb(255, 0, 0);
+
+/// Minimum radius of the holes painted by [`GerberRenderer::paint_misregistered_holes`], in points, so small holes
+/// are still visible when zoomed out.
+const MISREGISTERED_HOLE_MIN_RADIUS: f32 = 4.0;
+
+#[derive(Clone)]
 pub struct GerberRenderer<'a> {
     configuration: &'a RenderConfiguration,
     view: ViewState,
@@ -88,8 +533,36 @@ pub struct GerberRenderer<'a> {
 
     transform_matrix: Matrix3<f64>,
     transform_scaling: Vector2<f64>,
+
+    on_primitive: Option<PrimitiveCallback<'a>>,
+}
+
+/// The painters used by [`GerberRenderer::paint_layer_with_painters`].
+#[derive(Clone, Copy)]
+pub struct LayerPainters<'p> {
+    /// For the grid, see [`RenderConfiguration::grid`].
+    pub background: &'p egui::Painter,
+    /// For the primitives, including the ghost, shape numbers, vertex numbers and bounding boxes, since they are
+    /// drawn per-primitive.
+    pub content: &'p egui::Painter,
+    /// For the spatial index cells, see [`RenderConfiguration::use_spatial_index_cells`].
+    pub foreground: &'p egui::Painter,
 }
 
+impl<'p> LayerPainters<'p> {
+    /// Uses the same painter for everything, same as [`GerberRenderer::paint_layer`].
+    pub fn single(painter: &'p egui::Painter) -> Self {
+        Self {
+            background: painter,
+            content: painter,
+            foreground: painter,
+        }
+    }
+}
+
+/// See [`GerberRenderer::on_primitive`].
+pub type PrimitiveCallback<'a> = &'a dyn Fn(usize, &GerberPrimitive, Pos2);
+
 impl<'a> GerberRenderer<'a> {
     pub fn new(
         configuration: &'a RenderConfiguration,
@@ -97,6 +570,20 @@ impl<'a> GerberRenderer<'a> {
         transform: &GerberTransform,
         layer: &'a GerberLayer,
     ) -> Self {
+        let (transform_matrix, transform_scaling) = Self::transform_matrix(layer, transform);
+
+        Self {
+            configuration,
+            view,
+            layer,
+            transform_matrix,
+            transform_scaling,
+            on_primitive: None,
+        }
+    }
+
+    /// The combined image and render transform matrix, and its scaling factors.
+    fn transform_matrix(layer: &GerberLayer, transform: &GerberTransform) -> (Matrix3<f64>, Vector2<f64>) {
         let render_transform_matrix = transform.to_matrix();
         let image_transform_matrix = layer.image_transform().to_matrix();
 
@@ -105,15 +592,75 @@ impl<'a> GerberRenderer<'a> {
         // computing the transform_scaling from the matrix is expensive, so we cache it
         let transform_scaling = transform_matrix.get_scaling_factors();
 
+        (transform_matrix, transform_scaling)
+    }
+
+    /// A renderer for the same layer, configuration and view, with a different render transform.
+    fn with_transform(&self, transform: &GerberTransform) -> Self {
+        let (transform_matrix, transform_scaling) = Self::transform_matrix(self.layer, transform);
+
         Self {
-            configuration,
-            view,
-            layer,
+            configuration: self.configuration,
+            view: self.view,
+            layer: self.layer,
             transform_matrix,
             transform_scaling,
+            on_primitive: self.on_primitive,
         }
     }
 
+    /// A renderer for the repeat of a step-and-repeat block at `offset` (gerber units), see
+    /// [`GerberLayer::step_repeats`].
+    fn with_offset(&self, offset: &Vector2<f64>) -> Self {
+        Self {
+            transform_matrix: self.transform_matrix * Matrix3::new_translation(offset),
+            ..self.clone()
+        }
+    }
+
+    /// The renderers and ranges for drawing the primitives in `range` in order, the primitives of a step-and-repeat
+    /// block are drawn once for each repeat.
+    fn instances(&self, range: Range<usize>) -> Vec<(Self, Range<usize>)> {
+        let mut instances = Vec::new();
+        let mut start = range.start;
+
+        for step_repeat in self.layer.step_repeats() {
+            let block = step_repeat.primitives.start.max(start)
+                ..step_repeat
+                    .primitives
+                    .end
+                    .min(range.end);
+            if block.is_empty() {
+                continue;
+            }
+            instances.push((self.clone(), start..block.start));
+            instances.extend(
+                step_repeat
+                    .offsets
+                    .iter()
+                    .map(|offset| (self.with_offset(offset), block.clone())),
+            );
+            start = block.end;
+        }
+        instances.push((self.clone(), start..range.end.max(start)));
+
+        instances
+    }
+
+    /// Sets a callback for custom per-primitive overlays, e.g. a badge on flagged pads.
+    ///
+    /// The callback runs inside [`GerberRenderer::paint_layer`] and [`GerberRenderer::paint_range`], every time
+    /// they are called, so keep it cheap.  It is called once for each painted primitive, in layer order, after all
+    /// the primitives have been added to the painter, so anything the callback paints is on top of the layer.  The
+    /// arguments are the index of the primitive, the primitive and the screen position of the center of its
+    /// transformed bounding box.
+    ///
+    /// Not used by [`GerberRenderer::build_shapes`] and [`GerberRenderer::build_shapes_for_range`].
+    pub fn on_primitive(mut self, callback: PrimitiveCallback<'a>) -> Self {
+        self.on_primitive = Some(callback);
+        self
+    }
+
     /// converts gerber to screen coordinates, using the renderer transforms.
     /// coordinates are in gerber units.
     pub fn gerber_to_screen_coordinates(&self, position: &Point2<f64>) -> Pos2 {
@@ -127,14 +674,598 @@ impl<'a> GerberRenderer<'a> {
             .to_pos2()
     }
 
+    /// The inverse of [`GerberRenderer::gerber_to_screen_coordinates`], `None` if the transforms are not invertible.
+    fn screen_to_gerber_coordinates(&self, position: Pos2) -> Option<Point2<f64>> {
+        let inverse = self.transform_matrix.affine_inverse()?;
+        let position = ((position - self.view.translation).to_vec2() / self.view.scale).to_pos2();
+        let gerber = inverse.transform_pos2(position);
+
+        Some(Point2::new(gerber.x as f64, -(gerber.y as f64)))
+    }
+
+    /// Returns the screen rect that contains the given bounding box (gerber units) after applying the renderer
+    /// transforms.
+    pub fn gerber_to_screen_rect(&self, bbox: &BoundingBox) -> Rect {
+        let corners = bbox
+            .vertices()
+            .iter()
+            .map(|vertex| self.gerber_to_screen_coordinates(vertex))
+            .collect::<Vec<_>>();
+
+        Rect::from_points(&corners)
+    }
+
+    /// Returns the screen rect that contains the primitive with the given index after applying the image transform
+    /// of the layer, the render transform and the view, e.g. for drawing a highlight around a primitive.
+    ///
+    /// Returns `None` if there is no primitive with the given index.
+    pub fn primitive_screen_bbox(&self, index: usize) -> Option<Rect> {
+        let primitive = self.layer.primitives().get(index)?;
+
+        Some(self.gerber_to_screen_rect(&primitive.bounding_box()))
+    }
+
+    /// Paints a thin outline around the primitive under the cursor, for hover feedback, nothing is painted if no
+    /// primitive is hovered.
+    ///
+    /// Call after painting the layer, so the outline is on top.  `ui_state` must be updated with the same layer and
+    /// transform as the renderer, see [`UiState::update_hovered_primitive`].
+    pub fn paint_hovered_outline(&self, painter: &egui::Painter, ui_state: &UiState) {
+        if let Some(index) = ui_state.hovered_primitive() {
+            self.paint_outline(painter, index, HOVER_OUTLINE_COLOR);
+        }
+    }
+
+    /// Paints the outline of the area covered by the primitive with the given index, e.g. the capsule of a line.
+    ///
+    /// The outline is a constant width in physical pixels, regardless of the zoom level.  Nothing is painted if there
+    /// is no primitive with the given index.
+    pub fn paint_outline(&self, painter: &egui::Painter, index: usize, color: Color32) {
+        let Some(primitive) = self.layer.primitives().get(index) else {
+            return;
+        };
+        let stroke = Stroke::new(OUTLINE_PIXELS / painter.pixels_per_point(), color);
+
+        for outline in primitive.outlines(self.configuration.effective_arc_steps()) {
+            let points = outline
+                .iter()
+                .map(|point| self.gerber_to_screen_coordinates(point))
+                .collect::<Vec<_>>();
+            painter.add(Shape::closed_line(points, stroke));
+        }
+    }
+
+    /// Paints the holes with the given indices in red, e.g. the ones returned by
+    /// [`GerberLayer::misregistered_holes`], as a circle the size of the hole with a cross at its center.
+    ///
+    /// Call after painting the layer, so the holes are on top.  The holes are transformed like the layer of this
+    /// renderer, so use the renderer of the copper layer the holes were checked against.  Indices without a hole are
+    /// ignored.
+    pub fn paint_misregistered_holes(&self, painter: &egui::Painter, holes: &[DrillHole], flagged: &[usize]) {
+        let stroke = Stroke::new(OUTLINE_PIXELS / painter.pixels_per_point(), MISREGISTERED_HOLE_COLOR);
+        let screen_scale = self.view.scale
+            * self
+                .transform_scaling
+                .x
+                .min(self.transform_scaling.y) as f32;
+
+        for hole in flagged
+            .iter()
+            .filter_map(|&index| holes.get(index))
+        {
+            let center = self.gerber_to_screen_coordinates(&hole.center);
+            let radius = (hole.diameter as f32 / 2.0 * screen_scale).max(MISREGISTERED_HOLE_MIN_RADIUS);
+
+            painter.circle_stroke(center, radius, stroke);
+            let arm = radius * std::f32::consts::FRAC_1_SQRT_2;
+            painter.line_segment([center + Vec2::new(-arm, -arm), center + Vec2::new(arm, arm)], stroke);
+            painter.line_segment([center + Vec2::new(-arm, arm), center + Vec2::new(arm, -arm)], stroke);
+        }
+    }
+
+    /// Paints `color` over everything outside the clip path, within the clip rect of the painter, e.g. with the
+    /// background color so that copper outside the board outline is hidden, see [`GerberLayer::as_clip_path`].
+    ///
+    /// Call after painting the layers that should be clipped.  The clip path is transformed like the layer of this
+    /// renderer, so use the renderer of the profile layer, or one with the same transforms.  Nothing is painted if the
+    /// clip path is empty.
+    pub fn paint_clip_mask(&self, painter: &egui::Painter, clip_path: &ClipPath, color: Color32) {
+        if clip_path.is_empty() {
+            return;
+        }
+
+        // the area around the board, the contours of the board are holes in it
+        let area = painter.clip_rect().expand(1.0);
+        let mut contours = vec![
+            [
+                area.left_top(),
+                area.right_top(),
+                area.right_bottom(),
+                area.left_bottom(),
+            ]
+            .map(|corner| Point2::new(corner.x as f64, corner.y as f64))
+            .to_vec(),
+        ];
+        contours.extend(
+            clip_path
+                .contours()
+                .iter()
+                .map(|contour| {
+                    contour
+                        .iter()
+                        .map(|point| {
+                            let position = self.gerber_to_screen_coordinates(point);
+                            Point2::new(position.x as f64, position.y as f64)
+                        })
+                        .collect::<Vec<_>>()
+                }),
+        );
+
+        let tessellation = tessellate_contours(&contours);
+        let mesh = Mesh {
+            indices: tessellation.indices,
+            vertices: tessellation
+                .vertices
+                .into_iter()
+                .map(|[x, y]| Vertex {
+                    pos: Pos2::new(x, y),
+                    uv: egui::epaint::WHITE_UV,
+                    color,
+                })
+                .collect(),
+            ..Default::default()
+        };
+        painter.add(Shape::mesh(mesh));
+    }
+
+    /// Returns the indices of the primitives whose transformed bounding box intersects the viewport, in layer order.
+    ///
+    /// `viewport` is in screen coordinates, e.g. the rect the layer is painted into.
+    ///
+    /// The result depends on the `view` the renderer was created with, after panning or zooming create a new
+    /// renderer before calling this again.
     #[profiling::function]
-    pub fn paint_layer(&self, painter: &egui::Painter, base_color: Color32) {
-        for (index, primitive) in self
-            .layer
+    pub fn visible_primitive_indices(&self, viewport: Rect) -> Vec<usize> {
+        self.layer
             .primitives()
             .iter()
             .enumerate()
+            .filter(|(_, primitive)| self.is_visible(primitive, viewport))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// True if the transformed bounding box of the primitive intersects the viewport (screen coordinates).
+    fn is_visible(&self, primitive: &GerberPrimitive, viewport: Rect) -> bool {
+        self.gerber_to_screen_rect(&primitive.bounding_box())
+            .intersects(viewport)
+    }
+
+    /// True if the primitive should be numbered, see [`RenderConfiguration::number_visible_shapes_only`].
+    fn is_numbered(&self, primitive: &GerberPrimitive, viewport: Rect) -> bool {
+        if !self
+            .configuration
+            .number_visible_shapes_only
+        {
+            return true;
+        }
+
+        let screen_rect = self.gerber_to_screen_rect(&primitive.bounding_box());
+        screen_rect.intersects(viewport) && screen_rect.size().max_elem() >= SHAPE_NUMBER_MIN_PIXELS
+    }
+
+    #[profiling::function]
+    pub fn paint_layer(&self, painter: &egui::Painter, base_color: Color32) {
+        self.paint_range(painter, base_color, 0..self.layer.primitives().len());
+    }
+
+    /// Paints the layer once for each transform, e.g. the instances of a panel, or a moved copy next to the original.
+    ///
+    /// The transforms replace the render transform the renderer was created with, the image transform of the layer
+    /// still applies to each instance.  The grid and the spatial index cells are painted once, with the transform of
+    /// the renderer, the [`GerberRenderer::on_primitive`] callback is called for each primitive of each instance.  An
+    /// empty slice paints no primitives.
+    #[profiling::function]
+    pub fn paint_layer_instances(&self, painter: &egui::Painter, base_color: Color32, transforms: &[GerberTransform]) {
+        let range = 0..self.layer.primitives().len();
+
+        let instances = transforms
+            .iter()
+            .map(|transform| self.with_transform(transform))
+            .collect::<Vec<_>>();
+
+        painter.extend(self.build_background_shapes(painter.ctx()));
+        for instance in &instances {
+            painter.extend(instance.build_content_shapes(painter.ctx(), base_color, range.clone()));
+        }
+        painter.extend(self.build_foreground_shapes(painter.ctx()));
+
+        // after all the instances, so the overlays are on top, like `paint_layer`
+        for instance in &instances {
+            instance.call_on_primitive(range.clone());
+        }
+    }
+
+    /// Paints the layer like [`GerberRenderer::paint_layer`] and returns what was submitted to egui, for performance
+    /// debugging, e.g. to see the effect of [`RenderConfiguration::continuous_traces`] or
+    /// [`RenderConfiguration::min_feature_pixels`].
+    ///
+    /// Counting the vertices tessellates the shapes, so this is slower than `paint_layer`, use it only while
+    /// measuring.
+    #[profiling::function]
+    pub fn paint_layer_with_stats(&self, painter: &egui::Painter, base_color: Color32) -> RenderStats {
+        let range = 0..self.layer.primitives().len();
+        let shapes = self.build_shapes_for_range(painter.ctx(), base_color, range.clone());
+        let stats = RenderStats::from_shapes(painter.ctx(), &shapes);
+
+        painter.extend(shapes);
+        self.call_on_primitive(range);
+
+        stats
+    }
+
+    /// Paints only the primitives in the given range, a debugging aid.
+    ///
+    /// Shape numbers and unique shape colors are based on the index of the primitive in the layer, not the range,
+    /// so rendering `[0..k]` and increasing/decreasing `k` can be used to bisect a problematic primitive.
+    ///
+    /// The range is clamped to the number of primitives in the layer.
+    #[profiling::function]
+    pub fn paint_range(&self, painter: &egui::Painter, base_color: Color32, range: Range<usize>) {
+        painter.extend(self.build_shapes_for_range(painter.ctx(), base_color, range.clone()));
+
+        self.call_on_primitive(range);
+    }
+
+    /// Paints the layer like [`GerberRenderer::paint_layer`], but split over separate painters so that the app can
+    /// control the z-order of the grid, the primitives and the overlays relative to its own shapes, e.g. annotations
+    /// between the grid and the primitives.
+    ///
+    /// The painters can be on different egui layers, e.g. created with [`egui::Painter::with_layer_id`], or the same
+    /// layer, in which case the shapes are painted in the order the painters are used: background, content, then
+    /// foreground.
+    #[profiling::function]
+    pub fn paint_layer_with_painters(&self, painters: &LayerPainters<'_>, base_color: Color32) {
+        let range = 0..self.layer.primitives().len();
+
+        painters
+            .background
+            .extend(self.build_background_shapes(painters.background.ctx()));
+        painters
+            .content
+            .extend(self.build_content_shapes(painters.content.ctx(), base_color, range.clone()));
+        painters
+            .foreground
+            .extend(self.build_foreground_shapes(painters.foreground.ctx()));
+
+        self.call_on_primitive(range);
+    }
+
+    /// Calls the [`GerberRenderer::on_primitive`] callback, if any, for each primitive in the range.
+    fn call_on_primitive(&self, range: Range<usize>) {
+        let Some(on_primitive) = self.on_primitive else {
+            return;
+        };
+
+        let primitives = self.layer.primitives();
+        let end = range.end.min(primitives.len());
+        let start = range.start.min(end);
+
+        for (instance, range) in self.instances(start..end) {
+            for (index, primitive) in primitives[range.clone()]
+                .iter()
+                .enumerate()
+            {
+                let center = instance.gerber_to_screen_coordinates(&primitive.bounding_box().center());
+                on_primitive(range.start + index, primitive, center);
+            }
+        }
+    }
+
+    /// Returns the shapes that [`GerberRenderer::paint_layer`] would paint, in the same order.
+    ///
+    /// Use this to insert the layer at a specific z-order, or to post-process the shapes before adding them to a
+    /// painter.  The shapes are in screen coordinates; clipping is applied by the painter they are added to.
+    ///
+    /// The context is only used to lay out text, for shape and vertex numbering.
+    #[profiling::function]
+    pub fn build_shapes(&self, ctx: &Context, base_color: Color32) -> Vec<Shape> {
+        self.build_shapes_for_range(ctx, base_color, 0..self.layer.primitives().len())
+    }
+
+    /// Returns the shapes that [`GerberRenderer::paint_range`] would paint, in the same order.
+    #[profiling::function]
+    pub fn build_shapes_for_range(&self, ctx: &Context, base_color: Color32, range: Range<usize>) -> Vec<Shape> {
+        let mut shapes = self.build_background_shapes(ctx);
+        shapes.extend(self.build_content_shapes(ctx, base_color, range));
+        shapes.extend(self.build_foreground_shapes(ctx));
+
+        shapes
+    }
+
+    /// The grid, see [`LayerPainters::background`].
+    fn build_background_shapes(&self, ctx: &Context) -> Vec<Shape> {
+        let mut shapes = ShapeList::new(ctx);
+
+        if let Some(grid) = &self.configuration.grid {
+            self.render_grid(&mut shapes, ctx.content_rect(), grid.spacing(self.layer));
+        }
+
+        shapes.into_inner()
+    }
+
+    /// The ghost and the primitives, see [`LayerPainters::content`].
+    fn build_content_shapes(&self, ctx: &Context, base_color: Color32, range: Range<usize>) -> Vec<Shape> {
+        let primitives = self.layer.primitives();
+        let end = range.end.min(primitives.len());
+        let start = range.start.min(end);
+
+        let mut shapes = ShapeList::new(ctx);
+
+        if self
+            .configuration
+            .draw_untransformed_ghost
+        {
+            let ghost_configuration = RenderConfiguration {
+                use_unique_shape_colors: false,
+                use_shape_numbering: false,
+                use_vertex_numbering: false,
+                use_shape_bboxes: false,
+                draw_untransformed_ghost: false,
+                use_spatial_index_cells: false,
+                grid: None,
+                ..self.configuration.clone()
+            };
+            GerberRenderer::new(&ghost_configuration, self.view, &GerberTransform::default(), self.layer)
+                .render_primitives(&mut shapes, base_color.gamma_multiply(GHOST_OPACITY), start..end);
+        }
+
+        self.render_primitives(&mut shapes, base_color, start..end);
+
+        match self.configuration.hard_edges {
+            true => tessellate_without_feathering(ctx, shapes.into_inner()),
+            false => shapes.into_inner(),
+        }
+    }
+
+    /// Debug overlays that are not drawn per-primitive, see [`LayerPainters::foreground`].
+    fn build_foreground_shapes(&self, ctx: &Context) -> Vec<Shape> {
+        let mut shapes = ShapeList::new(ctx);
+
+        if self
+            .configuration
+            .use_spatial_index_cells
+        {
+            self.render_spatial_index_cells(&mut shapes);
+        }
+
+        shapes.into_inner()
+    }
+
+    /// Draws the grid lines that cross `area` (screen coordinates).
+    fn render_grid(&self, shapes: &mut ShapeList<'_>, area: Rect, spacing: f64) {
+        let screen_scale = self.view.scale as f64
+            * self
+                .transform_scaling
+                .x
+                .min(self.transform_scaling.y);
+        if spacing.is_nan() || spacing <= 0.0 || screen_scale <= 0.0 {
+            return;
+        }
+
+        let mut spacing = spacing;
+        while spacing * screen_scale < MIN_GRID_SPACING {
+            spacing *= 10.0;
+        }
+
+        let Some(corners) = [
+            area.left_top(),
+            area.right_top(),
+            area.right_bottom(),
+            area.left_bottom(),
+        ]
+        .into_iter()
+        .map(|corner| self.screen_to_gerber_coordinates(corner))
+        .collect::<Option<Vec<_>>>() else {
+            return;
+        };
+        let bbox = BoundingBox::from_points(&corners);
+
+        let stroke = Stroke::new(1.0, GRID_COLOR);
+        let lines = |min: f64, max: f64| (min / spacing).floor() as i64..=(max / spacing).ceil() as i64;
+
+        for column in lines(bbox.min.x, bbox.max.x) {
+            let x = column as f64 * spacing;
+            shapes.add(Shape::line_segment(
+                [
+                    self.gerber_to_screen_coordinates(&Point2::new(x, bbox.min.y)),
+                    self.gerber_to_screen_coordinates(&Point2::new(x, bbox.max.y)),
+                ],
+                stroke,
+            ));
+        }
+        for row in lines(bbox.min.y, bbox.max.y) {
+            let y = row as f64 * spacing;
+            shapes.add(Shape::line_segment(
+                [
+                    self.gerber_to_screen_coordinates(&Point2::new(bbox.min.x, y)),
+                    self.gerber_to_screen_coordinates(&Point2::new(bbox.max.x, y)),
+                ],
+                stroke,
+            ));
+        }
+    }
+
+    fn render_spatial_index_cells(&self, shapes: &mut ShapeList<'_>) {
+        for (cell, _count) in self.layer.spatial_index_cells() {
+            let points = cell
+                .vertices()
+                .iter()
+                .map(|vertex| self.gerber_to_screen_coordinates(vertex))
+                .collect::<Vec<_>>();
+
+            shapes.add(Shape::closed_line(points, Stroke::new(1.0, SPATIAL_INDEX_CELL_COLOR)));
+        }
+    }
+
+    /// See [`RenderConfiguration::tint`].
+    fn tinted(&self, color: Color32) -> Color32 {
+        match self.configuration.tint {
+            Some(tint) => color * tint,
+            None => color,
+        }
+    }
+
+    /// See [`RenderConfiguration::continuous_traces`].
+    fn joins_traces(&self) -> bool {
+        let configuration = self.configuration;
+
+        configuration.continuous_traces
+            && !configuration.use_unique_shape_colors
+            && !configuration.use_shape_numbering
+            && !configuration.use_shape_bboxes
+    }
+
+    /// Renders a line and the lines connected to it as a single stroked path, round caps are only drawn at the ends.
+    fn render_trace(
+        &self,
+        shapes: &mut ShapeList<'_>,
+        first: &LineGerberPrimitive,
+        connected: &[&LineGerberPrimitive],
+        color: Color32,
+    ) {
+        let color = first.exposure.to_color(&color);
+        let width = ((first.width as f32) * self.view.scale).max(
+            self.configuration
+                .effective_min_stroke_width(),
+        );
+        let stroke = Stroke::new(width, color);
+
+        let mut points = vec![self.gerber_to_screen_coordinates(&first.start)];
+        points.extend(
+            std::iter::once(first)
+                .chain(connected.iter().copied())
+                .map(|line| self.gerber_to_screen_coordinates(&line.end)),
+        );
+
+        let closed = connected
+            .last()
+            .is_some_and(|last| last.end == first.start);
+        if closed {
+            points.pop();
+        }
+
+        let corners = sharp_corners(&points, closed, self.configuration.miter_limit);
+        if closed && corners.is_empty() {
+            shapes.add(Shape::closed_line(points, stroke));
+            return;
+        }
+
+        let radius = width / 2.0;
+        for &corner in &corners {
+            shapes.add(Shape::convex_polygon(
+                bevel(&points, corner, radius),
+                color,
+                Stroke::NONE,
+            ));
+        }
+
+        // the path is split at the sharp corners, a closed path is opened at the first one
+        let (path, mut boundaries) = match closed {
+            true => {
+                let start = corners[0];
+                let path = points[start..]
+                    .iter()
+                    .chain(&points[..=start])
+                    .copied()
+                    .collect::<Vec<_>>();
+                let boundaries = corners
+                    .iter()
+                    .map(|corner| corner - start)
+                    .collect::<Vec<_>>();
+                (path, boundaries)
+            }
+            false => {
+                shapes.circle(points[0], radius, color, Stroke::NONE);
+                shapes.circle(*points.last().unwrap(), radius, color, Stroke::NONE);
+                let mut boundaries = vec![0];
+                boundaries.extend(&corners);
+                (points, boundaries)
+            }
+        };
+        boundaries.push(path.len() - 1);
+
+        for run in boundaries.windows(2) {
+            shapes.add(Shape::line(path[run[0]..=run[1]].to_vec(), stroke));
+        }
+    }
+
+    /// See [`RenderConfiguration::debug_clear_outline`].
+    fn render_clear_outline(&self, shapes: &mut ShapeList<'_>, primitive: &GerberPrimitive) {
+        let stroke = Stroke::new(OUTLINE_PIXELS / shapes.ctx.pixels_per_point(), CLEAR_OUTLINE_COLOR);
+        let (dash_length, gap_length) = CLEAR_OUTLINE_DASH;
+
+        for outline in primitive.outlines(self.configuration.effective_arc_steps()) {
+            let mut points = outline
+                .iter()
+                .map(|point| self.gerber_to_screen_coordinates(point))
+                .collect::<Vec<_>>();
+            // closed
+            points.extend(points.first().copied());
+
+            for dash in Shape::dashed_line(&points, stroke, dash_length, gap_length) {
+                shapes.add(dash);
+            }
+        }
+    }
+
+    fn render_primitives(&self, shapes: &mut ShapeList<'_>, base_color: Color32, range: Range<usize>) {
+        if self.layer.image_polarity() == ImagePolarity::Negative && !self.layer.is_empty() {
+            // the image area of a negative image is dark, the primitives (with inverted exposure) are drawn over it.
+            let vertices = self
+                .layer
+                .bounding_box()
+                .vertices()
+                .iter()
+                .map(|vertex| self.gerber_to_screen_coordinates(vertex))
+                .collect::<Vec<_>>();
+            shapes.add(Shape::convex_polygon(vertices, self.tinted(base_color), Stroke::NONE));
+        }
+
+        for (instance, range) in self.instances(range) {
+            instance.render_instance(shapes, base_color, range);
+        }
+    }
+
+    /// Renders the primitives in the range, with the transform of the renderer, see [`GerberRenderer::instances`].
+    fn render_instance(&self, shapes: &mut ShapeList<'_>, base_color: Color32, range: Range<usize>) {
+        let primitives = self.layer.primitives();
+        let apertures = self.layer.primitive_apertures();
+        let mut indices: Vec<usize> = range.collect();
+        if !self
+            .configuration
+            .hidden_apertures
+            .is_empty()
         {
+            indices.retain(|&index| {
+                apertures[index].is_none_or(|code| {
+                    !self
+                        .configuration
+                        .hidden_apertures
+                        .contains(&code)
+                })
+            });
+        }
+        if self.configuration.polarity_grouping {
+            // stable, so command order is preserved within each group
+            indices.sort_by_key(|&index| primitives[index].exposure() == Exposure::CutOut);
+        }
+
+        let mut position = 0;
+        while let Some(&index) = indices.get(position) {
+            position += 1;
+            let primitive = &primitives[index];
+
             let color = match self
                 .configuration
                 .use_unique_shape_colors
@@ -142,15 +1273,59 @@ impl<'a> GerberRenderer<'a> {
                 true => color::generate_pastel_color(index as u64),
                 false => base_color,
             };
+            let color = match self.configuration.solo_aperture {
+                Some(code) if apertures[index] == Some(code) => SOLO_APERTURE_COLOR,
+                Some(_) => color.gamma_multiply(SOLO_DIM_OPACITY),
+                None => color,
+            };
+            let color = self.tinted(color);
+
+            if self.configuration.debug_clear_outline && primitive.exposure() == Exposure::CutOut {
+                self.render_clear_outline(shapes, primitive);
+                continue;
+            }
+
+            // a flash of a zero-size aperture, see `DegenerateApertures::Dot`
+            if let GerberPrimitive::Circle(circle) = primitive
+                && circle.diameter == 0.0
+            {
+                if circle.exposure == Exposure::Add {
+                    render_dot(shapes, self.gerber_to_screen_coordinates(&circle.center), color);
+                }
+                continue;
+            }
+
+            if self.configuration.min_feature_pixels > 0.0 {
+                let screen_rect = self.gerber_to_screen_rect(&primitive.bounding_box());
+                if screen_rect.size().max_elem() < self.configuration.min_feature_pixels {
+                    if primitive.exposure() == Exposure::Add {
+                        render_dot(shapes, screen_rect.center(), color);
+                    }
+                    continue;
+                }
+            }
+
+            if self.joins_traces()
+                && let GerberPrimitive::Line(line) = primitive
+            {
+                let connected = connected_lines(line, primitives, &indices[position..]);
+                if !connected.is_empty() {
+                    position += connected.len();
+                    self.render_trace(shapes, line, &connected, color);
+                    continue;
+                }
+            }
 
-            let shape_number = match self.configuration.use_shape_numbering {
+            let shape_number = match self.configuration.use_shape_numbering
+                && self.is_numbered(primitive, shapes.ctx.content_rect())
+            {
                 true => Some(index),
                 false => None,
             };
 
             match primitive {
                 GerberPrimitive::Circle(circle) => circle.render(
-                    painter,
+                    shapes,
                     &self.view,
                     &self.transform_matrix,
                     &self.transform_scaling,
@@ -159,7 +1334,16 @@ impl<'a> GerberRenderer<'a> {
                     self.configuration,
                 ),
                 GerberPrimitive::Rectangle(rect) => rect.render(
-                    painter,
+                    shapes,
+                    &self.view,
+                    &self.transform_matrix,
+                    &self.transform_scaling,
+                    color,
+                    shape_number,
+                    self.configuration,
+                ),
+                GerberPrimitive::RoundedRectangle(rect) => rect.render(
+                    shapes,
                     &self.view,
                     &self.transform_matrix,
                     &self.transform_scaling,
@@ -168,7 +1352,7 @@ impl<'a> GerberRenderer<'a> {
                     self.configuration,
                 ),
                 GerberPrimitive::Line(line) => line.render(
-                    painter,
+                    shapes,
                     &self.view,
                     &self.transform_matrix,
                     &self.transform_scaling,
@@ -177,7 +1361,7 @@ impl<'a> GerberRenderer<'a> {
                     self.configuration,
                 ),
                 GerberPrimitive::Arc(arc) => arc.render(
-                    painter,
+                    shapes,
                     &self.view,
                     &self.transform_matrix,
                     &self.transform_scaling,
@@ -186,7 +1370,7 @@ impl<'a> GerberRenderer<'a> {
                     self.configuration,
                 ),
                 GerberPrimitive::Polygon(polygon) => polygon.render(
-                    painter,
+                    shapes,
                     &self.view,
                     &self.transform_matrix,
                     &self.transform_scaling,
@@ -199,10 +1383,127 @@ impl<'a> GerberRenderer<'a> {
     }
 }
 
+/// The lines at the start of `indices` that continue the trace of `line`, i.e. each starts where the previous one
+/// ends and has the same width and exposure.
+fn connected_lines<'a>(
+    line: &LineGerberPrimitive,
+    primitives: &'a [GerberPrimitive],
+    indices: &[usize],
+) -> Vec<&'a LineGerberPrimitive> {
+    let mut end = line.end;
+    indices
+        .iter()
+        .map_while(|&index| match &primitives[index] {
+            GerberPrimitive::Line(next)
+                if next.start == end && next.width == line.width && next.exposure == line.exposure =>
+            {
+                end = next.end;
+                Some(next)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// The indices of the vertices of the path whose join is sharper than the miter limit, see
+/// [`RenderConfiguration::miter_limit`].
+fn sharp_corners(points: &[Pos2], closed: bool, miter_limit: f32) -> Vec<usize> {
+    let n = points.len();
+    let vertices = match closed {
+        true => 0..n,
+        false => 1..n.saturating_sub(1),
+    };
+
+    vertices
+        .filter(|&index| {
+            let n0 = (points[index] - points[(index + n - 1) % n])
+                .normalized()
+                .rot90();
+            let n1 = (points[(index + 1) % n] - points[index])
+                .normalized()
+                .rot90();
+            // duplicated points have no direction
+            if n0 == Vec2::ZERO || n1 == Vec2::ZERO {
+                return false;
+            }
+
+            // the miter extends `1 / |normal|` half-widths from the center line, the same as the egui tessellator
+            let normal = (n0 + n1) / 2.0;
+            normal.length_sq() * miter_limit * miter_limit < 1.0
+        })
+        .collect()
+}
+
+/// The triangle that fills the outer side of the join at a vertex of the path, between the ends of the two strokes.
+fn bevel(points: &[Pos2], index: usize, radius: f32) -> Vec<Pos2> {
+    let n = points.len();
+    let point = points[index];
+    let n0 = (point - points[(index + n - 1) % n])
+        .normalized()
+        .rot90();
+    let direction = (points[(index + 1) % n] - point).normalized();
+    let n1 = direction.rot90();
+
+    // the outer side is the side the path turns away from
+    let offset = match direction.dot(n0) > 0.0 {
+        true => -radius,
+        false => radius,
+    };
+
+    vec![point, point + n0 * offset, point + n1 * offset]
+}
+
+/// Collects shapes, mirrors the subset of the `egui::Painter` API used by the renderables.
+struct ShapeList<'a> {
+    ctx: &'a Context,
+    shapes: Vec<Shape>,
+}
+
+impl<'a> ShapeList<'a> {
+    fn new(ctx: &'a Context) -> Self {
+        Self {
+            ctx,
+            shapes: Vec::new(),
+        }
+    }
+
+    fn into_inner(self) -> Vec<Shape> {
+        self.shapes
+    }
+
+    fn add(&mut self, shape: impl Into<Shape>) {
+        self.shapes.push(shape.into());
+    }
+
+    fn circle(&mut self, center: Pos2, radius: f32, fill_color: Color32, stroke: Stroke) {
+        self.add(CircleShape {
+            center,
+            radius,
+            fill: fill_color,
+            stroke,
+        });
+    }
+
+    fn rect(&mut self, rect: Rect, corner_radius: f32, fill_color: Color32, stroke: Stroke, stroke_kind: StrokeKind) {
+        self.add(RectShape::new(rect, corner_radius, fill_color, stroke, stroke_kind));
+    }
+
+    fn line_segment(&mut self, points: [Pos2; 2], stroke: Stroke) {
+        self.add(Shape::line_segment(points, stroke));
+    }
+
+    fn text(&mut self, pos: Pos2, anchor: Align2, text: impl ToString, font_id: FontId, text_color: Color32) {
+        let shape = self
+            .ctx
+            .fonts_mut(|fonts| Shape::text(fonts, pos, anchor, text, font_id, text_color));
+        self.add(shape);
+    }
+}
+
 trait Renderable {
     fn render(
         &self,
-        painter: &Painter,
+        shapes: &mut ShapeList<'_>,
         view: &ViewState,
         transform_matrix: &Matrix3<f64>,
         transform_scaling: &Vector2<f64>,
@@ -216,7 +1517,7 @@ impl Renderable for CircleGerberPrimitive {
     #[cfg_attr(feature = "profile-renderables", profiling::function)]
     fn render(
         &self,
-        painter: &Painter,
+        shapes: &mut ShapeList<'_>,
         view: &ViewState,
         transform_matrix: &Matrix3<f64>,
         transform_scaling: &Vector2<f64>,
@@ -227,7 +1528,9 @@ impl Renderable for CircleGerberPrimitive {
         let Self {
             center,
             diameter,
+            hole_diameter,
             exposure,
+            ..
         } = self;
 
         let color = exposure.to_color(&color);
@@ -239,12 +1542,26 @@ impl Renderable for CircleGerberPrimitive {
         let diameter = *diameter * transform_scaling.x;
 
         let radius = (diameter as f32 / 2.0) * view.scale;
-        #[cfg(feature = "egui")]
-        painter.circle(center, radius, color, Stroke::NONE);
+        match hole_diameter {
+            Some(hole_diameter) => {
+                // annulus, drawn as a stroke centered between the inner and outer radius
+                let hole_radius = ((*hole_diameter * transform_scaling.x) as f32 / 2.0) * view.scale;
+                let width = radius - hole_radius;
+                if width > 0.0 {
+                    shapes.circle(
+                        center,
+                        (radius + hole_radius) / 2.0,
+                        Color32::TRANSPARENT,
+                        Stroke::new(width, color),
+                    );
+                }
+            }
+            None => shapes.circle(center, radius, color, Stroke::NONE),
+        }
 
-        draw_bbox!(self, configuration, painter, color, view, transform_matrix);
+        draw_bbox!(self, configuration, shapes, color, view, transform_matrix);
         draw_shape_number(
-            painter,
+            shapes,
             view,
             transform_matrix,
             ShapeNumberPosition::Transformed(center),
@@ -257,7 +1574,7 @@ impl Renderable for RectangleGerberPrimitive {
     #[cfg_attr(feature = "profile-renderables", profiling::function)]
     fn render(
         &self,
-        painter: &Painter,
+        shapes: &mut ShapeList<'_>,
         view: &ViewState,
         transform_matrix: &Matrix3<f64>,
         transform_scaling: &Vector2<f64>,
@@ -270,6 +1587,7 @@ impl Renderable for RectangleGerberPrimitive {
             width,
             height,
             exposure,
+            ..
         } = self;
 
         let color = exposure.to_color(&color);
@@ -301,7 +1619,7 @@ impl Renderable for RectangleGerberPrimitive {
 
             let top_left = center - size / 2.0; // Calculate top-left from center
 
-            painter.rect(
+            shapes.rect(
                 Rect::from_min_size(top_left, size),
                 0.0,
                 color,
@@ -330,12 +1648,89 @@ impl Renderable for RectangleGerberPrimitive {
                 })
                 .collect();
 
-            painter.add(Shape::convex_polygon(screen_corners, color, Stroke::NONE));
+            shapes.add(Shape::convex_polygon(screen_corners, color, Stroke::NONE));
+        }
+
+        draw_bbox!(self, configuration, shapes, color, view, transform_matrix);
+        draw_shape_number(
+            shapes,
+            view,
+            transform_matrix,
+            ShapeNumberPosition::Transformed(center),
+            shape_number,
+        );
+    }
+}
+
+impl Renderable for RoundedRectangleGerberPrimitive {
+    #[cfg_attr(feature = "profile-renderables", profiling::function)]
+    fn render(
+        &self,
+        shapes: &mut ShapeList<'_>,
+        view: &ViewState,
+        transform_matrix: &Matrix3<f64>,
+        transform_scaling: &Vector2<f64>,
+        color: Color32,
+        shape_number: Option<usize>,
+        configuration: &RenderConfiguration,
+    ) {
+        let Self {
+            origin,
+            width,
+            height,
+            corner_radius,
+            exposure,
+            ..
+        } = self;
+
+        let color = exposure.to_color(&color);
+
+        let screen_center = Pos2::new(
+            origin.x as f32 + *width as f32 / 2.0,
+            -(origin.y as f32 + *height as f32 / 2.0),
+        );
+        let center = (view.translation + transform_matrix.transform_pos2(screen_center) * view.scale).to_pos2();
+
+        let screen_radius = (*corner_radius * transform_scaling.x) as f32 * view.scale;
+
+        // `CornerRadius` is limited to 255 points, larger corners are drawn as a polygon
+        if transform_matrix.is_axis_aligned() && screen_radius <= u8::MAX as f32 {
+            // Fast-path: same as `RectangleGerberPrimitive`
+            let mut width = *width as f32;
+            let mut height = *height as f32;
+
+            if transform_matrix.is_90_or_270_rotation() {
+                std::mem::swap(&mut width, &mut height);
+            }
+
+            width *= transform_scaling.x as f32;
+            height *= transform_scaling.y as f32;
+
+            let size = Vec2::new(width, height) * view.scale;
+
+            shapes.rect(
+                Rect::from_center_size(center, size),
+                screen_radius,
+                color,
+                Stroke::NONE,
+                StrokeKind::Middle,
+            );
+        } else {
+            let screen_points: Vec<Pos2> = self
+                .outline(Self::DEFAULT_CORNER_STEPS)
+                .iter()
+                .map(|point| {
+                    let local = Pos2::new(point.x as f32, -(point.y as f32));
+                    (view.translation + transform_matrix.transform_pos2(local) * view.scale).to_pos2()
+                })
+                .collect();
+
+            shapes.add(Shape::convex_polygon(screen_points, color, Stroke::NONE));
         }
 
-        draw_bbox!(self, configuration, painter, color, view, transform_matrix);
+        draw_bbox!(self, configuration, shapes, color, view, transform_matrix);
         draw_shape_number(
-            painter,
+            shapes,
             view,
             transform_matrix,
             ShapeNumberPosition::Transformed(center),
@@ -348,7 +1743,7 @@ impl Renderable for LineGerberPrimitive {
     #[cfg_attr(feature = "profile-renderables", profiling::function)]
     fn render(
         &self,
-        painter: &Painter,
+        shapes: &mut ShapeList<'_>,
         view: &ViewState,
         transform_matrix: &Matrix3<f64>,
         _transform_scaling: &Vector2<f64>,
@@ -361,6 +1756,7 @@ impl Renderable for LineGerberPrimitive {
             end,
             width,
             exposure,
+            ..
         } = self;
         let color = exposure.to_color(&color);
 
@@ -372,21 +1768,23 @@ impl Renderable for LineGerberPrimitive {
         let transformed_end_position =
             (view.translation + transform_matrix.transform_pos2(end_position) * view.scale).to_pos2();
 
-        painter.line_segment(
+        let width = ((*width as f32) * view.scale).max(configuration.effective_min_stroke_width());
+
+        shapes.line_segment(
             [transformed_start_position, transformed_end_position],
-            Stroke::new((*width as f32) * view.scale, color),
+            Stroke::new(width, color),
         );
         // Draw circles at either end of the line.
-        let radius = (*width as f32 / 2.0) * view.scale;
-        painter.circle(transformed_start_position, radius, color, Stroke::NONE);
-        painter.circle(transformed_end_position, radius, color, Stroke::NONE);
+        let radius = width / 2.0;
+        shapes.circle(transformed_start_position, radius, color, Stroke::NONE);
+        shapes.circle(transformed_end_position, radius, color, Stroke::NONE);
 
-        draw_bbox!(self, configuration, painter, color, view, transform_matrix);
+        draw_bbox!(self, configuration, shapes, color, view, transform_matrix);
 
         if shape_number.is_some() {
             let screen_center = (transformed_start_position + transformed_end_position.to_vec2()) / 2.0;
             draw_shape_number(
-                painter,
+                shapes,
                 view,
                 transform_matrix,
                 ShapeNumberPosition::Transformed(screen_center),
@@ -400,7 +1798,7 @@ impl Renderable for ArcGerberPrimitive {
     #[cfg_attr(feature = "profile-renderables", profiling::function)]
     fn render(
         &self,
-        painter: &Painter,
+        shapes: &mut ShapeList<'_>,
         view: &ViewState,
         transform_matrix: &Matrix3<f64>,
         _transform_scaling: &Vector2<f64>,
@@ -418,7 +1816,7 @@ impl Renderable for ArcGerberPrimitive {
         let screen_center = Pos2::new(center.x as f32, -(center.y as f32));
 
         let points = self
-            .generate_points()
+            .generate_points_with_steps(configuration.effective_arc_steps())
             .iter()
             .map(|p| {
                 let local = Vec2::new(p.x as f32, -p.y as f32);
@@ -432,23 +1830,23 @@ impl Renderable for ArcGerberPrimitive {
 
         let center_point = points[steps / 2];
 
-        painter.add(Shape::Path(PathShape {
+        shapes.add(Shape::Path(PathShape {
             points,
             closed: self.is_full_circle(),
             fill: Color32::TRANSPARENT,
             stroke: PathStroke {
-                width: *width as f32 * view.scale,
+                width: (*width as f32 * view.scale).max(configuration.effective_min_stroke_width()),
                 color: ColorMode::Solid(color),
                 kind: StrokeKind::Middle,
             },
         }));
 
-        draw_bbox!(self, configuration, painter, color, view, transform_matrix);
+        draw_bbox!(self, configuration, shapes, color, view, transform_matrix);
 
         // draw the shape number at the center of the arc, not at the origin of the arc, which for arcs with a
         // large radius but small sweep could be way off the screen.
         draw_shape_number(
-            painter,
+            shapes,
             view,
             transform_matrix,
             ShapeNumberPosition::Transformed(center_point),
@@ -461,7 +1859,7 @@ impl Renderable for PolygonGerberPrimitive {
     #[cfg_attr(feature = "profile-renderables", profiling::function)]
     fn render(
         &self,
-        painter: &Painter,
+        shapes: &mut ShapeList<'_>,
         view: &ViewState,
         transform_matrix: &Matrix3<f64>,
         _transform_scaling: &Vector2<f64>,
@@ -473,6 +1871,7 @@ impl Renderable for PolygonGerberPrimitive {
             center,
             exposure,
             geometry,
+            ..
         } = self;
         let color = exposure.to_color(&color);
 
@@ -492,7 +1891,7 @@ impl Renderable for PolygonGerberPrimitive {
                 })
                 .collect();
 
-            painter.add(Shape::convex_polygon(screen_vertices, color, Stroke::NONE));
+            shapes.add(Shape::convex_polygon(screen_vertices, color, Stroke::NONE));
         } else if let Some(tess) = &geometry.tessellation {
             // Transform tessellated geometry
             let vertices: Vec<Vertex> = tess
@@ -506,12 +1905,13 @@ impl Renderable for PolygonGerberPrimitive {
                     Vertex {
                         pos: position,
                         uv: egui::epaint::WHITE_UV,
+                        // `Color32` is premultiplied, as expected by the mesh blending
                         color,
                     }
                 })
                 .collect();
 
-            painter.add(Shape::Mesh(Arc::new(Mesh {
+            shapes.add(Shape::Mesh(Arc::new(Mesh {
                 vertices,
                 indices: tess.indices.clone(),
                 texture_id: egui::TextureId::default(),
@@ -532,7 +1932,7 @@ impl Renderable for PolygonGerberPrimitive {
                 .collect();
 
             for (i, pos) in debug_vertices.iter().enumerate() {
-                painter.text(
+                shapes.text(
                     *pos,
                     Align2::CENTER_CENTER,
                     format!("{}", i),
@@ -542,10 +1942,10 @@ impl Renderable for PolygonGerberPrimitive {
             }
         }
 
-        draw_bbox!(self, configuration, painter, color, view, transform_matrix);
+        draw_bbox!(self, configuration, shapes, color, view, transform_matrix);
 
         draw_shape_number(
-            painter,
+            shapes,
             view,
             transform_matrix,
             ShapeNumberPosition::Untransformed(screen_center),
@@ -555,7 +1955,7 @@ impl Renderable for PolygonGerberPrimitive {
 }
 
 fn draw_shape_number(
-    painter: &Painter,
+    shapes: &mut ShapeList<'_>,
     view: &ViewState,
     transform_matrix: &Matrix3<f64>,
     position: ShapeNumberPosition,
@@ -569,7 +1969,7 @@ fn draw_shape_number(
             (view.translation + transform_matrix.transform_pos2(position) * view.scale).to_pos2()
         }
     };
-    painter.text(
+    shapes.text(
         position,
         Align2::CENTER_CENTER,
         format!("{}", shape_number),
@@ -582,3 +1982,54 @@ enum ShapeNumberPosition {
     Transformed(Pos2),
     Untransformed(Pos2),
 }
+
+#[cfg(all(test, feature = "serde"))]
+mod serde_tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case::default(RenderConfiguration::default())]
+    #[case::debug(RenderConfiguration::debug())]
+    #[case::presentation(RenderConfiguration::presentation())]
+    #[case::film(RenderConfiguration::film())]
+    fn test_round_trip(#[case] configuration: RenderConfiguration) {
+        // when
+        let json = serde_json::to_string(&configuration).unwrap();
+        let loaded: RenderConfiguration = serde_json::from_str(&json).unwrap();
+
+        // then
+        assert_eq!(loaded, configuration);
+    }
+
+    #[rstest]
+    #[case::debug(RenderConfiguration::debug())]
+    #[case::presentation(RenderConfiguration::presentation())]
+    #[case::film(RenderConfiguration::film())]
+    fn test_missing_fields_are_defaults(#[case] configuration: RenderConfiguration) {
+        // given
+        // only the options that differ from the default are saved, e.g. by an older version
+        let default = serde_json::to_value(RenderConfiguration::default()).unwrap();
+        let mut json = serde_json::to_value(&configuration).unwrap();
+        json.as_object_mut()
+            .unwrap()
+            .retain(|key, value| default[key] != *value);
+        assert!(!json.as_object().unwrap().is_empty());
+
+        // when
+        let loaded: RenderConfiguration = serde_json::from_value(json).unwrap();
+
+        // then
+        assert_eq!(loaded, configuration);
+    }
+
+    #[test]
+    fn test_empty_object_is_the_default() {
+        // when
+        let loaded: RenderConfiguration = serde_json::from_str("{}").unwrap();
+
+        // then
+        assert_eq!(loaded, RenderConfiguration::default());
+    }
+}
@@ -0,0 +1,178 @@
+#[cfg(feature = "egui")]
+use egui::epaint::Color32;
+
+/// What a layer of a fabrication package is for, e.g. to pick a color or the stacking order of the layers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LayerFunction {
+    TopCopper,
+    /// Inner copper layer, numbered from the top, starting at 1.
+    InnerCopper(u8),
+    BottomCopper,
+    TopSolderMask,
+    BottomSolderMask,
+    TopSilkscreen,
+    BottomSilkscreen,
+    TopPaste,
+    BottomPaste,
+    /// The board outline.
+    Profile,
+}
+
+impl LayerFunction {
+    /// Guesses the function of a layer from its filename, using the common naming conventions of CAD tools, for
+    /// files without a `.FileFunction` attribute (X1 files) or before the file is parsed.
+    ///
+    /// This is a heuristic fallback, names that don't follow a convention return `None`.  Only the filename is used,
+    /// any directories are ignored, the comparison is case-insensitive.
+    ///
+    /// | Function           | Extension (Protel, Altium, EasyEDA, ...) | Suffix (KiCad)                  |
+    /// |--------------------|------------------------------------------|---------------------------------|
+    /// | Top copper         | `.gtl`                                   | `-F_Cu`                         |
+    /// | Inner copper `n`   | `.g1`, `.g2`, ...                        | `-In1_Cu`, `-In2_Cu`, ...       |
+    /// | Bottom copper      | `.gbl`                                   | `-B_Cu`                         |
+    /// | Top solder mask    | `.gts`                                   | `-F_Mask`                       |
+    /// | Bottom solder mask | `.gbs`                                   | `-B_Mask`                       |
+    /// | Top silkscreen     | `.gto`                                   | `-F_SilkS`, `-F_Silkscreen`     |
+    /// | Bottom silkscreen  | `.gbo`                                   | `-B_SilkS`, `-B_Silkscreen`     |
+    /// | Top paste          | `.gtp`                                   | `-F_Paste`                      |
+    /// | Bottom paste       | `.gbp`                                   | `-B_Paste`                      |
+    /// | Profile            | `.gko`, `.gm1`, `.gml`                   | `-Edge_Cuts`                    |
+    ///
+    /// KiCad suffixes are followed by the `.gbr` extension, or a Protel-style extension, and can also use a `.`
+    /// instead of the `_`, e.g. `board-F.Cu.gbr`.
+    pub fn guess_from_filename(filename: &str) -> Option<LayerFunction> {
+        let filename = filename
+            .rsplit(['/', '\\'])
+            .next()
+            .unwrap_or(filename)
+            .to_ascii_lowercase();
+        let (stem, extension) = filename
+            .rsplit_once('.')
+            .unwrap_or((&filename, ""));
+
+        let from_extension = match extension {
+            "gtl" => Some(LayerFunction::TopCopper),
+            "gbl" => Some(LayerFunction::BottomCopper),
+            "gts" => Some(LayerFunction::TopSolderMask),
+            "gbs" => Some(LayerFunction::BottomSolderMask),
+            "gto" => Some(LayerFunction::TopSilkscreen),
+            "gbo" => Some(LayerFunction::BottomSilkscreen),
+            "gtp" => Some(LayerFunction::TopPaste),
+            "gbp" => Some(LayerFunction::BottomPaste),
+            "gko" | "gm1" | "gml" => Some(LayerFunction::Profile),
+            _ => extension
+                .strip_prefix('g')
+                .and_then(|number| number.parse::<u8>().ok())
+                .filter(|number| *number > 0)
+                .map(LayerFunction::InnerCopper),
+        };
+        if from_extension.is_some() {
+            return from_extension;
+        }
+
+        let suffix = stem
+            .rsplit_once('-')
+            .map(|(_, suffix)| suffix)?
+            .replace('.', "_");
+        match suffix.as_str() {
+            "f_cu" => Some(LayerFunction::TopCopper),
+            "b_cu" => Some(LayerFunction::BottomCopper),
+            "f_mask" => Some(LayerFunction::TopSolderMask),
+            "b_mask" => Some(LayerFunction::BottomSolderMask),
+            "f_silks" | "f_silkscreen" => Some(LayerFunction::TopSilkscreen),
+            "b_silks" | "b_silkscreen" => Some(LayerFunction::BottomSilkscreen),
+            "f_paste" => Some(LayerFunction::TopPaste),
+            "b_paste" => Some(LayerFunction::BottomPaste),
+            "edge_cuts" => Some(LayerFunction::Profile),
+            _ => suffix
+                .strip_prefix("in")
+                .and_then(|suffix| suffix.strip_suffix("_cu"))
+                .and_then(|number| number.parse::<u8>().ok())
+                .filter(|number| *number > 0)
+                .map(LayerFunction::InnerCopper),
+        }
+    }
+}
+
+/// How to display a layer of a fabrication package, see [`LayerStyle::guess_from_filename`].
+#[cfg(feature = "egui")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayerStyle {
+    pub function: LayerFunction,
+    pub color: Color32,
+}
+
+#[cfg(feature = "egui")]
+impl LayerStyle {
+    /// The default style for a layer function, the colors are translucent so stacked layers are visible through each
+    /// other.
+    pub fn for_function(function: LayerFunction) -> Self {
+        let color = match function {
+            LayerFunction::TopCopper => Color32::from_rgba_unmultiplied(200, 50, 50, 180),
+            LayerFunction::InnerCopper(_) => Color32::from_rgba_unmultiplied(200, 160, 60, 180),
+            LayerFunction::BottomCopper => Color32::from_rgba_unmultiplied(50, 100, 200, 180),
+            LayerFunction::TopSolderMask | LayerFunction::BottomSolderMask => {
+                Color32::from_rgba_unmultiplied(40, 140, 60, 120)
+            }
+            LayerFunction::TopSilkscreen | LayerFunction::BottomSilkscreen => Color32::from_gray(230),
+            LayerFunction::TopPaste | LayerFunction::BottomPaste => Color32::from_rgba_unmultiplied(160, 160, 160, 180),
+            LayerFunction::Profile => Color32::from_rgb(230, 200, 40),
+        };
+
+        Self {
+            function,
+            color,
+        }
+    }
+
+    /// Guesses the style of a layer from its filename, e.g. to assign colors when loading a directory or an archive of
+    /// fabrication files, see [`LayerFunction::guess_from_filename`] for the naming conventions.
+    ///
+    /// This is a heuristic fallback for files without a `.FileFunction` attribute.
+    pub fn guess_from_filename(filename: &str) -> Option<LayerStyle> {
+        LayerFunction::guess_from_filename(filename).map(LayerStyle::for_function)
+    }
+}
+
+#[cfg(test)]
+mod guess_from_filename_tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("board.GTL", Some(LayerFunction::TopCopper))]
+    #[case("Gerber_BottomLayer.GBL", Some(LayerFunction::BottomCopper))]
+    #[case("board.gts", Some(LayerFunction::TopSolderMask))]
+    #[case("board.GBO", Some(LayerFunction::BottomSilkscreen))]
+    #[case("board.gtp", Some(LayerFunction::TopPaste))]
+    #[case("board.GKO", Some(LayerFunction::Profile))]
+    #[case("board.GM1", Some(LayerFunction::Profile))]
+    #[case("board.G2", Some(LayerFunction::InnerCopper(2)))]
+    #[case("board-F_Cu.gbr", Some(LayerFunction::TopCopper))]
+    #[case("board-B.Cu.gbr", Some(LayerFunction::BottomCopper))]
+    #[case("board-In3_Cu.gbr", Some(LayerFunction::InnerCopper(3)))]
+    #[case("board-F_Silkscreen.gbr", Some(LayerFunction::TopSilkscreen))]
+    #[case("board-B_Mask.gbs", Some(LayerFunction::BottomSolderMask))]
+    #[case("my-board-Edge_Cuts.gbr", Some(LayerFunction::Profile))]
+    #[case("gerbers/board.gtl", Some(LayerFunction::TopCopper))]
+    #[case("C:\\gerbers\\board-F_Paste.gbr", Some(LayerFunction::TopPaste))]
+    #[case("board.gbr", None)]
+    #[case("board.G0", None)]
+    #[case("drill.drl", None)]
+    #[case("README", None)]
+    fn test_guess_from_filename(#[case] filename: &str, #[case] expected: Option<LayerFunction>) {
+        // expect
+        assert_eq!(LayerFunction::guess_from_filename(filename), expected);
+    }
+
+    #[cfg(feature = "egui")]
+    #[test]
+    fn test_style_from_filename() {
+        // when
+        let style = LayerStyle::guess_from_filename("board.gtl").unwrap();
+
+        // then
+        assert_eq!(style, LayerStyle::for_function(LayerFunction::TopCopper));
+    }
+}
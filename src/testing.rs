@@ -18,6 +18,96 @@ pub fn gerber_commands_to_source(commands: &Vec<Command>) -> String {
     gerber_source
 }
 
+/// Builders for the commands of small test layers, in millimeters, with a 3.5 coordinate format and leading zeros
+/// omitted.
+pub mod commands {
+    use gerber_types::{
+        Aperture, ApertureDefinition, Circle, Command, CoordinateFormat, CoordinateMode, CoordinateNumber, Coordinates,
+        DCode, ExtendedCode, Operation, StepAndRepeat, Unit, ZeroOmission,
+    };
+
+    use crate::GerberLayer;
+
+    pub fn format() -> CoordinateFormat {
+        CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 3, 5)
+    }
+
+    pub fn at(x: f64, y: f64) -> Option<Coordinates> {
+        Some(Coordinates::new(
+            CoordinateNumber::try_from(x).unwrap(),
+            CoordinateNumber::try_from(y).unwrap(),
+            format(),
+        ))
+    }
+
+    /// The unit and the coordinate format.
+    pub fn header() -> Vec<Command> {
+        vec![
+            ExtendedCode::Unit(Unit::Millimeters).into(),
+            ExtendedCode::CoordinateFormat(format()).into(),
+        ]
+    }
+
+    pub fn definition(code: i32, aperture: Aperture) -> Command {
+        ExtendedCode::ApertureDefinition(ApertureDefinition::new(code, aperture)).into()
+    }
+
+    pub fn circle(code: i32, diameter: f64) -> Command {
+        definition(code, Aperture::Circle(Circle::new(diameter)))
+    }
+
+    pub fn select(code: i32) -> Command {
+        DCode::SelectAperture(code).into()
+    }
+
+    pub fn flash(x: f64, y: f64) -> Command {
+        DCode::Operation(Operation::Flash(at(x, y))).into()
+    }
+
+    pub fn move_to(x: f64, y: f64) -> Command {
+        DCode::Operation(Operation::Move(at(x, y))).into()
+    }
+
+    pub fn draw_to(x: f64, y: f64) -> Command {
+        DCode::Operation(Operation::Interpolate(at(x, y), None)).into()
+    }
+
+    /// The commands of `block` repeated `repeat_x` by `repeat_y` times, `distance` apart on both axes.
+    pub fn step_repeat(repeat_x: u32, repeat_y: u32, distance: f64, block: Vec<Command>) -> Vec<Command> {
+        let mut commands = vec![
+            ExtendedCode::StepAndRepeat(StepAndRepeat::Open {
+                repeat_x,
+                repeat_y,
+                distance_x: distance,
+                distance_y: distance,
+            })
+            .into(),
+        ];
+        commands.extend(block);
+        commands.push(ExtendedCode::StepAndRepeat(StepAndRepeat::Close).into());
+
+        commands
+    }
+
+    /// A layer with a flash of a circle aperture, `D10`, at each position.
+    pub fn flashes(diameter: f64, positions: &[(f64, f64)]) -> Vec<Command> {
+        let mut commands = header();
+        commands.extend([circle(10, diameter), select(10)]);
+        commands.extend(
+            positions
+                .iter()
+                .map(|&(x, y)| flash(x, y)),
+        );
+
+        commands
+    }
+
+    /// The layer of [`flashes`].
+    pub fn flashes_layer(diameter: f64, positions: &[(f64, f64)]) -> GerberLayer {
+        GerberLayer::new(flashes(diameter, positions))
+    }
+}
+
 pub mod geometry {
     use std::f64::consts::PI;
 
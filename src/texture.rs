@@ -0,0 +1,442 @@
+use std::ops::Range;
+
+use egui::{Color32, ColorImage, Painter, Pos2, Rect, TextureHandle, TextureOptions, Vec2};
+use nalgebra::{Matrix3, Point2};
+
+use crate::geometry::GerberTransform;
+use crate::layer::GerberLayer;
+use crate::types::Exposure;
+use crate::ui::ViewState;
+use crate::{Matrix3AffineInverseExt, Matrix3Point2Ext};
+
+/// How a [`LayerTexture`] was brought up to date, see [`LayerTexture::update`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureRepaint {
+    /// Nothing changed, or the view moved by less than half a pixel, the cached image is used as it is.
+    None,
+    /// The view was panned, the cached image was shifted by `shift` pixels and only the revealed margins, `pixels`
+    /// pixels in total, were rendered, see [`LayerTexture::dirty_rect`].
+    Margins { shift: [i32; 2], pixels: usize },
+    /// The whole image was rendered.
+    Full,
+}
+
+/// Everything, except the translation of the view, that the cached image depends on.
+#[derive(Debug, Clone, PartialEq)]
+struct TextureKey {
+    size: [usize; 2],
+    pixels_per_point: f32,
+    scale: f32,
+    matrix: Matrix3<f64>,
+    color: Color32,
+}
+
+/// A layer rendered to a texture, for large static layers where building the shapes of every primitive each frame is
+/// too slow, e.g. big boards on weak hardware.
+///
+/// The layer is rasterized on the CPU, with one sample at the center of each pixel, the top-most primitive at that
+/// point decides the color of the pixel, so edges are not anti-aliased.  Cut-outs are transparent, i.e. they subtract
+/// from the primitives below them, unlike [`GerberRenderer`](crate::GerberRenderer), which draws them in black.
+///
+/// The image is only rendered again when the viewport size, the zoom, the transform or the color changes; call
+/// [`LayerTexture::invalidate`] when the layer itself changes.
+pub struct LayerTexture {
+    /// When the view is panned, shift the cached image and only render the margins that are revealed, instead of the
+    /// whole viewport.  Zooming and transform changes always render the whole image.
+    pub dirty_rect: bool,
+
+    image: ColorImage,
+    key: Option<TextureKey>,
+    /// The translation of the view, relative to the viewport, that the cached image was rendered for, in points.
+    offset: Vec2,
+    texture: Option<TextureHandle>,
+}
+
+impl LayerTexture {
+    pub fn new(dirty_rect: bool) -> Self {
+        Self {
+            dirty_rect,
+            image: ColorImage::default(),
+            key: None,
+            offset: Vec2::ZERO,
+            texture: None,
+        }
+    }
+
+    /// Renders the whole image on the next update, e.g. after the layer was re-parsed.
+    pub fn invalidate(&mut self) {
+        self.key = None;
+    }
+
+    /// The cached image, with a size in pixels, as of the last call to [`LayerTexture::update`].
+    pub fn image(&self) -> &ColorImage {
+        &self.image
+    }
+
+    /// Brings the cached image up to date for the view and the viewport, and paints it.
+    ///
+    /// `transform` and `view` are the same as for [`GerberRenderer::new`](crate::GerberRenderer::new), the texture is
+    /// clipped to `viewport` by the painter.
+    pub fn paint(
+        &mut self,
+        painter: &Painter,
+        layer: &GerberLayer,
+        view: &ViewState,
+        transform: &GerberTransform,
+        viewport: Rect,
+        color: Color32,
+    ) -> TextureRepaint {
+        let pixels_per_point = painter.ctx().pixels_per_point();
+        let repaint = self.update(layer, view, transform, viewport, pixels_per_point, color);
+
+        let texture = match (&mut self.texture, repaint) {
+            (Some(texture), TextureRepaint::None) => texture,
+            (Some(texture), _) => {
+                texture.set(self.image.clone(), TextureOptions::NEAREST);
+                texture
+            }
+            (None, _) => self
+                .texture
+                .insert(painter.ctx().load_texture(
+                    "gerber_layer_texture",
+                    self.image.clone(),
+                    TextureOptions::NEAREST,
+                )),
+        };
+
+        // the part of a pan that is smaller than a pixel is not in the image yet
+        let residual = view.translation - viewport.min.to_vec2() - self.offset;
+        let size = Vec2::new(self.image.size[0] as f32, self.image.size[1] as f32) / pixels_per_point;
+        painter.image(
+            texture.id(),
+            Rect::from_min_size(viewport.min + residual, size),
+            Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)),
+            Color32::WHITE,
+        );
+
+        repaint
+    }
+
+    /// Brings the cached image up to date for the view and the viewport, without painting it, see
+    /// [`LayerTexture::paint`].
+    ///
+    /// The image covers `viewport`, at `pixels_per_point` pixels per point.
+    pub fn update(
+        &mut self,
+        layer: &GerberLayer,
+        view: &ViewState,
+        transform: &GerberTransform,
+        viewport: Rect,
+        pixels_per_point: f32,
+        color: Color32,
+    ) -> TextureRepaint {
+        let key = TextureKey {
+            size: [
+                (viewport.width() * pixels_per_point).round() as usize,
+                (viewport.height() * pixels_per_point).round() as usize,
+            ],
+            pixels_per_point,
+            scale: view.scale,
+            matrix: layer.image_transform().to_matrix() * transform.to_matrix(),
+            color,
+        };
+        let offset = view.translation - viewport.min.to_vec2();
+
+        if self.key.as_ref() != Some(&key) {
+            self.key = Some(key);
+            self.render_all(layer, offset);
+            return TextureRepaint::Full;
+        }
+
+        let delta = (offset - self.offset) * pixels_per_point;
+        let shift = [delta.x.round() as i32, delta.y.round() as i32];
+        if shift == [0, 0] {
+            return TextureRepaint::None;
+        }
+
+        let [width, height] = self.image.size;
+        if !self.dirty_rect || shift[0].unsigned_abs() as usize >= width || shift[1].unsigned_abs() as usize >= height {
+            self.render_all(layer, offset);
+            return TextureRepaint::Full;
+        }
+
+        self.shift(shift);
+        self.offset += Vec2::new(shift[0] as f32, shift[1] as f32) / pixels_per_point;
+
+        // the rows that were revealed, then the rest of the columns that were revealed
+        let rows = match shift[1] > 0 {
+            true => 0..shift[1] as usize,
+            false => height - shift[1].unsigned_abs() as usize..height,
+        };
+        let columns = match shift[0] > 0 {
+            true => 0..shift[0] as usize,
+            false => width - shift[0].unsigned_abs() as usize..width,
+        };
+        let other_rows = match shift[1] > 0 {
+            true => rows.end..height,
+            false => 0..rows.start,
+        };
+
+        let mut pixels = 0;
+        if let Some(renderer) = PixelRenderer::new(layer, self) {
+            for (x_range, y_range) in [(0..width, rows), (columns, other_rows)] {
+                pixels += x_range.len() * y_range.len();
+                renderer.render(&mut self.image, x_range, y_range);
+            }
+        }
+
+        TextureRepaint::Margins {
+            shift,
+            pixels,
+        }
+    }
+
+    fn render_all(&mut self, layer: &GerberLayer, offset: Vec2) {
+        let Some(size) = self.key.as_ref().map(|key| key.size) else {
+            return;
+        };
+
+        self.offset = offset;
+        self.image = ColorImage::filled(size, Color32::TRANSPARENT);
+
+        if let Some(renderer) = PixelRenderer::new(layer, self) {
+            let [width, height] = self.image.size;
+            renderer.render(&mut self.image, 0..width, 0..height);
+        }
+    }
+
+    /// Moves the pixels of the image by `shift` pixels, the pixels that are revealed keep their old color.
+    fn shift(&mut self, shift: [i32; 2]) {
+        let [width, height] = self.image.size;
+        let source = self.image.pixels.clone();
+
+        for y in 0..height {
+            let source_y = y as i64 - shift[1] as i64;
+            if source_y < 0 || source_y >= height as i64 {
+                continue;
+            }
+            for x in 0..width {
+                let source_x = x as i64 - shift[0] as i64;
+                if source_x < 0 || source_x >= width as i64 {
+                    continue;
+                }
+                self.image.pixels[y * width + x] = source[source_y as usize * width + source_x as usize];
+            }
+        }
+    }
+}
+
+/// Renders pixels of a [`LayerTexture`], from the position of each pixel in the layer.
+struct PixelRenderer<'a> {
+    layer: &'a GerberLayer,
+    /// From view coordinates, i.e. gerber coordinates after the image and render transforms, to the layer.
+    inverse: Matrix3<f64>,
+    offset: Vec2,
+    pixels_per_point: f32,
+    scale: f32,
+    color: Color32,
+}
+
+impl<'a> PixelRenderer<'a> {
+    /// `None` if the transform is not invertible, e.g. a zero scale, nothing is visible then.
+    fn new(layer: &'a GerberLayer, texture: &LayerTexture) -> Option<Self> {
+        let key = texture.key.as_ref()?;
+
+        Some(Self {
+            layer,
+            inverse: key.matrix.affine_inverse()?,
+            offset: texture.offset,
+            pixels_per_point: key.pixels_per_point,
+            scale: key.scale,
+            color: key.color,
+        })
+    }
+
+    fn render(&self, image: &mut ColorImage, x_range: Range<usize>, y_range: Range<usize>) {
+        let width = image.size[0];
+
+        for y in y_range {
+            for x in x_range.clone() {
+                // the center of the pixel, relative to the viewport, in points, then the same as
+                // `ViewState::screen_to_gerber_coords`
+                let position = Vec2::new(x as f32 + 0.5, y as f32 + 0.5) / self.pixels_per_point;
+                let view_position = (position - self.offset) / self.scale;
+                let point = self
+                    .inverse
+                    .transform_point2(Point2::new(view_position.x as f64, -view_position.y as f64));
+
+                let exposure = self
+                    .layer
+                    .instance_at(point)
+                    .map(|instance| self.layer.primitives()[instance.index].exposure());
+
+                image.pixels[y * width + x] = match exposure {
+                    Some(Exposure::Add) => self.color,
+                    Some(Exposure::CutOut) | None => Color32::TRANSPARENT,
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod layer_texture_tests {
+    use gerber_types::{Command, ExtendedCode, StepAndRepeat};
+    use rstest::rstest;
+
+    use super::*;
+    use crate::LayerConfiguration;
+    use crate::testing::commands::{circle, draw_to, flash, flashes, header, move_to, select};
+
+    const COLOR: Color32 = Color32::from_rgb(200, 100, 50);
+
+    /// Pads and a trace spread over a 100x60 point viewport at a scale of 2.
+    fn layer() -> GerberLayer {
+        let mut commands = flashes(4.0, &[(5.0, -5.0), (25.0, -20.0), (45.0, -10.0)]);
+        commands.extend([move_to(0.0, -25.0), draw_to(50.0, -5.0)]);
+        GerberLayer::new(commands)
+    }
+
+    fn view(translation: Vec2, scale: f32) -> ViewState {
+        ViewState {
+            translation,
+            scale,
+            ..ViewState::default()
+        }
+    }
+
+    fn viewport() -> Rect {
+        Rect::from_min_size(Pos2::new(10.0, 20.0), Vec2::new(100.0, 60.0))
+    }
+
+    fn full_render(layer: &GerberLayer, view: &ViewState) -> ColorImage {
+        let mut texture = LayerTexture::new(false);
+        texture.update(layer, view, &GerberTransform::default(), viewport(), 1.0, COLOR);
+        texture.image().clone()
+    }
+
+    #[rstest]
+    #[case::right_and_down(Vec2::new(7.0, 3.0))]
+    #[case::left_and_up(Vec2::new(-5.0, -11.0))]
+    #[case::horizontal(Vec2::new(12.0, 0.0))]
+    fn test_pan_renders_only_the_margins(#[case] pan: Vec2) {
+        // given
+        let layer = layer();
+        let before = view(Vec2::new(10.0, 20.0), 2.0);
+        let after = view(before.translation + pan, 2.0);
+        let mut texture = LayerTexture::new(true);
+        texture.update(&layer, &before, &GerberTransform::default(), viewport(), 1.0, COLOR);
+
+        // when
+        let repaint = texture.update(&layer, &after, &GerberTransform::default(), viewport(), 1.0, COLOR);
+
+        // then
+        let expected_pixels = 100 * pan.y.abs() as usize + pan.x.abs() as usize * (60 - pan.y.abs() as usize);
+        assert_eq!(repaint, TextureRepaint::Margins {
+            shift: [pan.x as i32, pan.y as i32],
+            pixels: expected_pixels,
+        });
+
+        // and the image is the same as a full render
+        let image = texture.image();
+        assert_eq!(image.pixels, full_render(&layer, &after).pixels);
+        assert!(image.pixels.contains(&COLOR));
+    }
+
+    #[test]
+    fn test_zoom_renders_everything() {
+        // given
+        let layer = layer();
+        let mut texture = LayerTexture::new(true);
+        texture.update(
+            &layer,
+            &view(Vec2::new(10.0, 20.0), 2.0),
+            &GerberTransform::default(),
+            viewport(),
+            1.0,
+            COLOR,
+        );
+
+        // when
+        let zoomed = view(Vec2::new(15.0, 20.0), 3.0);
+        let repaint = texture.update(&layer, &zoomed, &GerberTransform::default(), viewport(), 1.0, COLOR);
+
+        // then
+        assert_eq!(repaint, TextureRepaint::Full);
+        assert_eq!(texture.image().pixels, full_render(&layer, &zoomed).pixels);
+    }
+
+    #[test]
+    fn test_transform_change_renders_everything() {
+        // given
+        let layer = layer();
+        let view = view(Vec2::new(10.0, 20.0), 2.0);
+        let mut texture = LayerTexture::new(true);
+        texture.update(&layer, &view, &GerberTransform::default(), viewport(), 1.0, COLOR);
+
+        // when
+        let transform = GerberTransform {
+            offset: nalgebra::Vector2::new(1.0, 0.0),
+            ..GerberTransform::default()
+        };
+        let repaint = texture.update(&layer, &view, &transform, viewport(), 1.0, COLOR);
+
+        // then
+        assert_eq!(repaint, TextureRepaint::Full);
+    }
+
+    #[rstest]
+    #[case::dirty_rect_disabled(false, Vec2::new(3.0, 0.0), TextureRepaint::Full)]
+    #[case::larger_than_the_viewport(true, Vec2::new(150.0, 0.0), TextureRepaint::Full)]
+    #[case::less_than_half_a_pixel(true, Vec2::new(0.4, 0.0), TextureRepaint::None)]
+    fn test_pan(#[case] dirty_rect: bool, #[case] pan: Vec2, #[case] expected: TextureRepaint) {
+        // given
+        let layer = layer();
+        let before = view(Vec2::new(10.0, 20.0), 2.0);
+        let mut texture = LayerTexture::new(dirty_rect);
+        texture.update(&layer, &before, &GerberTransform::default(), viewport(), 1.0, COLOR);
+
+        // when
+        let after = view(before.translation + pan, 2.0);
+        let repaint = texture.update(&layer, &after, &GerberTransform::default(), viewport(), 1.0, COLOR);
+
+        // then
+        assert_eq!(repaint, expected);
+    }
+
+    #[test]
+    fn test_step_repeat_instances_are_rendered() {
+        // given
+        // a 4 x 2 step-and-repeat of a pad, kept once and expanded
+        let mut commands = header();
+        commands.extend([
+            circle(10, 4.0),
+            Command::ExtendedCode(ExtendedCode::StepAndRepeat(StepAndRepeat::Open {
+                repeat_x: 4,
+                repeat_y: 2,
+                distance_x: 12.0,
+                distance_y: 10.0,
+            })),
+            select(10),
+            flash(5.0, -25.0),
+            Command::ExtendedCode(ExtendedCode::StepAndRepeat(StepAndRepeat::Close)),
+        ]);
+        let configuration = LayerConfiguration {
+            instanced_step_repeat: true,
+            ..LayerConfiguration::default()
+        };
+        let instanced = GerberLayer::new_with_configuration(commands.clone(), &configuration);
+        let expanded = GerberLayer::new(commands);
+        let view = view(Vec2::new(10.0, 20.0), 2.0);
+
+        // when
+        let image = full_render(&instanced, &view);
+
+        // then
+        assert_eq!(instanced.primitives().len(), 1);
+        assert_eq!(image.pixels, full_render(&expanded, &view).pixels);
+
+        // and the last repeat, at (41, -15), is drawn
+        assert_eq!(image.pixels[30 * 100 + 82], COLOR);
+    }
+}
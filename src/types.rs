@@ -1,33 +1,53 @@
 use nalgebra::Point2;
 
-pub(crate) enum Winding {
-    /// Aka 'Positive' in Geometry
+use crate::geometry::signed_area;
+
+/// Millimeters per inch, exact by definition.
+pub const MM_PER_INCH: f64 = 25.4;
+
+/// The direction of the vertices of a closed contour, in gerber coordinates (Y up), formats with Y down (e.g. SVG
+/// or the screen) see the opposite direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Winding {
+    /// Aka 'Negative' in Geometry, see [`signed_area`].
     Clockwise,
-    /// Aka 'Negative' in Geometry
+    /// Aka 'Positive' in Geometry, e.g. the outer contours of GeoJSON (RFC 7946).
     CounterClockwise,
 }
 
 impl Winding {
-    pub(crate) fn from_vertices(vertices: &[Point2<f64>]) -> Self {
-        let mut sum = 0.0;
-        for i in 0..vertices.len() {
-            let j = (i + 1) % vertices.len();
-            sum += vertices[i].x * vertices[j].y - vertices[j].x * vertices[i].y;
-        }
-        if sum > 0.0 {
-            Winding::Clockwise
-        } else {
+    /// The winding of the closed contour, contours with zero area are [`Winding::Clockwise`].
+    pub fn from_vertices(vertices: &[Point2<f64>]) -> Self {
+        if signed_area(vertices) > 0.0 {
             Winding::CounterClockwise
+        } else {
+            Winding::Clockwise
+        }
+    }
+
+    pub fn reversed(&self) -> Winding {
+        match self {
+            Winding::Clockwise => Winding::CounterClockwise,
+            Winding::CounterClockwise => Winding::Clockwise,
         }
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) enum Exposure {
+pub enum Exposure {
     CutOut,
     Add,
 }
 
+impl Exposure {
+    pub(crate) fn inverted(&self) -> Exposure {
+        match self {
+            Exposure::CutOut => Exposure::Add,
+            Exposure::Add => Exposure::CutOut,
+        }
+    }
+}
+
 impl From<bool> for Exposure {
     fn from(value: bool) -> Self {
         match value {
@@ -36,3 +56,12 @@ impl From<bool> for Exposure {
         }
     }
 }
+
+/// Direction of an arc, in gerber coordinates (Y up).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArcDirection {
+    /// G02
+    Clockwise,
+    /// G03
+    CounterClockwise,
+}
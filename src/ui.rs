@@ -1,10 +1,13 @@
 use egui::{Pos2, Rect, Response, Ui, Vec2};
 use gerber_types::Unit;
 use log::trace;
-use nalgebra::Point2;
+use nalgebra::{Point2, Vector2};
 
-use crate::geometry::BoundingBox;
-use crate::{Invert, ToPos2};
+use crate::geometry::{BoundingBox, GerberTransform};
+use crate::layer::GerberLayer;
+use crate::measure::snap_to_grid;
+use crate::types::MM_PER_INCH;
+use crate::{Invert, Matrix3AffineInverseExt, Matrix3Point2Ext, ToPos2};
 
 #[derive(Debug, Default)]
 pub struct UiState {
@@ -12,28 +15,139 @@ pub struct UiState {
     pub center_screen_pos: Pos2,
     pub origin_screen_pos: Pos2,
 
-    // only valid if the mouse is over the viewport
+    // updated while the mouse is over the viewport, keeps the last position when the mouse leaves it
     pub cursor_gerber_coords: Option<Point2<f64>>,
+    // same as 'cursor_gerber_coords', but relative to 'ViewState::origin', use for coordinate readouts
+    pub cursor_origin_coords: Option<Point2<f64>>,
+    // same as 'cursor_gerber_coords', snapped to 'ViewState::grid_snap', use when placing markers and measurements
+    pub cursor_snapped_coords: Option<Point2<f64>>,
+
+    // updated by 'update', 'cursor_gerber_coords' keeps the last position when the mouse leaves the viewport
+    cursor_in_viewport: bool,
+
+    hovered_primitive: Option<usize>,
+    hovered_primitive_changed: bool,
+
+    // the primary button press that started in the viewport, until it is released
+    press: Option<Press>,
+    clicked: Option<Point2<f64>>,
+
+    /// How the mouse and touch input is handled by [`UiState::update`].
+    pub interaction: InteractionConfig,
+}
+
+/// A press of the primary button, or a touch, that started in the viewport, see
+/// [`InteractionConfig::click_drag_threshold`].
+#[derive(Debug, Clone, Copy)]
+struct Press {
+    origin: Pos2,
+    last: Pos2,
+    panning: bool,
+}
+
+impl Press {
+    fn new(position: Pos2) -> Self {
+        Self {
+            origin: position,
+            last: position,
+            panning: false,
+        }
+    }
+
+    /// Returns the distance to pan by for the pointer moving to `position`, zero until the pointer has moved further
+    /// than `threshold` from where it was pressed, then the whole distance from there, so the view doesn't lag behind.
+    fn drag_to(&mut self, position: Pos2, threshold: f32) -> Vec2 {
+        if !self.panning && (position - self.origin).length() > threshold {
+            self.panning = true;
+        }
+        if !self.panning {
+            return Vec2::ZERO;
+        }
+
+        let delta = position - self.last;
+        self.last = position;
+        delta
+    }
+}
+
+/// Options for the input handling of [`UiState::update`], to accommodate different user preferences and app
+/// conventions.
+#[derive(Debug, Clone)]
+pub struct InteractionConfig {
+    /// Which point stays in place when zooming with the scroll wheel.  Pinch-zoom is always around the center of the
+    /// fingers.
+    pub zoom_anchor: ZoomAnchor,
+    /// How far the pointer can move while pressed, in points, and still be a click rather than a pan, so a slightly
+    /// shaky click still picks, see [`UiState::clicked`].
+    pub click_drag_threshold: f32,
+}
+
+impl Default for InteractionConfig {
+    fn default() -> Self {
+        Self {
+            zoom_anchor: ZoomAnchor::Cursor,
+            click_drag_threshold: 4.0,
+        }
+    }
+}
+
+/// See [`InteractionConfig::zoom_anchor`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ZoomAnchor {
+    /// The point under the cursor, same as most CAD tools.
+    #[default]
+    Cursor,
+    /// The center of the viewport.
+    Center,
 }
 
 impl UiState {
-    pub fn update(&mut self, ui: &Ui, viewport: &Rect, response: &Response, view_state: &mut ViewState) {
+    /// Handles panning and zooming and updates the cursor position.
+    ///
+    /// * Mouse: drag with the primary button to pan, scroll to zoom around the cursor, see
+    ///   [`InteractionConfig::zoom_anchor`].  A press and release that moves less than
+    ///   [`InteractionConfig::click_drag_threshold`] is a click instead, see [`UiState::clicked`].
+    /// * Touch: drag with one finger to pan, pinch to zoom around the center of the fingers, which can be moved at the
+    ///   same time to pan (two-finger pan).
+    ///
+    /// Returns whether anything that affects the rendered view changed, see [`RepaintHint`].
+    pub fn update(&mut self, ui: &Ui, viewport: &Rect, response: &Response, view_state: &mut ViewState) -> RepaintHint {
+        let previous = (
+            view_state.translation,
+            view_state.scale,
+            self.cursor_gerber_coords,
+            self.cursor_in_viewport,
+        );
+
         view_state.handle_viewport_relocation(viewport);
 
         self.update_cursor_position(view_state, &response, ui);
         self.handle_panning(view_state, &response, ui);
         self.handle_zooming(view_state, &response, ui);
+        self.handle_multi_touch(view_state, response, ui);
 
         self.center_screen_pos = viewport.center();
-        self.origin_screen_pos = view_state.gerber_to_screen_coords(Point2::new(0.0, 0.0));
+        self.origin_screen_pos = view_state.gerber_to_screen_coords(view_state.origin.position());
 
         trace!(
             "update. view_state: {:?}, viewport: {:?}, cursor_gerber_coords: {:?}",
             view_state, viewport, self.cursor_gerber_coords
-        )
+        );
+
+        let current = (
+            view_state.translation,
+            view_state.scale,
+            self.cursor_gerber_coords,
+            self.cursor_in_viewport,
+        );
+        match current != previous {
+            true => RepaintHint::Repaint,
+            false => RepaintHint::None,
+        }
     }
 
     pub fn update_cursor_position(&mut self, view_state: &ViewState, response: &Response, ui: &Ui) {
+        self.cursor_in_viewport = response.hovered();
         if !response.hovered() {
             return;
         }
@@ -43,19 +157,111 @@ impl UiState {
         } else {
             self.cursor_gerber_coords = None;
         }
+
+        self.cursor_origin_coords = self.cursor_gerber_coords.map(|coords| {
+            view_state
+                .origin
+                .to_origin_coords(coords)
+        });
+        self.cursor_snapped_coords = self
+            .cursor_gerber_coords
+            .map(|coords| match view_state.grid_snap {
+                Some(spacing) => snap_to_grid(coords, spacing, view_state.origin.position()),
+                None => coords,
+            });
+    }
+
+    /// Updates the hovered primitive of the layer using the cursor position, call after `update`.
+    ///
+    /// `transform` must be the same transform used to render the layer.
+    ///
+    /// Returns true if the hovered primitive changed, including when the cursor enters or leaves a primitive, the same
+    /// value is then available from [`UiState::hovered_primitive_changed`] for the rest of the frame.
+    pub fn update_hovered_primitive(&mut self, layer: &GerberLayer, transform: &GerberTransform) -> bool {
+        let hovered_primitive = self
+            .cursor_gerber_coords
+            .filter(|_| self.cursor_in_viewport)
+            .and_then(|cursor| {
+                // the cursor position has the render transform and image transform applied, undo them to get back to
+                // the coordinates of the primitives.
+                let matrix = layer.image_transform().to_matrix() * transform.to_matrix();
+                matrix
+                    .affine_inverse()
+                    .map(|inverse| inverse.transform_point2(cursor))
+            })
+            .and_then(|point| layer.primitive_at(point));
+
+        self.hovered_primitive_changed = hovered_primitive != self.hovered_primitive;
+        self.hovered_primitive = hovered_primitive;
+
+        self.hovered_primitive_changed
+    }
+
+    /// The index of the primitive under the cursor, as of the last call to [`UiState::update_hovered_primitive`].
+    pub fn hovered_primitive(&self) -> Option<usize> {
+        self.hovered_primitive
+    }
+
+    /// True if the last call to [`UiState::update_hovered_primitive`] changed the hovered primitive.
+    pub fn hovered_primitive_changed(&self) -> bool {
+        self.hovered_primitive_changed
+    }
+
+    /// The gerber coordinates of a click in the viewport, e.g. to select the primitive under the cursor, only for the
+    /// frame in which the button was released, `None` otherwise.
+    ///
+    /// Same as [`UiState::cursor_gerber_coords`] at the time of the release, a press that moved further than
+    /// [`InteractionConfig::click_drag_threshold`] is a pan, not a click.
+    pub fn clicked(&self) -> Option<Point2<f64>> {
+        self.clicked
     }
 
     pub fn handle_panning(&mut self, view_state: &mut ViewState, response: &Response, ui: &Ui) {
-        if response.dragged_by(egui::PointerButton::Primary) {
-            let delta = response.drag_delta();
-            view_state.translation += delta;
-            ui.ctx().clear_animations();
+        self.clicked = None;
+
+        // during a multi-touch gesture the pointer follows one of the fingers, the gesture pans instead, and it is
+        // not a click either
+        if ui.input(|i| i.multi_touch().is_some()) {
+            self.press = None;
+            return;
+        }
+
+        let (pressed, released, position) = ui.input(|i| {
+            (
+                i.pointer.primary_pressed(),
+                i.pointer.primary_released(),
+                i.pointer.interact_pos(),
+            )
+        });
+
+        if pressed && response.hovered() {
+            self.press = position.map(Press::new);
+        }
+        let Some(press) = &mut self.press else {
+            return;
+        };
+
+        if let Some(position) = position {
+            let delta = press.drag_to(position, self.interaction.click_drag_threshold);
+            if delta != Vec2::ZERO {
+                view_state.translation += delta;
+                ui.ctx().clear_animations();
+            }
+        }
+
+        if released {
+            if !press.panning {
+                self.clicked = self
+                    .cursor_gerber_coords
+                    .filter(|_| self.cursor_in_viewport);
+            }
+            self.press = None;
         }
     }
 
     pub fn handle_zooming(&mut self, view_state: &mut ViewState, response: &Response, ui: &Ui) {
         // Only process zoom if the mouse pointer is actually over the viewport
-        if !response.hovered() {
+        if !response.hovered() || view_state.zoom_locked {
             return;
         }
 
@@ -87,14 +293,72 @@ impl UiState {
                 old_scale / zoom_factor
             };
 
-            if let Some(hover_pos) = response.hover_pos() {
-                let mouse_world = (hover_pos - view_state.translation) / old_scale;
-                view_state.translation = hover_pos - mouse_world * new_scale;
+            let anchor = match self.interaction.zoom_anchor {
+                ZoomAnchor::Cursor => response.hover_pos(),
+                ZoomAnchor::Center => Some(response.rect.center()),
+            };
+            if let Some(anchor) = anchor {
+                let anchor_world = (anchor - view_state.translation) / old_scale;
+                view_state.translation = anchor - anchor_world * new_scale;
             }
 
             view_state.scale = new_scale;
         }
     }
+
+    /// Pinch-zoom and two-finger pan, see [`UiState::update`].
+    pub fn handle_multi_touch(&mut self, view_state: &mut ViewState, response: &Response, ui: &Ui) {
+        let Some(multi_touch) = ui.input(|i| i.multi_touch()) else {
+            return;
+        };
+        // only gestures that start in the viewport
+        if !response
+            .rect
+            .contains(multi_touch.start_pos)
+        {
+            return;
+        }
+
+        let old_scale = view_state.scale;
+        let new_scale = match view_state.zoom_locked {
+            true => old_scale,
+            false => old_scale * multi_touch.zoom_delta,
+        };
+
+        // keep the point under the center of the fingers fixed, same as zooming around the cursor
+        let center = multi_touch.center_pos;
+        let center_world = (center - view_state.translation) / old_scale;
+        view_state.translation = center - center_world * new_scale + multi_touch.translation_delta;
+        view_state.scale = new_scale;
+
+        ui.ctx().clear_animations();
+    }
+}
+
+/// Returned by [`UiState::update`], so embedders that only repaint on demand know when a repaint is needed.
+///
+/// Only covers changes made by `UiState`, i.e. panning, zooming, viewport relocation and cursor movement.  Changes
+/// made by the application, e.g. animating a `GerberTransform`, still need the application to request a repaint.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RepaintHint {
+    /// The view and cursor are unchanged, the previous frame is still correct.
+    #[default]
+    None,
+    /// The view or cursor changed, overlays that depend on them (e.g. a crosshair) are stale.
+    Repaint,
+}
+
+impl RepaintHint {
+    pub fn needs_repaint(&self) -> bool {
+        *self == RepaintHint::Repaint
+    }
+
+    /// Requests a repaint from egui, if one is needed.
+    pub fn request_repaint(&self, ctx: &egui::Context) {
+        if self.needs_repaint() {
+            ctx.request_repaint();
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -105,6 +369,22 @@ pub struct ViewState {
 
     // used to track viewport relocation so that the translation can be updated
     pub previous_viewport_pos: Option<Pos2>,
+
+    /// Where the origin marker is drawn and what coordinate readouts are relative to.
+    pub origin: CoordinateOrigin,
+
+    /// Grid spacing, in gerber units, for [`UiState::cursor_snapped_coords`], the grid has a point at the origin.
+    /// `None` disables snapping.
+    pub grid_snap: Option<f64>,
+
+    /// How [`ViewState::fit_view`] frames the content, kept by [`ViewState::reset`].
+    pub framing: ViewFraming,
+
+    /// Ignores zooming with the mouse wheel and pinch gestures in [`UiState::update`], panning still works, e.g. to
+    /// keep the view at actual size, see [`ViewState::set_physical_scale`].  Kept by [`ViewState::reset`].
+    ///
+    /// Only user input is ignored, the methods that set the scale, e.g. [`ViewState::fit_view`], still change it.
+    pub zoom_locked: bool,
 }
 
 impl Default for ViewState {
@@ -114,6 +394,10 @@ impl Default for ViewState {
             scale: 1.0,
             base_scale: 1.0,
             previous_viewport_pos: None,
+            origin: CoordinateOrigin::default(),
+            grid_snap: None,
+            framing: ViewFraming::default(),
+            zoom_locked: false,
         }
     }
 }
@@ -131,30 +415,145 @@ impl ViewState {
         (gerber_pos * self.scale as f64).to_pos2() + self.translation
     }
 
+    /// Converts a length, e.g. a radius, from gerber units to points at the current zoom level.
+    ///
+    /// Only the view is used, for lengths in a layer with a render or image transform that scales, scale the length
+    /// first.
+    pub fn gerber_to_screen_length(&self, length: f64) -> f32 {
+        (length * self.scale as f64) as f32
+    }
+
+    /// Converts a length from points to gerber units at the current zoom level, e.g. for a pick tolerance of a few
+    /// pixels, the inverse of [`ViewState::gerber_to_screen_length`].
+    pub fn screen_to_gerber_length(&self, length: f32) -> f64 {
+        length as f64 / self.scale as f64
+    }
+
+    /// Converts a movement on screen, e.g. the `drag_delta` of a response, to the same movement in gerber units, e.g.
+    /// to drag a primitive or an annotation with the mouse.
+    ///
+    /// The scale and the Y-flip of the view are applied, the translation is not, as it doesn't change a movement.
+    /// Only the view is used, a render or image transform must be inverted separately.
+    pub fn screen_delta_to_gerber_delta(&self, delta: Vec2) -> Vector2<f64> {
+        Vector2::new(delta.x as f64, -(delta.y as f64)) / self.scale as f64
+    }
+
     /// inputs, viewport of UI area to render.
     /// bounding box of all gerber layers to render.
     /// initial zoom factor, e.g. 0.5 for 50%.
+    ///
+    /// The content is framed according to [`ViewState::framing`], the resulting scale is used as 100% zoom.  A
+    /// bounding box without a width or height, e.g. a single line, is fitted using the other axis, one without either
+    /// keeps the current scale.
     pub fn fit_view(&mut self, viewport: Rect, bbox: &BoundingBox, initial_zoom_factor: f32) {
         let content_width = bbox.width();
         let content_height = bbox.height();
 
+        let scales = [
+            fit_scale(viewport.width(), content_width),
+            fit_scale(viewport.height(), content_height),
+        ]
+        .into_iter()
+        .flatten();
+
         // Calculate scale to fit the content (100% zoom)
-        self.base_scale = f32::min(
-            viewport.width() / (content_width as f32),
-            viewport.height() / (content_height as f32),
-        ) * 0.95; // 0.95 to add margin
+        let base_scale = match self.framing {
+            ViewFraming::Contain => scales
+                .reduce(f32::min)
+                .map(|scale| scale * 0.95), // 0.95 to add margin
+            ViewFraming::Cover => scales.reduce(f32::max),
+        };
 
-        let scale = self.base_scale * initial_zoom_factor;
+        if let Some(base_scale) = base_scale {
+            self.base_scale = base_scale;
+            self.scale = base_scale * initial_zoom_factor;
+        }
 
         trace!(
             "Fit view. base_scale: {:.2}, scale: {:.2}, content_width: {:.2}, content_height: {:.2}",
-            self.base_scale, scale, content_width, content_height
+            self.base_scale, self.scale, content_width, content_height
         );
-        self.scale = scale;
 
         self.center_view(viewport, bbox);
     }
 
+    /// Resets the view to the identity view, no translation and a scale of 1, i.e. gerber 0,0 at the top-left of the
+    /// screen and 1 gerber unit per point.
+    ///
+    /// The origin used for display and the grid snapping are kept, see [`ViewState::origin`].
+    pub fn reset(&mut self) {
+        *self = Self {
+            origin: self.origin,
+            grid_snap: self.grid_snap,
+            framing: self.framing,
+            zoom_locked: self.zoom_locked,
+            ..Self::default()
+        };
+    }
+
+    /// Returns to the 'home' view, i.e. the bounding box fitted to the viewport, e.g. for a "fit to view" button.
+    ///
+    /// Same as [`ViewState::fit_view`].
+    pub fn home(&mut self, viewport: Rect, bbox: &BoundingBox, initial_zoom_factor: f32) {
+        self.fit_view(viewport, bbox, initial_zoom_factor);
+    }
+
+    /// Scales the view so the width of the bounding box fills the viewport, with the same margin as `fit_view`.
+    ///
+    /// The content is centered horizontally and the top of the bounding box is aligned with the top of the viewport,
+    /// the rest can then be panned into view, useful for tall boards/panels.
+    ///
+    /// A bounding box without a width, e.g. a single vertical line, keeps the current scale.
+    pub fn fit_width(&mut self, viewport: Rect, bbox: &BoundingBox) {
+        if let Some(scale) = fit_scale(viewport.width(), bbox.width()) {
+            self.base_scale = scale * 0.95;
+            self.scale = self.base_scale;
+        }
+
+        let margin = (viewport.width() - (bbox.width() as f32 * self.scale)) / 2.0;
+        let center = bbox.center();
+
+        self.translation = Vec2::new(
+            viewport.center().x - (center.x as f32 * self.scale),
+            viewport.min.y + margin + (bbox.max.y as f32 * self.scale),
+        );
+        trace!(
+            "Fit width. scale: {:.2}, translation: {:?}",
+            self.scale, self.translation
+        );
+
+        // ensure the viewport is not relocated this frame
+        self.previous_viewport_pos = None;
+    }
+
+    /// Scales the view so the height of the bounding box fills the viewport, with the same margin as `fit_view`.
+    ///
+    /// The content is centered vertically and the left of the bounding box is aligned with the left of the viewport,
+    /// the rest can then be panned into view, useful for long edge connectors.
+    ///
+    /// A bounding box without a height, e.g. a single horizontal line, keeps the current scale.
+    pub fn fit_height(&mut self, viewport: Rect, bbox: &BoundingBox) {
+        if let Some(scale) = fit_scale(viewport.height(), bbox.height()) {
+            self.base_scale = scale * 0.95;
+            self.scale = self.base_scale;
+        }
+
+        let margin = (viewport.height() - (bbox.height() as f32 * self.scale)) / 2.0;
+        let center = bbox.center();
+
+        self.translation = Vec2::new(
+            viewport.min.x + margin - (bbox.min.x as f32 * self.scale),
+            viewport.center().y + (center.y as f32 * self.scale),
+        );
+        trace!(
+            "Fit height. scale: {:.2}, translation: {:?}",
+            self.scale, self.translation
+        );
+
+        // ensure the viewport is not relocated this frame
+        self.previous_viewport_pos = None;
+    }
+
     pub fn center_view(&mut self, viewport: Rect, bbox: &BoundingBox) {
         let center = bbox.center();
 
@@ -181,14 +580,54 @@ impl ViewState {
         self.previous_viewport_pos = Some(viewport_pos);
     }
 
+    /// A view that maps one gerber unit to the number of device pixels for the given resolution, e.g. for
+    /// rasterizing a layer at 600 DPI for film.
+    ///
+    /// The scale depends on the units of the layer, use [`GerberLayer::units`], 1mm is `dpi / 25.4` pixels and 1in is
+    /// `dpi` pixels.  The view scale is in points, so this assumes 1 pixel per point, i.e. `pixels_per_point` of 1.0.
+    ///
+    /// The translation is zero, i.e. gerber 0,0 is at the top-left of the raster, translate the view so that the
+    /// bounding box is inside the raster.
+    pub fn for_dpi(dpi: f32, units: Unit) -> ViewState {
+        let scale = match units {
+            Unit::Millimeters => dpi / MM_PER_INCH as f32,
+            Unit::Inches => dpi,
+        };
+
+        ViewState {
+            scale,
+            base_scale: scale,
+            ..ViewState::default()
+        }
+    }
+
+    /// Sets the scale so that the layer is shown at its actual size on the display, one gerber millimeter (or inch)
+    /// is one millimeter (or inch) on the screen, e.g. to compare a footprint with a physical part.  Set
+    /// [`ViewState::zoom_locked`] to keep it at that size.
+    ///
+    /// `units` are the units of the layer, see [`GerberLayer::units`].  `points_per_inch` is the number of egui points
+    /// that cover one inch of the physical display, i.e. the display's DPI divided by
+    /// `egui::Context::pixels_per_point`.
+    ///
+    /// egui doesn't know the physical size of the display, and the DPI reported by the operating system is often a
+    /// nominal value, e.g. 96, rather than the real pixel density, so the DPI should come from the display's
+    /// specifications or from the user measuring a known length on the screen.  The translation is unchanged, use
+    /// [`ViewState::center_view`] to bring the content into view.
+    pub fn set_physical_scale(&mut self, units: Unit, points_per_inch: f32) {
+        self.scale = match units {
+            Unit::Millimeters => points_per_inch / MM_PER_INCH as f32,
+            Unit::Inches => points_per_inch,
+        };
+    }
+
     pub fn zoom_level_percent(&self, units: Unit, display_info: &DisplayInfo) -> f32 {
         // Get effective pixels per inch
         let device_ppi = display_info.effective_ppi();
 
         // Calculate what 100% zoom should be (reference scale)
         let reference_scale = match units {
-            Unit::Millimeters => device_ppi / 25.4, // Convert to pixels per mm
-            Unit::Inches => device_ppi,             // pixels per inch
+            Unit::Millimeters => device_ppi / MM_PER_INCH as f32, // Convert to pixels per mm
+            Unit::Inches => device_ppi,                           // pixels per inch
         };
 
         // Calculate zoom percentage
@@ -207,8 +646,8 @@ impl ViewState {
 
         // Calculate the reference scale for 100% zoom
         let reference_scale = match units {
-            Unit::Millimeters => device_ppi / 25.4, // Convert to pixels per mm
-            Unit::Inches => device_ppi,             // pixels per inch
+            Unit::Millimeters => device_ppi / MM_PER_INCH as f32, // Convert to pixels per mm
+            Unit::Inches => device_ppi,                           // pixels per inch
         };
 
         // Set the scale based on the desired zoom percentage
@@ -220,6 +659,63 @@ impl ViewState {
     }
 }
 
+/// How the content is framed by [`ViewState::fit_view`], see [`ViewState::framing`].
+///
+/// The scale is always uniform, so the aspect ratio of the content is preserved, this only controls whether the
+/// content is letterboxed or cropped when its aspect ratio differs from the viewport.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ViewFraming {
+    /// The whole bounding box is visible, with a small margin, leaving empty space on two sides.
+    #[default]
+    Contain,
+    /// The viewport is filled edge-to-edge, cropping the content on two sides.
+    Cover,
+}
+
+/// The origin used for display, see [`ViewState::origin`].
+///
+/// EDA tools don't agree on where the origin of a board is, the gerber file origin is often not the datum used in the
+/// design, e.g. when the board corner or center is used instead.  This only changes what is displayed, the primitives
+/// and the coordinates passed to/from the view are always gerber coordinates.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum CoordinateOrigin {
+    /// The origin of the gerber file, i.e. 0,0.
+    #[default]
+    File,
+    /// A user-defined datum, in gerber coordinates.
+    Datum(Point2<f64>),
+}
+
+impl CoordinateOrigin {
+    /// A datum at the bottom-left corner of the bounding box, e.g. of the board outline.
+    pub fn bottom_left(bbox: &BoundingBox) -> Self {
+        Self::Datum(bbox.min)
+    }
+
+    /// A datum at the center of the bounding box.
+    pub fn center(bbox: &BoundingBox) -> Self {
+        Self::Datum(bbox.center())
+    }
+
+    /// The position of the origin, in gerber coordinates.
+    pub fn position(&self) -> Point2<f64> {
+        match self {
+            CoordinateOrigin::File => Point2::origin(),
+            CoordinateOrigin::Datum(datum) => *datum,
+        }
+    }
+
+    /// Converts gerber coordinates to coordinates relative to this origin.
+    pub fn to_origin_coords(&self, gerber_coords: Point2<f64>) -> Point2<f64> {
+        Point2::from(gerber_coords - self.position())
+    }
+
+    /// Converts coordinates relative to this origin back to gerber coordinates, e.g. for user-entered positions.
+    pub fn to_gerber_coords(&self, origin_coords: Point2<f64>) -> Point2<f64> {
+        origin_coords + self.position().coords
+    }
+}
+
 /// Struct to hold display information including DPI values
 #[derive(Debug, Clone, Copy)]
 pub struct DisplayInfo {
@@ -269,3 +765,241 @@ impl DisplayInfo {
         self.dpi_y = dpi_y;
     }
 }
+
+/// The scale to fit `content_length` gerber units into `viewport_length` points, `None` if the content has no length.
+fn fit_scale(viewport_length: f32, content_length: f64) -> Option<f32> {
+    let scale = viewport_length / content_length as f32;
+
+    (scale.is_finite() && scale > 0.0).then_some(scale)
+}
+
+#[cfg(test)]
+mod for_dpi_tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case::millimeters(Unit::Millimeters, 1.0, 600.0 / 25.4)]
+    #[case::inches(Unit::Inches, 1.0, 600.0)]
+    #[case::thou(Unit::Inches, 0.001, 0.6)]
+    fn test_for_dpi(#[case] units: Unit, #[case] length: f64, #[case] expected_pixels: f32) {
+        // given
+        let view = ViewState::for_dpi(600.0, units);
+
+        // when
+        let start = view.gerber_to_screen_coords(Point2::new(0.0, 0.0));
+        let end = view.gerber_to_screen_coords(Point2::new(length, 0.0));
+
+        // then
+        assert!(((end.x - start.x) - expected_pixels).abs() < 1e-3);
+    }
+}
+
+#[cfg(test)]
+mod physical_scale_tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case::millimeters(Unit::Millimeters, 25.4)]
+    #[case::inches(Unit::Inches, 1.0)]
+    fn test_one_inch_is_one_inch(#[case] units: Unit, #[case] inch: f64) {
+        // given
+        // a 144 DPI display with 1.5 pixels per point
+        let points_per_inch = 144.0 / 1.5;
+        let mut view = ViewState::default();
+
+        // when
+        view.set_physical_scale(units, points_per_inch);
+
+        // then
+        assert!((view.gerber_to_screen_length(inch) - 96.0).abs() < 1e-3);
+    }
+}
+
+#[cfg(test)]
+mod screen_length_tests {
+    use super::*;
+
+    #[test]
+    fn test_length_conversions() {
+        // given
+        let view = ViewState {
+            scale: 4.0,
+            ..ViewState::default()
+        };
+
+        // expect
+        assert_eq!(view.gerber_to_screen_length(2.5), 10.0);
+        assert_eq!(view.screen_to_gerber_length(10.0), 2.5);
+
+        // and the same as the distance between converted coordinates
+        let start = view.gerber_to_screen_coords(Point2::new(1.0, 1.0));
+        let end = view.gerber_to_screen_coords(Point2::new(3.5, 1.0));
+        assert_eq!(end.x - start.x, view.gerber_to_screen_length(2.5));
+    }
+
+    #[test]
+    fn test_screen_delta_to_gerber_delta() {
+        // given
+        let view = ViewState {
+            scale: 4.0,
+            translation: Vec2::new(100.0, 50.0),
+            ..ViewState::default()
+        };
+
+        // when
+        let delta = view.screen_delta_to_gerber_delta(Vec2::new(10.0, 20.0));
+
+        // then
+        // screen Y is down, gerber Y is up
+        assert_eq!(delta, Vector2::new(2.5, -5.0));
+
+        // and the same as the distance between converted coordinates
+        let start = view.screen_to_gerber_coords(Pos2::new(30.0, 40.0));
+        let end = view.screen_to_gerber_coords(Pos2::new(40.0, 60.0));
+        assert_eq!(end - start, delta);
+    }
+}
+
+#[cfg(test)]
+mod fit_tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn bbox(min: (f64, f64), max: (f64, f64)) -> BoundingBox {
+        BoundingBox {
+            min: Point2::new(min.0, min.1),
+            max: Point2::new(max.0, max.1),
+        }
+    }
+
+    #[rstest]
+    #[case::fit_width(ViewState::fit_width as fn(&mut ViewState, Rect, &BoundingBox), bbox((0.0, 0.0), (20.0, 10.0)))]
+    #[case::fit_height(ViewState::fit_height as fn(&mut ViewState, Rect, &BoundingBox), bbox((0.0, 0.0), (10.0, 20.0)))]
+    fn test_fit(#[case] fit: fn(&mut ViewState, Rect, &BoundingBox), #[case] bbox: BoundingBox) {
+        // given
+        let viewport = Rect::from_min_size(Pos2::ZERO, Vec2::new(200.0, 200.0));
+        let mut view = ViewState::default();
+
+        // when
+        fit(&mut view, viewport, &bbox);
+
+        // then
+        // 20 units fill 200 points, with a 5% margin
+        assert!((view.scale - 9.5).abs() < 1e-4);
+        assert_eq!(view.base_scale, view.scale);
+    }
+
+    #[rstest]
+    #[case::fit_width_of_a_vertical_line(
+        ViewState::fit_width as fn(&mut ViewState, Rect, &BoundingBox),
+        bbox((5.0, 0.0), (5.0, 10.0))
+    )]
+    #[case::fit_height_of_a_horizontal_line(
+        ViewState::fit_height as fn(&mut ViewState, Rect, &BoundingBox),
+        bbox((0.0, 5.0), (10.0, 5.0))
+    )]
+    fn test_fit_without_extent_keeps_the_scale(
+        #[case] fit: fn(&mut ViewState, Rect, &BoundingBox),
+        #[case] bbox: BoundingBox,
+    ) {
+        // given
+        let viewport = Rect::from_min_size(Pos2::ZERO, Vec2::new(200.0, 200.0));
+        let mut view = ViewState {
+            scale: 2.0,
+            base_scale: 2.0,
+            ..ViewState::default()
+        };
+
+        // when
+        fit(&mut view, viewport, &bbox);
+
+        // then
+        assert_eq!(view.scale, 2.0);
+        assert_eq!(view.base_scale, 2.0);
+        assert!(view.translation.x.is_finite() && view.translation.y.is_finite());
+    }
+
+    #[rstest]
+    #[case::contain_vertical_line(ViewFraming::Contain, bbox((5.0, 0.0), (5.0, 20.0)), 9.5)]
+    #[case::contain_horizontal_line(ViewFraming::Contain, bbox((0.0, 5.0), (20.0, 5.0)), 9.5)]
+    #[case::cover_vertical_line(ViewFraming::Cover, bbox((5.0, 0.0), (5.0, 20.0)), 10.0)]
+    #[case::cover_horizontal_line(ViewFraming::Cover, bbox((0.0, 5.0), (20.0, 5.0)), 10.0)]
+    #[case::contain_point(ViewFraming::Contain, bbox((5.0, 5.0), (5.0, 5.0)), 2.0)]
+    #[case::cover_point(ViewFraming::Cover, bbox((5.0, 5.0), (5.0, 5.0)), 2.0)]
+    fn test_fit_view_without_width_or_height(
+        #[case] framing: ViewFraming,
+        #[case] bbox: BoundingBox,
+        #[case] expected_scale: f32,
+    ) {
+        // given
+        let viewport = Rect::from_min_size(Pos2::ZERO, Vec2::new(200.0, 200.0));
+        let mut view = ViewState {
+            scale: 2.0,
+            base_scale: 2.0,
+            framing,
+            ..ViewState::default()
+        };
+
+        // when
+        view.fit_view(viewport, &bbox, 1.0);
+
+        // then
+        // the axis with an extent is fitted, without either the scale is kept
+        assert!((view.scale - expected_scale).abs() < 1e-4);
+        assert_eq!(view.base_scale, view.scale);
+        assert!(view.translation.x.is_finite() && view.translation.y.is_finite());
+    }
+}
+
+#[cfg(test)]
+mod press_tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case::within_threshold(Pos2::new(13.0, 10.0), Vec2::ZERO, false)]
+    #[case::on_threshold(Pos2::new(14.0, 10.0), Vec2::ZERO, false)]
+    #[case::beyond_threshold(Pos2::new(13.0, 14.0), Vec2::new(3.0, 4.0), true)]
+    fn test_drag_to(#[case] position: Pos2, #[case] expected_delta: Vec2, #[case] expected_panning: bool) {
+        // given
+        let mut press = Press::new(Pos2::new(10.0, 10.0));
+
+        // when
+        let delta = press.drag_to(position, 4.0);
+
+        // then
+        assert_eq!(delta, expected_delta);
+        assert_eq!(press.panning, expected_panning);
+    }
+
+    #[test]
+    fn test_pans_by_the_whole_distance_once_past_the_threshold() {
+        // given
+        let mut press = Press::new(Pos2::new(10.0, 10.0));
+
+        // when
+        let deltas = [
+            Pos2::new(12.0, 10.0),
+            Pos2::new(16.0, 10.0),
+            Pos2::new(17.0, 10.0),
+            // back within the threshold, still panning
+            Pos2::new(11.0, 10.0),
+        ]
+        .map(|position| press.drag_to(position, 4.0));
+
+        // then
+        assert_eq!(deltas, [
+            Vec2::ZERO,
+            Vec2::new(6.0, 0.0),
+            Vec2::new(1.0, 0.0),
+            Vec2::new(-6.0, 0.0)
+        ]);
+        assert!(press.panning);
+    }
+}
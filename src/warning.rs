@@ -0,0 +1,120 @@
+/// A problem found while building the primitives of a layer.
+///
+/// Warnings don't prevent the layer from being built, the affected primitives are built on a best-effort basis.
+///
+/// `command_index` is the index of the gerber command that started the affected item, `contour` is the index of a
+/// region contour in the order they occur in the layer, starting at 0.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayerWarning {
+    /// The last vertex of a region contour is not the same as the first.
+    ///
+    /// If `auto_closed` is true the contour was closed with a segment from the last vertex to the first, otherwise it
+    /// was ignored, see `LayerConfiguration::auto_close_regions`.
+    UnclosedRegion {
+        contour: usize,
+        command_index: usize,
+        auto_closed: bool,
+    },
+    /// Two edges of a region contour cross each other, the fill of the region may not be as intended.
+    ///
+    /// `edges` are the indices of the two edges, edge 0 is from the first to the second vertex of the contour.
+    SelfIntersectingRegion {
+        contour: usize,
+        command_index: usize,
+        edges: (usize, usize),
+    },
+    /// A region contour with less than 3 vertices, it was ignored.
+    DegenerateRegion { contour: usize, command_index: usize },
+    /// An aperture macro uses a primitive that isn't supported, `primitive` is `"moire"` or `"thermal"`, the
+    /// primitive is not drawn.
+    ///
+    /// `command_index` is the index of the aperture definition that uses the macro.
+    UnsupportedMacroPrimitive {
+        command_index: usize,
+        macro_name: String,
+        primitive: &'static str,
+    },
+    /// A draw (D01) with an aperture other than a solid circle, which the gerber spec doesn't allow, it's not drawn.
+    UnsupportedDrawAperture { command_index: usize },
+    /// A flash (D03) inside a region, which the gerber spec doesn't allow, it's ignored.
+    FlashInRegion { command_index: usize },
+    /// An aperture that isn't defined was selected, the following flashes and draws are not drawn.
+    UndefinedAperture { command_index: usize, code: i32 },
+    /// An aperture definition references a macro that isn't defined, the aperture is not defined.
+    UndefinedMacro { command_index: usize, macro_name: String },
+    /// An aperture with a zero size, flashes are handled as configured by
+    /// [`LayerConfiguration::degenerate_apertures`].
+    ///
+    /// [`LayerConfiguration::degenerate_apertures`]: crate::LayerConfiguration::degenerate_apertures
+    DegenerateAperture { command_index: usize, code: i32 },
+    /// Building stopped because the layer has more than [`LayerConfiguration::max_primitives`] primitives, the layer
+    /// only has the first `limit` primitives.
+    ///
+    /// `command_index` is the index of the command that exceeded the limit.
+    ///
+    /// [`LayerConfiguration::max_primitives`]: crate::LayerConfiguration::max_primitives
+    PrimitiveLimitReached { command_index: usize, limit: usize },
+    /// A deprecated code that has no effect, e.g. the `G54` prefix of an aperture selection, which older CAM software
+    /// emits.
+    DeprecatedCode { command_index: usize, code: &'static str },
+}
+
+impl LayerWarning {
+    /// The category of feature that may not render correctly, `None` for problems that were handled, e.g. a region
+    /// that was auto-closed.
+    pub fn unsupported_feature(&self) -> Option<UnsupportedFeature> {
+        match self {
+            LayerWarning::UnclosedRegion {
+                ..
+            }
+            | LayerWarning::SelfIntersectingRegion {
+                ..
+            }
+            | LayerWarning::DegenerateRegion {
+                ..
+            }
+            | LayerWarning::DegenerateAperture {
+                ..
+            }
+            | LayerWarning::PrimitiveLimitReached {
+                ..
+            }
+            | LayerWarning::DeprecatedCode {
+                ..
+            } => None,
+            LayerWarning::UnsupportedMacroPrimitive {
+                ..
+            } => Some(UnsupportedFeature::MacroPrimitive),
+            LayerWarning::UnsupportedDrawAperture {
+                ..
+            } => Some(UnsupportedFeature::DrawAperture),
+            LayerWarning::FlashInRegion {
+                ..
+            } => Some(UnsupportedFeature::FlashInRegion),
+            LayerWarning::UndefinedAperture {
+                ..
+            }
+            | LayerWarning::UndefinedMacro {
+                ..
+            } => Some(UnsupportedFeature::UndefinedAperture),
+        }
+    }
+}
+
+/// A summary of the warnings of a layer, see [`GerberLayer::unsupported_features`].
+///
+/// Layers with any of these may not render correctly, e.g. to show a banner when a layer is loaded, the warnings have
+/// the details.
+///
+/// [`GerberLayer::unsupported_features`]: crate::GerberLayer::unsupported_features
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum UnsupportedFeature {
+    /// Moiré or thermal macro primitives.
+    MacroPrimitive,
+    /// Draws with an aperture other than a solid circle.
+    DrawAperture,
+    /// Flashes inside regions.
+    FlashInRegion,
+    /// References to apertures or macros that aren't defined.
+    UndefinedAperture,
+}
@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+
+use crate::layer::{GerberLayer, GerberPrimitive};
+use crate::types::Exposure;
+
+/// Widths are rounded to this precision, in gerber units, before they are compared, so that the rounding errors from
+/// parsing and building primitives don't split a width into several.
+const WIDTH_PRECISION: f64 = 1e-6;
+
+impl GerberLayer {
+    /// The widths of the lines and arcs that add material, i.e. the traces, in gerber units.
+    fn trace_widths(&self) -> impl Iterator<Item = f64> + '_ {
+        self.primitives()
+            .iter()
+            .filter(|primitive| primitive.exposure() == Exposure::Add)
+            .filter_map(|primitive| match primitive {
+                GerberPrimitive::Line(line) => Some(line.width),
+                GerberPrimitive::Arc(arc) => Some(arc.width),
+                _ => None,
+            })
+    }
+
+    /// Returns the width of the narrowest line or arc that adds material, e.g. to check the layer against the minimum
+    /// trace width of a manufacturer, or `None` if there are no lines or arcs.
+    ///
+    /// The width is in gerber units, before the image transform is applied.
+    pub fn min_line_width(&self) -> Option<f64> {
+        self.trace_widths()
+            .min_by(|a, b| a.total_cmp(b))
+    }
+
+    /// Returns each width of the lines and arcs that add material, with the number of lines and arcs of that width,
+    /// narrowest first.
+    ///
+    /// Widths that are the same to within 1 nanometer (or nano-inch) are counted together.  The widths are in gerber
+    /// units, before the image transform is applied.
+    pub fn line_width_histogram(&self) -> Vec<(f64, usize)> {
+        let mut histogram: BTreeMap<i64, (f64, usize)> = BTreeMap::new();
+        for width in self.trace_widths() {
+            let key = (width / WIDTH_PRECISION).round() as i64;
+            histogram
+                .entry(key)
+                .or_insert((width, 0))
+                .1 += 1;
+        }
+
+        histogram.into_values().collect()
+    }
+}
+
+#[cfg(test)]
+mod line_width_tests {
+    use gerber_types::{Command, ExtendedCode, Polarity};
+
+    use super::*;
+    use crate::testing::commands::{circle, draw_to, flash, header, move_to, select};
+
+    fn layer() -> GerberLayer {
+        let mut commands: Vec<Command> = header();
+        for (code, diameter) in [(10, 0.25), (11, 0.1), (12, 0.05), (13, 2.0)] {
+            commands.push(circle(code, diameter));
+        }
+        let trace = |commands: &mut Vec<Command>, code: i32, y: f64| {
+            commands.extend([select(code), move_to(0.0, y), draw_to(10.0, y)]);
+        };
+        trace(&mut commands, 10, 0.0);
+        trace(&mut commands, 11, 1.0);
+        trace(&mut commands, 10, 2.0);
+        // a pad, not a trace
+        commands.extend([select(13), flash(20.0, 0.0)]);
+        // a gap, not a trace
+        commands.push(ExtendedCode::LoadPolarity(Polarity::Clear).into());
+        trace(&mut commands, 12, 3.0);
+
+        GerberLayer::new(commands)
+    }
+
+    #[test]
+    fn test_min_line_width() {
+        // given
+        let layer = layer();
+
+        // expect
+        assert_eq!(layer.min_line_width(), Some(0.1));
+    }
+
+    #[test]
+    fn test_line_width_histogram() {
+        // given
+        let layer = layer();
+
+        // expect
+        assert_eq!(layer.line_width_histogram(), vec![(0.1, 1), (0.25, 2)]);
+    }
+
+    #[test]
+    fn test_no_lines() {
+        // given
+        let layer = GerberLayer::new(vec![]);
+
+        // expect
+        assert_eq!(layer.min_line_width(), None);
+        assert!(layer.line_width_histogram().is_empty());
+    }
+}